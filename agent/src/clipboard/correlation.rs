@@ -0,0 +1,290 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+
+/// How long copied content stays eligible for correlation
+const CLIPBOARD_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Max number of clipboard entries remembered
+const MAX_ENTRIES: usize = 32;
+
+/// Number of consecutive words per shingle
+const SHINGLE_WORDS: usize = 6;
+
+/// Minimum normalized length for content to be remembered as shingles
+const MIN_CONTENT_CHARS: usize = 24;
+
+/// Minimum length of a short copy remembered as a single token (card
+/// number, API key...); shorter snippets would produce false correlations
+const MIN_TOKEN_CHARS: usize = 8;
+
+/// Fraction of the clipboard shingles that must appear in a prompt
+const MIN_OVERLAP: f64 = 0.5;
+
+/// A sensitive clipboard copy, kept as hashes only (no plaintext)
+struct ClipboardEntry {
+    event_id: i64,
+    fingerprint: Fingerprint,
+    copied_at: Instant,
+}
+
+/// Hashes a clipboard copy is recognized by
+enum Fingerprint {
+    /// Word shingles of a text
+    Shingles(HashSet<u64>),
+    /// Short copy: hash of its alphanumeric characters, found in a prompt
+    /// even when its separators differ (`4970 1012` / `4970-1012`)
+    Token { hash: u64, len: usize },
+}
+
+/// A prompt that contains recently copied sensitive clipboard content
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipboardMatch {
+    /// ID of the clipboard event that recorded the copy
+    pub clipboard_event_id: i64,
+    /// Fraction of the clipboard content found in the prompt (0.0 - 1.0)
+    pub overlap: f64,
+    /// Seconds elapsed between the copy and the prompt
+    pub age_secs: u64,
+}
+
+/// Short-lived window of recently copied sensitive clipboard contents,
+/// shared between the clipboard monitor and the proxy interceptor.
+pub struct ClipboardHistory {
+    entries: Mutex<VecDeque<ClipboardEntry>>,
+}
+
+impl ClipboardHistory {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Remember sensitive clipboard content logged as `event_id`
+    pub fn remember(&self, event_id: i64, content: &str) {
+        let shingles = shingle_hashes(content);
+        let fingerprint = if !shingles.is_empty() {
+            Fingerprint::Shingles(shingles)
+        } else {
+            match token_fingerprint(content) {
+                Some(fingerprint) => fingerprint,
+                None => return,
+            }
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        prune(&mut entries);
+        if entries.len() >= MAX_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back(ClipboardEntry {
+            event_id,
+            fingerprint,
+            copied_at: Instant::now(),
+        });
+    }
+
+    /// Find the most recent remembered clipboard content present in `prompt`
+    pub fn find_in_prompt(&self, prompt: &str) -> Option<ClipboardMatch> {
+        let mut entries = self.entries.lock().unwrap();
+        prune(&mut entries);
+        if entries.is_empty() {
+            return None;
+        }
+
+        let prompt_shingles = shingle_hashes(prompt);
+        let normalized = prompt.to_lowercase();
+        let prompt_words = words(&normalized);
+
+        entries.iter().rev().find_map(|entry| {
+            let overlap = match &entry.fingerprint {
+                Fingerprint::Shingles(shingles) => {
+                    let common = shingles.intersection(&prompt_shingles).count();
+                    common as f64 / shingles.len() as f64
+                }
+                Fingerprint::Token { hash, len } => {
+                    if contains_token(&prompt_words, *hash, *len) {
+                        1.0
+                    } else {
+                        0.0
+                    }
+                }
+            };
+            (overlap >= MIN_OVERLAP).then(|| ClipboardMatch {
+                clipboard_event_id: entry.event_id,
+                overlap,
+                age_secs: entry.copied_at.elapsed().as_secs(),
+            })
+        })
+    }
+}
+
+impl Default for ClipboardHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Drop entries older than the correlation window
+fn prune(entries: &mut VecDeque<ClipboardEntry>) {
+    while entries
+        .front()
+        .is_some_and(|e| e.copied_at.elapsed() > CLIPBOARD_WINDOW)
+    {
+        entries.pop_front();
+    }
+}
+
+/// Hash overlapping word shingles of the normalized content.
+/// Content shorter than one shingle yields a single hash of all its words.
+fn shingle_hashes(content: &str) -> HashSet<u64> {
+    let normalized = content.to_lowercase();
+    let words = words(&normalized);
+
+    let total_chars: usize = words.iter().map(|w| w.len()).sum();
+    if total_chars < MIN_CONTENT_CHARS {
+        return HashSet::new();
+    }
+
+    if words.len() < SHINGLE_WORDS {
+        return HashSet::from([hash_words(&words)]);
+    }
+
+    words.windows(SHINGLE_WORDS).map(hash_words).collect()
+}
+
+/// Fingerprint of a copy too short for shingles. Only copies containing a
+/// digit are kept: secrets (card numbers, IBANs, keys) do, while short
+/// words and phrases would correlate with any prompt.
+fn token_fingerprint(content: &str) -> Option<Fingerprint> {
+    let token: String = words(&content.to_lowercase()).concat();
+    if token.len() < MIN_TOKEN_CHARS || !token.chars().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(Fingerprint::Token {
+        hash: hash_words(&[&token]),
+        len: token.len(),
+    })
+}
+
+/// Whether consecutive prompt words, joined, form the token of `len` bytes
+/// hashed as `hash`
+fn contains_token(words: &[&str], hash: u64, len: usize) -> bool {
+    (0..words.len()).any(|start| {
+        let mut token = String::with_capacity(len);
+        for word in &words[start..] {
+            token.push_str(word);
+            if token.len() >= len {
+                break;
+            }
+        }
+        token.len() == len && hash_words(&[&token]) == hash
+    })
+}
+
+/// Alphanumeric words of normalized content
+fn words(normalized: &str) -> Vec<&str> {
+    normalized
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+fn hash_words(words: &[&str]) -> u64 {
+    let mut hasher = Sha256::new();
+    hasher.update(words.join(" ").as_bytes());
+    let digest = hasher.finalize();
+    u64::from_be_bytes(digest[..8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COPIED: &str = "Le contrat avec le client Orange prévoit une remise de 35% sur la maintenance annuelle du parc.";
+
+    #[test]
+    fn test_prompt_containing_copy_matches() {
+        let history = ClipboardHistory::new();
+        history.remember(42, COPIED);
+
+        let prompt = format!("Résume ce texte : {} Merci.", COPIED);
+        let m = history.find_in_prompt(&prompt).unwrap();
+        assert_eq!(m.clipboard_event_id, 42);
+        assert!(m.overlap >= 0.99);
+    }
+
+    #[test]
+    fn test_reformatted_copy_matches() {
+        let history = ClipboardHistory::new();
+        history.remember(7, COPIED);
+
+        let prompt = COPIED.to_uppercase().replace(' ', "\n");
+        assert!(history.find_in_prompt(&prompt).is_some());
+    }
+
+    #[test]
+    fn test_unrelated_prompt_does_not_match() {
+        let history = ClipboardHistory::new();
+        history.remember(1, COPIED);
+        assert!(history
+            .find_in_prompt("Écris un poème sur la mer et les bateaux de pêche au lever du soleil")
+            .is_none());
+    }
+
+    #[test]
+    fn test_short_content_not_remembered() {
+        let history = ClipboardHistory::new();
+        history.remember(1, "ok merci");
+        assert!(history.find_in_prompt("ok merci").is_none());
+    }
+
+    #[test]
+    fn test_short_secret_in_prompt_matches() {
+        let history = ClipboardHistory::new();
+        history.remember(3, "4970 1012 3456 7890");
+        history.remember(4, "sk-proj-Xa81kQ");
+
+        let m = history
+            .find_in_prompt("Ma carte 4970-1012-3456-7890 a été refusée, pourquoi ?")
+            .unwrap();
+        assert_eq!(m.clipboard_event_id, 3);
+        assert_eq!(m.overlap, 1.0);
+        assert_eq!(
+            history
+                .find_in_prompt("Ma clé sk-proj-xa81kq ne marche plus")
+                .unwrap()
+                .clipboard_event_id,
+            4
+        );
+        assert!(history
+            .find_in_prompt("Ma carte 4970 1012 expire bientôt")
+            .is_none());
+        assert!(history
+            .find_in_prompt("Ma carte 49701012345678901 est bloquée")
+            .is_none());
+    }
+
+    #[test]
+    fn test_most_recent_entry_wins() {
+        let history = ClipboardHistory::new();
+        history.remember(1, COPIED);
+        history.remember(2, COPIED);
+        assert_eq!(
+            history.find_in_prompt(COPIED).unwrap().clipboard_event_id,
+            2
+        );
+    }
+
+    #[test]
+    fn test_capacity_bounded() {
+        let history = ClipboardHistory::new();
+        for i in 0..(MAX_ENTRIES as i64 + 5) {
+            history.remember(i, COPIED);
+        }
+        assert_eq!(history.entries.lock().unwrap().len(), MAX_ENTRIES);
+    }
+}
@@ -1,3 +1,4 @@
+pub mod correlation;
 pub mod monitor;
 
 #[cfg(target_os = "windows")]
@@ -1,28 +1,16 @@
+use serde_json::json;
+use sha2::{Digest, Sha256};
 use std::sync::Arc;
 use tokio::time::{interval, Duration};
-use tracing::{info, warn, debug};
-use sha2::{Sha256, Digest};
-use serde_json::json;
+use tracing::{debug, info, warn};
 
+use crate::clipboard::correlation::ClipboardHistory;
 use crate::config::AppConfig;
+use crate::rules::dlp::BUILTIN_DLP_PATTERNS;
 use crate::rules::engine::RuleEngine;
-use crate::rules::models::{EvaluationResult, RuleTarget};
+use crate::rules::models::{EvaluationContext, EvaluationResult, RuleTarget, ThresholdMetric};
 use crate::sync::queue::EventQueue;
 
-/// Built-in DLP patterns for common sensitive data types.
-/// These are always active as a baseline, independent of server rules.
-static BUILTIN_DLP_PATTERNS: &[(&str, &str, &str)] = &[
-    // (name, pattern, description)
-    ("credit_card", r"\b(?:4[0-9]{12}(?:[0-9]{3})?|5[1-5][0-9]{14}|3[47][0-9]{13}|6(?:011|5[0-9]{2})[0-9]{12})\b", "Numero de carte bancaire"),
-    ("french_ssn", r"\b[12][0-9]{2}(?:0[1-9]|1[0-2])[0-9]{2}[0-9]{3}[0-9]{3}[0-9]{2}\b", "Numero de securite sociale (France)"),
-    ("iban_fr", r"\bFR\d{2}\s?\d{4}\s?\d{4}\s?\d{4}\s?\d{4}\s?\d{4}\s?\d{3}\b", "IBAN francais"),
-    ("email_bulk", r"(?i)(?:[a-z0-9._%+\-]+@[a-z0-9.\-]+\.[a-z]{2,}\s*[,;\n]\s*){3,}", "Liste d adresses email (3+)"),
-    ("phone_fr", r"\b(?:(?:\+33|0033|0)\s?[1-9])(?:[\s.\-]?\d{2}){4}\b", "Numero de telephone francais"),
-    ("api_key_generic", r#"(?i)(?:api[_\-]?key|token|secret|password)\s*[:=]\s*['"]?[a-z0-9_\-]{20,}['"]?"#, "Cle API / token / mot de passe"),
-    ("private_key", r"-----BEGIN (?:RSA |EC |DSA )?PRIVATE KEY-----", "Cle privee cryptographique"),
-    ("aws_key", r"(?:AKIA|ABIA|ACCA|ASIA)[0-9A-Z]{16}", "Cle d acces AWS"),
-];
-
 /// Configuration for the clipboard monitor
 pub struct ClipboardMonitorConfig {
     /// Polling interval in milliseconds
@@ -61,10 +49,12 @@ impl ClipboardMonitorConfig {
 ///   1. Server-synced DLP rules (via RuleEngine)
 ///   2. Built-in DLP patterns (credit cards, SSN, API keys, etc.)
 ///
-/// Logs events with matched pattern metadata.
+/// Logs events with matched pattern metadata. Sensitive copies are also kept
+/// in `clipboard_history` (as hashes) for correlation with AI prompts.
 pub async fn start_monitoring(
     rule_engine: Arc<RuleEngine>,
     event_queue: Arc<EventQueue>,
+    clipboard_history: Arc<ClipboardHistory>,
     monitor_config: ClipboardMonitorConfig,
 ) -> anyhow::Result<()> {
     info!(
@@ -115,8 +105,16 @@ pub async fn start_monitoring(
         // Truncate content for scanning if too long
         let scan_content = truncate(&content, monitor_config.max_scan_length);
 
+        // Feed the behavioral threshold counters
+        rule_engine.record_activity(ThresholdMetric::ClipboardCount, None, 1);
+        rule_engine.record_activity(ThresholdMetric::ClipboardBytes, None, content.len() as u64);
+
         // --- Phase 1: Evaluate against server-synced rules ---
-        let rule_result = rule_engine.evaluate(&scan_content, RuleTarget::Clipboard).await;
+        let ctx = EvaluationContext::new(&scan_content);
+        let rule_result = rule_engine
+            .evaluate_context(&ctx, RuleTarget::Clipboard)
+            .await;
+        let language = ctx.language();
 
         // --- Phase 2: Built-in DLP pattern scan ---
         let dlp_matches = scan_builtin_patterns(&scan_content, &builtin_patterns);
@@ -125,9 +123,11 @@ pub async fn start_monitoring(
         let excerpt = truncate(&content, monitor_config.max_excerpt_length);
 
         match rule_result {
-            EvaluationResult::Blocked { rule_id, rule_name, message: _ } => {
+            EvaluationResult::Blocked {
+                rule_id, rule_name, ..
+            } => {
                 info!(%rule_name, "Clipboard content matched blocking rule");
-                let metadata = build_metadata(&dlp_matches, Some(&rule_name));
+                let metadata = build_metadata(&dlp_matches, Some(&rule_name), language);
 
                 if monitor_config.notifications_enabled {
                     show_notification(
@@ -136,21 +136,29 @@ pub async fn start_monitoring(
                     );
                 }
 
-                event_queue.log_event_with_metadata(
-                    "clipboard_block",
-                    None, None,
-                    Some(&hash),
-                    Some(&excerpt),
-                    None,
-                    Some(&rule_id),
-                    Some("critical"),
-                    Some(&metadata),
-                ).await;
+                let event_id = event_queue
+                    .log_event_with_metadata(
+                        "clipboard_block",
+                        None,
+                        None,
+                        Some(&hash),
+                        Some(&excerpt),
+                        None,
+                        Some(&rule_id),
+                        Some("critical"),
+                        Some(&metadata),
+                    )
+                    .await;
+                remember_sensitive(&clipboard_history, event_id, &scan_content);
             }
-            EvaluationResult::Alerted { rule_id, rule_name, severity } => {
+            EvaluationResult::Alerted {
+                rule_id,
+                rule_name,
+                severity,
+            } => {
                 info!(%rule_name, "Clipboard content triggered alert");
                 let sev = format!("{:?}", severity).to_lowercase();
-                let metadata = build_metadata(&dlp_matches, Some(&rule_name));
+                let metadata = build_metadata(&dlp_matches, Some(&rule_name), language);
 
                 if monitor_config.notifications_enabled && sev == "critical" {
                     show_notification(
@@ -159,21 +167,25 @@ pub async fn start_monitoring(
                     );
                 }
 
-                event_queue.log_event_with_metadata(
-                    "clipboard_alert",
-                    None, None,
-                    Some(&hash),
-                    Some(&excerpt),
-                    None,
-                    Some(&rule_id),
-                    Some(&sev),
-                    Some(&metadata),
-                ).await;
+                let event_id = event_queue
+                    .log_event_with_metadata(
+                        "clipboard_alert",
+                        None,
+                        None,
+                        Some(&hash),
+                        Some(&excerpt),
+                        None,
+                        Some(&rule_id),
+                        Some(&sev),
+                        Some(&metadata),
+                    )
+                    .await;
+                remember_sensitive(&clipboard_history, event_id, &scan_content);
             }
             EvaluationResult::Logged { rule_id } => {
                 // If no server rule matched but DLP patterns did, escalate to alert
                 if !dlp_matches.is_empty() {
-                    let metadata = build_metadata(&dlp_matches, None);
+                    let metadata = build_metadata(&dlp_matches, None, language);
                     info!(
                         patterns = dlp_matches.len(),
                         "Built-in DLP patterns matched clipboard content"
@@ -187,33 +199,40 @@ pub async fn start_monitoring(
                         );
                     }
 
-                    event_queue.log_event_with_metadata(
-                        "clipboard_alert",
-                        None, None,
-                        Some(&hash),
-                        Some(&excerpt),
-                        None,
-                        rule_id.as_deref(),
-                        Some("warning"),
-                        Some(&metadata),
-                    ).await;
+                    let event_id = event_queue
+                        .log_event_with_metadata(
+                            "clipboard_alert",
+                            None,
+                            None,
+                            Some(&hash),
+                            Some(&excerpt),
+                            None,
+                            rule_id.as_deref(),
+                            Some("warning"),
+                            Some(&metadata),
+                        )
+                        .await;
+                    remember_sensitive(&clipboard_history, event_id, &scan_content);
                 } else {
                     debug!("Clipboard content logged (no sensitive patterns)");
-                    event_queue.log_event(
-                        "clipboard_log",
-                        None, None,
-                        Some(&hash),
-                        Some(&truncate(&content, 200)),
-                        None,
-                        rule_id.as_deref(),
-                        Some("info"),
-                    ).await;
+                    event_queue
+                        .log_event(
+                            "clipboard_log",
+                            None,
+                            None,
+                            Some(&hash),
+                            Some(&truncate(&content, 200)),
+                            None,
+                            rule_id.as_deref(),
+                            Some("info"),
+                        )
+                        .await;
                 }
             }
             EvaluationResult::NoMatch => {
                 // Even without rule match, check built-in DLP
                 if !dlp_matches.is_empty() {
-                    let metadata = build_metadata(&dlp_matches, None);
+                    let metadata = build_metadata(&dlp_matches, None, language);
                     info!(
                         patterns = dlp_matches.len(),
                         "Built-in DLP patterns matched clipboard (no server rule)"
@@ -227,16 +246,20 @@ pub async fn start_monitoring(
                         );
                     }
 
-                    event_queue.log_event_with_metadata(
-                        "clipboard_alert",
-                        None, None,
-                        Some(&hash),
-                        Some(&excerpt),
-                        None,
-                        None,
-                        Some("warning"),
-                        Some(&metadata),
-                    ).await;
+                    let event_id = event_queue
+                        .log_event_with_metadata(
+                            "clipboard_alert",
+                            None,
+                            None,
+                            Some(&hash),
+                            Some(&excerpt),
+                            None,
+                            None,
+                            Some("warning"),
+                            Some(&metadata),
+                        )
+                        .await;
+                    remember_sensitive(&clipboard_history, event_id, &scan_content);
                 }
                 // No match at all → nothing to report
             }
@@ -244,6 +267,14 @@ pub async fn start_monitoring(
     }
 }
 
+/// Keep the hashes of sensitive clipboard content so the proxy can
+/// correlate it with prompts sent shortly afterwards
+fn remember_sensitive(history: &ClipboardHistory, event_id: Option<i64>, content: &str) {
+    if let Some(id) = event_id {
+        history.remember(id, content);
+    }
+}
+
 /// Result from scanning a single built-in DLP pattern
 struct DlpMatch<'a> {
     name: &'a str,
@@ -263,7 +294,8 @@ fn scan_builtin_patterns<'a>(
     for (name, regex, desc) in patterns {
         let found: Vec<regex::Match> = regex.find_iter(content).collect();
         if !found.is_empty() {
-            let samples: Vec<String> = found.iter()
+            let samples: Vec<String> = found
+                .iter()
                 .take(3)
                 .map(|m| redact_match(m.as_str()))
                 .collect();
@@ -287,20 +319,27 @@ fn redact_match(value: &str) -> String {
         "*".repeat(chars.len())
     } else {
         let visible: String = chars[..4].iter().collect();
-        format!("{}{}",visible, "*".repeat(chars.len() - 4))
+        format!("{}{}", visible, "*".repeat(chars.len() - 4))
     }
 }
 
 /// Build JSON metadata string for DLP matches
-fn build_metadata(dlp_matches: &[DlpMatch], rule_name: Option<&str>) -> String {
-    let dlp_data: Vec<serde_json::Value> = dlp_matches.iter().map(|m| {
-        json!({
-            "pattern": m.name,
-            "description": m.description,
-            "count": m.match_count,
-            "samples": m.samples,
+fn build_metadata(
+    dlp_matches: &[DlpMatch],
+    rule_name: Option<&str>,
+    language: Option<&str>,
+) -> String {
+    let dlp_data: Vec<serde_json::Value> = dlp_matches
+        .iter()
+        .map(|m| {
+            json!({
+                "pattern": m.name,
+                "description": m.description,
+                "count": m.match_count,
+                "samples": m.samples,
+            })
         })
-    }).collect();
+        .collect();
 
     let mut meta = json!({});
     if !dlp_data.is_empty() {
@@ -309,6 +348,9 @@ fn build_metadata(dlp_matches: &[DlpMatch], rule_name: Option<&str>) -> String {
     if let Some(name) = rule_name {
         meta["triggered_rule"] = json!(name);
     }
+    if let Some(lang) = language {
+        meta["language"] = json!(lang);
+    }
     meta.to_string()
 }
 
@@ -339,6 +381,11 @@ fn show_notification(title: &str, body: &str) {
             .args(["-WindowStyle", "Hidden", "-Command", &script])
             .spawn();
     }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    {
+        let _ = (title, body);
+    }
 }
 
 /// Get the current clipboard text (platform-specific)
@@ -473,10 +520,11 @@ mod tests {
             match_count: 1,
             samples: vec!["4532************".to_string()],
         }];
-        let meta = build_metadata(&dlp, Some("rule-test"));
+        let meta = build_metadata(&dlp, Some("rule-test"), Some("fr"));
         let parsed: serde_json::Value = serde_json::from_str(&meta).unwrap();
         assert!(parsed["dlp_matches"].is_array());
         assert_eq!(parsed["triggered_rule"], "rule-test");
+        assert_eq!(parsed["language"], "fr");
     }
 
     #[test]
@@ -50,6 +50,42 @@ pub struct AppConfig {
 
     /// Enrollment key for initial registration (env ICON_ENROLLMENT_KEY or config file)
     pub enrollment_key: Option<String>,
+
+    /// Interception transparente (Linux) : redirection nftables du trafic
+    /// HTTPS des plateformes IA vers l'agent
+    pub transparent_mode: bool,
+
+    /// Port d'écoute du proxy transparent
+    pub transparent_port: u16,
+
+    /// Proxy d'entreprise pour les connexions sortantes
+    /// (`http://[user:pass@]host:port` ou `socks5://...`). À défaut, le proxy
+    /// système en place avant l'agent est utilisé.
+    pub upstream_proxy: Option<String>,
+
+    /// Hôtes joints sans le proxy d'entreprise, séparés par des virgules
+    /// (noms, domaines `.exemple.local`, adresses IP), en plus des adresses
+    /// locales, privées et des noms d'intranet
+    pub upstream_proxy_bypass: Option<String>,
+
+    /// Sel des empreintes de clés d'API (HMAC-SHA256), identique sur tous les
+    /// postes et dans la console
+    pub api_key_salt: Option<String>,
+
+    /// Assistant IA approuvé vers lequel les navigations sur les plateformes
+    /// bloquées sont redirigées (302) ; `{{PROMPT}}` y reprend le prompt
+    pub approved_assistant_url: Option<String>,
+
+    /// Endpoint interne compatible OpenAI vers lequel les appels d'API des
+    /// plateformes bloquées sont réacheminés
+    pub approved_api_endpoint: Option<String>,
+
+    /// Clé d'API de l'endpoint interne, envoyée à la place de celle de
+    /// l'utilisateur
+    pub approved_api_key: Option<String>,
+
+    /// Modèle demandé à l'endpoint interne à la place de celui de la requête
+    pub approved_api_model: Option<String>,
 }
 
 impl AppConfig {
@@ -76,8 +112,13 @@ impl AppConfig {
             .set_default("websocket_url", "wss://icon.gs2e.ci")?
             .set_default("reverb_app_key", "icon-local-key")?
             .set_default("reverb_channel", "icon.rules")?
-            .set_default("data_dir", Self::default_data_dir().to_string_lossy().to_string())?
+            .set_default(
+                "data_dir",
+                Self::default_data_dir().to_string_lossy().to_string(),
+            )?
             .set_default("db_encryption_key", "CHANGE_ME_ON_INSTALL")?
+            .set_default("transparent_mode", false)?
+            .set_default("transparent_port", 8444_i64)?
             // Config file
             .add_source(config::File::from(config_path).required(false))
             // Environment variables (prefixed ICON_)
@@ -110,7 +151,7 @@ impl AppConfig {
         let data_dir = Self::default_data_dir();
 
         format!(
-r#"# =============================================================================
+            r#"# =============================================================================
 # Icon Agent Configuration
 # =============================================================================
 # This file is auto-generated by `icon-agent --generate-config`.
@@ -142,6 +183,48 @@ db_encryption_key = "CHANGE_ME_ON_INSTALL"
 # Port for the local HTTPS proxy interceptor
 proxy_port = 8443
 
+# Transparent interception (Linux only): HTTPS connections to monitored AI
+# domains are redirected to the agent by nftables rules, so that applications
+# ignoring the system proxy (CLI tools, SDKs, IDE plugins) are inspected too.
+# Requires root (or CAP_NET_ADMIN) and the `nft` command. Exact domains and
+# the base domain of "*.domain" patterns are resolved; other hosts matched by
+# suffix or regex patterns are redirected once seen through the system proxy.
+transparent_mode = false
+
+# Port of the transparent interception listener
+transparent_port = 8444
+
+# Corporate proxy for the agent's outbound connections (tunneled and
+# intercepted traffic): "http://[user:pass@]host:port" (HTTP CONNECT, Basic
+# auth) or "socks5://[user:pass@]host:port". When unset, the system proxy
+# configured before the agent installed its PAC file is used, if any. Plain
+# HTTP requests are sent to an HTTP proxy in absolute form.
+# upstream_proxy = "http://squid.example.local:3128"
+
+# Hosts reached without the corporate proxy (configured or previous system
+# proxy), comma-separated: host names, ".domain" suffixes or IP addresses.
+# Loopback, private and link-local addresses, single-label names and
+# .local/.internal/.lan names always are.
+# upstream_proxy_bypass = "wiki.example.com, .corp.example.com"
+
+# Salt of the API key fingerprints recorded in events and matched by ApiKey
+# rules (HMAC-SHA256 of the key). Use the same value on every machine and in
+# the console. A built-in salt is used when unset.
+# api_key_salt = ""
+
+# Redirect-to-approved-AI mode for fully blocked AI platforms. When unset,
+# blocked platforms get the block page.
+# Browser navigations are redirected (302) to the approved assistant;
+# {{{{PROMPT}}}} is replaced by the prompt of the navigated URL (?q=...), if any.
+# approved_assistant_url = "https://marcelia.gs2e.ci/?q={{{{PROMPT}}}}"
+
+# OpenAI-compatible API calls (chat completions, completions, embeddings) to
+# blocked platforms are rerouted to this internal endpoint, with its own API
+# key and model when set.
+# approved_api_endpoint = "https://ai.gs2e.local/v1"
+# approved_api_key = ""
+# approved_api_model = ""
+
 # Heartbeat interval in seconds (how often the agent pings the server)
 heartbeat_interval_secs = 60
 
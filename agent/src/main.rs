@@ -1,5 +1,5 @@
-mod config;
 mod clipboard;
+mod config;
 mod proxy;
 mod rules;
 mod service;
@@ -8,15 +8,16 @@ mod sync;
 mod update;
 
 use clap::Parser;
-use tracing::{info, warn, error};
 use std::path::PathBuf;
 use std::sync::Arc;
+use tracing::{error, info, warn};
 
+use crate::clipboard::correlation::ClipboardHistory;
 use crate::config::AppConfig;
 use crate::proxy::domain_filter::DomainFilter;
 use crate::proxy::tls::CaManager;
-use crate::storage::database::Database;
 use crate::rules::engine::RuleEngine;
+use crate::storage::database::Database;
 use crate::sync::api_client::ApiClient;
 use crate::sync::queue::EventQueue;
 
@@ -125,6 +126,9 @@ pub async fn run_agent(config_path: &Option<PathBuf>) -> anyhow::Result<()> {
     if let Err(e) = rule_engine.load_rules().await {
         warn!(error = %e, "Failed to load cached rules from local DB");
     }
+    if let Err(e) = rule_engine.load_dictionaries() {
+        warn!(error = %e, "Failed to load cached dictionaries from local DB");
+    }
 
     // Register with server or use existing credentials
     if let Some(mid) = &config.machine_id {
@@ -167,8 +171,46 @@ pub async fn run_agent(config_path: &Option<PathBuf>) -> anyhow::Result<()> {
         error!(error = %e, "Failed to sync rules, using cached rules");
     }
 
-    // Sync monitored domains from server
-    sync_domains_from_server(&api_client, &domain_filter).await;
+    // Sync entity dictionaries referenced by Dictionary rules
+    if let Err(e) = sync::rules_sync::sync_dictionaries(&api_client, &rule_engine).await {
+        error!(error = %e, "Failed to sync dictionaries, using cached dictionaries");
+    }
+
+    // Block templates: cached ones first, then the server's
+    match db.get_config(proxy::block_page::TEMPLATES_CONFIG_KEY) {
+        Ok(Some(cached)) => match serde_json::from_str(&cached) {
+            Ok(templates) => proxy::block_page::set_templates(templates),
+            Err(e) => warn!(error = %e, "Ignoring unreadable cached block templates"),
+        },
+        Ok(None) => {}
+        Err(e) => warn!(error = %e, "Failed to read cached block templates"),
+    }
+    if let Err(e) = sync::rules_sync::sync_block_templates(&api_client, &db).await {
+        error!(error = %e, "Failed to sync block templates, using cached templates");
+    }
+
+    // Platform catalog cached by the last domain sync
+    match db.get_config(proxy::platforms::CATALOG_CONFIG_KEY) {
+        Ok(Some(cached)) => match serde_json::from_str(&cached) {
+            Ok(specs) => proxy::platforms::set_catalog(specs),
+            Err(e) => warn!(error = %e, "Ignoring unreadable cached platform catalog"),
+        },
+        Ok(None) => {}
+        Err(e) => warn!(error = %e, "Failed to read cached platform catalog"),
+    }
+
+    // Approved tenants cached by the last domain sync
+    match db.get_config(proxy::domain_filter::TENANTS_CONFIG_KEY) {
+        Ok(Some(cached)) => match serde_json::from_str(&cached) {
+            Ok(tenants) => domain_filter.set_approved_tenants(tenants).await,
+            Err(e) => warn!(error = %e, "Ignoring unreadable cached approved tenants"),
+        },
+        Ok(None) => {}
+        Err(e) => warn!(error = %e, "Failed to read cached approved tenants"),
+    }
+
+    // Sync monitored domains, the platform catalog and approved tenants from server
+    sync_domains_from_server(&api_client, &domain_filter, &db).await;
 
     // -----------------------------------------------------------------------
     // First-boot setup: install CA certificate and configure system proxy
@@ -187,6 +229,61 @@ pub async fn run_agent(config_path: &Option<PathBuf>) -> anyhow::Result<()> {
     // Configure the OS to use our PAC file so that monitored AI domains are
     // automatically routed through the local MITM proxy.
     let pac_url = format!("http://127.0.0.1:{}/proxy.pac", config.proxy_port);
+
+    // Save the proxy settings our PAC file is about to replace, so that they
+    // are restored on shutdown and still apply to non-AI hosts
+    let saved_settings =
+        proxy::system_proxy::save_proxy_settings(&config.data_dir).unwrap_or_else(|e| {
+            error!(error = %e, "Failed to save the previous system proxy settings");
+            proxy::system_proxy::capture_proxy_settings()
+        });
+    let previous_proxy = match saved_settings
+        .as_ref()
+        .and_then(|s| s.previous_pac(&pac_url))
+    {
+        Some(url) => Some(proxy::system_proxy::PreviousProxy::Pac(url.to_string())),
+        None => proxy::system_proxy::detect_previous_proxy(&pac_url),
+    };
+    let previous_pac = match &previous_proxy {
+        Some(previous) => proxy::system_proxy::previous_pac_script(previous).await,
+        None => None,
+    };
+
+    // Outbound connections go through the corporate proxy, except for local
+    // and intranet hosts: the configured one, or the one the previous system
+    // configuration used
+    let upstream_proxy = config.upstream_proxy.clone().or_else(|| {
+        previous_proxy.as_ref().and_then(|previous| {
+            proxy::system_proxy::previous_upstream_proxy(previous, previous_pac.as_deref())
+        })
+    });
+    domain_filter.set_fallback_pac(previous_pac).await;
+    if let Some(url) = &upstream_proxy {
+        match proxy::upstream::UpstreamProxy::parse(url) {
+            Ok(upstream) => {
+                let upstream =
+                    upstream.with_bypass(config.upstream_proxy_bypass.as_deref().unwrap_or(""));
+                info!(proxy = %upstream, "Using upstream proxy for outbound connections");
+                proxy::upstream::set_upstream_proxy(Some(upstream));
+            }
+            Err(e) => error!(error = %e, "Invalid upstream proxy, connecting directly"),
+        }
+    }
+
+    // API keys seen by the proxy are only recorded as salted fingerprints
+    proxy::api_keys::set_salt(config.api_key_salt.clone());
+
+    // Blocked platforms: redirect to the approved AI instead of blocking
+    match proxy::approved_ai::ApprovedAi::from_config(&config) {
+        Ok(approved) => {
+            if approved.is_enabled() {
+                info!("Blocked AI platforms are redirected to the approved AI");
+            }
+            proxy::approved_ai::set_approved_ai(approved);
+        }
+        Err(e) => error!(error = %e, "Invalid approved AI settings, blocked platforms are blocked"),
+    }
+
     if let Err(e) = proxy::system_proxy::configure_system_proxy(&pac_url) {
         error!(error = %e, "Failed to configure system proxy — \
             traffic will not be routed through the agent");
@@ -194,14 +291,36 @@ pub async fn run_agent(config_path: &Option<PathBuf>) -> anyhow::Result<()> {
         info!(pac_url = %pac_url, "System proxy configured");
     }
 
+    // Transparent interception (Linux): redirect HTTPS traffic to the
+    // monitored domains to the agent, for applications ignoring the proxy.
+    let redirect_handle = if config.transparent_mode {
+        match proxy::transparent::install_redirect(config.transparent_port) {
+            Ok(()) => {
+                let df = domain_filter.clone();
+                Some(tokio::spawn(proxy::transparent::run_redirect_updater(df)))
+            }
+            Err(e) => {
+                error!(error = %e, "Failed to install transparent redirect rules");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Hashed window of recent sensitive clipboard copies, shared by the
+    // clipboard monitor and the proxy for clipboard-to-prompt correlation
+    let clipboard_history = Arc::new(ClipboardHistory::new());
+
     // Start all subsystems concurrently
     let proxy_handle = {
         let re = rule_engine.clone();
         let eq = event_queue.clone();
         let cfg = config.clone();
         let df = domain_filter.clone();
+        let ch = clipboard_history.clone();
         tokio::spawn(async move {
-            if let Err(e) = proxy::interceptor::start_proxy(cfg, re, eq, df).await {
+            if let Err(e) = proxy::interceptor::start_proxy(cfg, re, eq, df, ch).await {
                 error!(error = %e, "Proxy interceptor failed");
             }
         })
@@ -210,9 +329,10 @@ pub async fn run_agent(config_path: &Option<PathBuf>) -> anyhow::Result<()> {
     let clipboard_handle = {
         let re = rule_engine.clone();
         let eq = event_queue.clone();
+        let ch = clipboard_history.clone();
         let monitor_config = clipboard::monitor::ClipboardMonitorConfig::from_app_config(&config);
         tokio::spawn(async move {
-            if let Err(e) = clipboard::monitor::start_monitoring(re, eq, monitor_config).await {
+            if let Err(e) = clipboard::monitor::start_monitoring(re, eq, ch, monitor_config).await {
                 error!(error = %e, "Clipboard monitor failed");
             }
         })
@@ -255,8 +375,8 @@ pub async fn run_agent(config_path: &Option<PathBuf>) -> anyhow::Result<()> {
         #[cfg(unix)]
         {
             use tokio::signal::unix::{signal, SignalKind};
-            let mut sigterm = signal(SignalKind::terminate())
-                .expect("failed to register SIGTERM handler");
+            let mut sigterm =
+                signal(SignalKind::terminate()).expect("failed to register SIGTERM handler");
             tokio::select! {
                 _ = sigterm.recv() => {
                     info!("Received SIGTERM, shutting down...");
@@ -284,13 +404,20 @@ pub async fn run_agent(config_path: &Option<PathBuf>) -> anyhow::Result<()> {
         }
     }
 
-    // Clean up: remove system proxy configuration so the OS does not try to
-    // route traffic through a proxy that is no longer running.
-    info!("Removing system proxy configuration...");
-    if let Err(e) = proxy::system_proxy::remove_system_proxy() {
-        error!(error = %e, "Failed to remove system proxy configuration during shutdown");
+    if let Some(handle) = redirect_handle {
+        handle.abort();
+        if let Err(e) = proxy::transparent::remove_redirect() {
+            error!(error = %e, "Failed to remove transparent redirect rules during shutdown");
+        }
+    }
+
+    // Clean up: restore the previous system proxy configuration so the OS
+    // does not try to route traffic through a proxy that is no longer running.
+    info!("Restoring system proxy configuration...");
+    if let Err(e) = proxy::system_proxy::restore_system_proxy(&config.data_dir) {
+        error!(error = %e, "Failed to restore system proxy configuration during shutdown");
     } else {
-        info!("System proxy configuration removed");
+        info!("System proxy configuration restored");
     }
 
     Ok(())
@@ -314,10 +441,11 @@ fn handle_generate_config(config_path_override: &Option<PathBuf>) -> anyhow::Res
     let content = AppConfig::generate_default_config_toml();
     std::fs::write(&target_path, &content)?;
 
-    println!("Default configuration written to: {}", target_path.display());
     println!(
-        "Edit this file to customize the agent configuration before starting the service."
+        "Default configuration written to: {}",
+        target_path.display()
     );
+    println!("Edit this file to customize the agent configuration before starting the service.");
 
     Ok(())
 }
@@ -408,10 +536,43 @@ fn persist_credentials(
     Ok(())
 }
 
-/// Fetch monitored domains from the server and update the DomainFilter.
-async fn sync_domains_from_server(api_client: &ApiClient, domain_filter: &DomainFilter) {
+/// Fetch monitored domains from the server and update the DomainFilter,
+/// along with the platform catalog (cached in the local DB).
+async fn sync_domains_from_server(
+    api_client: &ApiClient,
+    domain_filter: &DomainFilter,
+    db: &Database,
+) {
     match api_client.sync_domains().await {
         Ok(resp) => {
+            if !resp.platforms.is_empty() {
+                match serde_json::to_string(&resp.platforms) {
+                    Ok(json) => {
+                        if let Err(e) = db.set_config(proxy::platforms::CATALOG_CONFIG_KEY, &json) {
+                            warn!(error = %e, "Failed to cache platform catalog");
+                        }
+                    }
+                    Err(e) => warn!(error = %e, "Failed to serialize platform catalog"),
+                }
+                info!(
+                    count = resp.platforms.len(),
+                    "Platform catalog updated from server"
+                );
+                proxy::platforms::set_catalog(resp.platforms);
+            }
+
+            match serde_json::to_string(&resp.approved_tenants) {
+                Ok(json) => {
+                    if let Err(e) = db.set_config(proxy::domain_filter::TENANTS_CONFIG_KEY, &json) {
+                        warn!(error = %e, "Failed to cache approved tenants");
+                    }
+                }
+                Err(e) => warn!(error = %e, "Failed to serialize approved tenants"),
+            }
+            domain_filter
+                .set_approved_tenants(resp.approved_tenants)
+                .await;
+
             let domains: Vec<(String, bool)> = resp
                 .domains
                 .into_iter()
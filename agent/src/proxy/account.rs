@@ -0,0 +1,184 @@
+//! Account context of intercepted requests: the organization, workspace or
+//! tenant an AI platform request is made for, used to tell enterprise
+//! accounts from personal ones.
+
+use crate::proxy::request_parser::ParsedHttpRequest;
+
+/// Platforms whose web apps declare the organization of their requests when
+/// there is one. A web app request of these platforms without organization
+/// comes from a personal account; other platforms have no detectable
+/// account context.
+const ACCOUNT_PLATFORMS: [&str; 2] = ["chatgpt", "claude"];
+
+/// Headers naming the organization or workspace of a request
+pub const TENANT_HEADERS: [&str; 2] = [
+    // OpenAI API: organization billed for the call (org-...)
+    "openai-organization",
+    // ChatGPT web app: workspace of the signed-in account
+    "chatgpt-account-id",
+];
+
+/// Cookie holding the selected ChatGPT workspace
+const ACCOUNT_COOKIE: &str = "_account";
+
+/// Organization declared by a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tenant {
+    pub id: String,
+    /// Where the id was found (header name, cookie or path)
+    pub source: &'static str,
+}
+
+/// Whether the account context of `platform` requests is detected
+pub fn is_detected(platform: &str) -> bool {
+    ACCOUNT_PLATFORMS.contains(&platform)
+}
+
+/// Whether a request comes from a web app session (session cookie, workspace
+/// header, organization in the path) rather than an API client. Only those
+/// are classified: an API call without organization is not a personal
+/// account, its key is checked by the ApiKey condition.
+pub fn is_web_session(req: &ParsedHttpRequest) -> bool {
+    let has_header = |name: &str| {
+        req.headers
+            .iter()
+            .any(|(k, v)| k.eq_ignore_ascii_case(name) && !v.trim().is_empty())
+    };
+    has_header("cookie")
+        || has_header("chatgpt-account-id")
+        || organization_in_path(&req.path).is_some()
+}
+
+/// Organization a request is made for, if it declares one
+pub fn detect_tenant(req: &ParsedHttpRequest) -> Option<Tenant> {
+    let header = |name: &str| {
+        req.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.trim())
+            .filter(|v| !v.is_empty())
+    };
+
+    if let Some((source, id)) = TENANT_HEADERS
+        .iter()
+        .find_map(|name| header(name).map(|id| (*name, id)))
+    {
+        return Some(Tenant {
+            id: id.to_string(),
+            source,
+        });
+    }
+
+    // claude.ai: /api/organizations/{uuid}/chat_conversations/...
+    if let Some(id) = organization_in_path(&req.path) {
+        return Some(Tenant {
+            id: id.to_string(),
+            source: "path",
+        });
+    }
+
+    header("cookie")?
+        .split(';')
+        .filter_map(|c| c.trim().split_once('='))
+        .find(|(name, value)| *name == ACCOUNT_COOKIE && !value.is_empty())
+        .map(|(_, id)| Tenant {
+            id: id.to_string(),
+            source: "cookie",
+        })
+}
+
+fn organization_in_path(path: &str) -> Option<&str> {
+    let path = path.split('?').next()?;
+    let mut segments = path.split('/');
+    segments.find(|s| *s == "organizations")?;
+    segments.next().filter(|id| !id.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(path: &str, headers: &[(&str, &str)]) -> ParsedHttpRequest {
+        ParsedHttpRequest {
+            method: "POST".to_string(),
+            path: path.to_string(),
+            host: "chatgpt.com".to_string(),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body: Vec::new(),
+            content_type: None,
+        }
+    }
+
+    #[test]
+    fn test_detect_tenant() {
+        let api = request(
+            "/v1/chat/completions",
+            &[("OpenAI-Organization", "org-Acme42")],
+        );
+        assert_eq!(
+            detect_tenant(&api),
+            Some(Tenant {
+                id: "org-Acme42".to_string(),
+                source: "openai-organization"
+            })
+        );
+
+        let web = request(
+            "/backend-api/conversation",
+            &[
+                ("Cookie", "oai-did=x; _account=8f2c7c1e-workspace"),
+                ("ChatGPT-Account-Id", ""),
+            ],
+        );
+        assert_eq!(detect_tenant(&web).unwrap().id, "8f2c7c1e-workspace");
+
+        let claude = request(
+            "/api/organizations/5b1d-4e2a/chat_conversations/c1/completion",
+            &[],
+        );
+        assert_eq!(
+            detect_tenant(&claude),
+            Some(Tenant {
+                id: "5b1d-4e2a".to_string(),
+                source: "path"
+            })
+        );
+
+        assert_eq!(
+            detect_tenant(&request(
+                "/backend-api/conversation",
+                &[("Cookie", "oai-did=x")]
+            )),
+            None
+        );
+        assert!(is_detected("chatgpt"));
+        assert!(!is_detected("gemini"));
+    }
+
+    #[test]
+    fn test_is_web_session() {
+        assert!(is_web_session(&request(
+            "/backend-api/conversation",
+            &[("Cookie", "oai-did=x")]
+        )));
+        assert!(is_web_session(&request(
+            "/backend-api/conversation",
+            &[("ChatGPT-Account-Id", "ws-1")]
+        )));
+        assert!(is_web_session(&request(
+            "/api/organizations/5b1d/chat_conversations/c1/completion",
+            &[]
+        )));
+        assert!(!is_web_session(&request(
+            "/v1/messages",
+            &[("x-api-key", "sk-ant-api03-x")]
+        )));
+        assert!(!is_web_session(&request(
+            "/v1/chat/completions",
+            &[("OpenAI-Organization", "org-Acme42")]
+        )));
+    }
+}
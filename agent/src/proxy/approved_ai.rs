@@ -0,0 +1,418 @@
+//! Redirect-to-approved-AI mode for blocked platforms: instead of the block
+//! page, browser navigations are redirected (302) to the company's approved
+//! assistant, and OpenAI-compatible API calls are rerouted to its internal
+//! endpoint (`approved_assistant_url` and `approved_api_*` settings).
+
+use std::sync::{Arc, RwLock};
+
+use reqwest::Url;
+
+use crate::config::AppConfig;
+use crate::proxy::request_parser::ParsedHttpRequest;
+use crate::proxy::{account, api_keys};
+
+/// Placeholder of the assistant URL replaced by the user's prompt
+const PROMPT_PLACEHOLDER: &str = "{{PROMPT}}";
+
+/// Query parameters AI sites prefill the prompt from (`chatgpt.com/?q=...`)
+const PROMPT_PARAMS: [&str; 2] = ["q", "prompt"];
+
+/// Routes of the OpenAI-compatible API that are rerouted (chat and legacy
+/// completions, embeddings)
+const OPENAI_ROUTES: [&str; 2] = ["/completions", "/embeddings"];
+
+/// Headers not copied to a rerouted request: connection-specific ones, and
+/// the ones the request is rebuilt with
+const REROUTE_SKIPPED_HEADERS: [&str; 6] = [
+    "host",
+    "content-length",
+    "transfer-encoding",
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+];
+
+/// Session and account headers of the platform, never sent to another host;
+/// API key headers (see [`api_keys::KEY_HEADERS`]) and organization headers
+/// (see [`account::TENANT_HEADERS`]) are not either
+const SESSION_HEADERS: [&str; 4] = [
+    "cookie",
+    "proxy-authorization",
+    "openai-project",
+    "anthropic-organization-id",
+];
+
+/// Approved AI that blocked platforms are redirected to. Blocked platforms
+/// get the block page when nothing is configured.
+#[derive(Debug, Clone, Default)]
+pub struct ApprovedAi {
+    /// Assistant browser navigations are redirected to; `{{PROMPT}}` is
+    /// replaced by the prompt of the navigation, if any
+    pub assistant_url: Option<String>,
+    /// Internal OpenAI-compatible endpoint API calls are rerouted to
+    pub endpoint: Option<ApiEndpoint>,
+}
+
+/// Internal OpenAI-compatible API endpoint
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiEndpoint {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    /// Prefix of the API routes (`/v1`)
+    pub base_path: String,
+    /// Key of the endpoint (`Authorization: Bearer`); the user's credentials
+    /// are never forwarded, requests are sent without authentication when
+    /// unset
+    pub api_key: Option<String>,
+    /// Model requested in place of the user's
+    pub model: Option<String>,
+}
+
+/// Current approved AI settings
+static APPROVED_AI: RwLock<Option<Arc<ApprovedAi>>> = RwLock::new(None);
+
+/// Set the approved AI blocked platforms are redirected to
+pub fn set_approved_ai(approved: ApprovedAi) {
+    if let Ok(mut current) = APPROVED_AI.write() {
+        *current = Some(Arc::new(approved));
+    }
+}
+
+/// Current approved AI settings (nothing configured by default)
+pub fn current() -> Arc<ApprovedAi> {
+    APPROVED_AI
+        .read()
+        .ok()
+        .and_then(|a| a.clone())
+        .unwrap_or_default()
+}
+
+impl ApprovedAi {
+    pub fn from_config(config: &AppConfig) -> anyhow::Result<Self> {
+        let endpoint = config
+            .approved_api_endpoint
+            .as_deref()
+            .filter(|url| !url.is_empty())
+            .map(|url| {
+                ApiEndpoint::parse(
+                    url,
+                    config.approved_api_key.clone().filter(|k| !k.is_empty()),
+                    config.approved_api_model.clone().filter(|m| !m.is_empty()),
+                )
+            })
+            .transpose()?;
+        let assistant_url = config
+            .approved_assistant_url
+            .clone()
+            .filter(|u| !u.is_empty());
+        if let Some(url) = &assistant_url {
+            Url::parse(&url.replace(PROMPT_PLACEHOLDER, ""))
+                .map_err(|e| anyhow::anyhow!("Invalid approved assistant URL {}: {}", url, e))?;
+        }
+        Ok(Self {
+            assistant_url,
+            endpoint,
+        })
+    }
+
+    /// Whether blocked platforms are redirected rather than blocked
+    pub fn is_enabled(&self) -> bool {
+        self.assistant_url.is_some() || self.endpoint.is_some()
+    }
+
+    /// Endpoint an API request is rerouted to: an OpenAI-compatible call,
+    /// when an endpoint is configured
+    pub fn reroute_target(&self, req: &ParsedHttpRequest) -> Option<&ApiEndpoint> {
+        let endpoint = self.endpoint.as_ref()?;
+        let route = req.path.split('?').next().unwrap_or_default();
+        (req.method == "POST" && OPENAI_ROUTES.iter().any(|r| route.ends_with(r)))
+            .then_some(endpoint)
+    }
+
+    /// Location a browser navigation is redirected to, carrying the prompt
+    /// of the navigated URL over when the assistant URL asks for it
+    pub fn redirect_location(&self, req: &ParsedHttpRequest) -> Option<String> {
+        let url = self.assistant_url.as_ref()?;
+        let prompt = navigation_prompt(req).unwrap_or_default();
+        Some(url.replace(PROMPT_PLACEHOLDER, &percent_encode(&prompt)))
+    }
+}
+
+impl ApiEndpoint {
+    /// Parse the endpoint URL (`https://ai.example.local/v1`)
+    pub fn parse(
+        url: &str,
+        api_key: Option<String>,
+        model: Option<String>,
+    ) -> anyhow::Result<Self> {
+        let parsed = Url::parse(url)
+            .map_err(|e| anyhow::anyhow!("Invalid approved API endpoint {}: {}", url, e))?;
+        let tls = match parsed.scheme() {
+            "https" => true,
+            "http" => false,
+            scheme => anyhow::bail!("Unsupported approved API endpoint scheme: {}", scheme),
+        };
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| anyhow::anyhow!("Approved API endpoint has no host: {}", url))?;
+        Ok(Self {
+            tls,
+            host: host
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+            port: parsed
+                .port_or_known_default()
+                .unwrap_or(if tls { 443 } else { 80 }),
+            base_path: parsed.path().trim_end_matches('/').to_string(),
+            api_key,
+            model,
+        })
+    }
+
+    /// `host:port` to connect to
+    pub fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    /// Rebuild `req` for the endpoint: route under the endpoint's base path,
+    /// Host of the endpoint, its API key and model when configured, and none
+    /// of the user's credentials for the blocked platform
+    pub fn rewrite(&self, req: &ParsedHttpRequest) -> Vec<u8> {
+        // `/v1/chat/completions` and `/chat/completions` are the same route
+        let route = req
+            .path
+            .strip_prefix("/v1")
+            .filter(|r| r.starts_with('/'))
+            .unwrap_or(&req.path);
+        let default_port = if self.tls { 443 } else { 80 };
+        let host = if self.port == default_port {
+            self.host.clone()
+        } else {
+            self.authority()
+        };
+
+        let mut body = req.body.clone();
+        if let Some(model) = &self.model {
+            if let Ok(serde_json::Value::Object(mut fields)) = serde_json::from_slice(&req.body) {
+                fields.insert("model".to_string(), serde_json::json!(model));
+                body = serde_json::Value::Object(fields).to_string().into_bytes();
+            }
+        }
+
+        let mut out = format!(
+            "{} {}{} HTTP/1.1\r\nHost: {}\r\n",
+            req.method, self.base_path, route, host
+        );
+        for (name, value) in &req.headers {
+            if REROUTE_SKIPPED_HEADERS
+                .iter()
+                .any(|h| name.eq_ignore_ascii_case(h))
+                || is_credential_header(name)
+            {
+                continue;
+            }
+            out.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if let Some(key) = &self.api_key {
+            out.push_str(&format!("Authorization: Bearer {}\r\n", key));
+        }
+        out.push_str(&format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        ));
+
+        let mut bytes = out.into_bytes();
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+}
+
+/// Header carrying credentials or the account of the user on the platform
+fn is_credential_header(name: &str) -> bool {
+    api_keys::KEY_HEADERS
+        .iter()
+        .chain(account::TENANT_HEADERS.iter())
+        .chain(SESSION_HEADERS.iter())
+        .any(|h| name.eq_ignore_ascii_case(h))
+}
+
+/// Build the redirection (302) of a navigation to the approved assistant
+pub fn build_redirect(location: &str) -> Vec<u8> {
+    format!(
+        "HTTP/1.1 302 Found\r\n\
+         Location: {}\r\n\
+         Content-Length: 0\r\n\
+         Cache-Control: no-store\r\n\
+         Connection: close\r\n\
+         X-Icon-Blocked: true\r\n\
+         \r\n",
+        location
+    )
+    .into_bytes()
+}
+
+/// Prompt prefilled by the navigated URL (`?q=...`)
+pub fn navigation_prompt(req: &ParsedHttpRequest) -> Option<String> {
+    let url = Url::parse(&format!("http://localhost{}", req.path)).ok()?;
+    url.query_pairs()
+        .find(|(name, value)| PROMPT_PARAMS.contains(&name.as_ref()) && !value.trim().is_empty())
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Percent-encode a URL component (RFC 3986 unreserved characters are kept)
+fn percent_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &str,
+    ) -> ParsedHttpRequest {
+        ParsedHttpRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            host: "api.openai.com".to_string(),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body: body.as_bytes().to_vec(),
+            content_type: Some("application/json".to_string()),
+        }
+    }
+
+    fn approved() -> ApprovedAi {
+        ApprovedAi {
+            assistant_url: Some("https://marcelia.gs2e.ci/chat?q={{PROMPT}}".to_string()),
+            endpoint: Some(
+                ApiEndpoint::parse(
+                    "https://ai.gs2e.local:8443/openai/v1/",
+                    Some("internal-key".to_string()),
+                    Some("llama3".to_string()),
+                )
+                .unwrap(),
+            ),
+        }
+    }
+
+    #[test]
+    fn test_redirect_carries_prompt() {
+        let approved = approved();
+        let nav = request("GET", "/?q=R%C3%A9sume+ce+contrat&hints=search", &[], "");
+        assert_eq!(
+            approved.redirect_location(&nav).as_deref(),
+            Some("https://marcelia.gs2e.ci/chat?q=R%C3%A9sume%20ce%20contrat")
+        );
+        let nav = request("GET", "/c/abc", &[], "");
+        assert_eq!(
+            approved.redirect_location(&nav).as_deref(),
+            Some("https://marcelia.gs2e.ci/chat?q=")
+        );
+
+        let redirect = String::from_utf8(build_redirect("https://marcelia.gs2e.ci/")).unwrap();
+        assert!(
+            redirect.starts_with("HTTP/1.1 302 Found\r\nLocation: https://marcelia.gs2e.ci/\r\n")
+        );
+        assert!(!ApprovedAi::default().is_enabled());
+    }
+
+    #[test]
+    fn test_reroute_target() {
+        let approved = approved();
+        assert!(approved
+            .reroute_target(&request("POST", "/v1/chat/completions", &[], "{}"))
+            .is_some());
+        assert!(approved
+            .reroute_target(&request("POST", "/chat/completions?x=1", &[], "{}"))
+            .is_some());
+        assert!(approved
+            .reroute_target(&request("POST", "/v1/embeddings", &[], "{}"))
+            .is_some());
+        assert!(approved
+            .reroute_target(&request("POST", "/v1/messages", &[], "{}"))
+            .is_none());
+        assert!(approved
+            .reroute_target(&request("GET", "/v1/chat/completions", &[], ""))
+            .is_none());
+        let assistant_only = ApprovedAi {
+            endpoint: None,
+            ..approved
+        };
+        assert!(assistant_only
+            .reroute_target(&request("POST", "/v1/chat/completions", &[], "{}"))
+            .is_none());
+    }
+
+    #[test]
+    fn test_rewrite_request() {
+        let endpoint = approved().endpoint.unwrap();
+        assert_eq!(endpoint.authority(), "ai.gs2e.local:8443");
+        assert!(endpoint.tls);
+
+        let req = request(
+            "POST",
+            "/v1/chat/completions",
+            &[
+                ("Host", "api.openai.com"),
+                ("Authorization", "Bearer sk-user"),
+                ("OpenAI-Organization", "org-user"),
+                ("Cookie", "__Secure-next-auth.session-token=user-session"),
+                ("Accept", "text/event-stream"),
+                ("Content-Length", "53"),
+            ],
+            r#"{"model":"gpt-4o","messages":[],"stream":true}"#,
+        );
+        let rewritten = String::from_utf8(endpoint.rewrite(&req)).unwrap();
+        let (head, body) = rewritten.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with(
+            "POST /openai/v1/chat/completions HTTP/1.1\r\nHost: ai.gs2e.local:8443\r\n"
+        ));
+        assert!(head.contains("Accept: text/event-stream\r\n"));
+        assert!(head.contains("Authorization: Bearer internal-key\r\n"));
+        assert!(!head.contains("sk-user"));
+        assert!(!head.contains("org-user"));
+        assert!(!head.contains("user-session"));
+        assert!(!head.contains("api.openai.com"));
+        assert!(head.contains(&format!("Content-Length: {}\r\n", body.len())));
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["model"], "llama3");
+        assert_eq!(body["stream"], true);
+
+        // Without a key of its own, the endpoint gets no credentials at all
+        let plain = ApiEndpoint::parse("http://10.0.0.5/v1", None, None).unwrap();
+        let headers = [
+            ("Authorization", "Bearer sk-user"),
+            ("x-goog-api-key", "sk-user"),
+            ("Content-Type", "application/json"),
+        ];
+        let rewritten =
+            String::from_utf8(plain.rewrite(&request("POST", "/chat/completions", &headers, "{}")))
+                .unwrap();
+        assert!(rewritten.starts_with("POST /v1/chat/completions HTTP/1.1\r\nHost: 10.0.0.5\r\nContent-Type: application/json\r\n"));
+        assert!(!rewritten.contains("sk-user"));
+        assert!(!rewritten.contains("Authorization"));
+
+        assert!(ApiEndpoint::parse("ftp://ai.local", None, None).is_err());
+    }
+}
@@ -0,0 +1,790 @@
+//! Responses served in place of a blocked request or answer. Browser
+//! navigations get an HTML page; API and in-app requests get the error (JSON)
+//! or streamed answer (SSE) of the platform's own API, so that the message
+//! shows up inside the chat UI. Texts come from templates synced from the
+//! server, chosen per rule and per locale, with built-in fallbacks.
+
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::proxy::platforms;
+use crate::proxy::request_parser::ParsedHttpRequest;
+
+/// Config key of the block templates cached from the last sync
+pub const TEMPLATES_CONFIG_KEY: &str = "block_templates";
+
+/// Template used for rules that do not name one
+const DEFAULT_TEMPLATE: &str = "default";
+
+/// Locale used when none of the client's languages has a template
+const DEFAULT_LOCALE: &str = "fr";
+
+/// Block template of one locale. Variables: `{{MESSAGE}}`, `{{RULE_NAME}}`,
+/// `{{INCIDENT_ID}}`, `{{SUPPORT_CONTACT}}` and `{{PLATFORM}}`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BlockTemplate {
+    /// Name rules refer to; `default` replaces the built-in template
+    pub name: String,
+    /// Language tag (`fr`, `en-US`...)
+    pub locale: String,
+    /// HTML page shown to browser navigations
+    pub html: String,
+    /// Message shown inside the chat (JSON error, streamed answer); the
+    /// built-in text of the locale when absent
+    #[serde(default)]
+    pub text: Option<String>,
+}
+
+/// Block templates synced from the server
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct BlockTemplateSet {
+    #[serde(default)]
+    pub templates: Vec<BlockTemplate>,
+    /// Who to contact about a block (`{{SUPPORT_CONTACT}}`)
+    #[serde(default)]
+    pub support_contact: Option<String>,
+}
+
+/// Current templates
+static TEMPLATES: RwLock<BlockTemplateSet> = RwLock::new(BlockTemplateSet {
+    templates: Vec::new(),
+    support_contact: None,
+});
+
+/// Replace the block templates
+pub fn set_templates(set: BlockTemplateSet) {
+    if let Ok(mut current) = TEMPLATES.write() {
+        *current = set;
+    }
+}
+
+/// A block decided by a rule, identified by an incident ID shown to the user
+/// and recorded with the event
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockNotice {
+    pub message: String,
+    pub rule_name: String,
+    /// Template named by the rule
+    pub template: Option<String>,
+    pub incident_id: String,
+}
+
+impl BlockNotice {
+    pub fn new(message: &str, rule_name: &str, template: Option<&str>) -> Self {
+        Self {
+            message: message.to_string(),
+            rule_name: rule_name.to_string(),
+            template: template.map(|t| t.to_string()),
+            incident_id: Uuid::new_v4().to_string(),
+        }
+    }
+}
+
+/// What the blocked client expects, to answer it in a form it displays
+#[derive(Debug, Clone)]
+pub struct ClientContext {
+    pub platform: &'static str,
+    pub path: String,
+    /// Browser navigation (`Accept: text/html`)
+    pub html: bool,
+    /// Streamed answer expected (SSE)
+    pub stream: bool,
+    /// Accepted languages, most preferred first
+    pub locales: Vec<String>,
+}
+
+impl ClientContext {
+    pub fn from_request(platform: &'static str, req: &ParsedHttpRequest) -> Self {
+        let header = |name: &str| {
+            req.headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        };
+        let accept = header("accept").unwrap_or_default().to_ascii_lowercase();
+        let stream = accept.contains("text/event-stream")
+            || req.path.contains(":streamGenerateContent")
+            || req.path.contains("alt=sse")
+            || serde_json::from_slice::<serde_json::Value>(&req.body)
+                .is_ok_and(|body| body.get("stream") == Some(&json!(true)));
+
+        Self {
+            platform,
+            path: req.path.clone(),
+            html: accept.contains("text/html"),
+            stream,
+            locales: header("accept-language")
+                .map(parse_accept_language)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Browser opening a blocked site, before any request is read
+    pub fn browser(platform: &'static str) -> Self {
+        Self {
+            platform,
+            path: "/".to_string(),
+            html: true,
+            stream: false,
+            locales: Vec::new(),
+        }
+    }
+
+    /// Client of a relayed answer whose request is unknown: a stream or an
+    /// API error is the only form it can display
+    pub fn api(platform: &'static str, stream: bool) -> Self {
+        Self {
+            platform,
+            path: "/".to_string(),
+            html: false,
+            stream,
+            locales: Vec::new(),
+        }
+    }
+}
+
+/// Rendered block response
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockBody {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+/// Render the response replacing a blocked request or answer
+pub fn render(notice: &BlockNotice, client: &ClientContext) -> BlockBody {
+    if client.html {
+        return BlockBody {
+            status: 403,
+            content_type: "text/html; charset=utf-8",
+            body: render_html(notice, client),
+        };
+    }
+
+    let text = render_text(notice, client);
+    let dialect = Dialect::of(client);
+    if client.stream {
+        // 200 so that the client reads the stream and shows the answer
+        return BlockBody {
+            status: 200,
+            content_type: "text/event-stream; charset=utf-8",
+            body: dialect.stream_answer(&text, &notice.incident_id),
+        };
+    }
+    BlockBody {
+        status: 403,
+        content_type: "application/json",
+        body: dialect.error(&text).to_string(),
+    }
+}
+
+/// Build the HTTP/1.1 response replacing a blocked request or answer
+pub fn build_block_response(notice: &BlockNotice, client: &ClientContext) -> Vec<u8> {
+    let rendered = render(notice, client);
+    let reason = if rendered.status == 200 {
+        "OK"
+    } else {
+        "Forbidden"
+    };
+
+    let mut out = format!(
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Cache-Control: no-store\r\n\
+         Connection: close\r\n\
+         X-Icon-Blocked: true\r\n\
+         \r\n",
+        rendered.status,
+        reason,
+        rendered.content_type,
+        rendered.body.len()
+    );
+    out.push_str(&rendered.body);
+    out.into_bytes()
+}
+
+/// Build the SSE events injected when a streamed answer is cut by a Block
+/// rule: a last piece of answer carrying the block message, then the end of
+/// the stream, in the format of the platform
+pub fn build_stream_notice(notice: &BlockNotice, client: &ClientContext) -> Vec<u8> {
+    let text = format!("\n\n{}", render_text(notice, client));
+    Dialect::of(client).stream_cut(&text).into_bytes()
+}
+
+/// Message shown inside the chat
+pub fn render_text(notice: &BlockNotice, client: &ClientContext) -> String {
+    let set = TEMPLATES.read().map(|s| s.clone()).unwrap_or_default();
+    let text = match select(&set.templates, notice.template.as_deref(), &client.locales) {
+        Some(template) => match &template.text {
+            Some(text) => text.clone(),
+            None => builtin(std::slice::from_ref(&template.locale))
+                .text
+                .to_string(),
+        },
+        None => builtin(&client.locales).text.to_string(),
+    };
+    fill(&text, notice, client, set.support_contact.as_deref(), false)
+}
+
+/// Block page shown to browser navigations
+pub fn render_html(notice: &BlockNotice, client: &ClientContext) -> String {
+    let set = TEMPLATES.read().map(|s| s.clone()).unwrap_or_default();
+    let html = match select(&set.templates, notice.template.as_deref(), &client.locales) {
+        Some(template) => template.html.clone(),
+        None => builtin(&client.locales).page(),
+    };
+    fill(&html, notice, client, set.support_contact.as_deref(), true)
+}
+
+/// Template of the rule (or the default one) in the preferred locale, or
+/// else in any locale
+fn select<'a>(
+    templates: &'a [BlockTemplate],
+    name: Option<&str>,
+    locales: &[String],
+) -> Option<&'a BlockTemplate> {
+    for name in name.into_iter().chain([DEFAULT_TEMPLATE]) {
+        let named: Vec<&BlockTemplate> = templates.iter().filter(|t| t.name == name).collect();
+        let preferred = locales
+            .iter()
+            .map(|l| l.as_str())
+            .chain([DEFAULT_LOCALE])
+            .find_map(|locale| named.iter().find(|t| locale_matches(&t.locale, locale)));
+        if let Some(template) = preferred.or(named.first()) {
+            return Some(template);
+        }
+    }
+    None
+}
+
+/// `fr-CI` is served by a `fr` template and the other way round
+fn locale_matches(template: &str, locale: &str) -> bool {
+    template.eq_ignore_ascii_case(locale)
+        || language(template).eq_ignore_ascii_case(language(locale))
+}
+
+fn language(locale: &str) -> &str {
+    locale.split(['-', '_']).next().unwrap_or(locale)
+}
+
+/// Languages of an `Accept-Language` header by decreasing quality
+fn parse_accept_language(header: &str) -> Vec<String> {
+    let mut locales: Vec<(f32, String)> = header
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && tag != "*" && quality > 0.0).then(|| (quality, tag.to_string()))
+        })
+        .collect();
+    // Stable sort: equal qualities keep the header order
+    locales.sort_by(|a, b| b.0.total_cmp(&a.0));
+    locales.into_iter().map(|(_, tag)| tag).collect()
+}
+
+/// Substitute the template variables, HTML-escaped for pages
+fn fill(
+    template: &str,
+    notice: &BlockNotice,
+    client: &ClientContext,
+    support: Option<&str>,
+    html: bool,
+) -> String {
+    let value = |v: &str| if html { escape_html(v) } else { v.to_string() };
+    template
+        .replace("{{MESSAGE}}", &value(&notice.message))
+        .replace("{{RULE_NAME}}", &value(&notice.rule_name))
+        .replace("{{INCIDENT_ID}}", &value(&notice.incident_id))
+        .replace("{{SUPPORT_CONTACT}}", &value(support.unwrap_or_default()))
+        .replace("{{PLATFORM}}", &value(client.platform))
+}
+
+fn escape_html(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// API whose error and stream formats the client understands
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dialect {
+    /// OpenAI chat completions, also spoken by Mistral, Perplexity, Copilot
+    OpenAi,
+    Anthropic,
+    Gemini,
+    /// ChatGPT web app (`/backend-api/conversation`)
+    ChatGptWeb,
+}
+
+impl Dialect {
+    fn of(client: &ClientContext) -> Self {
+        match platforms::catalog().format(client.platform) {
+            Some("anthropic") => Dialect::Anthropic,
+            Some("gemini") => Dialect::Gemini,
+            Some("chatgpt") if client.path.starts_with("/backend-") => Dialect::ChatGptWeb,
+            _ => Dialect::OpenAi,
+        }
+    }
+
+    /// Error body of a refused request
+    fn error(self, text: &str) -> serde_json::Value {
+        match self {
+            Dialect::Anthropic => json!({
+                "type": "error",
+                "error": { "type": "permission_error", "message": text },
+            }),
+            Dialect::Gemini => json!({
+                "error": { "code": 403, "message": text, "status": "PERMISSION_DENIED" },
+            }),
+            Dialect::ChatGptWeb => json!({ "detail": text }),
+            Dialect::OpenAi => json!({
+                "error": {
+                    "message": text,
+                    "type": "icon_blocked",
+                    "param": null,
+                    "code": "blocked_by_policy",
+                },
+            }),
+        }
+    }
+
+    /// Complete streamed answer made of the block message
+    fn stream_answer(self, text: &str, incident_id: &str) -> String {
+        let id = format!("icon-{}", incident_id);
+        match self {
+            Dialect::OpenAi => sse_data(&[json!({
+                "id": id,
+                "object": "chat.completion.chunk",
+                "choices": [{
+                    "index": 0,
+                    "delta": { "role": "assistant", "content": text },
+                    "finish_reason": "content_filter",
+                }],
+            })]),
+            Dialect::Anthropic => anthropic_events(&[
+                (
+                    "message_start",
+                    json!({
+                        "type": "message_start",
+                        "message": {
+                            "id": id,
+                            "type": "message",
+                            "role": "assistant",
+                            "content": [],
+                            "model": "icon",
+                            "stop_reason": null,
+                            "usage": { "input_tokens": 0, "output_tokens": 0 },
+                        },
+                    }),
+                ),
+                (
+                    "content_block_start",
+                    json!({
+                        "type": "content_block_start",
+                        "index": 0,
+                        "content_block": { "type": "text", "text": "" },
+                    }),
+                ),
+                ("content_block_delta", anthropic_delta(text)),
+                (
+                    "content_block_stop",
+                    json!({ "type": "content_block_stop", "index": 0 }),
+                ),
+                ("message_delta", anthropic_stop()),
+                ("message_stop", json!({ "type": "message_stop" })),
+            ]),
+            Dialect::Gemini | Dialect::ChatGptWeb => self.stream_message(text, &id),
+        }
+    }
+
+    /// End of a cut stream: the block message appended to the answer
+    fn stream_cut(self, text: &str) -> String {
+        match self {
+            Dialect::OpenAi => sse_data(&[json!({
+                "object": "chat.completion.chunk",
+                "choices": [{
+                    "index": 0,
+                    "delta": { "content": text },
+                    "finish_reason": "content_filter",
+                }],
+            })]),
+            Dialect::Anthropic => anthropic_events(&[
+                ("content_block_delta", anthropic_delta(text)),
+                (
+                    "content_block_stop",
+                    json!({ "type": "content_block_stop", "index": 0 }),
+                ),
+                ("message_delta", anthropic_stop()),
+                ("message_stop", json!({ "type": "message_stop" })),
+            ]),
+            Dialect::Gemini => self.stream_message(text, ""),
+            // Delta encoding: append to the part being streamed
+            Dialect::ChatGptWeb => sse_data(&[json!({
+                "p": "/message/content/parts/0",
+                "o": "append",
+                "v": text,
+            })]),
+        }
+    }
+
+    /// Single-event answer of the Gemini and ChatGPT web streams
+    fn stream_message(self, text: &str, id: &str) -> String {
+        match self {
+            Dialect::Gemini => format!(
+                "data: {}\n\n",
+                json!({
+                    "candidates": [{
+                        "content": { "role": "model", "parts": [{ "text": text }] },
+                        "finishReason": "STOP",
+                        "index": 0,
+                    }],
+                })
+            ),
+            _ => sse_data(&[json!({
+                "message": {
+                    "id": id,
+                    "author": { "role": "assistant" },
+                    "content": { "content_type": "text", "parts": [text] },
+                    "status": "finished_successfully",
+                    "end_turn": true,
+                },
+                "error": null,
+            })]),
+        }
+    }
+}
+
+/// `data:` events followed by the `[DONE]` terminator
+fn sse_data(events: &[serde_json::Value]) -> String {
+    let mut out: String = events.iter().map(|e| format!("data: {}\n\n", e)).collect();
+    out.push_str("data: [DONE]\n\n");
+    out
+}
+
+fn anthropic_events(events: &[(&str, serde_json::Value)]) -> String {
+    events
+        .iter()
+        .map(|(name, data)| format!("event: {}\ndata: {}\n\n", name, data))
+        .collect()
+}
+
+fn anthropic_delta(text: &str) -> serde_json::Value {
+    json!({
+        "type": "content_block_delta",
+        "index": 0,
+        "delta": { "type": "text_delta", "text": text },
+    })
+}
+
+fn anthropic_stop() -> serde_json::Value {
+    json!({
+        "type": "message_delta",
+        "delta": { "stop_reason": "end_turn", "stop_sequence": null },
+        "usage": { "output_tokens": 0 },
+    })
+}
+
+/// Built-in texts of a language
+struct Builtin {
+    lang: &'static str,
+    title: &'static str,
+    heading: &'static str,
+    rule: &'static str,
+    incident: &'static str,
+    text: &'static str,
+}
+
+const BUILTINS: [Builtin; 2] = [
+    Builtin {
+        lang: "fr",
+        title: "Icon - Requête bloquée",
+        heading: "Requête bloquée par Icon",
+        rule: "Règle",
+        incident: "Incident",
+        text:
+            "🛡️ Bloqué par Icon : {{MESSAGE}} (règle : {{RULE_NAME}}, incident : {{INCIDENT_ID}})",
+    },
+    Builtin {
+        lang: "en",
+        title: "Icon - Request blocked",
+        heading: "Request blocked by Icon",
+        rule: "Rule",
+        incident: "Incident",
+        text: "🛡️ Blocked by Icon: {{MESSAGE}} (rule: {{RULE_NAME}}, incident: {{INCIDENT_ID}})",
+    },
+];
+
+/// Built-in texts of the first locale available, French otherwise
+fn builtin(locales: &[String]) -> &'static Builtin {
+    locales
+        .iter()
+        .find_map(|l| {
+            BUILTINS
+                .iter()
+                .find(|b| language(l).eq_ignore_ascii_case(b.lang))
+        })
+        .unwrap_or(&BUILTINS[0])
+}
+
+impl Builtin {
+    fn page(&self) -> String {
+        BUILTIN_PAGE
+            .replace("{{LANG}}", self.lang)
+            .replace("{{TITLE}}", self.title)
+            .replace("{{HEADING}}", self.heading)
+            .replace("{{RULE_LABEL}}", self.rule)
+            .replace("{{INCIDENT_LABEL}}", self.incident)
+    }
+}
+
+/// Built-in HTML page, localized by [`Builtin::page`]
+const BUILTIN_PAGE: &str = r#"<!DOCTYPE html>
+<html lang="{{LANG}}">
+<head>
+    <meta charset="utf-8">
+    <title>{{TITLE}}</title>
+    <style>
+        body {
+            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', sans-serif;
+            display: flex; justify-content: center; align-items: center;
+            min-height: 100vh; margin: 0;
+            background: linear-gradient(135deg, #1a1a2e 0%, #16213e 100%);
+            color: #e0e0e0;
+        }
+        .container {
+            text-align: center; padding: 3rem;
+            background: rgba(255,255,255,0.05);
+            border-radius: 16px; border: 1px solid rgba(255,255,255,0.1);
+            max-width: 500px;
+        }
+        .icon { font-size: 4rem; margin-bottom: 1rem; }
+        h1 { color: #e74c3c; font-size: 1.5rem; margin-bottom: 0.5rem; }
+        p { color: #bbb; line-height: 1.6; }
+        .rule { color: #f39c12; font-weight: 600; margin-top: 1rem; }
+        .incident { font-size: 0.85rem; color: #888; }
+        .support { margin-top: 1.5rem; }
+    </style>
+</head>
+<body>
+    <div class="container">
+        <div class="icon">🛡️</div>
+        <h1>{{HEADING}}</h1>
+        <p>{{MESSAGE}}</p>
+        <p class="rule">{{RULE_LABEL}} : {{RULE_NAME}}</p>
+        <p class="incident">{{INCIDENT_LABEL}} {{INCIDENT_ID}}</p>
+        <p class="support">{{SUPPORT_CONTACT}}</p>
+    </div>
+</body>
+</html>"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notice() -> BlockNotice {
+        BlockNotice::new("Interdit <b>", "Rule-1", None)
+    }
+
+    fn request(path: &str, headers: &[(&str, &str)], body: &str) -> ParsedHttpRequest {
+        ParsedHttpRequest {
+            method: "POST".to_string(),
+            path: path.to_string(),
+            host: "api.openai.com".to_string(),
+            headers: headers
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            body: body.as_bytes().to_vec(),
+            content_type: Some("application/json".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_block_response() {
+        let notice = notice();
+        let resp = build_block_response(&notice, &ClientContext::browser("chatgpt"));
+        let text = String::from_utf8(resp).unwrap();
+        assert!(text.starts_with("HTTP/1.1 403 Forbidden"));
+        assert!(text.contains("Content-Type: text/html"));
+        assert!(text.contains("Interdit &lt;b&gt;"));
+        assert!(text.contains("Rule-1"));
+        assert!(text.contains(&notice.incident_id));
+        assert!(!text.contains("{{"));
+    }
+
+    #[test]
+    fn test_api_errors_per_platform() {
+        let notice = notice();
+        let error = |platform, path| {
+            let client = ClientContext::from_request(platform, &request(path, &[], "{}"));
+            let rendered = render(&notice, &client);
+            assert_eq!(rendered.status, 403);
+            assert_eq!(rendered.content_type, "application/json");
+            serde_json::from_str::<serde_json::Value>(&rendered.body).unwrap()
+        };
+
+        let openai = error("chatgpt", "/v1/chat/completions");
+        assert_eq!(openai["error"]["code"], "blocked_by_policy");
+        let message = openai["error"]["message"].as_str().unwrap();
+        assert!(message.contains("Interdit <b>") && message.contains(&notice.incident_id));
+        assert_eq!(
+            error("claude", "/v1/messages")["error"]["type"],
+            "permission_error"
+        );
+        assert_eq!(
+            error("gemini", "/v1beta/models/gemini-pro:generateContent")["error"]["status"],
+            "PERMISSION_DENIED"
+        );
+        assert!(error("mistral", "/v1/chat/completions")["error"]["message"].is_string());
+    }
+
+    #[test]
+    fn test_stream_answers_per_platform() {
+        let notice = notice();
+        let streamed = |platform, req: ParsedHttpRequest| {
+            let rendered = render(&notice, &ClientContext::from_request(platform, &req));
+            assert_eq!(rendered.status, 200);
+            assert!(rendered.content_type.starts_with("text/event-stream"));
+            rendered.body
+        };
+
+        let body = streamed(
+            "chatgpt",
+            request("/v1/chat/completions", &[], r#"{"stream":true}"#),
+        );
+        let first: serde_json::Value =
+            serde_json::from_str(body.lines().next().unwrap().strip_prefix("data: ").unwrap())
+                .unwrap();
+        assert!(first["choices"][0]["delta"]["content"]
+            .as_str()
+            .unwrap()
+            .contains("Interdit"));
+        assert!(body.ends_with("data: [DONE]\n\n"));
+
+        let body = streamed(
+            "claude",
+            request("/v1/messages", &[("Accept", "text/event-stream")], "{}"),
+        );
+        assert!(body.starts_with("event: message_start\n"));
+        assert!(body.contains("\"text_delta\""));
+        assert!(body.ends_with("event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n"));
+
+        let body = streamed(
+            "gemini",
+            request(
+                "/v1beta/models/gemini-pro:streamGenerateContent?alt=sse",
+                &[],
+                "{}",
+            ),
+        );
+        assert!(body.contains("\"finishReason\":\"STOP\""));
+
+        let body = streamed(
+            "chatgpt",
+            request(
+                "/backend-api/conversation",
+                &[("accept", "text/event-stream")],
+                "{}",
+            ),
+        );
+        assert!(body.contains("\"parts\":[\"🛡️"));
+    }
+
+    #[test]
+    fn test_stream_notice() {
+        let notice = notice();
+        let text = String::from_utf8(build_stream_notice(
+            &notice,
+            &ClientContext::api("chatgpt", true),
+        ))
+        .unwrap();
+        let first: serde_json::Value =
+            serde_json::from_str(text.lines().next().unwrap().strip_prefix("data: ").unwrap())
+                .unwrap();
+        let delta = first["choices"][0]["delta"]["content"].as_str().unwrap();
+        assert!(delta.contains("Interdit"));
+        assert!(delta.contains("Rule-1"));
+        assert!(text.ends_with("data: [DONE]\n\n"));
+
+        let web = ClientContext {
+            path: "/backend-api/conversation".to_string(),
+            ..ClientContext::api("chatgpt", true)
+        };
+        let text = String::from_utf8(build_stream_notice(&notice, &web)).unwrap();
+        assert!(text.starts_with("data: {\"o\":\"append\",\"p\":\"/message/content/parts/0\""));
+    }
+
+    #[test]
+    fn test_parse_accept_language() {
+        assert_eq!(
+            parse_accept_language("en;q=0.5, fr-CI, de;q=0.8, *;q=0.1"),
+            vec!["fr-CI", "de", "en"]
+        );
+        assert!(parse_accept_language("").is_empty());
+    }
+
+    #[test]
+    fn test_template_selection() {
+        let template = |name: &str, locale: &str, text: Option<&str>| BlockTemplate {
+            name: name.to_string(),
+            locale: locale.to_string(),
+            html: format!("<p>{} {}</p>", name, locale),
+            text: text.map(|t| t.to_string()),
+        };
+        let templates = vec![
+            template("default", "fr", None),
+            template("default", "en", None),
+            template(
+                "secrets",
+                "en-US",
+                Some("{{MESSAGE}} - {{SUPPORT_CONTACT}}"),
+            ),
+        ];
+        let locales = |l: &[&str]| l.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+
+        let select_name =
+            |name, l: &[&str]| select(&templates, name, &locales(l)).map(|t| t.html.clone());
+        assert_eq!(
+            select_name(Some("secrets"), &["fr"]).as_deref(),
+            Some("<p>secrets en-US</p>")
+        );
+        assert_eq!(
+            select_name(None, &["en-GB", "fr"]).as_deref(),
+            Some("<p>default en</p>")
+        );
+        assert_eq!(
+            select_name(Some("missing"), &["de"]).as_deref(),
+            Some("<p>default fr</p>")
+        );
+        assert_eq!(select(&[], None, &locales(&["en"])), None);
+
+        // Built-in texts follow the client's language
+        let english = ClientContext {
+            locales: locales(&["en-US"]),
+            ..ClientContext::api("chatgpt", false)
+        };
+        assert!(render_text(&notice(), &english).starts_with("🛡️ Blocked by Icon: Interdit <b>"));
+        assert!(render_html(&notice(), &english).contains("<html lang=\"en\">"));
+        assert!(
+            render_text(&notice(), &ClientContext::api("chatgpt", false))
+                .starts_with("🛡️ Bloqué par Icon")
+        );
+    }
+}
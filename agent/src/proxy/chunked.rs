@@ -0,0 +1,245 @@
+/// Maximum length of a chunk-size or trailer line
+const MAX_LINE_LEN: usize = 8 * 1024;
+
+/// Header fields (name, value), as used for trailers
+pub type Fields = Vec<(String, String)>;
+
+/// Decoder state
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    /// Reading a chunk-size line (hex size + optional extensions)
+    Size,
+    /// Reading chunk data, with the number of bytes left in the chunk
+    Data(usize),
+    /// Expecting the CRLF that terminates chunk data
+    DataEnd,
+    /// Reading trailer lines after the last chunk
+    Trailer,
+    /// Final CRLF received: the body is complete
+    Done,
+}
+
+/// Incremental decoder for `Transfer-Encoding: chunked` bodies.
+///
+/// Bytes can be fed as they arrive from the network, split at any position
+/// (inside a size line, inside chunk data or inside a CRLF).
+pub struct ChunkedDecoder {
+    state: State,
+    line: Vec<u8>,
+    trailers: Fields,
+}
+
+impl ChunkedDecoder {
+    pub fn new() -> Self {
+        Self {
+            state: State::Size,
+            line: Vec::new(),
+            trailers: Vec::new(),
+        }
+    }
+
+    /// Whether the terminating chunk and trailers have been received
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    /// Consume the decoder, returning the trailer fields received after the
+    /// last chunk
+    pub fn into_trailers(self) -> Fields {
+        self.trailers
+    }
+
+    /// Decode `input`, appending chunk payloads to `out`.
+    /// Returns the number of input bytes consumed: less than `input.len()`
+    /// only when the body ends before the end of `input`.
+    pub fn feed(&mut self, input: &[u8], out: &mut Vec<u8>) -> anyhow::Result<usize> {
+        let mut pos = 0;
+
+        while pos < input.len() {
+            match self.state {
+                State::Size | State::Trailer => {
+                    let Some(line) = self.take_line(input, &mut pos)? else {
+                        break;
+                    };
+                    if self.state == State::Size {
+                        let size = parse_chunk_size(&line)?;
+                        self.state = if size == 0 {
+                            State::Trailer
+                        } else {
+                            State::Data(size)
+                        };
+                    } else if line.is_empty() {
+                        self.state = State::Done;
+                    } else if let Some((name, value)) =
+                        String::from_utf8_lossy(&line).split_once(':')
+                    {
+                        self.trailers
+                            .push((name.trim().to_string(), value.trim().to_string()));
+                    }
+                }
+                State::Data(remaining) => {
+                    let n = remaining.min(input.len() - pos);
+                    out.extend_from_slice(&input[pos..pos + n]);
+                    pos += n;
+                    self.state = if n == remaining {
+                        State::DataEnd
+                    } else {
+                        State::Data(remaining - n)
+                    };
+                }
+                State::DataEnd => {
+                    let Some(line) = self.take_line(input, &mut pos)? else {
+                        break;
+                    };
+                    if !line.is_empty() {
+                        anyhow::bail!("Missing CRLF after chunk data");
+                    }
+                    self.state = State::Size;
+                }
+                State::Done => break,
+            }
+        }
+
+        Ok(pos)
+    }
+
+    /// Accumulate bytes up to the next LF; returns the line without CRLF
+    /// once complete
+    fn take_line(&mut self, input: &[u8], pos: &mut usize) -> anyhow::Result<Option<Vec<u8>>> {
+        let rest = &input[*pos..];
+        match rest.iter().position(|&b| b == b'\n') {
+            Some(i) => {
+                self.line.extend_from_slice(&rest[..i]);
+                *pos += i + 1;
+                let mut line = std::mem::take(&mut self.line);
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                Ok(Some(line))
+            }
+            None => {
+                self.line.extend_from_slice(rest);
+                *pos = input.len();
+                if self.line.len() > MAX_LINE_LEN {
+                    anyhow::bail!("Chunk line too long");
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Default for ChunkedDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parse a chunk-size line, ignoring chunk extensions (`;name=value`)
+fn parse_chunk_size(line: &[u8]) -> anyhow::Result<usize> {
+    let text = std::str::from_utf8(line)?;
+    let hex = text.split(';').next().unwrap_or("").trim();
+    usize::from_str_radix(hex, 16).map_err(|_| anyhow::anyhow!("Invalid chunk size: {:?}", hex))
+}
+
+/// Decode a complete chunked body held in memory.
+/// Returns the payload and the trailer fields; a body truncated before the
+/// last chunk yields the payload received so far.
+pub fn decode_body(body: &[u8]) -> anyhow::Result<(Vec<u8>, Fields)> {
+    let mut decoder = ChunkedDecoder::new();
+    let mut out = Vec::new();
+    decoder.feed(body, &mut out)?;
+    Ok((out, decoder.into_trailers()))
+}
+
+/// Frame `data` as a single chunk
+pub fn encode_chunk(data: &[u8]) -> Vec<u8> {
+    let mut out = format!("{:x}\r\n", data.len()).into_bytes();
+    out.extend_from_slice(data);
+    out.extend_from_slice(b"\r\n");
+    out
+}
+
+/// Terminating zero-length chunk (no trailers)
+pub const LAST_CHUNK: &[u8] = b"0\r\n\r\n";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &[u8] = b"5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\n\r\n";
+
+    #[test]
+    fn test_decode_whole_body() {
+        let mut decoder = ChunkedDecoder::new();
+        let mut out = Vec::new();
+        assert_eq!(decoder.feed(BODY, &mut out).unwrap(), BODY.len());
+        assert!(decoder.is_done());
+        assert_eq!(out, b"hello, world");
+    }
+
+    #[test]
+    fn test_decode_byte_by_byte() {
+        let mut decoder = ChunkedDecoder::new();
+        let mut out = Vec::new();
+        for byte in BODY {
+            assert!(!decoder.is_done());
+            decoder.feed(std::slice::from_ref(byte), &mut out).unwrap();
+        }
+        assert!(decoder.is_done());
+        assert_eq!(out, b"hello, world");
+    }
+
+    #[test]
+    fn test_stops_at_end_of_body() {
+        let mut input = BODY.to_vec();
+        input.extend_from_slice(b"HTTP/1.1 200 OK\r\n");
+        let mut decoder = ChunkedDecoder::new();
+        let mut out = Vec::new();
+        assert_eq!(decoder.feed(&input, &mut out).unwrap(), BODY.len());
+    }
+
+    #[test]
+    fn test_trailers_split_across_feeds() {
+        let input = b"4\r\nWiki\r\n0\r\nX-Checksum: abc\r\nX-Other:  1 \r\n\r\n";
+        for split in 1..input.len() {
+            let mut decoder = ChunkedDecoder::new();
+            let mut out = Vec::new();
+            decoder.feed(&input[..split], &mut out).unwrap();
+            decoder.feed(&input[split..], &mut out).unwrap();
+            assert!(decoder.is_done(), "split at {}", split);
+            assert_eq!(out, b"Wiki");
+            assert_eq!(
+                decoder.into_trailers(),
+                [
+                    ("X-Checksum".to_string(), "abc".to_string()),
+                    ("X-Other".to_string(), "1".to_string()),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_body_truncated() {
+        let (out, trailers) = decode_body(b"5\r\nhello\r\n3\r\nwo").unwrap();
+        assert_eq!(out, b"hellowo");
+        assert!(trailers.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_size() {
+        let mut decoder = ChunkedDecoder::new();
+        assert!(decoder.feed(b"zz\r\n", &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        let mut framed = encode_chunk(b"data: x\n\n");
+        framed.extend_from_slice(LAST_CHUNK);
+        let mut decoder = ChunkedDecoder::new();
+        let mut out = Vec::new();
+        decoder.feed(&framed, &mut out).unwrap();
+        assert!(decoder.is_done());
+        assert_eq!(out, b"data: x\n\n");
+    }
+}
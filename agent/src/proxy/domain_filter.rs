@@ -1,16 +1,44 @@
-use std::collections::HashSet;
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
+use tracing::warn;
+
+use crate::proxy::account;
+use crate::proxy::domain_pattern::{DomainPattern, IpNetwork, PatternSet};
+use crate::rules::models::AccountType;
+
+/// Config key of the approved tenants cached from the last domain sync
+pub const TENANTS_CONFIG_KEY: &str = "approved_tenants";
+
+/// Max number of intercepted host names remembered for the transparent mode
+const MAX_SEEN_HOSTS: usize = 1024;
+
+/// Organization (or workspace) approved for enterprise use of a platform
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ApprovedTenant {
+    pub platform: String,
+    pub tenant_id: String,
+}
 
-/// Manages the list of AI domains to intercept
+/// Manages the list of AI domains to intercept.
+/// Entries are domain patterns (see [`DomainPattern`]).
 pub struct DomainFilter {
-    domains: RwLock<HashSet<String>>,
-    blocked_domains: RwLock<HashSet<String>>,
+    domains: RwLock<PatternSet>,
+    blocked_domains: RwLock<PatternSet>,
+    /// PAC script in place before the agent, used for non-AI hosts
+    fallback_pac: RwLock<Option<String>>,
+    approved_tenants: RwLock<Vec<ApprovedTenant>>,
+    /// Host names intercepted so far, so that hosts matched by suffix and
+    /// regex patterns are redirected in transparent mode too
+    seen_hosts: Mutex<BTreeSet<String>>,
 }
 
 impl DomainFilter {
     /// Default AI domains to monitor
     pub fn with_defaults() -> Self {
-        let defaults: HashSet<String> = [
+        let defaults = [
             // OpenAI / ChatGPT
             "api.openai.com",
             "chat.openai.com",
@@ -32,67 +60,273 @@ impl DomainFilter {
             // Perplexity
             "api.perplexity.ai",
             "www.perplexity.ai",
-        ]
-        .iter()
-        .map(|s| s.to_string())
-        .collect();
+        ];
 
         Self {
-            domains: RwLock::new(defaults),
-            blocked_domains: RwLock::new(HashSet::new()),
+            domains: RwLock::new(parse_patterns(defaults.iter().copied())),
+            blocked_domains: RwLock::new(PatternSet::default()),
+            fallback_pac: RwLock::new(None),
+            approved_tenants: RwLock::new(Vec::new()),
+            seen_hosts: Mutex::new(BTreeSet::new()),
         }
     }
 
     /// Check if a domain should be intercepted (monitored)
     pub async fn should_intercept(&self, host: &str) -> bool {
-        let domains = self.domains.read().await;
-        // Check exact match or subdomain match
-        domains.iter().any(|d| host == d.as_str() || host.ends_with(&format!(".{}", d)))
+        let intercept = self.domains.read().await.matches(host);
+        if intercept && host.parse::<std::net::IpAddr>().is_err() {
+            let mut seen = self.seen_hosts.lock().unwrap();
+            if seen.len() < MAX_SEEN_HOSTS {
+                seen.insert(host.trim_end_matches('.').to_ascii_lowercase());
+            }
+        }
+        intercept
+    }
+
+    /// Monitored host names to resolve, sorted: exact hosts, base domains of
+    /// suffix patterns, and the hosts intercepted so far that still match
+    pub async fn monitored_domains(&self) -> Vec<String> {
+        let patterns = self.domains.read().await;
+        let mut domains = patterns.hosts();
+        domains.extend(
+            self.seen_hosts
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|host| patterns.matches(host))
+                .cloned(),
+        );
+        domains.sort();
+        domains.dedup();
+        domains
+    }
+
+    /// Regex patterns of the monitored domains: their hosts are only
+    /// redirected in transparent mode once seen through the proxy
+    pub async fn unresolvable_patterns(&self) -> Vec<String> {
+        self.domains
+            .read()
+            .await
+            .patterns()
+            .iter()
+            .filter(|p| matches!(p, DomainPattern::Regex(_)))
+            .map(|p| p.to_string())
+            .collect()
+    }
+
+    /// Monitored IP networks (IP address and network patterns)
+    pub async fn monitored_networks(&self) -> Vec<IpNetwork> {
+        self.domains.read().await.networks().to_vec()
     }
 
     /// Check if a domain is completely blocked
     pub async fn is_blocked(&self, host: &str) -> bool {
-        let blocked = self.blocked_domains.read().await;
-        blocked.iter().any(|d| host == d.as_str() || host.ends_with(&format!(".{}", d)))
+        self.blocked_domains.read().await.matches(host)
     }
 
-    /// Update domains from server-provided list
+    /// Update domains from server-provided list. Invalid patterns are skipped.
     pub async fn update_domains(&self, monitored: Vec<(String, bool)>) {
-        let mut domains = self.domains.write().await;
-        let mut blocked = self.blocked_domains.write().await;
+        let domains = parse_patterns(monitored.iter().map(|(d, _)| d.as_str()));
+        let blocked = parse_patterns(
+            monitored
+                .iter()
+                .filter(|(_, is_blocked)| *is_blocked)
+                .map(|(d, _)| d.as_str()),
+        );
 
-        domains.clear();
-        blocked.clear();
+        *self.domains.write().await = domains;
+        *self.blocked_domains.write().await = blocked;
+    }
 
-        for (domain, is_blocked) in monitored {
-            domains.insert(domain.clone());
-            if is_blocked {
-                blocked.insert(domain);
-            }
-        }
+    /// Replace the organizations approved for enterprise use
+    pub async fn set_approved_tenants(&self, tenants: Vec<ApprovedTenant>) {
+        *self.approved_tenants.write().await = tenants;
+    }
+
+    /// Type of account a `platform` request declaring `tenant` comes from:
+    /// enterprise for an approved organization, unknown for another one,
+    /// personal without organization. None when the platform's account
+    /// context is not detected.
+    pub async fn account_type(&self, platform: &str, tenant: Option<&str>) -> Option<AccountType> {
+        let Some(tenant) = tenant else {
+            return account::is_detected(platform).then_some(AccountType::Personal);
+        };
+        let approved = self
+            .approved_tenants
+            .read()
+            .await
+            .iter()
+            .any(|t| t.platform == platform && t.tenant_id.eq_ignore_ascii_case(tenant));
+        Some(if approved {
+            AccountType::Enterprise
+        } else {
+            AccountType::Unknown
+        })
+    }
+
+    /// Set the PAC script that non-monitored hosts are resolved with
+    pub async fn set_fallback_pac(&self, script: Option<String>) {
+        *self.fallback_pac.write().await = script;
     }
 
     /// Generate a PAC (Proxy Auto-Config) file content
-    /// that redirects only monitored domains to the local proxy
+    /// that redirects only monitored domains to the local proxy, with the
+    /// same matching rules as [`DomainFilter::should_intercept`].
+    /// Other hosts go through the previous PAC script when there is one,
+    /// otherwise DIRECT.
     pub async fn generate_pac(&self, proxy_port: u16) -> String {
         let domains = self.domains.read().await;
+        let fallback_pac = self.fallback_pac.read().await;
 
-        let conditions: Vec<String> = domains
+        let mut conditions: Vec<String> = domains
+            .patterns()
             .iter()
-            .map(|d| format!("        dnsDomainIs(host, \"{}\")", d))
+            .map(|p| format!("        {}", p.pac_condition()))
             .collect();
+        if conditions.is_empty() {
+            // `if ()` is a syntax error
+            conditions.push("        false".to_string());
+        }
+
+        // The previous script is wrapped in a function scope so that its
+        // FindProxyForURL and globals do not clash with ours
+        let (fallback_definition, fallback) = match fallback_pac.as_deref() {
+            Some(script) => (
+                format!(
+                    "var previousFindProxyForURL = (function () {{\n{}\nreturn FindProxyForURL;\n}})();\n\n",
+                    script.trim()
+                ),
+                "previousFindProxyForURL(url, host)",
+            ),
+            None => (String::new(), "\"DIRECT\""),
+        };
 
         format!(
-            r#"function FindProxyForURL(url, host) {{
+            r#"{}function FindProxyForURL(url, host) {{
+    host = host.toLowerCase();
     if (
 {}
     ) {{
         return "PROXY 127.0.0.1:{}";
     }}
-    return "DIRECT";
+    return {};
 }}"#,
+            fallback_definition,
             conditions.join(" ||\n"),
             proxy_port,
+            fallback,
         )
     }
 }
+
+fn parse_patterns<'a>(entries: impl Iterator<Item = &'a str>) -> PatternSet {
+    let mut patterns = Vec::new();
+    for entry in entries {
+        match DomainPattern::parse(entry) {
+            Ok(parsed) => patterns.extend(parsed),
+            Err(e) => warn!(entry = %entry, error = %e, "Skipping invalid domain pattern"),
+        }
+    }
+    PatternSet::new(patterns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pac_falls_back_to_previous_script() {
+        let filter = DomainFilter::with_defaults();
+        let pac = filter.generate_pac(8443).await;
+        assert!(pac.starts_with("function FindProxyForURL(url, host) {"));
+        assert!(pac.contains(
+            "        host == \"chatgpt.com\" ||\n        shExpMatch(host, \"*.chatgpt.com\")"
+        ));
+        assert!(pac.ends_with("    return \"DIRECT\";\n}"));
+
+        let previous = "function FindProxyForURL(url, host) { return \"PROXY squid:3128\"; }";
+        filter.set_fallback_pac(Some(previous.to_string())).await;
+        let pac = filter.generate_pac(8443).await;
+        assert!(pac.starts_with("var previousFindProxyForURL = (function () {\n"));
+        assert!(pac.contains(previous));
+        assert!(pac.contains("return \"PROXY 127.0.0.1:8443\";"));
+        assert!(pac.contains("    return previousFindProxyForURL(url, host);\n}"));
+
+        // No monitored domain: the script is still valid
+        filter.update_domains(Vec::new()).await;
+        assert!(filter
+            .generate_pac(8443)
+            .await
+            .contains("    if (\n        false\n    ) {"));
+    }
+
+    #[tokio::test]
+    async fn test_account_types() {
+        let filter = DomainFilter::with_defaults();
+        filter
+            .set_approved_tenants(vec![ApprovedTenant {
+                platform: "chatgpt".to_string(),
+                tenant_id: "org-Acme42".to_string(),
+            }])
+            .await;
+
+        assert_eq!(
+            filter.account_type("chatgpt", Some("ORG-acme42")).await,
+            Some(AccountType::Enterprise)
+        );
+        assert_eq!(
+            filter.account_type("chatgpt", Some("org-Other")).await,
+            Some(AccountType::Unknown)
+        );
+        assert_eq!(
+            filter.account_type("claude", Some("org-Acme42")).await,
+            Some(AccountType::Unknown)
+        );
+        assert_eq!(
+            filter.account_type("chatgpt", None).await,
+            Some(AccountType::Personal)
+        );
+        assert_eq!(filter.account_type("gemini", None).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_server_patterns() {
+        let filter = DomainFilter::with_defaults();
+        filter
+            .update_domains(vec![
+                ("*.openai.com".to_string(), false),
+                ("=claude.ai".to_string(), true),
+                ("/(unclosed/".to_string(), false),
+                ("/^chat[0-9]+\\.example\\.com$/".to_string(), false),
+                ("10.8.0.0/16".to_string(), false),
+            ])
+            .await;
+
+        assert!(filter.should_intercept("api.openai.com").await);
+        assert!(!filter.should_intercept("openai.com").await);
+        assert!(filter.should_intercept("claude.ai").await);
+        assert!(!filter.should_intercept("www.claude.ai").await);
+        assert!(filter.should_intercept("10.8.1.2").await);
+        assert!(filter.is_blocked("claude.ai").await);
+        assert!(!filter.is_blocked("api.openai.com").await);
+        // Suffix base domain, exact hosts and the matching hosts seen so far
+        assert!(filter.should_intercept("chat2.example.com").await);
+        assert_eq!(
+            filter.monitored_domains().await,
+            vec![
+                "api.openai.com",
+                "chat2.example.com",
+                "claude.ai",
+                "openai.com"
+            ]
+        );
+        assert_eq!(
+            filter.unresolvable_patterns().await,
+            vec!["/^chat[0-9]+\\.example\\.com$/"]
+        );
+        assert_eq!(
+            filter.monitored_networks().await,
+            vec![IpNetwork::parse("10.8.0.0/16").unwrap()]
+        );
+    }
+}
@@ -0,0 +1,490 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+
+use regex::Regex;
+
+/// A domain entry of the monitored or blocked lists.
+///
+/// Syntax:
+/// - `=api.openai.com`: this host only
+/// - `*.openai.com`: any subdomain of openai.com (not openai.com itself)
+/// - `/^chat(gpt)?\.example\.com$/`: regular expression on the host name,
+///   limited to the syntax shared with JavaScript (it is copied to the PAC
+///   file)
+/// - `10.20.0.0/16`, `2001:db8::/32`, `10.20.0.1`: IP address or network
+/// - `openai.com`: the host and all its subdomains (`=openai.com` plus
+///   `*.openai.com`)
+#[derive(Debug, Clone)]
+pub enum DomainPattern {
+    Exact(String),
+    Suffix(String),
+    Regex(Regex),
+    Network(IpNetwork),
+}
+
+impl DomainPattern {
+    /// Parse a list entry. A bare host name yields an exact and a suffix
+    /// pattern.
+    pub fn parse(entry: &str) -> anyhow::Result<Vec<DomainPattern>> {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            anyhow::bail!("empty domain pattern");
+        }
+
+        if let Some(expr) = entry.strip_prefix('/').and_then(|e| e.strip_suffix('/')) {
+            check_js_compatible(expr)
+                .map_err(|e| anyhow::anyhow!("invalid domain regex {}: {}", entry, e))?;
+            let regex = Regex::new(&format!("(?i){}", expr))
+                .map_err(|e| anyhow::anyhow!("invalid domain regex {}: {}", entry, e))?;
+            return Ok(vec![DomainPattern::Regex(regex)]);
+        }
+        if let Some(network) = IpNetwork::parse(entry) {
+            return Ok(vec![DomainPattern::Network(network)]);
+        }
+        if let Some(host) = entry.strip_prefix('=') {
+            return Ok(vec![DomainPattern::Exact(normalize_host(host)?)]);
+        }
+        if let Some(domain) = entry.strip_prefix("*.") {
+            return Ok(vec![DomainPattern::Suffix(normalize_host(domain)?)]);
+        }
+
+        let host = normalize_host(entry)?;
+        Ok(vec![
+            DomainPattern::Exact(host.clone()),
+            DomainPattern::Suffix(host),
+        ])
+    }
+
+    /// Equivalent PAC condition, evaluated on the lowercased `host`
+    pub fn pac_condition(&self) -> String {
+        match self {
+            DomainPattern::Exact(host) => format!("host == {}", js_string(host)),
+            DomainPattern::Suffix(domain) => {
+                format!("shExpMatch(host, {})", js_string(&format!("*.{}", domain)))
+            }
+            DomainPattern::Regex(regex) => {
+                // The (?i) prefix is ours; JavaScript takes the flag apart
+                let expr = regex.as_str().trim_start_matches("(?i)");
+                format!("new RegExp({}, \"i\").test(host)", js_string(expr))
+            }
+            DomainPattern::Network(network) => network.pac_condition(),
+        }
+    }
+}
+
+impl fmt::Display for DomainPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DomainPattern::Exact(host) => write!(f, "={}", host),
+            DomainPattern::Suffix(domain) => write!(f, "*.{}", domain),
+            DomainPattern::Regex(regex) => {
+                write!(f, "/{}/", regex.as_str().trim_start_matches("(?i)"))
+            }
+            DomainPattern::Network(network) => write!(f, "{}", network),
+        }
+    }
+}
+
+/// IP network (address and prefix length)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// Parse `addr/prefix` or a single address
+    pub fn parse(s: &str) -> Option<IpNetwork> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (
+                addr.parse::<IpAddr>().ok()?,
+                Some(prefix.parse::<u8>().ok()?),
+            ),
+            None => (
+                s.trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .parse()
+                    .ok()?,
+                None,
+            ),
+        };
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        if prefix > max {
+            return None;
+        }
+        Some(IpNetwork { addr, prefix })
+    }
+
+    pub fn is_ipv4(&self) -> bool {
+        self.addr.is_ipv4()
+    }
+
+    /// Same network with the host bits of the address cleared
+    /// (`10.20.3.4/16` → `10.20.0.0/16`)
+    pub fn masked(&self) -> IpNetwork {
+        let addr = match self.addr {
+            IpAddr::V4(addr) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                IpAddr::V4((u32::from(addr) & mask).into())
+            }
+            IpAddr::V6(addr) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                IpAddr::V6((u128::from(addr) & mask).into())
+            }
+        };
+        IpNetwork {
+            addr,
+            prefix: self.prefix,
+        }
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+
+    /// PAC condition matching IP literal hosts in this network. Host names
+    /// are not resolved, like in the agent.
+    fn pac_condition(&self) -> String {
+        match self.addr {
+            IpAddr::V4(addr) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                format!(
+                    "(/^\\d+\\.\\d+\\.\\d+\\.\\d+$/.test(host) && isInNet(host, \"{}\", \"{}\"))",
+                    addr,
+                    std::net::Ipv4Addr::from(mask)
+                )
+            }
+            IpAddr::V6(_) => format!(
+                "(host.indexOf(\":\") >= 0 && typeof isInNetEx == \"function\" && isInNetEx(host, \"{}\"))",
+                self
+            ),
+        }
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Set of domain patterns with fast host lookup: host name patterns are
+/// stored in a trie of reversed labels (`com` → `openai` → `api`), so a lookup
+/// costs one step per label of the host instead of a scan of the list.
+#[derive(Debug, Default)]
+pub struct PatternSet {
+    trie: LabelNode,
+    regexes: Vec<Regex>,
+    networks: Vec<IpNetwork>,
+    patterns: Vec<DomainPattern>,
+}
+
+#[derive(Debug, Default)]
+struct LabelNode {
+    children: HashMap<String, LabelNode>,
+    /// A host ending at this node matches
+    exact: bool,
+    /// Any host strictly below this node matches
+    suffix: bool,
+}
+
+impl PatternSet {
+    pub fn new(patterns: Vec<DomainPattern>) -> Self {
+        let mut set = PatternSet::default();
+        for pattern in patterns {
+            set.insert(pattern);
+        }
+        set
+    }
+
+    pub fn insert(&mut self, pattern: DomainPattern) {
+        match &pattern {
+            DomainPattern::Exact(host) => self.node_mut(host).exact = true,
+            DomainPattern::Suffix(domain) => self.node_mut(domain).suffix = true,
+            DomainPattern::Regex(regex) => self.regexes.push(regex.clone()),
+            DomainPattern::Network(network) => self.networks.push(*network),
+        }
+        self.patterns.push(pattern);
+    }
+
+    fn node_mut(&mut self, host: &str) -> &mut LabelNode {
+        host.rsplit('.').fold(&mut self.trie, |node, label| {
+            node.children.entry(label.to_string()).or_default()
+        })
+    }
+
+    /// Whether `host` (name or IP literal, any case) matches a pattern
+    pub fn matches(&self, host: &str) -> bool {
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+        if let Ok(ip) = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+        {
+            return self.networks.iter().any(|n| n.contains(ip));
+        }
+
+        let mut node = &self.trie;
+        let mut labels = host.rsplit('.').peekable();
+        while let Some(label) = labels.next() {
+            match node.children.get(label) {
+                Some(child) => node = child,
+                None => break,
+            }
+            match labels.peek() {
+                Some(_) if node.suffix => return true,
+                None if node.exact => return true,
+                _ => {}
+            }
+        }
+
+        self.regexes.iter().any(|r| r.is_match(&host))
+    }
+
+    /// IP network patterns of the set
+    pub fn networks(&self) -> &[IpNetwork] {
+        &self.networks
+    }
+
+    /// Patterns of the set, in insertion order
+    pub fn patterns(&self) -> &[DomainPattern] {
+        &self.patterns
+    }
+
+    /// Concrete host names of the set: exact hosts, and the base domain of
+    /// suffix patterns (`*.openai.com` → `openai.com`)
+    pub fn hosts(&self) -> Vec<String> {
+        self.patterns
+            .iter()
+            .filter_map(|p| match p {
+                DomainPattern::Exact(host) | DomainPattern::Suffix(host) => Some(host.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn normalize_host(host: &str) -> anyhow::Result<String> {
+    let host = host.trim().trim_end_matches('.').to_ascii_lowercase();
+    let valid = !host.is_empty()
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        });
+    if !valid {
+        anyhow::bail!("invalid host name in domain pattern: {}", host);
+    }
+    Ok(host)
+}
+
+/// Reject the regex syntax of the `regex` crate that JavaScript's RegExp
+/// does not share (or reads differently): named groups `(?P<..>)`, inline
+/// flags, `\A` / `\z` anchors, Unicode classes, POSIX classes and class set
+/// operations
+fn check_js_compatible(expr: &str) -> anyhow::Result<()> {
+    const UNSUPPORTED: [&str; 6] = ["(?P<", r"\A", r"\z", r"\p", r"\P", "[[:"];
+    if let Some(syntax) = UNSUPPORTED.iter().find(|s| expr.contains(*s)) {
+        anyhow::bail!("{} is not supported in PAC files", syntax);
+    }
+    if let Some(operator) = class_set_operator(expr) {
+        anyhow::bail!("{} is not supported in PAC files", operator);
+    }
+    // Inline flags: `(?i)`, `(?x:...)`, `(?-s)`...; `(?:` and `(?<name>` are fine
+    let flags = expr.match_indices("(?").any(|(i, _)| {
+        expr[i + 2..]
+            .chars()
+            .next()
+            .is_some_and(|c| matches!(c, 'i' | 'm' | 's' | 'U' | 'u' | 'x' | 'R' | '-'))
+    });
+    if flags {
+        anyhow::bail!("inline flags are not supported in PAC files");
+    }
+    Ok(())
+}
+
+/// First class set operation (`&&`, `--`, `~~`) found inside a character
+/// class; outside of one these are plain characters
+fn class_set_operator(expr: &str) -> Option<&'static str> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '[' => {
+                depth += 1;
+                // A `]` right after the opening bracket is a literal
+                if chars.get(i + 1) == Some(&'^') {
+                    i += 1;
+                }
+                if chars.get(i + 1) == Some(&']') {
+                    i += 1;
+                }
+            }
+            ']' if depth > 0 => depth -= 1,
+            c @ ('&' | '-' | '~') if depth > 0 && chars.get(i + 1) == Some(&c) => {
+                return Some(match c {
+                    '&' => "&&",
+                    '-' => "--",
+                    _ => "~~",
+                });
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Quote a string for inclusion in the PAC script
+fn js_string(s: &str) -> String {
+    serde_json::to_string(s).unwrap_or_else(|_| "\"\"".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(entries: &[&str]) -> PatternSet {
+        PatternSet::new(
+            entries
+                .iter()
+                .flat_map(|e| DomainPattern::parse(e).unwrap())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_pattern_types() {
+        let set = set(&[
+            "=api.openai.com",
+            "*.anthropic.com",
+            "chatgpt.com",
+            r"/^gemini\d*\.google\.com$/",
+            "10.20.0.0/16",
+        ]);
+
+        assert!(set.matches("api.openai.com"));
+        assert!(set.matches("API.OpenAI.com."));
+        assert!(!set.matches("openai.com"));
+        assert!(!set.matches("files.api.openai.com"));
+
+        assert!(set.matches("api.anthropic.com"));
+        assert!(!set.matches("anthropic.com"));
+        assert!(!set.matches("notanthropic.com"));
+
+        assert!(set.matches("chatgpt.com"));
+        assert!(set.matches("ab.chatgpt.com"));
+
+        assert!(set.matches("gemini2.google.com"));
+        assert!(!set.matches("mail.google.com"));
+
+        assert!(set.matches("10.20.3.4"));
+        assert!(!set.matches("10.21.3.4"));
+        assert!(!set.matches("com"));
+    }
+
+    #[test]
+    fn test_ip_networks() {
+        let net = IpNetwork::parse("2001:db8::/32").unwrap();
+        assert!(net.contains("2001:db8::1".parse().unwrap()));
+        assert!(!net.contains("2001:db9::1".parse().unwrap()));
+        assert!(!net.contains("10.0.0.1".parse().unwrap()));
+        assert!(IpNetwork::parse("10.0.0.0/33").is_none());
+        assert!(IpNetwork::parse("0.0.0.0/0")
+            .unwrap()
+            .contains("8.8.8.8".parse().unwrap()));
+
+        let set = set(&["2001:db8::/32", "127.0.0.1"]);
+        assert!(set.matches("[2001:db8::5]"));
+        assert!(set.matches("127.0.0.1"));
+        assert!(!set.matches("127.0.0.2"));
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        assert!(DomainPattern::parse("").is_err());
+        assert!(DomainPattern::parse("/(unclosed/").is_err());
+        assert!(DomainPattern::parse("*.").is_err());
+        assert!(DomainPattern::parse("bad host.com").is_err());
+
+        // Rust-only regex syntax would not compile (or match differently)
+        // in the PAC file
+        assert!(DomainPattern::parse(r"/^(?P<name>chat)\.example\.com$/").is_err());
+        assert!(DomainPattern::parse(r"/(?i)^chat\.example\.com$/").is_err());
+        assert!(DomainPattern::parse(r"/^chat\.example\.com\z/").is_err());
+        assert!(DomainPattern::parse(r"/^\p{L}+\.example\.com$/").is_err());
+        assert!(DomainPattern::parse(r"/^[[:alpha:]]+\.example\.com$/").is_err());
+        assert!(DomainPattern::parse(r"/^[a-z--q]+\.example\.com$/").is_err());
+        assert!(DomainPattern::parse(r"/^[\w&&[^_]]+\.example\.com$/").is_err());
+        // Outside of a character class, `--` is a plain string (punycode)
+        assert!(DomainPattern::parse(r"/^xn--.*\.example$/").is_ok());
+        assert!(DomainPattern::parse(r"/^[a-z]+--[\-a-z]+\.example$/").is_ok());
+        assert!(DomainPattern::parse(r"/^(?:chat|api)\.example\.com$/").is_ok());
+        assert!(DomainPattern::parse(r"/^(?<sub>chat)\.example\.com$/").is_ok());
+    }
+
+    #[test]
+    fn test_masked_network() {
+        assert_eq!(
+            IpNetwork::parse("10.20.3.4/16")
+                .unwrap()
+                .masked()
+                .to_string(),
+            "10.20.0.0/16"
+        );
+        assert_eq!(
+            IpNetwork::parse("2001:db8::5/32")
+                .unwrap()
+                .masked()
+                .to_string(),
+            "2001:db8::/32"
+        );
+        assert_eq!(
+            IpNetwork::parse("10.0.0.1").unwrap().masked().to_string(),
+            "10.0.0.1/32"
+        );
+    }
+
+    #[test]
+    fn test_pac_conditions() {
+        let pac = |entry: &str| -> Vec<String> {
+            DomainPattern::parse(entry)
+                .unwrap()
+                .iter()
+                .map(|p| p.pac_condition())
+                .collect()
+        };
+        assert_eq!(pac("=api.openai.com"), vec![r#"host == "api.openai.com""#]);
+        assert_eq!(
+            pac("*.openai.com"),
+            vec![r#"shExpMatch(host, "*.openai.com")"#]
+        );
+        assert_eq!(
+            pac(r"/^chat\.example\.com$/"),
+            vec![r#"new RegExp("^chat\\.example\\.com$", "i").test(host)"#]
+        );
+        assert_eq!(
+            pac("10.20.0.0/16"),
+            vec![
+                r#"(/^\d+\.\d+\.\d+\.\d+$/.test(host) && isInNet(host, "10.20.0.0", "255.255.0.0"))"#
+            ]
+        );
+        assert_eq!(pac("claude.ai").len(), 2);
+    }
+}
@@ -0,0 +1,289 @@
+use std::borrow::Cow;
+use std::io::{Read, Write};
+
+use tracing::debug;
+
+/// Maximum decoded size of a body (protects against decompression bombs)
+const MAX_DECODED_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Content codings understood by the interceptor
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Coding {
+    Identity,
+    Gzip,
+    Deflate,
+    Brotli,
+    Zstd,
+}
+
+impl Coding {
+    fn parse(token: &str) -> Option<Self> {
+        match token.trim().to_ascii_lowercase().as_str() {
+            "" | "identity" => Some(Self::Identity),
+            "gzip" | "x-gzip" => Some(Self::Gzip),
+            "deflate" => Some(Self::Deflate),
+            "br" => Some(Self::Brotli),
+            "zstd" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a Content-Encoding header into the codings applied, in order.
+/// Returns None if one of them is not supported.
+fn parse_codings(content_encoding: &str) -> Option<Vec<Coding>> {
+    content_encoding
+        .split(',')
+        .map(Coding::parse)
+        .collect::<Option<Vec<_>>>()
+        .map(|codings| {
+            codings
+                .into_iter()
+                .filter(|c| *c != Coding::Identity)
+                .collect()
+        })
+}
+
+/// Whether a body with this Content-Encoding is not plain: plain text
+/// appended to it would corrupt it
+pub fn is_encoded(content_encoding: Option<&str>) -> bool {
+    content_encoding.is_some_and(|ce| parse_codings(ce).is_none_or(|codings| !codings.is_empty()))
+}
+
+/// Decode a complete body according to its Content-Encoding header, for
+/// inspection only (the original bytes are what gets forwarded).
+/// Returns None if the coding is unsupported or the body is corrupt.
+pub fn decode_body<'a>(body: &'a [u8], content_encoding: Option<&str>) -> Option<Cow<'a, [u8]>> {
+    let codings = match content_encoding {
+        Some(ce) => parse_codings(ce)?,
+        None => return Some(Cow::Borrowed(body)),
+    };
+
+    // Codings are listed in the order they were applied
+    let mut data = Cow::Borrowed(body);
+    for coding in codings.iter().rev() {
+        match decode_one(&data, *coding) {
+            Ok(decoded) => data = Cow::Owned(decoded),
+            Err(e) => {
+                debug!(error = %e, coding = ?coding, "Failed to decode body");
+                return None;
+            }
+        }
+    }
+    Some(data)
+}
+
+fn decode_one(data: &[u8], coding: Coding) -> anyhow::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    match coding {
+        Coding::Identity => out.extend_from_slice(data),
+        Coding::Gzip => read_limited(flate2::read::MultiGzDecoder::new(data), &mut out)?,
+        // "deflate" is zlib-wrapped, but some servers send raw deflate
+        Coding::Deflate => {
+            if read_limited(flate2::read::ZlibDecoder::new(data), &mut out).is_err() {
+                out.clear();
+                read_limited(flate2::read::DeflateDecoder::new(data), &mut out)?;
+            }
+        }
+        Coding::Brotli => read_limited(brotli::Decompressor::new(data, 4096), &mut out)?,
+        Coding::Zstd => read_limited(zstd::stream::read::Decoder::new(data)?, &mut out)?,
+    }
+    Ok(out)
+}
+
+fn read_limited<R: Read>(reader: R, out: &mut Vec<u8>) -> anyhow::Result<()> {
+    reader.take(MAX_DECODED_SIZE + 1).read_to_end(out)?;
+    if out.len() as u64 > MAX_DECODED_SIZE {
+        anyhow::bail!("Decoded body too large");
+    }
+    Ok(())
+}
+
+/// Incremental decoder for compressed streamed bodies (SSE)
+pub struct StreamDecoder {
+    inner: StreamInner,
+    decoded_len: u64,
+}
+
+enum StreamInner {
+    Identity,
+    Gzip(flate2::write::MultiGzDecoder<Vec<u8>>),
+    Deflate(flate2::write::ZlibDecoder<Vec<u8>>),
+    Brotli(Box<brotli::DecompressorWriter<Vec<u8>>>),
+    Zstd(zstd::stream::write::Decoder<'static, Vec<u8>>),
+    /// Unsupported or corrupt stream: nothing is decoded
+    Failed,
+}
+
+impl StreamDecoder {
+    pub fn new(content_encoding: Option<&str>) -> Self {
+        let codings = content_encoding
+            .map(parse_codings)
+            .unwrap_or(Some(Vec::new()));
+        let inner = match codings.as_deref() {
+            Some([]) => StreamInner::Identity,
+            Some([Coding::Gzip]) => {
+                StreamInner::Gzip(flate2::write::MultiGzDecoder::new(Vec::new()))
+            }
+            Some([Coding::Deflate]) => {
+                StreamInner::Deflate(flate2::write::ZlibDecoder::new(Vec::new()))
+            }
+            Some([Coding::Brotli]) => {
+                StreamInner::Brotli(Box::new(brotli::DecompressorWriter::new(Vec::new(), 4096)))
+            }
+            Some([Coding::Zstd]) => match zstd::stream::write::Decoder::new(Vec::new()) {
+                Ok(decoder) => StreamInner::Zstd(decoder),
+                Err(_) => StreamInner::Failed,
+            },
+            // Stacked codings are not streamed
+            _ => {
+                debug!(?content_encoding, "Unsupported streamed Content-Encoding");
+                StreamInner::Failed
+            }
+        };
+        Self {
+            inner,
+            decoded_len: 0,
+        }
+    }
+
+    /// Decode the next bytes of the stream; returns the decoded bytes
+    /// available so far
+    pub fn feed(&mut self, data: &[u8]) -> Vec<u8> {
+        let result = match &mut self.inner {
+            StreamInner::Identity => return data.to_vec(),
+            StreamInner::Failed => return Vec::new(),
+            StreamInner::Gzip(d) => write_and_take(d, data, |d| d.get_mut()),
+            StreamInner::Deflate(d) => write_and_take(d, data, |d| d.get_mut()),
+            StreamInner::Brotli(d) => write_and_take(d.as_mut(), data, |d| d.get_mut()),
+            StreamInner::Zstd(d) => write_and_take(d, data, |d| d.get_mut()),
+        };
+
+        match result {
+            Ok(decoded) => {
+                self.decoded_len += decoded.len() as u64;
+                if self.decoded_len > MAX_DECODED_SIZE {
+                    debug!("Decoded stream too large, inspection stopped");
+                    self.inner = StreamInner::Failed;
+                }
+                decoded
+            }
+            Err(e) => {
+                debug!(error = %e, "Failed to decode streamed body");
+                self.inner = StreamInner::Failed;
+                Vec::new()
+            }
+        }
+    }
+}
+
+fn write_and_take<W: Write>(
+    decoder: &mut W,
+    data: &[u8],
+    output: impl Fn(&mut W) -> &mut Vec<u8>,
+) -> std::io::Result<Vec<u8>> {
+    decoder.write_all(data)?;
+    decoder.flush()?;
+    Ok(std::mem::take(output(decoder)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &[u8] = b"data: {\"choices\":[{\"delta\":{\"content\":\"Bonjour\"}}]}\n\n";
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn brotli(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        {
+            let mut encoder = brotli::CompressorWriter::new(&mut out, 4096, 5, 22);
+            encoder.write_all(data).unwrap();
+        }
+        out
+    }
+
+    fn zstd(data: &[u8]) -> Vec<u8> {
+        zstd::encode_all(data, 3).unwrap()
+    }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decode_body() {
+        assert_eq!(
+            decode_body(&gzip(TEXT), Some("gzip")).unwrap().as_ref(),
+            TEXT
+        );
+        assert_eq!(
+            decode_body(&brotli(TEXT), Some("br")).unwrap().as_ref(),
+            TEXT
+        );
+        assert_eq!(
+            decode_body(&zstd(TEXT), Some("zstd")).unwrap().as_ref(),
+            TEXT
+        );
+        assert_eq!(
+            decode_body(&deflate(TEXT), Some("Deflate"))
+                .unwrap()
+                .as_ref(),
+            TEXT
+        );
+        assert_eq!(decode_body(TEXT, None).unwrap().as_ref(), TEXT);
+        assert_eq!(decode_body(TEXT, Some("identity")).unwrap().as_ref(), TEXT);
+    }
+
+    #[test]
+    fn test_decode_stacked_codings() {
+        let encoded = brotli(&gzip(TEXT));
+        assert_eq!(
+            decode_body(&encoded, Some("gzip, br")).unwrap().as_ref(),
+            TEXT
+        );
+    }
+
+    #[test]
+    fn test_decode_unsupported_or_corrupt() {
+        assert!(decode_body(TEXT, Some("compress")).is_none());
+        assert!(decode_body(TEXT, Some("gzip")).is_none());
+    }
+
+    #[test]
+    fn test_stream_decoder_split_input() {
+        let mut body = Vec::new();
+        for _ in 0..20 {
+            body.extend_from_slice(TEXT);
+        }
+
+        for (encoding, encoded) in [
+            ("gzip", gzip(&body)),
+            ("br", brotli(&body)),
+            ("zstd", zstd(&body)),
+            ("deflate", deflate(&body)),
+        ] {
+            let mut decoder = StreamDecoder::new(Some(encoding));
+            let mut decoded = Vec::new();
+            for piece in encoded.chunks(7) {
+                decoded.extend(decoder.feed(piece));
+            }
+            assert_eq!(decoded, body, "{}", encoding);
+        }
+    }
+
+    #[test]
+    fn test_stream_decoder_corrupt() {
+        let mut decoder = StreamDecoder::new(Some("gzip"));
+        assert!(decoder.feed(b"not gzip at all").is_empty());
+        assert!(decoder.feed(&gzip(TEXT)).is_empty());
+    }
+}
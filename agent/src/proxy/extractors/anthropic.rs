@@ -0,0 +1,170 @@
+//! Anthropic payloads: the Messages API (`api.anthropic.com/v1/messages`)
+//! and the claude.ai web endpoints (`/api/organizations/{org}/
+//! chat_conversations/{id}/completion`).
+
+use serde_json::Value;
+
+use super::join_texts;
+
+/// Prompt of a Messages API request (`system` prompt, then the last user
+/// turn, text of their content blocks) or of a claude.ai completion request
+/// (`prompt` plus the text of the attached files)
+pub fn extract_prompt(body: &Value) -> Option<String> {
+    if let Some(messages) = body.get("messages").and_then(Value::as_array) {
+        let system = body.get("system").and_then(content_text);
+        let user_turn = messages
+            .iter()
+            .rev()
+            .find(|m| m.get("role").and_then(Value::as_str) == Some("user"))
+            .and_then(|m| m.get("content"))
+            .and_then(content_text);
+        return join_texts(system.into_iter().chain(user_turn));
+    }
+
+    // claude.ai web flow
+    let prompt = body.get("prompt").and_then(Value::as_str)?;
+    let attachments = body
+        .get("attachments")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|a| a.get("extracted_content").and_then(Value::as_str))
+        .map(str::to_string);
+    join_texts(std::iter::once(prompt.to_string()).chain(attachments))
+}
+
+/// Answer of a non-streamed Messages API response (text content blocks)
+pub fn extract_response(body: &Value) -> Option<String> {
+    if body.get("type").and_then(Value::as_str) == Some("message") {
+        return content_text(body.get("content")?);
+    }
+    // claude.ai legacy completion
+    body.get("completion")
+        .and_then(Value::as_str)
+        .filter(|c| !c.is_empty())
+        .map(str::to_string)
+}
+
+/// Text delta of a streamed event: `content_block_delta` events with a
+/// `text_delta` (Messages API and current claude.ai), the initial text of a
+/// `content_block_start`, or legacy claude.ai `completion` events
+pub fn extract_stream_event(event: &Value) -> Option<String> {
+    let text = match event.get("type").and_then(Value::as_str)? {
+        "content_block_delta" => {
+            let delta = event.get("delta")?;
+            if delta.get("type").and_then(Value::as_str) != Some("text_delta") {
+                // thinking_delta, input_json_delta, signature_delta...
+                return None;
+            }
+            delta.get("text")?
+        }
+        "content_block_start" => event.get("content_block")?.get("text")?,
+        "completion" => event.get("completion")?,
+        _ => return None,
+    };
+    text.as_str().filter(|t| !t.is_empty()).map(str::to_string)
+}
+
+/// Text of a message content: a string, or an array of content blocks
+/// (`text` blocks, text documents and tool results; images are skipped)
+fn content_text(content: &Value) -> Option<String> {
+    match content {
+        Value::String(s) => join_texts([s.clone()]),
+        Value::Array(blocks) => join_texts(blocks.iter().filter_map(block_text)),
+        _ => None,
+    }
+}
+
+fn block_text(block: &Value) -> Option<String> {
+    match block.get("type").and_then(Value::as_str)? {
+        "text" => block.get("text")?.as_str().map(str::to_string),
+        "document" => {
+            let source = block.get("source")?;
+            match source.get("type").and_then(Value::as_str)? {
+                "text" => source.get("data")?.as_str().map(str::to_string),
+                "content" => content_text(source.get("content")?),
+                _ => None,
+            }
+        }
+        "tool_result" => content_text(block.get("content")?),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(s: &str) -> Value {
+        serde_json::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_messages_api_prompt() {
+        let body = json(
+            r#"{"model":"claude-sonnet-4-5","system":[{"type":"text","text":"Tu es un assistant."}],
+                "messages":[
+                    {"role":"user","content":"Bonjour"},
+                    {"role":"assistant","content":[{"type":"text","text":"Bonjour !"}]},
+                    {"role":"user","content":[
+                        {"type":"image","source":{"type":"base64","media_type":"image/png","data":"iVBOR"}},
+                        {"type":"text","text":"Analyse ce contrat"},
+                        {"type":"document","source":{"type":"text","media_type":"text/plain","data":"Article 1 : confidentiel"}}
+                    ]}
+                ]}"#,
+        );
+        assert_eq!(
+            extract_prompt(&body).as_deref(),
+            Some("Tu es un assistant.\n\nAnalyse ce contrat\n\nArticle 1 : confidentiel")
+        );
+
+        // Data may be pasted in the system prompt only
+        let body = json(
+            r#"{"model":"claude-sonnet-4-5","system":"Contexte : contrat SECRET-PROJECT",
+                "messages":[{"role":"user","content":"Résume le contexte"}]}"#,
+        );
+        assert_eq!(
+            extract_prompt(&body).as_deref(),
+            Some("Contexte : contrat SECRET-PROJECT\n\nRésume le contexte")
+        );
+    }
+
+    #[test]
+    fn test_claude_web_prompt() {
+        let body = json(
+            r#"{"prompt":"Résume la pièce jointe","timezone":"Africa/Abidjan",
+                "attachments":[{"file_name":"notes.txt","file_type":"text/plain","extracted_content":"Budget 2026 : 1,2 Md"}],
+                "files":[]}"#,
+        );
+        assert_eq!(
+            extract_prompt(&body).as_deref(),
+            Some("Résume la pièce jointe\n\nBudget 2026 : 1,2 Md")
+        );
+    }
+
+    #[test]
+    fn test_response_and_stream_events() {
+        let response = json(
+            r#"{"type":"message","role":"assistant","content":[{"type":"thinking","thinking":"..."},{"type":"text","text":"Voici le résumé."}]}"#,
+        );
+        assert_eq!(
+            extract_response(&response).as_deref(),
+            Some("Voici le résumé.")
+        );
+
+        let delta = json(
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Voi"}}"#,
+        );
+        assert_eq!(extract_stream_event(&delta).as_deref(), Some("Voi"));
+        let thinking = json(
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"thinking_delta","thinking":"hmm"}}"#,
+        );
+        assert_eq!(extract_stream_event(&thinking), None);
+        let legacy = json(r#"{"type":"completion","completion":" ci","stop_reason":null}"#);
+        assert_eq!(extract_stream_event(&legacy).as_deref(), Some(" ci"));
+        assert_eq!(
+            extract_stream_event(&json(r#"{"type":"message_stop"}"#)),
+            None
+        );
+    }
+}
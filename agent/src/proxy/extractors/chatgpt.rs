@@ -0,0 +1,293 @@
+//! ChatGPT web app payloads (`chatgpt.com/backend-api/conversation`).
+//!
+//! Requests carry `messages[].content.parts[]`; answers stream either
+//! cumulative snapshots of the message (`{"message": {...}}` events) or,
+//! with delta encoding v1, JSON-patch-like operations appending to
+//! `/message/content/parts/0` (`{"p": ..., "o": "append", "v": "text"}`,
+//! where later events may omit `p` and `o`).
+
+use serde_json::{json, Map, Value};
+
+use super::{join_texts, StreamState};
+
+/// Prefix of the file references in image parts
+const FILE_SERVICE_PREFIX: &str = "file-service://";
+
+/// Prompt of a conversation request: text parts of the last user message
+pub fn extract_prompt(body: &Value) -> Option<String> {
+    let message = last_user_message(body)?;
+    join_texts(message_texts(message.get("content")?))
+}
+
+/// Uploaded files referenced by the last user message and custom GPT in use
+pub fn extract_metadata(body: &Value) -> Map<String, Value> {
+    let mut metadata = Map::new();
+
+    let gizmo_id = body.get("gizmo_id").and_then(Value::as_str).or_else(|| {
+        let mode = body.get("conversation_mode")?;
+        mode.get("gizmo_id").and_then(Value::as_str)
+    });
+    if let Some(gizmo_id) = gizmo_id {
+        metadata.insert("gpt_id".to_string(), json!(gizmo_id));
+    }
+
+    let mut files: Vec<Value> = Vec::new();
+    if let Some(message) = last_user_message(body) {
+        let attachments = message
+            .pointer("/metadata/attachments")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten();
+        for attachment in attachments {
+            let Some(id) = attachment.get("id").and_then(Value::as_str) else {
+                continue;
+            };
+            let mut file = Map::new();
+            file.insert("id".to_string(), json!(id));
+            for field in ["name", "mime_type", "size"] {
+                if let Some(value) = attachment.get(field) {
+                    file.insert(field.to_string(), value.clone());
+                }
+            }
+            files.push(Value::Object(file));
+        }
+
+        // Images pasted in the message are referenced by asset pointers
+        let pointers = message
+            .pointer("/content/parts")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|part| part.get("asset_pointer").and_then(Value::as_str))
+            .map(|pointer| pointer.trim_start_matches(FILE_SERVICE_PREFIX));
+        for id in pointers {
+            if !files.iter().any(|f| f["id"] == id) {
+                files.push(json!({ "id": id }));
+            }
+        }
+    }
+    if !files.is_empty() {
+        metadata.insert("files".to_string(), Value::Array(files));
+    }
+
+    metadata
+}
+
+/// Answer of a non-streamed conversation response
+pub fn extract_response(body: &Value) -> Option<String> {
+    let message = body.get("message")?;
+    if !is_assistant_text(message) {
+        return None;
+    }
+    join_texts(message_texts(message.get("content")?))
+}
+
+/// Text added by one streamed event
+pub fn extract_stream_event(event: &Value, state: &mut StreamState) -> Option<String> {
+    if let Some(message) = event.get("message") {
+        return snapshot(message, state);
+    }
+
+    let value = event.get("v")?;
+    if event.get("o").and_then(Value::as_str) == Some("patch") {
+        let text: String = value
+            .as_array()?
+            .iter()
+            .filter_map(|op| apply_operation(op, op.get("v")?, state))
+            .collect();
+        return (!text.is_empty()).then_some(text);
+    }
+    apply_operation(event, value, state)
+}
+
+/// Apply one delta-encoding operation; `p` and `o` default to those of the
+/// previous operation
+fn apply_operation(op: &Value, value: &Value, state: &mut StreamState) -> Option<String> {
+    if let Some(path) = op.get("p").and_then(Value::as_str) {
+        state.patch_path = Some(path.to_string());
+    }
+    if let Some(operation) = op.get("o").and_then(Value::as_str) {
+        state.patch_op = Some(operation.to_string());
+    }
+    let path = state.patch_path.as_deref().unwrap_or("");
+    let operation = state.patch_op.as_deref().unwrap_or("append");
+
+    match operation {
+        // A new message: its initial snapshot
+        "add" if path.is_empty() => snapshot(value.get("message")?, state),
+        "append" if path.starts_with("/message/content/parts/") && state.assistant => {
+            let text = value.as_str().filter(|t| !t.is_empty())?;
+            state.snapshot.push_str(text);
+            Some(text.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// New text of a cumulative message snapshot compared to the previous one
+fn snapshot(message: &Value, state: &mut StreamState) -> Option<String> {
+    if state.begin_message(message.get("id").and_then(Value::as_str)) {
+        state.assistant = is_assistant_text(message);
+    }
+    if !state.assistant {
+        return None;
+    }
+    state.snapshot_delta(message_texts(message.get("content")?).join("\n"))
+}
+
+fn last_user_message(body: &Value) -> Option<&Value> {
+    body.get("messages")?
+        .as_array()?
+        .iter()
+        .rev()
+        .find(|m| m.pointer("/author/role").and_then(Value::as_str) == Some("user"))
+}
+
+fn is_assistant_text(message: &Value) -> bool {
+    let role = message.pointer("/author/role").and_then(Value::as_str);
+    let content_type = message
+        .pointer("/content/content_type")
+        .and_then(Value::as_str);
+    role == Some("assistant") && matches!(content_type, Some("text") | Some("multimodal_text"))
+}
+
+/// Text of a message content: string parts (images and other objects are
+/// skipped), or the `text` of code and other typed contents
+fn message_texts(content: &Value) -> Vec<String> {
+    match content.get("parts").and_then(Value::as_array) {
+        Some(parts) => parts
+            .iter()
+            .filter_map(|p| p.as_str().map(str::to_string))
+            .collect(),
+        None => content
+            .get("text")
+            .and_then(Value::as_str)
+            .map(|t| vec![t.to_string()])
+            .unwrap_or_default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(s: &str) -> Value {
+        serde_json::from_str(s).unwrap()
+    }
+
+    const REQUEST: &str = r#"{
+        "action": "next",
+        "messages": [{
+            "id": "aaa1",
+            "author": {"role": "user"},
+            "content": {
+                "content_type": "multimodal_text",
+                "parts": [
+                    {"content_type": "image_asset_pointer", "asset_pointer": "file-service://file-Img1", "width": 800},
+                    "Que montre ce tableau ?"
+                ]
+            },
+            "metadata": {
+                "attachments": [
+                    {"id": "file-Img1", "name": "ventes.png", "mime_type": "image/png", "size": 48213},
+                    {"id": "file-Doc2", "name": "budget.xlsx", "size": 9120}
+                ]
+            }
+        }],
+        "conversation_id": "6710c3f2-0000-8000-9000-000000000000",
+        "parent_message_id": "client-created-root",
+        "model": "auto",
+        "conversation_mode": {"kind": "gizmo_interaction", "gizmo_id": "g-Rh2vWmXqA"}
+    }"#;
+
+    #[test]
+    fn test_conversation_request() {
+        let body = json(REQUEST);
+        assert_eq!(
+            extract_prompt(&body).as_deref(),
+            Some("Que montre ce tableau ?")
+        );
+
+        let metadata = extract_metadata(&body);
+        assert_eq!(metadata["gpt_id"], "g-Rh2vWmXqA");
+        assert_eq!(
+            metadata["files"],
+            json(
+                r#"[{"id":"file-Img1","name":"ventes.png","mime_type":"image/png","size":48213},
+                    {"id":"file-Doc2","name":"budget.xlsx","size":9120}]"#
+            )
+        );
+
+        // OpenAI API format: not a ChatGPT web request
+        assert_eq!(
+            extract_prompt(&json(r#"{"messages":[{"role":"user","content":"hi"}]}"#)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cumulative_snapshots() {
+        let mut state = StreamState::default();
+        let event = |text: &str, role: &str, id: &str| {
+            json(&format!(
+                r#"{{"message":{{"id":"{}","author":{{"role":"{}"}},"content":{{"content_type":"text","parts":["{}"]}},"status":"in_progress"}}}}"#,
+                id, role, text
+            ))
+        };
+
+        // The user message echoed back is not part of the answer
+        assert_eq!(
+            extract_stream_event(&event("Bonjour", "user", "u1"), &mut state),
+            None
+        );
+        assert_eq!(
+            extract_stream_event(&event("Le", "assistant", "a1"), &mut state).as_deref(),
+            Some("Le")
+        );
+        assert_eq!(
+            extract_stream_event(&event("Le tableau", "assistant", "a1"), &mut state).as_deref(),
+            Some(" tableau")
+        );
+        assert_eq!(
+            extract_stream_event(&event("Le tableau", "assistant", "a1"), &mut state),
+            None
+        );
+        assert_eq!(
+            extract_stream_event(&event("Le tableau montre", "assistant", "a1"), &mut state)
+                .as_deref(),
+            Some(" montre")
+        );
+    }
+
+    #[test]
+    fn test_delta_encoding() {
+        let mut state = StreamState::default();
+        let events = [
+            r#""v1""#,
+            r#"{"p":"","o":"add","v":{"message":{"id":"a1","author":{"role":"assistant"},"content":{"content_type":"text","parts":[""]},"status":"in_progress"}}}"#,
+            r#"{"p":"/message/content/parts/0","o":"append","v":"Le"}"#,
+            r#"{"v":" tableau"}"#,
+            r#"{"p":"","o":"patch","v":[{"p":"/message/content/parts/0","o":"append","v":" montre"},{"p":"/message/status","o":"replace","v":"finished_successfully"}]}"#,
+            r#"{"type":"message_stream_complete"}"#,
+        ];
+        let text: String = events
+            .iter()
+            .filter_map(|e| extract_stream_event(&json(e), &mut state))
+            .collect();
+        assert_eq!(text, "Le tableau montre");
+
+        // Tool messages are not part of the answer
+        let mut state = StreamState::default();
+        let tool = json(
+            r#"{"p":"","o":"add","v":{"message":{"id":"t1","author":{"role":"tool"},"content":{"content_type":"text","parts":[""]}}}}"#,
+        );
+        assert_eq!(extract_stream_event(&tool, &mut state), None);
+        assert_eq!(
+            extract_stream_event(
+                &json(r#"{"p":"/message/content/parts/0","o":"append","v":"search"}"#),
+                &mut state
+            ),
+            None
+        );
+    }
+}
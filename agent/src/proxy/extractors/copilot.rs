@@ -0,0 +1,172 @@
+//! Microsoft Copilot answers, streamed over WebSocket:
+//! - `copilot.microsoft.com` events: `{"event": "appendText", "text": ...}`,
+//!   `{"event": "citation", "url": ...}`, `{"event": "done"}`
+//! - SignalR (Bing / Edge Copilot): `update` invocations carrying cumulative
+//!   snapshots of the bot message (`{"type": 1, "target": "update",
+//!   "arguments": [{"messages": [...]}]}`), then a completion record
+//!   (`{"type": 2, "item": {"messages": [...]}}`) and a close (`type` 3)
+//!
+//! GitHub Copilot's API is OpenAI-compatible and needs no native extractor.
+
+use serde_json::Value;
+
+use super::StreamState;
+
+/// Answer of a buffered SignalR completion record
+pub fn extract_response(body: &Value, state: &mut StreamState) -> Option<String> {
+    let messages = body.pointer("/item/messages")?.as_array()?;
+    let answer = messages.iter().rev().find(|m| is_bot_answer(m))?;
+    collect_attributions(answer, state);
+    answer
+        .get("text")
+        .and_then(Value::as_str)
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+}
+
+/// Text added by one event (or SignalR record)
+pub fn extract_stream_event(event: &Value, state: &mut StreamState) -> Option<String> {
+    if let Some(kind) = event.get("event").and_then(Value::as_str) {
+        return match kind {
+            "appendText" => event
+                .get("text")
+                .and_then(Value::as_str)
+                .filter(|t| !t.is_empty())
+                .map(str::to_string),
+            "citation" => {
+                if let Some(url) = event.get("url").and_then(Value::as_str) {
+                    state.add_citation(url);
+                }
+                None
+            }
+            "done" => {
+                state.completed = true;
+                None
+            }
+            _ => None,
+        };
+    }
+
+    match event.get("type").and_then(Value::as_u64)? {
+        // Invocation: cumulative snapshot of the bot message
+        1 if event.get("target").and_then(Value::as_str) == Some("update") => {
+            let messages = event.pointer("/arguments/0/messages")?.as_array()?;
+            let answer = messages.iter().rev().find(|m| is_bot_answer(m))?;
+            collect_attributions(answer, state);
+            state.begin_message(answer.get("messageId").and_then(Value::as_str));
+            state.snapshot_delta(answer.get("text")?.as_str()?.to_string())
+        }
+        // Completion: the final message may add what the updates missed
+        2 => {
+            state.completed = true;
+            let messages = event.pointer("/item/messages")?.as_array()?;
+            let answer = messages.iter().rev().find(|m| is_bot_answer(m))?;
+            collect_attributions(answer, state);
+            if !state.begin_message(answer.get("messageId").and_then(Value::as_str)) {
+                return state.snapshot_delta(answer.get("text")?.as_str()?.to_string());
+            }
+            answer
+                .get("text")?
+                .as_str()
+                .filter(|t| !t.is_empty())
+                .map(str::to_string)
+        }
+        3 => {
+            state.completed = true;
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Bot answer (not the echoed user message nor internal search messages)
+fn is_bot_answer(message: &Value) -> bool {
+    message.get("author").and_then(Value::as_str) == Some("bot")
+        && message.get("messageType").and_then(Value::as_str).is_none()
+}
+
+fn collect_attributions(message: &Value, state: &mut StreamState) {
+    let urls: Vec<&str> = message
+        .get("sourceAttributions")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|s| s.get("seeMoreUrl").and_then(Value::as_str))
+        .collect();
+    for url in urls {
+        state.add_citation(url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(s: &str) -> Value {
+        serde_json::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_copilot_events() {
+        let mut state = StreamState::default();
+        let events = [
+            r#"{"event":"received","messageId":"m1"}"#,
+            r#"{"event":"startMessage","messageId":"m2"}"#,
+            r#"{"event":"appendText","messageId":"m2","partId":"0","text":"Voici "}"#,
+            r#"{"event":"appendText","messageId":"m2","partId":"0","text":"la réponse"}"#,
+            r#"{"event":"citation","messageId":"m2","title":"Source","url":"https://learn.microsoft.com/fr-fr/"}"#,
+            r#"{"event":"done","messageId":"m2"}"#,
+        ];
+        let text: String = events
+            .iter()
+            .filter_map(|e| extract_stream_event(&json(e), &mut state))
+            .collect();
+        assert_eq!(text, "Voici la réponse");
+        assert_eq!(state.citations(), ["https://learn.microsoft.com/fr-fr/"]);
+        assert!(state.take_completed());
+        assert!(!state.take_completed());
+    }
+
+    #[test]
+    fn test_signalr_updates() {
+        let mut state = StreamState::default();
+        let update = |text: &str| {
+            json(&format!(
+                r#"{{"type":1,"target":"update","arguments":[{{"messages":[{{"text":"{}","author":"bot","messageId":"b1"}}]}}]}}"#,
+                text
+            ))
+        };
+        assert_eq!(
+            extract_stream_event(&update("Le"), &mut state).as_deref(),
+            Some("Le")
+        );
+        assert_eq!(
+            extract_stream_event(&update("Le rapport"), &mut state).as_deref(),
+            Some(" rapport")
+        );
+
+        let search = json(
+            r#"{"type":1,"target":"update","arguments":[{"messages":[{"text":"Recherche de : rapport","author":"bot","messageType":"InternalSearchQuery","messageId":"s1"}]}]}"#,
+        );
+        assert_eq!(extract_stream_event(&search, &mut state), None);
+
+        let done = json(
+            r#"{"type":2,"invocationId":"0","item":{"messages":[
+                {"text":"Résume le rapport","author":"user","messageId":"u1"},
+                {"text":"Le rapport annuel","author":"bot","messageId":"b1","sourceAttributions":[{"providerDisplayName":"x","seeMoreUrl":"https://example.org/rapport"}]}
+            ]}}"#,
+        );
+        assert_eq!(
+            extract_stream_event(&done, &mut state).as_deref(),
+            Some(" annuel")
+        );
+        assert_eq!(state.citations(), ["https://example.org/rapport"]);
+        assert!(state.take_completed());
+
+        let mut buffered = StreamState::default();
+        assert_eq!(
+            extract_response(&done, &mut buffered).as_deref(),
+            Some("Le rapport annuel")
+        );
+    }
+}
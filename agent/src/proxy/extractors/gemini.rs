@@ -0,0 +1,110 @@
+//! Gemini API answers (`generativelanguage.googleapis.com`):
+//! `generateContent` returns one `GenerateContentResponse`,
+//! `streamGenerateContent` a JSON array of them, or one per SSE event with
+//! `alt=sse`. Each chunk carries the new text only.
+
+use serde_json::Value;
+
+use super::StreamState;
+
+/// Answer of a buffered response: one response or an array of chunks
+pub fn extract_response(body: &Value, state: &mut StreamState) -> Option<String> {
+    let text: String = match body {
+        Value::Array(chunks) => chunks.iter().filter_map(|c| chunk_text(c, state)).collect(),
+        chunk => chunk_text(chunk, state)?,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+/// Text of one streamed chunk
+pub fn extract_stream_event(event: &Value, state: &mut StreamState) -> Option<String> {
+    chunk_text(event, state)
+}
+
+/// Text of the first candidate of a chunk (thought summaries excluded);
+/// cited sources of all candidates are collected in `state`
+fn chunk_text(chunk: &Value, state: &mut StreamState) -> Option<String> {
+    let candidates = chunk.get("candidates")?.as_array()?;
+    for candidate in candidates {
+        collect_citations(candidate, state);
+    }
+    if candidates.iter().any(|c| c.get("finishReason").is_some()) {
+        state.completed = true;
+    }
+
+    let text: String = candidates
+        .first()?
+        .pointer("/content/parts")?
+        .as_array()?
+        .iter()
+        .filter(|part| part.get("thought").and_then(Value::as_bool) != Some(true))
+        .filter_map(|part| part.get("text").and_then(Value::as_str))
+        .collect();
+    (!text.is_empty()).then_some(text)
+}
+
+fn collect_citations(candidate: &Value, state: &mut StreamState) {
+    let sources = [
+        "/citationMetadata/citationSources",
+        "/citationMetadata/citations",
+    ]
+    .iter()
+    .filter_map(|p| candidate.pointer(p).and_then(Value::as_array))
+    .flatten()
+    .filter_map(|s| s.get("uri").and_then(Value::as_str));
+    let grounding = candidate
+        .pointer("/groundingMetadata/groundingChunks")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|c| c.pointer("/web/uri").and_then(Value::as_str));
+    for url in sources.chain(grounding) {
+        state.add_citation(url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streamed_array() {
+        let body: Value = serde_json::from_str(
+            r#"[
+                {"candidates":[{"content":{"parts":[{"text":"Pensons...","thought":true},{"text":"La capitale"}],"role":"model"}}]},
+                {"candidates":[{"content":{"parts":[{"text":" est Yamoussoukro."}],"role":"model"},
+                    "citationMetadata":{"citationSources":[{"startIndex":0,"endIndex":20,"uri":"https://fr.wikipedia.org/wiki/Yamoussoukro"}]},
+                    "groundingMetadata":{"groundingChunks":[{"web":{"uri":"https://www.gouv.ci","title":"gouv.ci"}}]},
+                    "finishReason":"STOP"}],
+                 "usageMetadata":{"promptTokenCount":8,"candidatesTokenCount":9}}
+            ]"#,
+        )
+        .unwrap();
+        let mut state = StreamState::default();
+        assert_eq!(
+            extract_response(&body, &mut state).as_deref(),
+            Some("La capitale est Yamoussoukro.")
+        );
+        assert_eq!(
+            state.citations(),
+            [
+                "https://fr.wikipedia.org/wiki/Yamoussoukro",
+                "https://www.gouv.ci"
+            ]
+        );
+        assert!(state.take_completed());
+    }
+
+    #[test]
+    fn test_sse_chunk() {
+        let event: Value =
+            serde_json::from_str(r#"{"candidates":[{"content":{"parts":[{"text":"Bonjour"}],"role":"model"},"index":0}]}"#)
+                .unwrap();
+        let mut state = StreamState::default();
+        assert_eq!(
+            extract_stream_event(&event, &mut state).as_deref(),
+            Some("Bonjour")
+        );
+        assert!(!state.take_completed());
+    }
+}
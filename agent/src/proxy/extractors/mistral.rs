@@ -0,0 +1,139 @@
+//! Mistral answers (`api.mistral.ai`, Le Chat):
+//! - chat completions, OpenAI-compatible, except that `content` may be an
+//!   array of chunks: `{"type": "text", "text": ...}` or, with web search,
+//!   `{"type": "tool_reference", "title": ..., "url": ...}`
+//! - Conversations API: `outputs` entries of type `message.output`, streamed
+//!   as `message.output.delta` events
+
+use serde_json::Value;
+
+use super::{choice_content, StreamState};
+
+/// Answer of a buffered response
+pub fn extract_response(body: &Value, state: &mut StreamState) -> Option<String> {
+    if let Some(outputs) = body.get("outputs").and_then(Value::as_array) {
+        let text: String = outputs
+            .iter()
+            .filter(|o| o.get("type").and_then(Value::as_str) == Some("message.output"))
+            .filter_map(|o| content_text(o.get("content")?, state))
+            .collect();
+        return (!text.is_empty()).then_some(text);
+    }
+    content_text(choice_content(body, "message")?, state)
+}
+
+/// Text added by one streamed event
+pub fn extract_stream_event(event: &Value, state: &mut StreamState) -> Option<String> {
+    match event.get("type").and_then(Value::as_str) {
+        Some("message.output.delta") => content_text(event.get("content")?, state),
+        Some("conversation.response.done") => {
+            state.completed = true;
+            None
+        }
+        _ => {
+            if event
+                .pointer("/choices/0/finish_reason")
+                .is_some_and(|r| !r.is_null())
+            {
+                state.completed = true;
+            }
+            content_text(choice_content(event, "delta")?, state)
+        }
+    }
+}
+
+/// Text of a content string or chunk array; referenced sources go to `state`
+fn content_text(content: &Value, state: &mut StreamState) -> Option<String> {
+    let text = match content {
+        Value::String(text) => text.clone(),
+        Value::Array(chunks) => {
+            let mut text = String::new();
+            for chunk in chunks {
+                match chunk.get("type").and_then(Value::as_str) {
+                    Some("text") => {
+                        text.push_str(chunk.get("text").and_then(Value::as_str).unwrap_or(""))
+                    }
+                    Some("tool_reference") => {
+                        if let Some(url) = chunk.get("url").and_then(Value::as_str) {
+                            state.add_citation(url);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            text
+        }
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(s: &str) -> Value {
+        serde_json::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_chat_completion_chunks() {
+        let body = json(
+            r#"{"id":"c1","object":"chat.completion","model":"mistral-large-latest","choices":[{"index":0,"finish_reason":"stop","message":{"role":"assistant","content":[
+                {"type":"text","text":"Selon l'INSEE, "},
+                {"type":"tool_reference","tool":"web_search","title":"INSEE","url":"https://www.insee.fr/fr/statistiques"},
+                {"type":"text","text":"le chômage baisse."}
+            ]}}]}"#,
+        );
+        let mut state = StreamState::default();
+        assert_eq!(
+            extract_response(&body, &mut state).as_deref(),
+            Some("Selon l'INSEE, le chômage baisse.")
+        );
+        assert_eq!(state.citations(), ["https://www.insee.fr/fr/statistiques"]);
+
+        let plain = json(r#"{"choices":[{"message":{"role":"assistant","content":"Bonjour"}}]}"#);
+        assert_eq!(
+            extract_response(&plain, &mut StreamState::default()).as_deref(),
+            Some("Bonjour")
+        );
+    }
+
+    #[test]
+    fn test_stream_events() {
+        let mut state = StreamState::default();
+        let events = [
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":""},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"Bon"},"finish_reason":null}]}"#,
+            r#"{"choices":[{"index":0,"delta":{"content":"jour"},"finish_reason":"stop"}]}"#,
+        ];
+        let text: String = events
+            .iter()
+            .filter_map(|e| extract_stream_event(&json(e), &mut state))
+            .collect();
+        assert_eq!(text, "Bonjour");
+        assert!(state.take_completed());
+    }
+
+    #[test]
+    fn test_conversations_api() {
+        let body = json(
+            r#"{"conversation_id":"conv_1","outputs":[
+                {"type":"tool.execution","name":"web_search"},
+                {"type":"message.output","role":"assistant","content":[{"type":"text","text":"Réponse"},{"type":"tool_reference","url":"https://mistral.ai/news"}]}
+            ]}"#,
+        );
+        let mut state = StreamState::default();
+        assert_eq!(
+            extract_response(&body, &mut state).as_deref(),
+            Some("Réponse")
+        );
+        assert_eq!(state.citations(), ["https://mistral.ai/news"]);
+
+        let delta = json(r#"{"type":"message.output.delta","output_index":0,"content":"Ré"}"#);
+        assert_eq!(
+            extract_stream_event(&delta, &mut state).as_deref(),
+            Some("Ré")
+        );
+    }
+}
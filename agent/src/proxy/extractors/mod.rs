@@ -0,0 +1,140 @@
+//! Native extractors for the payload formats that JSON paths cannot
+//! describe (content-block arrays, cumulative snapshots, typed streaming
+//! events...). A platform catalog entry selects one with its `format` field.
+
+use serde_json::{Map, Value};
+
+pub mod anthropic;
+pub mod chatgpt;
+pub mod copilot;
+pub mod gemini;
+pub mod mistral;
+pub mod perplexity;
+
+/// State kept across the events of one streamed answer (or the parts of a
+/// buffered one)
+#[derive(Debug, Default)]
+pub struct StreamState {
+    /// Text of the message being streamed, as of the last event
+    snapshot: String,
+    /// Id of the message being streamed
+    message_id: Option<String>,
+    /// Whether the message being streamed is the assistant's answer
+    assistant: bool,
+    /// Path and operation of the last patch, implied by the next ones
+    patch_path: Option<String>,
+    patch_op: Option<String>,
+    /// Sources cited by the answer
+    citations: Vec<String>,
+    /// The platform signalled the end of the answer
+    completed: bool,
+}
+
+impl StreamState {
+    /// Sources cited by the answer so far (URLs, deduplicated)
+    pub fn citations(&self) -> &[String] {
+        &self.citations
+    }
+
+    /// Whether the platform signalled the end of the answer since the last
+    /// call (for channels carrying several answers, like WebSockets)
+    pub fn take_completed(&mut self) -> bool {
+        std::mem::take(&mut self.completed)
+    }
+
+    /// Forget the answer, to start the next one on the same channel
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    fn add_citation(&mut self, url: &str) {
+        let url = url.trim();
+        if !url.is_empty() && !self.citations.iter().any(|c| c == url) {
+            self.citations.push(url.to_string());
+        }
+    }
+
+    /// Start tracking message `id`, unless it is the current one.
+    /// Returns whether it is a new message.
+    fn begin_message(&mut self, id: Option<&str>) -> bool {
+        if id.is_some() && id == self.message_id.as_deref() {
+            return false;
+        }
+        self.message_id = id.map(str::to_string);
+        self.snapshot.clear();
+        true
+    }
+
+    /// Text a cumulative snapshot of the current message adds to the
+    /// previous one. A snapshot that does not extend the previous one
+    /// rewrites the message: it becomes the new reference without reporting
+    /// text twice.
+    fn snapshot_delta(&mut self, text: String) -> Option<String> {
+        let delta = text
+            .strip_prefix(self.snapshot.as_str())
+            .map(str::to_string);
+        self.snapshot = text;
+        delta.filter(|d| !d.is_empty())
+    }
+}
+
+/// Prompt of a request body in the given payload format
+pub fn extract_prompt(format: &str, body: &Value) -> Option<String> {
+    match format {
+        "anthropic" => anthropic::extract_prompt(body),
+        "chatgpt" => chatgpt::extract_prompt(body),
+        _ => None,
+    }
+}
+
+/// Details of a request worth recording with the prompt event (attached
+/// files, custom assistant...)
+pub fn extract_metadata(format: &str, body: &Value) -> Map<String, Value> {
+    match format {
+        "chatgpt" => chatgpt::extract_metadata(body),
+        _ => Map::new(),
+    }
+}
+
+/// Answer of a buffered response body in the given payload format; cited
+/// sources are collected in `state`
+pub fn extract_response(format: &str, body: &Value, state: &mut StreamState) -> Option<String> {
+    match format {
+        "anthropic" => anthropic::extract_response(body),
+        "chatgpt" => chatgpt::extract_response(body),
+        "copilot" => copilot::extract_response(body, state),
+        "gemini" => gemini::extract_response(body, state),
+        "mistral" => mistral::extract_response(body, state),
+        "perplexity" => perplexity::extract_response(body, state),
+        _ => None,
+    }
+}
+
+/// Text added by one streamed event in the given payload format
+pub fn extract_stream_event(
+    format: &str,
+    event: &Value,
+    state: &mut StreamState,
+) -> Option<String> {
+    match format {
+        "anthropic" => anthropic::extract_stream_event(event),
+        "chatgpt" => chatgpt::extract_stream_event(event, state),
+        "copilot" => copilot::extract_stream_event(event, state),
+        "gemini" => gemini::extract_stream_event(event, state),
+        "mistral" => mistral::extract_stream_event(event, state),
+        "perplexity" => perplexity::extract_stream_event(event, state),
+        _ => None,
+    }
+}
+
+/// Join the non-empty texts with blank lines; None when there are none
+fn join_texts<I: IntoIterator<Item = String>>(texts: I) -> Option<String> {
+    let texts: Vec<String> = texts.into_iter().filter(|t| !t.trim().is_empty()).collect();
+    (!texts.is_empty()).then(|| texts.join("\n\n"))
+}
+
+/// Text of the first choice of an OpenAI-compatible chat completion
+/// (`message` for complete answers, `delta` for streamed chunks)
+fn choice_content<'a>(body: &'a Value, field: &str) -> Option<&'a Value> {
+    body.get("choices")?.get(0)?.get(field)?.get("content")
+}
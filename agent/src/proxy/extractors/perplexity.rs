@@ -0,0 +1,133 @@
+//! Perplexity answers:
+//! - API (`api.perplexity.ai`): OpenAI-compatible chat completions, with the
+//!   sources in top-level `citations` and `search_results` (every streamed
+//!   chunk repeats them)
+//! - web app: cumulative snapshots, the answer in
+//!   `blocks[].markdown_block.answer` and the sources in `web_results`
+
+use serde_json::Value;
+
+use super::{choice_content, StreamState};
+
+/// Answer of a buffered response
+pub fn extract_response(body: &Value, state: &mut StreamState) -> Option<String> {
+    collect_citations(body, state);
+    if let Some(text) = choice_content(body, "message").and_then(Value::as_str) {
+        return Some(text.to_string()).filter(|t| !t.is_empty());
+    }
+    markdown_answer(body)
+}
+
+/// Text added by one streamed event
+pub fn extract_stream_event(event: &Value, state: &mut StreamState) -> Option<String> {
+    collect_citations(event, state);
+    if event
+        .pointer("/choices/0/finish_reason")
+        .is_some_and(|r| !r.is_null())
+        || event.get("status").and_then(Value::as_str) == Some("COMPLETED")
+    {
+        state.completed = true;
+    }
+
+    if let Some(delta) = choice_content(event, "delta") {
+        return delta.as_str().filter(|t| !t.is_empty()).map(str::to_string);
+    }
+    let answer = markdown_answer(event)?;
+    state.begin_message(event.get("backend_uuid").and_then(Value::as_str));
+    state.snapshot_delta(answer)
+}
+
+/// Answer of the web app's markdown blocks
+fn markdown_answer(body: &Value) -> Option<String> {
+    let text: String = body
+        .get("blocks")?
+        .as_array()?
+        .iter()
+        .filter_map(|b| b.pointer("/markdown_block/answer").and_then(Value::as_str))
+        .collect();
+    (!text.is_empty()).then_some(text)
+}
+
+fn collect_citations(body: &Value, state: &mut StreamState) {
+    let list = |field: &str| {
+        body.get(field)
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+    };
+    let citations = list("citations").filter_map(Value::as_str);
+    let results = list("search_results")
+        .chain(list("web_results"))
+        .filter_map(|r| r.get("url").and_then(Value::as_str));
+    for url in citations.chain(results) {
+        state.add_citation(url);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(s: &str) -> Value {
+        serde_json::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_api_citations() {
+        let body = json(
+            r#"{"id":"p1","model":"sonar","citations":["https://www.legifrance.gouv.fr/","https://www.service-public.fr/"],
+                "search_results":[{"title":"Service Public","url":"https://www.service-public.fr/"},{"title":"CNIL","url":"https://www.cnil.fr/"}],
+                "choices":[{"index":0,"finish_reason":"stop","message":{"role":"assistant","content":"Le RGPD s'applique [1][2]."}}]}"#,
+        );
+        let mut state = StreamState::default();
+        assert_eq!(
+            extract_response(&body, &mut state).as_deref(),
+            Some("Le RGPD s'applique [1][2].")
+        );
+        assert_eq!(
+            state.citations(),
+            [
+                "https://www.legifrance.gouv.fr/",
+                "https://www.service-public.fr/",
+                "https://www.cnil.fr/"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_api_stream() {
+        let mut state = StreamState::default();
+        let events = [
+            r#"{"citations":["https://a.example"],"choices":[{"delta":{"content":"Le "},"finish_reason":null}]}"#,
+            r#"{"citations":["https://a.example"],"choices":[{"delta":{"content":"RGPD"},"finish_reason":"stop"}]}"#,
+        ];
+        let text: String = events
+            .iter()
+            .filter_map(|e| extract_stream_event(&json(e), &mut state))
+            .collect();
+        assert_eq!(text, "Le RGPD");
+        assert_eq!(state.citations(), ["https://a.example"]);
+        assert!(state.take_completed());
+    }
+
+    #[test]
+    fn test_web_snapshots() {
+        let mut state = StreamState::default();
+        let snapshot = |answer: &str, status: &str| {
+            json(&format!(
+                r#"{{"backend_uuid":"b1","status":"{}","blocks":[{{"intended_usage":"ask_text","markdown_block":{{"answer":"{}"}}}}],"web_results":[{{"name":"CNIL","url":"https://www.cnil.fr/"}}]}}"#,
+                status, answer
+            ))
+        };
+        assert_eq!(
+            extract_stream_event(&snapshot("La CNIL", "PENDING"), &mut state).as_deref(),
+            Some("La CNIL")
+        );
+        assert_eq!(
+            extract_stream_event(&snapshot("La CNIL contrôle", "COMPLETED"), &mut state).as_deref(),
+            Some(" contrôle")
+        );
+        assert_eq!(state.citations(), ["https://www.cnil.fr/"]);
+        assert!(state.take_completed());
+    }
+}
@@ -0,0 +1,378 @@
+use std::sync::Arc;
+
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full, Limited, StreamBody};
+use hyper::body::{Body, Bytes, Frame, Incoming};
+use hyper::client::conn::http2::SendRequest;
+use hyper::header::{
+    HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, HOST,
+};
+use hyper::service::service_fn;
+use hyper::{Request, Response, StatusCode};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::proxy::block_page::{self, BlockNotice, ClientContext};
+use crate::proxy::encoding;
+use crate::proxy::interceptor::{
+    self, HostCheck, PromptVerdict, ProxyState, ResponseExchange, StreamInspector, MAX_READ_SIZE,
+};
+use crate::proxy::request_parser::{self, ParsedHttpRequest};
+use crate::proxy::stream;
+
+/// Body type of the requests and responses relayed by the HTTP/2 proxy
+type ProxyBody = BoxBody<Bytes, hyper::Error>;
+
+/// Frames buffered between the upstream stream and the client stream
+const FRAME_CHANNEL_SIZE: usize = 16;
+
+/// Proxy an HTTP/2 connection between the decrypted client and upstream
+/// streams. Each client stream is inspected and forwarded on its own
+/// upstream stream, so that multiplexed requests are not serialized.
+pub async fn proxy_http2<C, U>(
+    state: Arc<ProxyState>,
+    platform: &'static str,
+    host: String,
+    client: C,
+    upstream: U,
+) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    U: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (sender, connection) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(upstream)).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            debug!(error = %e, "Upstream HTTP/2 connection error");
+        }
+    });
+
+    let host_check = Arc::new(HostCheck::new(&host, platform));
+    let host: Arc<str> = host.into();
+    let service = service_fn(move |req: Request<Incoming>| {
+        let state = state.clone();
+        let host = host.clone();
+        let host_check = host_check.clone();
+        let sender = sender.clone();
+        async move {
+            let response =
+                match forward_stream(&state, platform, &host, &host_check, sender, req).await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        debug!(host = %host, error = %e, "HTTP/2 stream error");
+                        text_response(
+                            StatusCode::BAD_GATEWAY,
+                            "text/plain",
+                            "Upstream error".to_string(),
+                        )
+                    }
+                };
+            Ok::<_, hyper::Error>(response)
+        }
+    });
+
+    hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+        .serve_connection(TokioIo::new(client), service)
+        .await?;
+    Ok(())
+}
+
+/// Inspect one client stream, forward it upstream and relay the response
+async fn forward_stream(
+    state: &Arc<ProxyState>,
+    platform: &'static str,
+    host: &Arc<str>,
+    host_check: &HostCheck,
+    mut sender: SendRequest<ProxyBody>,
+    req: Request<Incoming>,
+) -> anyhow::Result<Response<ProxyBody>> {
+    // The :authority must name the domain the session was opened for; a
+    // request fronted to another platform is inspected as that one
+    let authority = req
+        .uri()
+        .authority()
+        .map(|a| a.to_string())
+        .or_else(|| header_str(req.headers(), HOST.as_str()))
+        .unwrap_or_default();
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "/".to_string());
+    let method = req.method().as_str().to_string();
+    let headers: Vec<(String, String)> = req
+        .headers()
+        .iter()
+        .map(|(k, v)| {
+            (
+                k.as_str().to_string(),
+                String::from_utf8_lossy(v.as_bytes()).to_string(),
+            )
+        })
+        .collect();
+
+    let Some(platform) = host_check.request_platform(state, &authority).await else {
+        let notice = BlockNotice::new(
+            interceptor::DOMAIN_BLOCK_MESSAGE,
+            interceptor::DOMAIN_BLOCK_RULE,
+            None,
+        );
+        let parsed = ParsedHttpRequest {
+            method,
+            path,
+            host: authority,
+            content_type: None,
+            headers,
+            body: Vec::new(),
+        };
+        return Ok(block_response(
+            &notice,
+            &ClientContext::from_request(platform, &parsed),
+        ));
+    };
+
+    let is_api = request_parser::is_api_endpoint(&path, platform);
+    if !is_api {
+        sender.ready().await?;
+        let response = sender.send_request(req.map(|body| body.boxed())).await?;
+        return Ok(response.map(|body| body.boxed()));
+    }
+
+    let (mut parts, body) = req.into_parts();
+    // Inspected answers are requested unencoded, so that a block notice can
+    // be appended to them
+    parts
+        .headers
+        .insert(ACCEPT_ENCODING, HeaderValue::from_static("identity"));
+    let body = Limited::new(body, MAX_READ_SIZE)
+        .collect()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to read request body: {}", e))?
+        .to_bytes();
+    let parsed = ParsedHttpRequest {
+        method: method.clone(),
+        path,
+        host: if authority.is_empty() {
+            host.to_string()
+        } else {
+            authority
+        },
+        content_type: header_str(&parts.headers, CONTENT_TYPE.as_str()),
+        headers,
+        body: body.to_vec(),
+    };
+    let client = ClientContext::from_request(platform, &parsed);
+
+    if let PromptVerdict::Block(notice) =
+        interceptor::inspect_prompt(state, platform, host, &parsed).await
+    {
+        return Ok(block_response(&notice, &client));
+    }
+
+    sender.ready().await?;
+    let response = sender
+        .send_request(Request::from_parts(parts, full(body)))
+        .await?;
+    inspect_response(
+        state.clone(),
+        platform,
+        host.clone(),
+        method,
+        client,
+        response,
+    )
+    .await
+}
+
+/// Relay an AI answer: SSE streams are inspected frame by frame and cut on a
+/// Block rule, other answers are buffered so they can be replaced
+async fn inspect_response(
+    state: Arc<ProxyState>,
+    platform: &'static str,
+    host: Arc<str>,
+    method: String,
+    client: ClientContext,
+    response: Response<Incoming>,
+) -> anyhow::Result<Response<ProxyBody>> {
+    let is_event_stream = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(stream::is_stream_content_type);
+    let (mut parts, mut body) = response.into_parts();
+    let content_encoding = header_str(&parts.headers, CONTENT_ENCODING.as_str());
+
+    if is_event_stream {
+        // The stream may be cut and extended with a block notice
+        parts.headers.remove(CONTENT_LENGTH);
+        let (tx, rx) = mpsc::channel(FRAME_CHANNEL_SIZE);
+        tokio::spawn(async move {
+            let exchange = ResponseExchange {
+                platform,
+                host: &host,
+                method: &method,
+                inspect: true,
+                client: Some(&client),
+            };
+            let inspector = StreamInspector::new(content_encoding.as_deref(), platform);
+            let encoded = encoding::is_encoded(content_encoding.as_deref());
+            pump_event_stream(&state, &exchange, inspector, encoded, body, tx).await;
+        });
+        return Ok(Response::from_parts(parts, channel_body(rx)));
+    }
+
+    // --- Non-streamed answer: buffer it so it can be replaced if blocked ---
+    let mut buffered = Vec::new();
+    while let Some(frame) = body.frame().await {
+        let frame = frame?;
+        if let Some(data) = frame.data_ref() {
+            buffered.extend_from_slice(data);
+        }
+
+        if buffered.len() > MAX_READ_SIZE {
+            // Too large to inspect: relay what we have, then the rest
+            debug!(host = %host, "Response too large to inspect, relaying as-is");
+            let (tx, rx) = mpsc::channel(FRAME_CHANNEL_SIZE);
+            tokio::spawn(async move {
+                if tx
+                    .send(Ok(Frame::data(Bytes::from(buffered))))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+                while let Some(frame) = body.frame().await {
+                    if tx.send(frame).await.is_err() {
+                        return;
+                    }
+                }
+            });
+            return Ok(Response::from_parts(parts, channel_body(rx)));
+        }
+    }
+
+    let exchange = ResponseExchange {
+        platform,
+        host: &host,
+        method: &method,
+        inspect: true,
+        client: Some(&client),
+    };
+    let answer = encoding::decode_body(&buffered, content_encoding.as_deref())
+        .and_then(|body| request_parser::extract_response(&body, platform));
+    if let Some(answer) = answer {
+        if let Some(notice) =
+            interceptor::log_response(&state, &exchange, &answer.text, &answer.metadata(), None)
+                .await
+        {
+            return Ok(block_response(&notice, &client));
+        }
+    }
+    Ok(Response::from_parts(parts, full(Bytes::from(buffered))))
+}
+
+/// Forward the frames of a streamed answer to the client while inspecting
+/// them; on a Block rule, send the block notice and end the stream. A body
+/// still content-encoded is ended without notice.
+async fn pump_event_stream(
+    state: &ProxyState,
+    exchange: &ResponseExchange<'_>,
+    mut inspector: StreamInspector,
+    encoded: bool,
+    mut body: Incoming,
+    tx: mpsc::Sender<Result<Frame<Bytes>, hyper::Error>>,
+) {
+    let mut finished = false;
+
+    while let Some(frame) = body.frame().await {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(e) => {
+                let _ = tx.send(Err(e)).await;
+                return;
+            }
+        };
+
+        if let Some(data) = frame.data_ref() {
+            inspector.push(data);
+
+            // Last data frame: final evaluation before it is forwarded
+            let verdict = if body.is_end_stream() {
+                finished = true;
+                inspector.finish(state, exchange).await
+            } else {
+                inspector.check(state, exchange).await
+            };
+            if let Some(notice) = verdict {
+                // Dropping the sender ends the client stream after the notice
+                if !encoded {
+                    let notice =
+                        block_page::build_stream_notice(&notice, &exchange.client_context());
+                    let _ = tx.send(Ok(Frame::data(Bytes::from(notice)))).await;
+                }
+                return;
+            }
+        }
+
+        if tx.send(Ok(frame)).await.is_err() {
+            // Client reset the stream
+            return;
+        }
+    }
+
+    if !finished {
+        if let Some(notice) = inspector.finish(state, exchange).await {
+            if !encoded {
+                let notice = block_page::build_stream_notice(&notice, &exchange.client_context());
+                let _ = tx.send(Ok(Frame::data(Bytes::from(notice)))).await;
+            }
+        }
+    }
+}
+
+/// Body fed by frames sent on a channel; ends when the sender is dropped
+fn channel_body(rx: mpsc::Receiver<Result<Frame<Bytes>, hyper::Error>>) -> ProxyBody {
+    let frames = futures_util::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|frame| (frame, rx))
+    });
+    StreamBody::new(frames).boxed()
+}
+
+fn full(data: Bytes) -> ProxyBody {
+    Full::new(data).map_err(|never| match never {}).boxed()
+}
+
+fn header_str(headers: &hyper::HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+fn text_response(status: StatusCode, content_type: &str, body: String) -> Response<ProxyBody> {
+    let mut response = Response::new(full(Bytes::from(body)));
+    *response.status_mut() = status;
+    if let Ok(value) = content_type.parse() {
+        response.headers_mut().insert(CONTENT_TYPE, value);
+    }
+    response
+}
+
+fn block_response(notice: &BlockNotice, client: &ClientContext) -> Response<ProxyBody> {
+    let rendered = block_page::render(notice, client);
+    let status = StatusCode::from_u16(rendered.status).unwrap_or(StatusCode::FORBIDDEN);
+    let mut response = text_response(status, rendered.content_type, rendered.body);
+    let headers = response.headers_mut();
+    headers.insert(
+        "x-icon-blocked",
+        hyper::header::HeaderValue::from_static("true"),
+    );
+    headers.insert(
+        hyper::header::CACHE_CONTROL,
+        hyper::header::HeaderValue::from_static("no-store"),
+    );
+    response
+}
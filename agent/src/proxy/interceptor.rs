@@ -1,32 +1,121 @@
 use std::sync::Arc;
 
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use serde_json::json;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpListener;
-use tracing::{debug, info};
+use tokio_rustls::rustls::server::Acceptor;
+use tokio_rustls::LazyConfigAcceptor;
+use tracing::{debug, error, info, warn};
 
+use crate::clipboard::correlation::ClipboardHistory;
 use crate::config::AppConfig;
+use crate::proxy::account;
+use crate::proxy::api_keys;
+use crate::proxy::approved_ai::{self, ApiEndpoint, ApprovedAi};
+use crate::proxy::block_page::{self, BlockNotice, ClientContext};
+use crate::proxy::chunked;
+use crate::proxy::conversation::ConversationTracker;
 use crate::proxy::domain_filter::DomainFilter;
+use crate::proxy::encoding::{self, StreamDecoder};
+use crate::proxy::http2;
 use crate::proxy::request_parser;
-use crate::proxy::tls::CaManager;
+use crate::proxy::sni::{self, ClientHello};
+use crate::proxy::stream::{self, BodyFraming, BodyReader, ResponseHead, SseAccumulator};
+use crate::proxy::tls::{CaManager, ALPN_H2, ALPN_HTTP1};
+use crate::proxy::transparent;
+use crate::proxy::upstream;
+use crate::proxy::websocket;
 use crate::rules::engine::RuleEngine;
-use crate::rules::models::{EvaluationResult, RuleTarget};
+use crate::rules::models::{EvaluationContext, EvaluationResult, RuleTarget, ThresholdMetric};
 use crate::sync::queue::EventQueue;
 
 /// Maximum size we'll read from a single HTTP message (16 MB)
-const MAX_READ_SIZE: usize = 16 * 1024 * 1024;
+pub const MAX_READ_SIZE: usize = 16 * 1024 * 1024;
 
 /// Initial read buffer size (64 KB)
 const INITIAL_BUF_SIZE: usize = 64 * 1024;
 
+/// Block page shown for a blocked AI platform
+pub const DOMAIN_BLOCK_MESSAGE: &str =
+    "L'accès à cette plateforme IA est interdit par la politique de sécurité GS2E.";
+pub const DOMAIN_BLOCK_RULE: &str = "Blocage de domaine";
+
+/// How long a tunnel waits for the client to speak first (TLS ClientHello)
+/// before it is relayed as-is, for server-first protocols (SMTP, SSH...)
+const CLIENT_HELLO_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Streamed responses are re-evaluated each time this much new text has
+/// been accumulated; the step then grows with the text (a fraction of the
+/// text already evaluated), so that a long answer is not evaluated over and
+/// over
+const STREAM_EVAL_STEP: usize = 512;
+
+/// Shared state handed to every proxied connection
+pub struct ProxyState {
+    rule_engine: Arc<RuleEngine>,
+    event_queue: Arc<EventQueue>,
+    domain_filter: Arc<DomainFilter>,
+    ca_manager: Arc<CaManager>,
+    /// Conversation state is shared by all connections: a conversation can
+    /// span several TLS connections from the same browser.
+    conversations: ConversationTracker,
+    /// Recently copied sensitive clipboard content (hashed), for correlation
+    clipboard_history: Arc<ClipboardHistory>,
+    proxy_port: u16,
+}
+
+/// Outcome of the inspection of an intercepted prompt
+pub enum PromptVerdict {
+    /// Forward the request upstream
+    Forward,
+    /// Answer the client with a block response instead of forwarding
+    Block(BlockNotice),
+}
+
+/// Context of an upstream response being relayed to the client
+pub struct ResponseExchange<'a> {
+    pub platform: &'static str,
+    pub host: &'a str,
+    /// Method of the request the response answers (HEAD has no body)
+    pub method: &'a str,
+    /// Whether the response carries an AI answer to inspect
+    pub inspect: bool,
+    /// Client of the request, to answer it in a form it displays when the
+    /// answer is blocked
+    pub client: Option<&'a ClientContext>,
+}
+
+impl ResponseExchange<'_> {
+    /// Client the answer is relayed to; an API client when the request is
+    /// unknown
+    pub fn client_context(&self) -> ClientContext {
+        self.client
+            .cloned()
+            .unwrap_or_else(|| ClientContext::api(self.platform, false))
+    }
+}
+
+/// What to do with a client connection after relaying a response
+#[derive(Debug, PartialEq)]
+enum RelayOutcome {
+    /// Read the next request
+    KeepAlive,
+    /// Close the connection
+    Close,
+    /// The connection was upgraded to a WebSocket, with the extensions
+    /// accepted by the server (`Sec-WebSocket-Extensions`)
+    WebSocket { extensions: Option<String> },
+}
+
 /// Start the local MITM proxy that intercepts AI platform traffic
 pub async fn start_proxy(
     config: AppConfig,
     rule_engine: Arc<RuleEngine>,
     event_queue: Arc<EventQueue>,
     domain_filter: Arc<DomainFilter>,
+    clipboard_history: Arc<ClipboardHistory>,
 ) -> anyhow::Result<()> {
     let bind_addr = format!("127.0.0.1:{}", config.proxy_port);
-    let proxy_port = config.proxy_port;
     let listener = TcpListener::bind(&bind_addr).await?;
     info!(addr = %bind_addr, "MITM proxy listening");
 
@@ -34,17 +123,34 @@ pub async fn start_proxy(
     let ca_manager = CaManager::load_or_create(&config.data_dir)?;
     info!("TLS CA manager initialized");
 
+    let state = Arc::new(ProxyState {
+        rule_engine,
+        event_queue,
+        domain_filter,
+        ca_manager,
+        conversations: ConversationTracker::new(),
+        clipboard_history,
+        proxy_port: config.proxy_port,
+    });
+
+    if config.transparent_mode {
+        let state = state.clone();
+        let port = config.transparent_port;
+        tokio::spawn(async move {
+            if let Err(e) = run_transparent_listener(state, port).await {
+                error!(error = %e, "Transparent interception listener failed");
+            }
+        });
+    }
+
     loop {
         let (stream, peer_addr) = listener.accept().await?;
         debug!(%peer_addr, "New connection");
 
-        let re = rule_engine.clone();
-        let eq = event_queue.clone();
-        let df = domain_filter.clone();
-        let ca = ca_manager.clone();
+        let state = state.clone();
 
         tokio::spawn(async move {
-            if let Err(e) = handle_connection(stream, re, eq, df, ca, proxy_port).await {
+            if let Err(e) = handle_connection(stream, state).await {
                 debug!(error = %e, "Connection handling error");
             }
         });
@@ -54,12 +160,10 @@ pub async fn start_proxy(
 /// Handle a single proxied connection (HTTP CONNECT tunnel or plain HTTP)
 async fn handle_connection(
     mut client_stream: tokio::net::TcpStream,
-    rule_engine: Arc<RuleEngine>,
-    event_queue: Arc<EventQueue>,
-    domain_filter: Arc<DomainFilter>,
-    ca_manager: Arc<CaManager>,
-    proxy_port: u16,
+    state: Arc<ProxyState>,
 ) -> anyhow::Result<()> {
+    let domain_filter = &state.domain_filter;
+
     // Read the initial request (CONNECT for HTTPS, or plain HTTP)
     let mut buf = vec![0u8; 8192];
     let n = client_stream.read(&mut buf).await?;
@@ -70,7 +174,7 @@ async fn handle_connection(
 
     // --- Serve PAC file for direct HTTP GET /proxy.pac requests ---
     if is_pac_request(&request_line) {
-        let pac_body = domain_filter.generate_pac(proxy_port).await;
+        let pac_body = domain_filter.generate_pac(state.proxy_port).await;
         let response = format!(
             "HTTP/1.1 200 OK\r\n\
              Content-Type: application/x-ns-proxy-autoconfig\r\n\
@@ -87,49 +191,434 @@ async fn handle_connection(
         return Ok(());
     }
 
+    // --- Plain HTTP request in absolute form (http://host/path) ---
+    if parse_absolute_target(&request_line).is_some() {
+        // Replay the bytes already read in front of the client stream
+        let (reader, writer) = client_stream.into_split();
+        let client = tokio::io::join((&buf[..n]).chain(reader), writer);
+        return proxy_plain_http(&state, client).await;
+    }
+
     // Parse CONNECT host:port
-    let (host, port) = parse_connect_target(&request_line)
+    let (connect_host, port) = parse_connect_target(&request_line)
         .ok_or_else(|| anyhow::anyhow!("Failed to parse CONNECT target"))?;
+    let connect_host = connect_host.trim_end_matches('.').to_ascii_lowercase();
+    let target_addr = format!("{}:{}", connect_host, port);
 
-    // --- Not an AI domain: tunnel directly without inspection ---
-    if !domain_filter.should_intercept(&host).await {
-        return tunnel_direct(&mut client_stream, &host, port).await;
+    // Accept the tunnel, then peek the ClientHello: its SNI names the host
+    // the client really talks to, which may differ from the CONNECT target
+    // (domain fronting)
+    client_stream
+        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+        .await?;
+    let Some((mut client, hello)) = peek_client_hello(client_stream).await? else {
+        return Ok(());
+    };
+    let sni = match hello {
+        ClientHello::Parsed { server_name } => server_name,
+        // Not TLS: nothing to decrypt
+        _ => return tunnel_direct(&mut client, &target_addr).await,
+    };
+
+    let connect_monitored = domain_filter.should_intercept(&connect_host).await;
+    let sni_monitored = match &sni {
+        Some(name) => domain_filter.should_intercept(name).await,
+        None => false,
+    };
+    if let Some(name) = sni.as_deref().filter(|name| *name != connect_host) {
+        if connect_monitored || sni_monitored {
+            log_host_mismatch(&state, "sni", &connect_host, name).await;
+        }
+    }
+
+    // --- Neither host is an AI domain: tunnel directly without inspection ---
+    if !connect_monitored && !sni_monitored {
+        return tunnel_direct(&mut client, &target_addr).await;
     }
 
+    // The forged certificate must match the name the client validates
+    let host = sni.unwrap_or_else(|| connect_host.clone());
+
     // --- Domain is fully blocked ---
-    if domain_filter.is_blocked(&host).await {
-        let blocked = request_parser::build_block_response(
-            "L'accès à cette plateforme IA est interdit par la politique de sécurité GS2E.",
-            "Blocage de domaine",
-        );
-        client_stream
-            .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
-            .await?;
+    if domain_filter.is_blocked(&connect_host).await || domain_filter.is_blocked(&host).await {
+        return serve_domain_block(&state, client, &host).await;
+    }
+
+    // --- Domain is monitored: perform full MITM TLS interception ---
+    intercept_tls(state, client, host, &target_addr).await
+}
+
+/// Client stream with the bytes already read from it replayed first
+type ReplayStream = tokio::io::Join<
+    tokio::io::Chain<std::io::Cursor<Vec<u8>>, tokio::net::tcp::OwnedReadHalf>,
+    tokio::net::tcp::OwnedWriteHalf,
+>;
+
+/// Read the TLS ClientHello a client sends, without consuming it: the
+/// returned stream replays the bytes read. None if the client closed the
+/// connection first; `Incomplete` if it sent no full ClientHello within
+/// `CLIENT_HELLO_TIMEOUT` (the server may be expected to speak first).
+async fn peek_client_hello(
+    mut stream: tokio::net::TcpStream,
+) -> anyhow::Result<Option<(ReplayStream, ClientHello)>> {
+    let mut prefix = Vec::new();
+    let mut buf = vec![0u8; 4096];
+    let deadline = tokio::time::Instant::now() + CLIENT_HELLO_TIMEOUT;
+    let hello = loop {
+        let n = match tokio::time::timeout_at(deadline, stream.read(&mut buf)).await {
+            Ok(read) => read?,
+            Err(_) => break ClientHello::Incomplete,
+        };
+        if n == 0 {
+            return Ok(None);
+        }
+        prefix.extend_from_slice(&buf[..n]);
+        match sni::parse_client_hello(&prefix) {
+            ClientHello::Incomplete if prefix.len() < sni::MAX_CLIENT_HELLO_SIZE => continue,
+            other => break other,
+        }
+    };
+
+    let (reader, writer) = stream.into_split();
+    let client = tokio::io::join(std::io::Cursor::new(prefix).chain(reader), writer);
+    Ok(Some((client, hello)))
+}
+
+/// Log an evasion event: the host name declared at one layer (CONNECT
+/// target, TLS SNI) differs from the one used at the next (SNI, HTTP Host)
+async fn log_host_mismatch(state: &ProxyState, check: &str, declared: &str, actual: &str) {
+    warn!(
+        check,
+        declared, actual, "Host mismatch between protocol layers"
+    );
+    let platform = request_parser::identify_platform(actual)
+        .or_else(|| request_parser::identify_platform(declared))
+        .unwrap_or("unknown");
+    let metadata = json!({
+        "check": check,
+        "declared_host": declared,
+        "actual_host": actual,
+    })
+    .to_string();
+    state
+        .event_queue
+        .log_event_with_metadata(
+            "evasion",
+            Some(platform),
+            Some(actual),
+            None,
+            None,
+            None,
+            None,
+            Some("high"),
+            Some(&metadata),
+        )
+        .await;
+}
+
+/// Checks the Host of the requests decrypted on a TLS session against the
+/// session's host name (SNI); each mismatch is reported once per session
+pub struct HostCheck {
+    tls_host: String,
+    platform: &'static str,
+    reported: std::sync::atomic::AtomicBool,
+}
 
-        // We need to do a TLS handshake to deliver the block page over HTTPS
-        let acceptor = ca_manager.make_tls_acceptor(&host).await?;
-        let mut tls_client = acceptor.accept(client_stream).await?;
+impl HostCheck {
+    pub fn new(tls_host: &str, platform: &'static str) -> Self {
+        Self {
+            tls_host: tls_host.to_ascii_lowercase(),
+            platform,
+            reported: std::sync::atomic::AtomicBool::new(false),
+        }
+    }
+
+    /// Check the Host header (or `:authority`) of a request; returns the
+    /// platform the request is inspected as (the one the Host names, if
+    /// monitored), or None if it names a blocked domain
+    pub async fn request_platform(
+        &self,
+        state: &ProxyState,
+        host_header: &str,
+    ) -> Option<&'static str> {
+        let host = strip_port(host_header)
+            .trim_end_matches('.')
+            .to_ascii_lowercase();
+        if host.is_empty() || host == self.tls_host {
+            return Some(self.platform);
+        }
+        if !self
+            .reported
+            .swap(true, std::sync::atomic::Ordering::Relaxed)
+        {
+            log_host_mismatch(state, "host_header", &self.tls_host, &host).await;
+        }
+        if state.domain_filter.is_blocked(&host).await {
+            return None;
+        }
+        Some(request_parser::identify_platform(&host).unwrap_or(self.platform))
+    }
+}
+
+/// Host part of a `host[:port]` authority
+fn strip_port(authority: &str) -> &str {
+    if let Some(rest) = authority.strip_prefix('[') {
+        // IPv6 literal
+        return rest.split(']').next().unwrap_or(rest);
+    }
+    match authority.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => authority,
+    }
+}
+
+/// Answer a client opening a TLS session to a blocked domain with the block
+/// page (a TLS handshake is needed to deliver it over HTTPS), or redirect its
+/// request to the approved AI
+async fn serve_domain_block<C>(state: &ProxyState, client: C, host: &str) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+{
+    let platform = request_parser::identify_platform(host).unwrap_or("unknown");
+    let acceptor = state.ca_manager.make_tls_acceptor(host).await?;
+    let mut tls_client = acceptor.accept(client).await?;
+
+    let approved = approved_ai::current();
+    if approved.is_enabled() {
+        // The request tells a navigation from an API call
+        let request_data = read_http_message(&mut tls_client).await?;
+        if !request_data.is_empty() {
+            answer_blocked_request(
+                state,
+                &approved,
+                platform,
+                host,
+                &request_data,
+                &mut tls_client,
+            )
+            .await?;
+        }
+    } else {
+        let notice = BlockNotice::new(DOMAIN_BLOCK_MESSAGE, DOMAIN_BLOCK_RULE, None);
+        let blocked = block_page::build_block_response(&notice, &ClientContext::browser(platform));
         tls_client.write_all(&blocked).await?;
-        tls_client.shutdown().await?;
+        log_domain_block(
+            state,
+            platform,
+            host,
+            None,
+            json!({ "incident_id": notice.incident_id }),
+        )
+        .await;
+    }
+    tls_client.shutdown().await?;
+    Ok(())
+}
 
-        let platform = request_parser::identify_platform(&host).unwrap_or("unknown");
-        event_queue
-            .log_event("domain_block", Some(platform), Some(&host), None, None, None, None, Some("critical"))
+/// Answer a request (origin form) to a blocked AI platform: navigations are
+/// redirected to the approved assistant and OpenAI-compatible API calls are
+/// rerouted to the approved endpoint when configured; anything else gets the
+/// block response.
+async fn answer_blocked_request<C>(
+    state: &ProxyState,
+    approved: &ApprovedAi,
+    platform: &'static str,
+    host: &str,
+    request_data: &[u8],
+    client: &mut C,
+) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+{
+    let parsed = request_parser::parse_raw_request(request_data);
+    let client_context = parsed
+        .as_ref()
+        .map(|r| ClientContext::from_request(platform, r))
+        .unwrap_or_else(|| ClientContext::browser(platform));
+
+    if let Some(req) = &parsed {
+        if let Some(endpoint) = approved.reroute_target(req) {
+            return reroute_request(
+                state,
+                endpoint,
+                platform,
+                host,
+                req,
+                &client_context,
+                client,
+            )
             .await;
+        }
+        let location = approved
+            .redirect_location(req)
+            .filter(|_| client_context.html && req.method == "GET");
+        if let Some(location) = location {
+            client
+                .write_all(&approved_ai::build_redirect(&location))
+                .await?;
+            info!(host = %host, "Navigation to blocked platform redirected to the approved assistant");
+            let prompt = approved_ai::navigation_prompt(req);
+            let metadata = json!({ "action": "redirect", "redirect_url": approved.assistant_url });
+            log_domain_block(state, platform, host, prompt.as_deref(), metadata).await;
+            return Ok(());
+        }
+    }
+
+    let notice = BlockNotice::new(DOMAIN_BLOCK_MESSAGE, DOMAIN_BLOCK_RULE, None);
+    client
+        .write_all(&block_page::build_block_response(&notice, &client_context))
+        .await?;
+    log_domain_block(
+        state,
+        platform,
+        host,
+        None,
+        json!({ "incident_id": notice.incident_id }),
+    )
+    .await;
+    Ok(())
+}
+
+/// Forward an OpenAI-compatible API call to a blocked platform to the
+/// approved endpoint, with the usual prompt and answer inspection
+async fn reroute_request<C>(
+    state: &ProxyState,
+    endpoint: &ApiEndpoint,
+    platform: &'static str,
+    host: &str,
+    req: &request_parser::ParsedHttpRequest,
+    client_context: &ClientContext,
+    client: &mut C,
+) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+{
+    if let PromptVerdict::Block(notice) = inspect_prompt(state, platform, host, req).await {
+        client
+            .write_all(&block_page::build_block_response(&notice, client_context))
+            .await?;
         return Ok(());
     }
 
-    // --- Domain is monitored: perform full MITM TLS interception ---
+    info!(host = %host, endpoint = %endpoint.host, "API call to blocked platform rerouted to the approved endpoint");
+    let metadata = json!({ "action": "reroute", "rerouted_to": endpoint.authority() });
+    log_domain_block(state, platform, host, None, metadata).await;
+
+    let connection = if endpoint.tls {
+        upstream::connect(&endpoint.authority()).await.map(Err)
+    } else {
+        upstream::connect_http(&endpoint.authority()).await.map(Ok)
+    };
+    let connection = match connection {
+        Ok(connection) => connection,
+        Err(e) => {
+            debug!(endpoint = %endpoint.host, error = %e, "Failed to connect to the approved endpoint");
+            client
+                .write_all(
+                    b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+    let request = endpoint.rewrite(req);
+    let exchange = ResponseExchange {
+        platform,
+        host: &endpoint.host,
+        method: &req.method,
+        inspect: true,
+        client: Some(client_context),
+    };
+    match connection {
+        Err(upstream_tcp) => {
+            let connector = state.ca_manager.make_tls_connector(&[ALPN_HTTP1]);
+            let mut upstream = connector
+                .connect(CaManager::server_name(&endpoint.host)?, upstream_tcp)
+                .await?;
+            upstream.write_all(&request).await?;
+            relay_response(state, &exchange, &mut upstream, client).await?;
+        }
+        Ok(mut http) => {
+            let request = http.request(&request, &endpoint.authority());
+            http.stream.write_all(&request).await?;
+            relay_response(state, &exchange, &mut http.stream, client).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Log a request to a blocked AI platform, with what was done with it
+async fn log_domain_block(
+    state: &ProxyState,
+    platform: &str,
+    host: &str,
+    prompt: Option<&str>,
+    metadata: serde_json::Value,
+) {
+    let prompt = prompt.map(|p| request_parser::truncate(p, 500));
+    state
+        .event_queue
+        .log_event_with_metadata(
+            "domain_block",
+            Some(platform),
+            Some(host),
+            None,
+            prompt.as_deref(),
+            None,
+            None,
+            Some("critical"),
+            Some(&metadata.to_string()),
+        )
+        .await;
+}
+
+/// Full MITM interception of a TLS session to a monitored domain: `client`
+/// is about to send its ClientHello, `target_addr` is the upstream server.
+async fn intercept_tls<C>(
+    state: Arc<ProxyState>,
+    client: C,
+    host: String,
+    target_addr: &str,
+) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let ca_manager = &state.ca_manager;
     let platform = request_parser::identify_platform(&host).unwrap_or("unknown");
 
-    // Step 1: Respond 200 to the CONNECT request
-    client_stream
-        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+    // Step 1: Read the client's TLS ClientHello to learn the protocols it offers
+    let start = match LazyConfigAcceptor::new(Acceptor::default(), client).await {
+        Ok(s) => s,
+        Err(e) => {
+            debug!(host = %host, error = %e, "Failed to read client TLS hello");
+            return Ok(());
+        }
+    };
+    let client_offers_h2 = start
+        .client_hello()
+        .alpn()
+        .is_some_and(|mut protocols| protocols.any(|p| p == ALPN_H2));
+
+    // Step 2: Connect to the UPSTREAM server, offering h2 only if the client
+    // can speak it, so that both legs end up on the same protocol
+    let upstream_tcp = upstream::connect(target_addr).await?;
+    let alpn: &[&[u8]] = if client_offers_h2 {
+        &[ALPN_H2, ALPN_HTTP1]
+    } else {
+        &[ALPN_HTTP1]
+    };
+    let connector = ca_manager.make_tls_connector(alpn);
+    let server_name = CaManager::server_name(&host)?;
+    let tls_upstream = connector.connect(server_name, upstream_tcp).await?;
+    let use_h2 = tls_upstream.get_ref().1.alpn_protocol() == Some(ALPN_H2);
+    debug!(host = %host, h2 = use_h2, "TLS handshake with upstream completed");
+
+    // Step 3: Finish the handshake with the CLIENT using our forged
+    // certificate and the protocol negotiated upstream
+    let server_config = ca_manager
+        .get_server_config_with_alpn(&host, if use_h2 { ALPN_H2 } else { ALPN_HTTP1 })
         .await?;
-
-    // Step 2: TLS handshake with the CLIENT using our forged certificate
-    let acceptor = ca_manager.make_tls_acceptor(&host).await?;
-    let mut tls_client = match acceptor.accept(client_stream).await {
+    let tls_client = match start.into_stream(server_config).await {
         Ok(s) => s,
         Err(e) => {
             debug!(host = %host, error = %e, "TLS handshake with client failed");
@@ -138,16 +627,95 @@ async fn handle_connection(
     };
     debug!(host = %host, "TLS handshake with client completed");
 
-    // Step 3: Connect to the UPSTREAM server and perform TLS handshake
-    let target_addr = format!("{}:{}", host, port);
-    let upstream_tcp = tokio::net::TcpStream::connect(&target_addr).await?;
-    let connector = ca_manager.make_tls_connector();
-    let server_name = CaManager::server_name(&host)?;
-    let mut tls_upstream = connector.connect(server_name, upstream_tcp).await?;
-    debug!(host = %host, "TLS handshake with upstream completed");
-
     // Step 4: Bidirectional proxying with inspection
-    // We process request/response pairs in a loop (HTTP/1.1 keep-alive)
+    if use_h2 {
+        http2::proxy_http2(state, platform, host, tls_client, tls_upstream).await
+    } else {
+        proxy_http1(&state, platform, &host, tls_client, tls_upstream).await
+    }
+}
+
+/// Accept connections redirected by the transparent interception rules
+/// (Linux, see `transparent`)
+async fn run_transparent_listener(state: Arc<ProxyState>, port: u16) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    // IPv6 connections are redirected to ::1
+    let listener_v6 = match TcpListener::bind(("::1", port)).await {
+        Ok(l) => Some(l),
+        Err(e) => {
+            debug!(error = %e, "IPv6 transparent listener unavailable");
+            None
+        }
+    };
+    info!(port, "Transparent interception listening");
+
+    loop {
+        let accepted = match &listener_v6 {
+            Some(v6) => tokio::select! {
+                r = listener.accept() => r,
+                r = v6.accept() => r,
+            },
+            None => listener.accept().await,
+        };
+        let (stream, peer_addr) = accepted?;
+        debug!(%peer_addr, "New transparent connection");
+
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_transparent_connection(stream, state).await {
+                debug!(error = %e, "Transparent connection handling error");
+            }
+        });
+    }
+}
+
+/// Handle a connection redirected by the firewall: the original destination
+/// comes from `SO_ORIGINAL_DST` and the host name from the TLS SNI
+async fn handle_transparent_connection(
+    client_stream: tokio::net::TcpStream,
+    state: Arc<ProxyState>,
+) -> anyhow::Result<()> {
+    let original = transparent::original_destination(&client_stream)?;
+    let target_addr = original.to_string();
+
+    let Some((mut client, hello)) = peek_client_hello(client_stream).await? else {
+        return Ok(());
+    };
+    let host = match hello {
+        ClientHello::Parsed {
+            server_name: Some(name),
+        } => name,
+        ClientHello::Parsed { server_name: None } => original.ip().to_string(),
+        // Not TLS: relay to the original destination
+        _ => return tunnel_direct(&mut client, &target_addr).await,
+    };
+
+    let domain_filter = &state.domain_filter;
+    if !domain_filter.should_intercept(&host).await {
+        return tunnel_direct(&mut client, &target_addr).await;
+    }
+    if domain_filter.is_blocked(&host).await {
+        return serve_domain_block(&state, client, &host).await;
+    }
+    debug!(host = %host, %original, "Transparent TLS interception");
+    intercept_tls(state, client, host, &target_addr).await
+}
+
+/// Proxy HTTP/1.1 request/response pairs between the decrypted client and
+/// upstream streams (keep-alive loop)
+async fn proxy_http1<C, U>(
+    state: &ProxyState,
+    platform: &'static str,
+    host: &str,
+    mut tls_client: C,
+    mut tls_upstream: U,
+) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    U: AsyncRead + AsyncWrite + Unpin,
+{
+    let host_check = HostCheck::new(host, platform);
+
     loop {
         // --- Read the HTTP request from the client ---
         let request_data = match read_http_message(&mut tls_client).await {
@@ -159,6 +727,24 @@ async fn handle_connection(
         // Parse the HTTP request
         let parsed_request = request_parser::parse_raw_request(&request_data);
 
+        // The Host header must name the domain the session was opened for;
+        // a request fronted to another platform is inspected as that one
+        let platform = match parsed_request {
+            Some(ref req) => match host_check.request_platform(state, &req.host).await {
+                Some(platform) => platform,
+                None => {
+                    let notice = BlockNotice::new(DOMAIN_BLOCK_MESSAGE, DOMAIN_BLOCK_RULE, None);
+                    let blocked = block_page::build_block_response(
+                        &notice,
+                        &ClientContext::from_request(platform, req),
+                    );
+                    tls_client.write_all(&blocked).await?;
+                    continue;
+                }
+            },
+            None => platform,
+        };
+
         // Determine if this is an API endpoint that carries prompts
         let is_api = parsed_request
             .as_ref()
@@ -167,163 +753,66 @@ async fn handle_connection(
 
         if is_api {
             if let Some(ref req) = parsed_request {
-                // Extract the prompt from the request body
-                let prompt = request_parser::extract_prompt(&req.body, platform);
-
-                if let Some(ref prompt_text) = prompt {
-                    info!(
-                        host = %host,
-                        platform,
-                        path = %req.path,
-                        prompt_len = prompt_text.len(),
-                        "Intercepted AI prompt"
-                    );
-
-                    // --- Evaluate the prompt against the rule engine ---
-                    let result = rule_engine.evaluate(prompt_text, RuleTarget::Prompt).await;
-
-                    match result {
-                        EvaluationResult::Blocked {
-                            rule_id,
-                            rule_name,
-                            message,
-                        } => {
-                            info!(%rule_name, "BLOCKED prompt");
-
-                            // Send block page to client
-                            let block_response =
-                                request_parser::build_block_response(&message, &rule_name);
-                            tls_client.write_all(&block_response).await?;
-
-                            // Log the blocked event
-                            let hash = request_parser::content_hash(&req.body);
-                            event_queue
-                                .log_event(
-                                    "block",
-                                    Some(platform),
-                                    Some(&host),
-                                    Some(&hash),
-                                    Some(&request_parser::truncate(prompt_text, 500)),
-                                    None,
-                                    Some(&rule_id),
-                                    Some("critical"),
-                                )
-                                .await;
-
-                            // Don't forward to upstream — continue to next request
-                            continue;
-                        }
-                        EvaluationResult::Alerted {
-                            rule_id,
-                            rule_name,
-                            severity,
-                        } => {
-                            info!(%rule_name, "Alert on prompt, forwarding anyway");
-                            let hash = request_parser::content_hash(&req.body);
-                            let sev = format!("{:?}", severity).to_lowercase();
-                            event_queue
-                                .log_event(
-                                    "alert",
-                                    Some(platform),
-                                    Some(&host),
-                                    Some(&hash),
-                                    Some(&request_parser::truncate(prompt_text, 500)),
-                                    None,
-                                    Some(&rule_id),
-                                    Some(&sev),
-                                )
-                                .await;
-                            // Fall through to forward
-                        }
-                        EvaluationResult::Logged { rule_id } => {
-                            let hash = request_parser::content_hash(&req.body);
-                            event_queue
-                                .log_event(
-                                    "prompt",
-                                    Some(platform),
-                                    Some(&host),
-                                    Some(&hash),
-                                    Some(&request_parser::truncate(prompt_text, 500)),
-                                    None,
-                                    rule_id.as_deref(),
-                                    Some("info"),
-                                )
-                                .await;
-                        }
-                        EvaluationResult::NoMatch => {
-                            // Log the prompt even if no rule matched
-                            let hash = request_parser::content_hash(&req.body);
-                            event_queue
-                                .log_event(
-                                    "prompt",
-                                    Some(platform),
-                                    Some(&host),
-                                    Some(&hash),
-                                    Some(&request_parser::truncate(prompt_text, 500)),
-                                    None,
-                                    None,
-                                    Some("info"),
-                                )
-                                .await;
-                        }
-                    }
+                if let PromptVerdict::Block(notice) =
+                    inspect_prompt(state, platform, host, req).await
+                {
+                    // Send the block response to the client and don't forward to upstream
+                    let client = ClientContext::from_request(platform, req);
+                    let block_response = block_page::build_block_response(&notice, &client);
+                    tls_client.write_all(&block_response).await?;
+                    continue;
                 }
             }
         }
 
         // --- Forward the request to upstream ---
+        // Inspected answers are requested unencoded, so that a block notice
+        // can be appended to them
+        let request_data = if is_api {
+            with_identity_encoding(&request_data)
+        } else {
+            request_data
+        };
         if let Err(e) = tls_upstream.write_all(&request_data).await {
             debug!(error = %e, "Failed to write to upstream");
             break;
         }
 
-        // --- Read the response from upstream ---
-        let response_data = match read_http_message(&mut tls_upstream).await {
-            Ok(data) if data.is_empty() => break,
-            Ok(data) => data,
-            Err(_) => break,
+        // --- Relay the response from upstream, inspecting it on the fly ---
+        let method = parsed_request
+            .as_ref()
+            .map(|r| r.method.as_str())
+            .unwrap_or("GET");
+        let client_context = parsed_request
+            .as_ref()
+            .map(|r| ClientContext::from_request(platform, r));
+        let exchange = ResponseExchange {
+            platform,
+            host,
+            method,
+            inspect: is_api && parsed_request.is_some(),
+            client: client_context.as_ref(),
         };
-
-        // If this was an API endpoint, try to extract and log the response
-        if is_api && parsed_request.is_some() {
-            let response_text = request_parser::extract_response(&response_data, platform);
-            if let Some(ref resp_text) = response_text {
-                let hash = request_parser::content_hash(&response_data);
-
-                // Evaluate the response against rules too
-                let result = rule_engine.evaluate(resp_text, RuleTarget::Response).await;
-                let (event_type, rule_id, severity) = match result {
-                    EvaluationResult::Alerted {
-                        rule_id, severity, ..
-                    } => {
-                        let sev = format!("{:?}", severity).to_lowercase();
-                        ("response_alert", Some(rule_id), sev)
-                    }
-                    EvaluationResult::Logged { rule_id } => {
-                        ("response", rule_id, "info".to_string())
-                    }
-                    _ => ("response", None, "info".to_string()),
+        match relay_response(state, &exchange, &mut tls_upstream, &mut tls_client).await {
+            Ok(RelayOutcome::KeepAlive) => {}
+            Ok(RelayOutcome::Close) => break,
+            Ok(RelayOutcome::WebSocket { extensions }) => {
+                let path = parsed_request
+                    .as_ref()
+                    .map(|r| r.path.as_str())
+                    .unwrap_or("/");
+                let session = websocket::WsSession {
+                    platform,
+                    host,
+                    path,
+                    extensions: extensions.as_deref(),
                 };
-
-                event_queue
-                    .log_event(
-                        event_type,
-                        Some(platform),
-                        Some(&host),
-                        Some(&hash),
-                        None,
-                        Some(&request_parser::truncate(resp_text, 500)),
-                        rule_id.as_deref(),
-                        Some(&severity),
-                    )
-                    .await;
+                return websocket::relay_websocket(state, &session, tls_client, tls_upstream).await;
+            }
+            Err(e) => {
+                debug!(error = %e, "Failed to relay response");
+                break;
             }
-        }
-
-        // --- Forward the response to the client ---
-        if let Err(e) = tls_client.write_all(&response_data).await {
-            debug!(error = %e, "Failed to write response to client");
-            break;
         }
     }
 
@@ -334,128 +823,950 @@ async fn handle_connection(
     Ok(())
 }
 
-/// Read a complete HTTP message (headers + body) from a TLS stream.
-///
-/// Handles:
-/// - Content-Length: reads exactly that many body bytes
-/// - Transfer-Encoding: chunked: reads until the final 0-length chunk
-/// - No body: returns just the headers (for GET, HEAD, etc.)
-async fn read_http_message<S: AsyncReadExt + Unpin>(stream: &mut S) -> anyhow::Result<Vec<u8>> {
-    let mut buf = vec![0u8; INITIAL_BUF_SIZE];
-    let mut total = 0;
+/// Forward plain HTTP requests sent in absolute form (`POST http://host/path`),
+/// as used for self-hosted LLM gateways reached without TLS. Monitored
+/// domains go through the same prompt and response inspection as the TLS
+/// path; the bodies of other requests are streamed as-is.
+async fn proxy_plain_http<C>(state: &ProxyState, mut client: C) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+{
+    let domain_filter = &state.domain_filter;
+    // Upstream connection, reused while requests target the same origin
+    let mut upstream: Option<(String, u16, upstream::HttpConnection)> = None;
 
-    // Phase 1: Read until we have the complete headers (\r\n\r\n)
     loop {
-        if total >= MAX_READ_SIZE {
-            anyhow::bail!("HTTP message too large");
-        }
+        let (head, body_start) = match read_http_head(&mut client).await {
+            Ok((head, Some(body_start))) => (head, body_start),
+            Ok((_, None)) | Err(_) => break,
+        };
+        let Some((host, port, path)) =
+            parse_absolute_target(&String::from_utf8_lossy(&head[..body_start]))
+        else {
+            client
+                .write_all(
+                    b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .await?;
+            break;
+        };
 
-        if total >= buf.len() {
-            buf.resize(buf.len() * 2, 0);
-        }
+        let intercept = domain_filter.should_intercept(&host).await;
+        let platform = request_parser::identify_platform(&host).unwrap_or("unknown");
 
-        let n = stream.read(&mut buf[total..]).await?;
-        if n == 0 {
-            return Ok(buf[..total].to_vec()); // Connection closed
+        // Only the requests to monitored domains are buffered for inspection;
+        // the body of the others is streamed once the request is sent
+        let (request_data, streamed_body) = if intercept {
+            match read_http_body(&mut client, head, body_start).await {
+                Ok(data) => (data, None),
+                Err(_) => break,
+            }
+        } else {
+            match RequestBody::from_headers(&String::from_utf8_lossy(&head[..body_start])) {
+                Ok(framing) => (
+                    head[..body_start].to_vec(),
+                    Some((framing, head[body_start..].to_vec())),
+                ),
+                Err(_) => break,
+            }
+        };
+
+        // Origin servers expect the origin form (`POST /path`)
+        let request_data = to_origin_form(&request_data, &path);
+
+        if intercept && domain_filter.is_blocked(&host).await {
+            let approved = approved_ai::current();
+            answer_blocked_request(
+                state,
+                &approved,
+                platform,
+                &host,
+                &request_data,
+                &mut client,
+            )
+            .await?;
+            break;
         }
-        total += n;
+        let parsed_request = request_parser::parse_raw_request(&request_data);
+        let is_api = intercept
+            && parsed_request
+                .as_ref()
+                .is_some_and(|r| request_parser::is_api_endpoint(&r.path, platform));
 
-        // Check if we have the complete headers
-        if let Some(header_end) = find_header_end(&buf[..total]) {
-            let body_start = header_end + 4; // after \r\n\r\n
-            let header_section = String::from_utf8_lossy(&buf[..header_end]);
-
-            // Determine body length
-            let content_length = extract_content_length(&header_section);
-            let is_chunked = header_section
-                .to_lowercase()
-                .contains("transfer-encoding: chunked");
-
-            if let Some(cl) = content_length {
-                // Content-Length mode: read exactly `cl` bytes of body
-                let expected_total = body_start + cl;
-                if expected_total > MAX_READ_SIZE {
-                    anyhow::bail!("HTTP message body too large: {} bytes", cl);
+        if is_api {
+            if let Some(ref req) = parsed_request {
+                if let PromptVerdict::Block(notice) =
+                    inspect_prompt(state, platform, &host, req).await
+                {
+                    let client_context = ClientContext::from_request(platform, req);
+                    let block_response = block_page::build_block_response(&notice, &client_context);
+                    client.write_all(&block_response).await?;
+                    continue;
                 }
+            }
+        }
+
+        let request_data = if is_api {
+            with_identity_encoding(&request_data)
+        } else {
+            request_data
+        };
 
-                while total < expected_total {
-                    if total >= buf.len() {
-                        buf.resize(std::cmp::min(buf.len() * 2, expected_total + 1024), 0);
-                    }
-                    let n = stream.read(&mut buf[total..]).await?;
-                    if n == 0 {
-                        break; // Connection closed prematurely
-                    }
-                    total += n;
+        // --- Forward the request, on a new connection if the origin changed ---
+        let reusable = upstream
+            .as_ref()
+            .is_some_and(|(h, p, _)| h.eq_ignore_ascii_case(&host) && *p == port);
+        if !reusable {
+            let stream = match upstream::connect_http(&format!("{}:{}", host, port)).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!(host = %host, port, error = %e, "Failed to connect to HTTP upstream");
+                    client
+                        .write_all(b"HTTP/1.1 502 Bad Gateway\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                        .await?;
+                    break;
                 }
+            };
+            upstream = Some((host.clone(), port, stream));
+        }
+        let Some((_, _, connection)) = upstream.as_mut() else {
+            break;
+        };
+        let request_data = connection.request(&request_data, &format!("{}:{}", host, port));
+        let upstream_stream = &mut connection.stream;
+        if let Err(e) = upstream_stream.write_all(&request_data).await {
+            debug!(error = %e, "Failed to write to upstream");
+            break;
+        }
+        if let Some((framing, start)) = streamed_body {
+            if let Err(e) = forward_http_body(&mut client, upstream_stream, framing, &start).await {
+                debug!(error = %e, "Failed to forward request body");
+                break;
+            }
+        }
 
-                return Ok(buf[..total].to_vec());
-            } else if is_chunked {
-                // Chunked transfer encoding: read until 0\r\n\r\n
-                loop {
-                    if total >= MAX_READ_SIZE {
-                        anyhow::bail!("Chunked message too large");
-                    }
-
-                    // Check if we've reached the final chunk
-                    let body_so_far = &buf[body_start..total];
-                    if is_chunked_complete(body_so_far) {
-                        return Ok(buf[..total].to_vec());
-                    }
-
-                    if total >= buf.len() {
-                        buf.resize(buf.len() * 2, 0);
-                    }
-                    let n = stream.read(&mut buf[total..]).await?;
-                    if n == 0 {
-                        return Ok(buf[..total].to_vec());
-                    }
-                    total += n;
+        let method = parsed_request
+            .as_ref()
+            .map(|r| r.method.as_str())
+            .unwrap_or("GET");
+        let client_context = parsed_request
+            .as_ref()
+            .map(|r| ClientContext::from_request(platform, r));
+        let exchange = ResponseExchange {
+            platform,
+            host: &host,
+            method,
+            inspect: is_api && parsed_request.is_some(),
+            client: client_context.as_ref(),
+        };
+        match relay_response(state, &exchange, upstream_stream, &mut client).await {
+            Ok(RelayOutcome::KeepAlive) => {}
+            Ok(RelayOutcome::Close) => break,
+            Ok(RelayOutcome::WebSocket { extensions }) => {
+                let Some((_, _, connection)) = upstream.take() else {
+                    break;
+                };
+                let mut upstream_stream = connection.stream;
+                if !intercept {
+                    tokio::io::copy_bidirectional(&mut client, &mut upstream_stream).await?;
+                    return Ok(());
                 }
-            } else {
-                // No Content-Length and not chunked → body is empty
-                // (or the server will close the connection to signal end)
-                return Ok(buf[..total].to_vec());
+                let session = websocket::WsSession {
+                    platform,
+                    host: &host,
+                    path: &path,
+                    extensions: extensions.as_deref(),
+                };
+                return websocket::relay_websocket(state, &session, client, upstream_stream).await;
+            }
+            Err(e) => {
+                debug!(error = %e, "Failed to relay response");
+                break;
             }
         }
     }
-}
 
-/// Find the position of \r\n\r\n in the buffer
-fn find_header_end(data: &[u8]) -> Option<usize> {
-    data.windows(4)
-        .position(|w| w == b"\r\n\r\n")
+    let _ = client.shutdown().await;
+    if let Some((_, _, mut connection)) = upstream {
+        let _ = connection.stream.shutdown().await;
+    }
+    Ok(())
 }
 
-/// Extract Content-Length value from headers
-fn extract_content_length(headers: &str) -> Option<usize> {
-    for line in headers.lines() {
-        if let Some(value) = line.strip_prefix("Content-Length:").or_else(|| line.strip_prefix("content-length:")) {
-            return value.trim().parse().ok();
-        }
-        // Case-insensitive check
-        let lower = line.to_lowercase();
-        if lower.starts_with("content-length:") {
-            let parts: Vec<&str> = line.splitn(2, ':').collect();
-            if parts.len() == 2 {
-                return parts[1].trim().parse().ok();
+/// Extract the prompt of an intercepted API request, evaluate it against the
+/// rule engine and log the resulting event.
+///
+/// A compressed body (Content-Encoding) is decoded for inspection only; the
+/// caller forwards the original bytes.
+pub async fn inspect_prompt(
+    state: &ProxyState,
+    platform: &str,
+    host: &str,
+    req: &request_parser::ParsedHttpRequest,
+) -> PromptVerdict {
+    let content_encoding = req
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("content-encoding"))
+        .map(|(_, v)| v.as_str());
+    let decoded;
+    let req = match content_encoding {
+        Some(ce) => match encoding::decode_body(&req.body, Some(ce)) {
+            Some(body) => {
+                decoded = request_parser::ParsedHttpRequest {
+                    body: body.into_owned(),
+                    ..req.clone()
+                };
+                &decoded
             }
-        }
+            None => {
+                debug!(host = %host, content_encoding = %ce, "Undecodable request body, not inspected");
+                return PromptVerdict::Forward;
+            }
+        },
+        None => req,
+    };
+
+    match request_parser::extract_prompt(&req.body, platform) {
+        Some(prompt_text) => evaluate_prompt(state, platform, host, req, prompt_text).await,
+        None => PromptVerdict::Forward,
     }
-    None
 }
 
-/// Check if a chunked transfer body is complete (ends with 0\r\n\r\n)
-fn is_chunked_complete(body: &[u8]) -> bool {
-    // The final chunk is "0\r\n\r\n" or "0\r\n" followed by optional trailers and "\r\n"
-    if body.len() >= 5 {
-        let tail = &body[body.len().saturating_sub(7)..];
-        // Look for "0\r\n\r\n" pattern
-        tail.windows(5).any(|w| w == b"0\r\n\r\n")
-    } else {
-        false
+/// Evaluate a prompt already extracted from `req` (HTTP body or WebSocket
+/// message) against the rule engine and log the resulting event.
+pub async fn evaluate_prompt(
+    state: &ProxyState,
+    platform: &str,
+    host: &str,
+    req: &request_parser::ParsedHttpRequest,
+    prompt_text: String,
+) -> PromptVerdict {
+    info!(
+        host = %host,
+        platform,
+        path = %req.path,
+        prompt_len = prompt_text.len(),
+        "Intercepted AI prompt"
+    );
+
+    // --- Track the conversation this prompt belongs to ---
+    let conversation = state
+        .conversations
+        .record(platform, &req.path, &req.body, &prompt_text);
+    let mut metadata = json!({
+        "conversation_id": conversation.conversation_id,
+        "conversation_messages": conversation.message_count,
+    });
+    for (key, value) in request_parser::extract_request_metadata(&req.body, &req.path, platform) {
+        metadata[key] = value;
     }
-}
+
+    // --- Look for recently copied sensitive clipboard content ---
+    let clipboard_match = state.clipboard_history.find_in_prompt(&prompt_text);
+    if let Some(ref m) = clipboard_match {
+        metadata["clipboard_event_id"] = json!(m.clipboard_event_id);
+    }
+
+    // --- Feed the behavioral threshold counters ---
+    let rule_engine = &state.rule_engine;
+    rule_engine.record_activity(ThresholdMetric::PromptCount, Some(platform), 1);
+    rule_engine.record_activity(
+        ThresholdMetric::PromptBytes,
+        Some(platform),
+        prompt_text.len() as u64,
+    );
+
+    // --- Tell enterprise accounts from personal ones (web apps only) ---
+    let tenant = account::detect_tenant(req);
+    let account_type = if account::is_web_session(req) {
+        state
+            .domain_filter
+            .account_type(platform, tenant.as_ref().map(|t| t.id.as_str()))
+            .await
+    } else {
+        None
+    };
+    if let Some(account_type) = account_type {
+        metadata["account_type"] = json!(account_type.as_str());
+    }
+    if let Some(tenant) = &tenant {
+        metadata["tenant_id"] = json!(tenant.id);
+    }
+
+    // --- Fingerprint the API key, never the key itself ---
+    let api_key = api_keys::detect_api_key(req);
+    if let Some(key) = &api_key {
+        metadata["api_key_fingerprint"] = json!(key.fingerprint);
+        metadata["api_key_source"] = json!(key.source);
+    }
+
+    // --- Evaluate the prompt against the rule engine ---
+    let ctx = EvaluationContext::new(&prompt_text)
+        .with_platform(platform)
+        .with_account(account_type)
+        .with_api_key(api_key.map(|k| k.fingerprint))
+        .with_conversation(conversation);
+    let result = rule_engine.evaluate_context(&ctx, RuleTarget::Prompt).await;
+    // Recorded when a Language rule needed it; not detected for the event only
+    if let Some(language) = ctx.detected_language() {
+        metadata["language"] = json!(language);
+    }
+
+    let (event_type, rule_id, severity, verdict) = match result {
+        EvaluationResult::Blocked {
+            rule_id,
+            rule_name,
+            message,
+            template,
+        } => {
+            info!(%rule_name, "BLOCKED prompt");
+            let notice = BlockNotice::new(&message, &rule_name, template.as_deref());
+            metadata["incident_id"] = json!(notice.incident_id);
+            (
+                "block",
+                Some(rule_id),
+                "critical".to_string(),
+                PromptVerdict::Block(notice),
+            )
+        }
+        EvaluationResult::Alerted {
+            rule_id,
+            rule_name,
+            severity,
+        } => {
+            info!(%rule_name, "Alert on prompt, forwarding anyway");
+            let sev = format!("{:?}", severity).to_lowercase();
+            ("alert", Some(rule_id), sev, PromptVerdict::Forward)
+        }
+        EvaluationResult::Logged { rule_id } => (
+            "prompt",
+            rule_id,
+            "info".to_string(),
+            PromptVerdict::Forward,
+        ),
+        // Log the prompt even if no rule matched
+        EvaluationResult::NoMatch => ("prompt", None, "info".to_string(), PromptVerdict::Forward),
+    };
+
+    let hash = request_parser::content_hash(&req.body);
+    let prompt_event_id = state
+        .event_queue
+        .log_event_with_metadata(
+            event_type,
+            Some(platform),
+            Some(host),
+            Some(&hash),
+            Some(&request_parser::truncate(&prompt_text, 500)),
+            None,
+            rule_id.as_deref(),
+            Some(&severity),
+            Some(&metadata.to_string()),
+        )
+        .await;
+
+    // --- Emit a correlated event linking the clipboard copy and the prompt ---
+    if let Some(m) = clipboard_match {
+        info!(
+            clipboard_event_id = m.clipboard_event_id,
+            overlap = m.overlap,
+            "Prompt contains recently copied sensitive clipboard content"
+        );
+        let correlation = json!({
+            "clipboard_event_id": m.clipboard_event_id,
+            "prompt_event_id": prompt_event_id,
+            "overlap": m.overlap,
+            "seconds_since_copy": m.age_secs,
+            "conversation_id": metadata["conversation_id"],
+        });
+        state
+            .event_queue
+            .log_event_with_metadata(
+                "clipboard_paste",
+                Some(platform),
+                Some(host),
+                Some(&hash),
+                None,
+                None,
+                rule_id.as_deref(),
+                Some("warning"),
+                Some(&correlation.to_string()),
+            )
+            .await;
+    }
+
+    verdict
+}
+
+/// Relay one upstream response to the client.
+///
+/// Bodies are forwarded as they arrive instead of being buffered, so that
+/// streamed (SSE) answers reach the user incrementally. For inspected
+/// responses, the streamed text is accumulated in parallel and re-evaluated
+/// regularly; a Block rule cuts the stream and injects a block notice.
+/// Non-streamed answers are buffered (up to `MAX_READ_SIZE`) so that they can
+/// be replaced by a block page.
+///
+/// Returns what to do with the connection after the response.
+async fn relay_response<U, C>(
+    state: &ProxyState,
+    exchange: &ResponseExchange<'_>,
+    upstream: &mut U,
+    client: &mut C,
+) -> anyhow::Result<RelayOutcome>
+where
+    U: AsyncRead + Unpin,
+    C: AsyncWrite + Unpin,
+{
+    // Forward interim 1xx responses until the final one
+    let (head, leftover) = loop {
+        let Some((head, leftover)) = stream::read_response_head(upstream).await? else {
+            return Ok(RelayOutcome::Close);
+        };
+        if !head.is_informational() {
+            break (head, leftover);
+        }
+        client.write_all(&head.raw).await?;
+    };
+
+    // Switching protocols: the connection no longer carries HTTP
+    if head.status_code == 101 {
+        client.write_all(&head.raw).await?;
+        client.write_all(&leftover).await?;
+        let websocket = head
+            .header("upgrade")
+            .is_some_and(|u| u.eq_ignore_ascii_case("websocket"));
+        return Ok(if websocket {
+            RelayOutcome::WebSocket {
+                extensions: head
+                    .header("sec-websocket-extensions")
+                    .map(|e| e.to_string()),
+            }
+        } else {
+            RelayOutcome::Close
+        });
+    }
+
+    let framing = head.framing(exchange.method);
+    let keep_alive = if head.keep_alive() && framing != BodyFraming::UntilClose {
+        RelayOutcome::KeepAlive
+    } else {
+        RelayOutcome::Close
+    };
+    let mut reader = BodyReader::new(framing, leftover);
+
+    if !exchange.inspect {
+        client.write_all(&head.raw).await?;
+        while let Some(piece) = reader.next(upstream).await? {
+            client.write_all(&piece.raw).await?;
+        }
+        return Ok(keep_alive);
+    }
+
+    if head.is_event_stream() {
+        return relay_event_stream(
+            state,
+            exchange,
+            &head,
+            framing,
+            &mut reader,
+            upstream,
+            client,
+        )
+        .await
+        .map(|completed| {
+            if completed {
+                keep_alive
+            } else {
+                RelayOutcome::Close
+            }
+        });
+    }
+
+    // --- Non-streamed answer: buffer it so it can be replaced if blocked ---
+    let mut raw = Vec::new();
+    let mut body = Vec::new();
+    while let Some(piece) = reader.next(upstream).await? {
+        raw.extend_from_slice(&piece.raw);
+        body.extend_from_slice(&piece.decoded);
+
+        if raw.len() > MAX_READ_SIZE {
+            // Too large to inspect: forward what we have and relay the rest
+            debug!(host = %exchange.host, "Response too large to inspect, relaying as-is");
+            client.write_all(&head.raw).await?;
+            client.write_all(&raw).await?;
+            while let Some(piece) = reader.next(upstream).await? {
+                client.write_all(&piece.raw).await?;
+            }
+            return Ok(keep_alive);
+        }
+    }
+
+    let answer = encoding::decode_body(&body, head.header("content-encoding"))
+        .and_then(|body| request_parser::extract_response(&body, exchange.platform));
+    if let Some(answer) = answer {
+        if let Some(notice) =
+            log_response(state, exchange, &answer.text, &answer.metadata(), None).await
+        {
+            client
+                .write_all(&block_page::build_block_response(
+                    &notice,
+                    &exchange.client_context(),
+                ))
+                .await?;
+            return Ok(RelayOutcome::Close);
+        }
+    }
+
+    client.write_all(&head.raw).await?;
+    client.write_all(&raw).await?;
+    Ok(keep_alive)
+}
+
+/// Relay a Server-Sent Events answer chunk by chunk while accumulating its
+/// text. Returns false if the stream was cut by a Block rule.
+async fn relay_event_stream<U, C>(
+    state: &ProxyState,
+    exchange: &ResponseExchange<'_>,
+    head: &ResponseHead,
+    framing: BodyFraming,
+    reader: &mut BodyReader,
+    upstream: &mut U,
+    client: &mut C,
+) -> anyhow::Result<bool>
+where
+    U: AsyncRead + Unpin,
+    C: AsyncWrite + Unpin,
+{
+    client.write_all(&head.raw).await?;
+
+    let mut inspector = StreamInspector::new(head.header("content-encoding"), exchange.platform);
+    let encoded = encoding::is_encoded(head.header("content-encoding"));
+
+    while let Some(piece) = reader.next(upstream).await? {
+        inspector.push(&piece.decoded);
+
+        // Final evaluation (and event) before the end of stream is forwarded
+        if reader.is_done() {
+            if let Some(notice) = inspector.finish(state, exchange).await {
+                inject_block_notice(client, framing, encoded, exchange, &notice).await?;
+                return Ok(false);
+            }
+            client.write_all(&piece.raw).await?;
+            client.flush().await?;
+            return Ok(true);
+        }
+
+        // Evaluate the text received so far before forwarding more of it
+        if let Some(notice) = inspector.check(state, exchange).await {
+            inject_block_notice(client, framing, encoded, exchange, &notice).await?;
+            return Ok(false);
+        }
+
+        client.write_all(&piece.raw).await?;
+        client.flush().await?;
+    }
+
+    // Close-delimited stream: the upstream has closed, the client has not yet
+    if let Some(notice) = inspector.finish(state, exchange).await {
+        inject_block_notice(client, framing, encoded, exchange, &notice).await?;
+    }
+    Ok(false)
+}
+
+/// Accumulates the text of a streamed (SSE) answer and evaluates it against
+/// the Response rules as it grows. Shared by the HTTP/1.1 and HTTP/2 paths.
+pub struct StreamInspector {
+    decoder: StreamDecoder,
+    sse: SseAccumulator,
+    evaluated_len: usize,
+}
+
+impl StreamInspector {
+    /// `content_encoding` is the Content-Encoding of the streamed body
+    pub fn new(content_encoding: Option<&str>, platform: &'static str) -> Self {
+        Self {
+            decoder: StreamDecoder::new(content_encoding),
+            sse: SseAccumulator::for_platform(platform),
+            evaluated_len: 0,
+        }
+    }
+
+    /// Feed body bytes (de-chunked, possibly still content-encoded)
+    pub fn push(&mut self, body: &[u8]) {
+        let decoded = self.decoder.feed(body);
+        self.sse.feed(&decoded);
+    }
+
+    /// Re-evaluate the text received so far once enough new text has
+    /// arrived. Returns the block notice (after logging the cut answer) when
+    /// a Block rule matches.
+    pub async fn check(
+        &mut self,
+        state: &ProxyState,
+        exchange: &ResponseExchange<'_>,
+    ) -> Option<BlockNotice> {
+        let step = STREAM_EVAL_STEP.max(self.evaluated_len / 4);
+        if self.sse.text().len() < self.evaluated_len + step {
+            return None;
+        }
+        self.evaluated_len = self.sse.text().len();
+
+        let ctx = EvaluationContext::new(self.sse.text()).with_platform(exchange.platform);
+        let result = state
+            .rule_engine
+            .evaluate_context(&ctx, RuleTarget::Response)
+            .await;
+        if let EvaluationResult::Blocked { rule_name, .. } = &result {
+            info!(%rule_name, host = %exchange.host, "BLOCKED streamed response");
+            // The logged event carries the incident ID of the notice
+            return log_response(
+                state,
+                exchange,
+                self.sse.text(),
+                &self.sse.metadata(),
+                Some(result),
+            )
+            .await;
+        }
+        None
+    }
+
+    /// Log the complete streamed answer; returns the block notice when a
+    /// Block rule matches it
+    pub async fn finish(
+        &self,
+        state: &ProxyState,
+        exchange: &ResponseExchange<'_>,
+    ) -> Option<BlockNotice> {
+        if self.sse.text().is_empty() {
+            return None;
+        }
+        log_response(state, exchange, self.sse.text(), &self.sse.metadata(), None).await
+    }
+}
+
+impl Default for StreamInspector {
+    fn default() -> Self {
+        Self::new(None, "unknown")
+    }
+}
+
+/// Write the block notice at the current position of a cut SSE stream and
+/// terminate the body so that the client ends the answer cleanly. A body
+/// still content-encoded (the server ignored `Accept-Encoding: identity`)
+/// is only terminated: plain text would break the client's decoder.
+async fn inject_block_notice<C: AsyncWrite + Unpin>(
+    client: &mut C,
+    framing: BodyFraming,
+    encoded: bool,
+    exchange: &ResponseExchange<'_>,
+    notice: &BlockNotice,
+) -> anyhow::Result<()> {
+    let notice = if encoded {
+        Vec::new()
+    } else {
+        block_page::build_stream_notice(notice, &exchange.client_context())
+    };
+    match framing {
+        BodyFraming::Chunked => {
+            if !notice.is_empty() {
+                client.write_all(&chunked::encode_chunk(&notice)).await?;
+            }
+            client.write_all(chunked::LAST_CHUNK).await?;
+        }
+        BodyFraming::UntilClose => client.write_all(&notice).await?,
+        // A fixed-length body cannot be extended: the connection is closed
+        BodyFraming::ContentLength(_) | BodyFraming::Empty => {}
+    }
+    client.flush().await?;
+    Ok(())
+}
+
+/// Evaluate an AI answer against the rule engine and log the resulting event.
+/// `result` is the evaluation already made by the caller (a stream cut
+/// mid-answer), if any; `metadata` (cited sources, model usage) is recorded
+/// with the event.
+/// Returns the block notice when a Block rule matched.
+pub async fn log_response(
+    state: &ProxyState,
+    exchange: &ResponseExchange<'_>,
+    text: &str,
+    metadata: &serde_json::Map<String, serde_json::Value>,
+    result: Option<EvaluationResult>,
+) -> Option<BlockNotice> {
+    let result = match result {
+        Some(result) => result,
+        None => {
+            let ctx = EvaluationContext::new(text).with_platform(exchange.platform);
+            state
+                .rule_engine
+                .evaluate_context(&ctx, RuleTarget::Response)
+                .await
+        }
+    };
+
+    let (event_type, rule_id, severity, blocked) = match result {
+        EvaluationResult::Blocked {
+            rule_id,
+            rule_name,
+            message,
+            template,
+        } => (
+            "response_block",
+            Some(rule_id),
+            "critical".to_string(),
+            Some(BlockNotice::new(&message, &rule_name, template.as_deref())),
+        ),
+        EvaluationResult::Alerted {
+            rule_id, severity, ..
+        } => {
+            let sev = format!("{:?}", severity).to_lowercase();
+            ("response_alert", Some(rule_id), sev, None)
+        }
+        EvaluationResult::Logged { rule_id } => ("response", rule_id, "info".to_string(), None),
+        EvaluationResult::NoMatch => ("response", None, "info".to_string(), None),
+    };
+
+    let hash = request_parser::content_hash(text.as_bytes());
+    let mut metadata = metadata.clone();
+    if let Some(notice) = &blocked {
+        metadata.insert("incident_id".to_string(), json!(notice.incident_id));
+    }
+    let metadata = (!metadata.is_empty()).then(|| serde_json::Value::Object(metadata).to_string());
+    state
+        .event_queue
+        .log_event_with_metadata(
+            event_type,
+            Some(exchange.platform),
+            Some(exchange.host),
+            Some(&hash),
+            None,
+            Some(&request_parser::truncate(text, 500)),
+            rule_id.as_deref(),
+            Some(&severity),
+            metadata.as_deref(),
+        )
+        .await;
+
+    blocked
+}
+
+/// Read a complete HTTP message (headers + body) from a TLS stream.
+///
+/// Handles:
+/// - Content-Length: reads exactly that many body bytes
+/// - Transfer-Encoding: chunked: reads until the final 0-length chunk
+/// - No body: returns just the headers (for GET, HEAD, etc.)
+///
+/// Messages carrying both Content-Length and Transfer-Encoding are rejected
+/// (RFC 9112 §6.3): the upstream would frame them differently than the
+/// inspection does.
+async fn read_http_message<S: AsyncReadExt + Unpin>(stream: &mut S) -> anyhow::Result<Vec<u8>> {
+    match read_http_head(stream).await? {
+        (buf, Some(body_start)) => read_http_body(stream, buf, body_start).await,
+        // Connection closed
+        (buf, None) => Ok(buf),
+    }
+}
+
+/// Framing of a request body
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RequestBody {
+    Empty,
+    Length(usize),
+    Chunked,
+}
+
+impl RequestBody {
+    /// Framing declared by the header section of a request
+    fn from_headers(header_section: &str) -> anyhow::Result<Self> {
+        let content_length = extract_content_length(header_section);
+        let transfer_encoding = extract_transfer_encoding(header_section);
+        if content_length.is_some() && transfer_encoding.is_some() {
+            anyhow::bail!("HTTP message with both Content-Length and Transfer-Encoding");
+        }
+        Ok(match (content_length, transfer_encoding) {
+            (Some(cl), _) => RequestBody::Length(cl),
+            (None, Some(te)) if te.contains("chunked") => RequestBody::Chunked,
+            // No Content-Length and not chunked → body is empty
+            // (or the server will close the connection to signal end)
+            _ => RequestBody::Empty,
+        })
+    }
+}
+
+/// Read the head of an HTTP message. Returns the bytes read, which may go
+/// on with the start of the body, and the offset of the body; None when the
+/// connection closed before the end of the headers.
+async fn read_http_head<S: AsyncReadExt + Unpin>(
+    stream: &mut S,
+) -> anyhow::Result<(Vec<u8>, Option<usize>)> {
+    let mut buf = vec![0u8; INITIAL_BUF_SIZE];
+    let mut total = 0;
+
+    loop {
+        if total >= MAX_READ_SIZE {
+            anyhow::bail!("HTTP message too large");
+        }
+
+        if total >= buf.len() {
+            buf.resize(buf.len() * 2, 0);
+        }
+
+        let n = stream.read(&mut buf[total..]).await?;
+        if n == 0 {
+            buf.truncate(total);
+            return Ok((buf, None));
+        }
+        total += n;
+
+        if let Some(header_end) = find_header_end(&buf[..total]) {
+            buf.truncate(total);
+            return Ok((buf, Some(header_end + 4)));
+        }
+    }
+}
+
+/// Read the rest of the body of the message whose head (and possibly the
+/// start of the body) is in `buf`, and return the whole message
+async fn read_http_body<S: AsyncReadExt + Unpin>(
+    stream: &mut S,
+    mut buf: Vec<u8>,
+    body_start: usize,
+) -> anyhow::Result<Vec<u8>> {
+    let header_section = String::from_utf8_lossy(&buf[..body_start - 4]).to_string();
+    let mut chunk = vec![0u8; INITIAL_BUF_SIZE];
+
+    match RequestBody::from_headers(&header_section)? {
+        RequestBody::Length(cl) => {
+            // Content-Length mode: read exactly `cl` bytes of body
+            let expected_total = body_start + cl;
+            if expected_total > MAX_READ_SIZE {
+                anyhow::bail!("HTTP message body too large: {} bytes", cl);
+            }
+
+            while buf.len() < expected_total {
+                let n = stream.read(&mut chunk).await?;
+                if n == 0 {
+                    break; // Connection closed prematurely
+                }
+                buf.extend_from_slice(&chunk[..n]);
+            }
+            Ok(buf)
+        }
+        RequestBody::Chunked => {
+            // Chunked transfer encoding: decode incrementally until the
+            // last chunk and its trailers have been received
+            let mut decoder = chunked::ChunkedDecoder::new();
+            let mut payload = Vec::new();
+            let mut fed = body_start;
+            loop {
+                let consumed = decoder.feed(&buf[fed..], &mut payload)?;
+                fed += consumed;
+                // Only the framing matters here; the parser decodes the body
+                payload.clear();
+                if decoder.is_done() {
+                    buf.truncate(fed);
+                    return Ok(buf);
+                }
+
+                if buf.len() >= MAX_READ_SIZE {
+                    anyhow::bail!("Chunked message too large");
+                }
+                let n = stream.read(&mut chunk).await?;
+                if n == 0 {
+                    return Ok(buf);
+                }
+                buf.extend_from_slice(&chunk[..n]);
+            }
+        }
+        RequestBody::Empty => Ok(buf),
+    }
+}
+
+/// Forward the body of a request to `upstream` as it is read from `client`,
+/// without buffering it; `start` holds the body bytes already read
+async fn forward_http_body<C, U>(
+    client: &mut C,
+    upstream: &mut U,
+    framing: RequestBody,
+    start: &[u8],
+) -> anyhow::Result<()>
+where
+    C: AsyncRead + Unpin,
+    U: AsyncWrite + Unpin,
+{
+    match framing {
+        RequestBody::Empty => Ok(()),
+        RequestBody::Length(cl) => {
+            let start = &start[..start.len().min(cl)];
+            upstream.write_all(start).await?;
+            let remaining = (cl - start.len()) as u64;
+            let copied = tokio::io::copy(&mut client.take(remaining), upstream).await?;
+            if copied < remaining {
+                anyhow::bail!("Request body ended prematurely");
+            }
+            Ok(())
+        }
+        RequestBody::Chunked => {
+            let mut decoder = chunked::ChunkedDecoder::new();
+            let mut payload = Vec::new();
+            let mut data = start.to_vec();
+            let mut chunk = vec![0u8; INITIAL_BUF_SIZE];
+            loop {
+                let consumed = decoder.feed(&data, &mut payload)?;
+                payload.clear();
+                upstream.write_all(&data[..consumed]).await?;
+                if decoder.is_done() {
+                    return Ok(());
+                }
+                let n = client.read(&mut chunk).await?;
+                if n == 0 {
+                    anyhow::bail!("Request body ended prematurely");
+                }
+                data.clear();
+                data.extend_from_slice(&chunk[..n]);
+            }
+        }
+    }
+}
+
+/// Find the position of \r\n\r\n in the buffer
+fn find_header_end(data: &[u8]) -> Option<usize> {
+    data.windows(4).position(|w| w == b"\r\n\r\n")
+}
+
+/// Extract the Transfer-Encoding value from headers, lowercased
+fn extract_transfer_encoding(headers: &str) -> Option<String> {
+    headers.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("transfer-encoding")
+            .then(|| value.trim().to_ascii_lowercase())
+    })
+}
+
+/// Extract Content-Length value from headers
+fn extract_content_length(headers: &str) -> Option<usize> {
+    for line in headers.lines() {
+        if let Some(value) = line
+            .strip_prefix("Content-Length:")
+            .or_else(|| line.strip_prefix("content-length:"))
+        {
+            return value.trim().parse().ok();
+        }
+        // Case-insensitive check
+        let lower = line.to_lowercase();
+        if lower.starts_with("content-length:") {
+            let parts: Vec<&str> = line.splitn(2, ':').collect();
+            if parts.len() == 2 {
+                return parts[1].trim().parse().ok();
+            }
+        }
+    }
+    None
+}
 
 /// Check if the request is a plain HTTP GET for the PAC file
 fn is_pac_request(request: &str) -> bool {
@@ -483,19 +1794,117 @@ fn parse_connect_target(request: &str) -> Option<(String, u16)> {
     }
 }
 
-/// Tunnel a connection directly without TLS inspection (for non-AI domains)
-async fn tunnel_direct(
-    client: &mut tokio::net::TcpStream,
-    host: &str,
-    port: u16,
-) -> anyhow::Result<()> {
-    let target_addr = format!("{}:{}", host, port);
-    let mut target = tokio::net::TcpStream::connect(&target_addr).await?;
+/// Parse the target of an absolute-form plain HTTP request
+/// (`GET http://host:port/path HTTP/1.1`), returns (host, port, path)
+fn parse_absolute_target(request: &str) -> Option<(String, u16, String)> {
+    let first_line = request.lines().next()?;
+    let parts: Vec<&str> = first_line.split_whitespace().collect();
+    if parts.len() < 3 || parts[0] == "CONNECT" {
+        return None;
+    }
 
-    client
-        .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
-        .await?;
+    let target = parts[1];
+    let scheme_end = target.find("://")?;
+    if !target[..scheme_end].eq_ignore_ascii_case("http") {
+        return None;
+    }
+    let rest = &target[scheme_end + 3..];
+    let (authority, path) = match rest.find(['/', '?']) {
+        Some(i) if rest[i..].starts_with('?') => (&rest[..i], format!("/{}", &rest[i..])),
+        Some(i) => (&rest[..i], rest[i..].to_string()),
+        None => (rest, "/".to_string()),
+    };
+    // Drop credentials (user:pass@host)
+    let authority = authority.rsplit('@').next().unwrap_or(authority);
+
+    let (host, port) = if let Some(bracketed) = authority.strip_prefix('[') {
+        // IPv6 literal: [::1]:8080
+        let end = bracketed.find(']')?;
+        let port = bracketed[end + 1..]
+            .strip_prefix(':')
+            .map(|p| p.parse().ok());
+        (bracketed[..end].to_string(), port)
+    } else {
+        match authority.rsplit_once(':') {
+            Some((host, port)) => (host.to_string(), Some(port.parse().ok())),
+            None => (authority.to_string(), None),
+        }
+    };
+    let port = match port {
+        Some(Some(port)) => port,
+        Some(None) => return None,
+        None => 80,
+    };
+    if host.is_empty() {
+        return None;
+    }
+    Some((host, port, path))
+}
+
+/// Rewrite an absolute-form request to the origin form expected by origin
+/// servers, dropping the headers meant for the proxy
+fn to_origin_form(request: &[u8], path: &str) -> Vec<u8> {
+    let Some(header_end) = find_header_end(request) else {
+        return request.to_vec();
+    };
+    let head = String::from_utf8_lossy(&request[..header_end]);
+    let mut lines = head.split("\r\n");
+
+    let mut out = String::new();
+    if let Some(request_line) = lines.next() {
+        let parts: Vec<&str> = request_line.splitn(3, ' ').collect();
+        match parts.as_slice() {
+            [method, _, version] => out.push_str(&format!("{} {} {}", method, path, version)),
+            _ => out.push_str(request_line),
+        }
+    }
+    for line in lines {
+        let name = line.split(':').next().unwrap_or("").trim();
+        if name.eq_ignore_ascii_case("proxy-connection")
+            || name.eq_ignore_ascii_case("proxy-authorization")
+        {
+            continue;
+        }
+        out.push_str("\r\n");
+        out.push_str(line);
+    }
+
+    let mut bytes = out.into_bytes();
+    bytes.extend_from_slice(&request[header_end..]);
+    bytes
+}
+
+/// Rewrite the Accept-Encoding header of a request to `identity`, so that
+/// an inspected answer comes back unencoded
+fn with_identity_encoding(request: &[u8]) -> Vec<u8> {
+    let Some(header_end) = find_header_end(request) else {
+        return request.to_vec();
+    };
+    let head = String::from_utf8_lossy(&request[..header_end]);
+    let mut lines = head.split("\r\n");
+
+    let mut out = lines.next().unwrap_or("").to_string();
+    for line in lines {
+        let name = line.split(':').next().unwrap_or("").trim();
+        if name.eq_ignore_ascii_case("accept-encoding") {
+            continue;
+        }
+        out.push_str("\r\n");
+        out.push_str(line);
+    }
+    out.push_str("\r\nAccept-Encoding: identity");
 
+    let mut bytes = out.into_bytes();
+    bytes.extend_from_slice(&request[header_end..]);
+    bytes
+}
+
+/// Tunnel a connection directly without TLS inspection (for non-AI domains)
+async fn tunnel_direct<C>(client: &mut C, target_addr: &str) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut target = upstream::connect(target_addr).await?;
     tokio::io::copy_bidirectional(client, &mut target).await?;
     Ok(())
 }
@@ -503,6 +1912,448 @@ async fn tunnel_direct(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::proxy::domain_filter::ApprovedTenant;
+    use crate::rules::models::{
+        AccountType, Rule, RuleAction, RuleCategory, RuleCondition, RuleScope,
+    };
+    use crate::storage::database::Database;
+    use crate::sync::api_client::ApiClient;
+
+    async fn test_state(rules: Vec<Rule>) -> (ProxyState, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = AppConfig::load_from(Some(&dir.path().join("missing.toml"))).unwrap();
+        config.data_dir = dir.path().to_path_buf();
+
+        let db = Arc::new(Database::init(dir.path(), "test-key").unwrap());
+        db.run_migrations().unwrap();
+        let rule_engine = Arc::new(RuleEngine::new(db.clone()));
+        rule_engine.update_rules(rules).await.unwrap();
+        let api_client = Arc::new(ApiClient::new(&config).unwrap());
+
+        let state = ProxyState {
+            rule_engine,
+            event_queue: Arc::new(EventQueue::new(db, api_client, 30, 100)),
+            domain_filter: Arc::new(DomainFilter::with_defaults()),
+            ca_manager: CaManager::load_or_create(dir.path()).unwrap(),
+            conversations: ConversationTracker::new(),
+            clipboard_history: Arc::new(ClipboardHistory::new()),
+            proxy_port: 0,
+        };
+        (state, dir)
+    }
+
+    fn block_rule(keyword: &str) -> Rule {
+        Rule {
+            id: "no-secret".to_string(),
+            name: "no-secret".to_string(),
+            version: 1,
+            category: RuleCategory::Block,
+            target: RuleTarget::Response,
+            condition: RuleCondition::Keyword {
+                keywords: vec![keyword.to_string()],
+                match_all: false,
+            },
+            action: RuleAction::Block {
+                message: "Contenu interdit".to_string(),
+                template: None,
+            },
+            priority: 10,
+            enabled: true,
+            scope: RuleScope::Message,
+        }
+    }
+
+    /// Chunked SSE response with one event per chunk
+    fn sse_response(deltas: &[String]) -> Vec<u8> {
+        let mut out = b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+        for delta in deltas {
+            let event = format!(
+                "data: {}\n\n",
+                json!({"choices": [{"delta": {"content": delta}}]})
+            );
+            out.extend(chunked::encode_chunk(event.as_bytes()));
+        }
+        out.extend(chunked::encode_chunk(b"data: [DONE]\n\n"));
+        out.extend_from_slice(chunked::LAST_CHUNK);
+        out
+    }
+
+    const EXCHANGE: ResponseExchange<'static> = ResponseExchange {
+        platform: "chatgpt",
+        host: "chatgpt.com",
+        method: "POST",
+        inspect: true,
+        client: None,
+    };
+
+    #[tokio::test]
+    async fn test_stream_relayed_unchanged() {
+        let (state, _dir) = test_state(vec![block_rule("SECRET-PROJECT")]).await;
+        let response = sse_response(&["Bonjour".to_string(), " le monde".to_string()]);
+
+        let mut upstream = &response[..];
+        let mut client = Vec::new();
+        let outcome = relay_response(&state, &EXCHANGE, &mut upstream, &mut client)
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RelayOutcome::KeepAlive);
+        assert_eq!(client, response);
+    }
+
+    #[tokio::test]
+    async fn test_stream_cut_when_block_rule_matches() {
+        let (state, _dir) = test_state(vec![block_rule("SECRET-PROJECT")]).await;
+        let mut deltas: Vec<String> = (0..20)
+            .map(|i| format!("Paragraphe {} sans rien de particulier. ", i))
+            .collect();
+        deltas.push("Voici le SECRET-PROJECT complet.".to_string());
+        deltas.extend((0..40).map(|i| format!("Suite {} de la réponse après le secret. ", i)));
+        let response = sse_response(&deltas);
+
+        // Small pipe so that the stream arrives in many reads
+        let (mut upstream, mut server) = tokio::io::duplex(64);
+        tokio::spawn(async move {
+            let _ = server.write_all(&response).await;
+        });
+        let mut client = Vec::new();
+        let outcome = relay_response(&state, &EXCHANGE, &mut upstream, &mut client)
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RelayOutcome::Close);
+        let forwarded = String::from_utf8_lossy(&client);
+        assert!(forwarded.contains("Paragraphe 0"));
+        assert!(!forwarded.contains("Suite 39"));
+        assert!(forwarded.contains("Contenu interdit"));
+        assert!(client.ends_with(chunked::LAST_CHUNK));
+    }
+
+    #[tokio::test]
+    async fn test_http2_streams_inspected() {
+        use http_body_util::{BodyExt, Full};
+        use hyper::body::{Bytes, Incoming};
+        use hyper::service::service_fn;
+        use hyper::{Request, Response};
+        use hyper_util::rt::{TokioExecutor, TokioIo};
+
+        let prompt_rule = Rule {
+            id: "no-forbidden-word".to_string(),
+            target: RuleTarget::Prompt,
+            condition: RuleCondition::Keyword {
+                keywords: vec!["MOT-INTERDIT".to_string()],
+                match_all: false,
+            },
+            ..block_rule("MOT-INTERDIT")
+        };
+        let (state, _dir) = test_state(vec![block_rule("SECRET-PROJECT"), prompt_rule]).await;
+
+        let (client_io, proxy_client_io) = tokio::io::duplex(64 * 1024);
+        let (proxy_upstream_io, upstream_io) = tokio::io::duplex(64 * 1024);
+
+        // Fake upstream answering with an SSE stream
+        tokio::spawn(async move {
+            let service = service_fn(|req: Request<Incoming>| async move {
+                let text = if req.uri().path().ends_with("/secret") {
+                    "Voici le SECRET-PROJECT"
+                } else {
+                    "Bonjour"
+                };
+                let body = format!(
+                    "data: {}\n\ndata: [DONE]\n\n",
+                    json!({"choices": [{"delta": {"content": text}}]})
+                );
+                Ok::<_, std::convert::Infallible>(
+                    Response::builder()
+                        .header("content-type", "text/event-stream")
+                        .body(Full::new(Bytes::from(body)))
+                        .unwrap(),
+                )
+            });
+            hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+                .serve_connection(TokioIo::new(upstream_io), service)
+                .await
+        });
+        tokio::spawn(http2::proxy_http2(
+            Arc::new(state),
+            "chatgpt",
+            "chatgpt.com".to_string(),
+            proxy_client_io,
+            proxy_upstream_io,
+        ));
+
+        let (mut sender, connection) =
+            hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(client_io))
+                .await
+                .unwrap();
+        tokio::spawn(connection);
+
+        let post = |path: &str, prompt: &str| {
+            let body = json!({"messages": [{"role": "user", "content": prompt}]}).to_string();
+            Request::post(format!("https://chatgpt.com{}", path))
+                .header("content-type", "application/json")
+                .body(Full::new(Bytes::from(body)))
+                .unwrap()
+        };
+
+        // Clean answer relayed as-is
+        let resp = sender
+            .send_request(post("/v1/chat/completions", "Salut"))
+            .await
+            .unwrap();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&body).contains("Bonjour"));
+
+        // Answer matching a Block rule is cut and replaced by the notice
+        let resp = sender
+            .send_request(post("/v1/chat/completions/secret", "Salut"))
+            .await
+            .unwrap();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        let text = String::from_utf8_lossy(&body);
+        assert!(text.contains("Contenu interdit"));
+        assert!(!text.contains("Voici le SECRET-PROJECT"));
+
+        // Prompt matching a Block rule is never forwarded
+        let resp = sender
+            .send_request(post("/v1/chat/completions", "Le MOT-INTERDIT"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 403);
+    }
+
+    /// Masked single-frame client text message
+    fn ws_text_frame(text: &str) -> Vec<u8> {
+        let mask = [1u8, 2, 3, 4];
+        let mut frame = vec![0x81, 0x80 | text.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(text.bytes().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    #[tokio::test]
+    async fn test_websocket_messages_inspected() {
+        let prompt_rule = Rule {
+            target: RuleTarget::Prompt,
+            ..block_rule("MOT-INTERDIT")
+        };
+        let (state, _dir) = test_state(vec![prompt_rule]).await;
+
+        let (mut client, proxy_client) = tokio::io::duplex(4096);
+        let (proxy_upstream, mut upstream) = tokio::io::duplex(4096);
+        let relay = tokio::spawn(async move {
+            let session = websocket::WsSession {
+                platform: "perplexity",
+                host: "www.perplexity.ai",
+                path: "/socket.io/?EIO=4&transport=websocket",
+                extensions: None,
+            };
+            websocket::relay_websocket(&state, &session, proxy_client, proxy_upstream).await
+        });
+
+        // Allowed message reaches the server unchanged
+        let allowed = ws_text_frame(r#"42["perplexity_ask","Bonjour",{}]"#);
+        client.write_all(&allowed).await.unwrap();
+        let mut received = vec![0u8; allowed.len()];
+        upstream.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, allowed);
+
+        // Blocked message closes the connection with 1008; a server frame
+        // not yet complete is held back rather than cut by the close frame
+        upstream
+            .write_all(b"\x81\x05hello\x81\x05wor")
+            .await
+            .unwrap();
+        let mut received = [0u8; 7];
+        client.read_exact(&mut received).await.unwrap();
+        assert_eq!(&received, b"\x81\x05hello");
+        client
+            .write_all(&ws_text_frame(
+                r#"42["perplexity_ask","Le MOT-INTERDIT",{}]"#,
+            ))
+            .await
+            .unwrap();
+        let mut close = Vec::new();
+        client.read_to_end(&mut close).await.unwrap();
+        assert_eq!(close[0], 0x88);
+        assert_eq!(u16::from_be_bytes([close[2], close[3]]), 1008);
+        assert!(String::from_utf8_lossy(&close[4..]).contains("Contenu interdit"));
+
+        relay.await.unwrap().unwrap();
+        let mut rest = Vec::new();
+        upstream.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_websocket_inspected_after_oversized_message() {
+        let prompt_rule = Rule {
+            target: RuleTarget::Prompt,
+            ..block_rule("MOT-INTERDIT")
+        };
+        let (state, _dir) = test_state(vec![prompt_rule]).await;
+
+        let (mut client, proxy_client) = tokio::io::duplex(64 * 1024);
+        let (proxy_upstream, mut upstream) = tokio::io::duplex(64 * 1024);
+        let relay = tokio::spawn(async move {
+            let session = websocket::WsSession {
+                platform: "perplexity",
+                host: "www.perplexity.ai",
+                path: "/socket.io/?EIO=4&transport=websocket",
+                extensions: None,
+            };
+            websocket::relay_websocket(&state, &session, proxy_client, proxy_upstream).await
+        });
+
+        // Binary frame too large to inspect, masked with a zero key
+        let mut oversized = vec![0x82, 0x80 | 127];
+        oversized.extend_from_slice(&(MAX_READ_SIZE as u64 + 1).to_be_bytes());
+        oversized.extend_from_slice(&[0; 4]);
+        oversized.resize(oversized.len() + MAX_READ_SIZE + 1, b'x');
+        let blocked = ws_text_frame(r#"42["perplexity_ask","Le MOT-INTERDIT",{}]"#);
+        let sent = [oversized.clone(), blocked].concat();
+        let writer = tokio::spawn(async move {
+            client.write_all(&sent).await.unwrap();
+            let mut close = Vec::new();
+            client.read_to_end(&mut close).await.unwrap();
+            close
+        });
+
+        // The oversized message is relayed, the next one is still inspected
+        let mut forwarded = Vec::new();
+        upstream.read_to_end(&mut forwarded).await.unwrap();
+        assert!(forwarded == oversized);
+        let close = writer.await.unwrap();
+        assert_eq!(close[0], 0x88);
+        assert_eq!(u16::from_be_bytes([close[2], close[3]]), 1008);
+        relay.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_buffered_answer_replaced_by_block_page() {
+        let (state, _dir) = test_state(vec![block_rule("SECRET-PROJECT")]).await;
+        let body = json!({"choices": [{"message": {"role": "assistant", "content": "Le SECRET-PROJECT"}}]}).to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+
+        let mut upstream = response.as_bytes();
+        let mut client = Vec::new();
+        relay_response(&state, &EXCHANGE, &mut upstream, &mut client)
+            .await
+            .unwrap();
+
+        let forwarded = String::from_utf8_lossy(&client);
+        assert!(forwarded.starts_with("HTTP/1.1 403"));
+        assert!(!forwarded.contains("SECRET-PROJECT"));
+    }
+
+    #[tokio::test]
+    async fn test_block_response_follows_client() {
+        let (state, dir) = test_state(vec![block_rule("SECRET-PROJECT")]).await;
+        let body =
+            json!({"type": "message", "content": [{"type": "text", "text": "Le SECRET-PROJECT"}]})
+                .to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let raw = "POST /v1/messages HTTP/1.1\r\nHost: api.anthropic.com\r\nAccept: application/json\r\nAccept-Language: en-US\r\nContent-Length: 2\r\n\r\n{}";
+        let req = request_parser::parse_raw_request(raw.as_bytes()).unwrap();
+        let client_context = ClientContext::from_request("claude", &req);
+        let exchange = ResponseExchange {
+            platform: "claude",
+            host: "api.anthropic.com",
+            client: Some(&client_context),
+            ..EXCHANGE
+        };
+
+        let mut upstream = response.as_bytes();
+        let mut client = Vec::new();
+        relay_response(&state, &exchange, &mut upstream, &mut client)
+            .await
+            .unwrap();
+
+        // Anthropic error the API client can display, in the user's language
+        let forwarded = String::from_utf8(client).unwrap();
+        assert!(forwarded.starts_with("HTTP/1.1 403 Forbidden\r\n"));
+        assert!(forwarded.contains("Content-Type: application/json\r\n"));
+        let (_, body) = forwarded.split_once("\r\n\r\n").unwrap();
+        let error: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(error["error"]["type"], "permission_error");
+        let message = error["error"]["message"].as_str().unwrap();
+        assert!(message.starts_with("🛡️ Blocked by Icon: Contenu interdit"));
+
+        // The incident ID shown to the user is the one of the event
+        let events = queued_events(&dir, "response_block");
+        let metadata: serde_json::Value =
+            serde_json::from_str(events[0].metadata.as_deref().unwrap()).unwrap();
+        let incident_id = metadata["incident_id"].as_str().unwrap();
+        assert!(message.contains(incident_id));
+    }
+
+    #[tokio::test]
+    async fn test_compressed_answers_inspected() {
+        use std::io::Write;
+
+        let (state, _dir) = test_state(vec![block_rule("SECRET-PROJECT")]).await;
+        let gzip = |data: &[u8]| {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+
+        // Buffered JSON answer
+        let body = gzip(
+            json!({"choices": [{"message": {"content": "Le SECRET-PROJECT"}}]})
+                .to_string()
+                .as_bytes(),
+        );
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        )
+        .into_bytes();
+        response.extend(body);
+        let mut client = Vec::new();
+        relay_response(&state, &EXCHANGE, &mut response.as_slice(), &mut client)
+            .await
+            .unwrap();
+        assert!(String::from_utf8_lossy(&client).starts_with("HTTP/1.1 403"));
+
+        // Streamed answer: the compressed chunks are forwarded untouched
+        // until the decoded text matches, then the body is ended without a
+        // plain-text notice the client could not decode
+        let events: String = ["Le ", "SECRET-PROJECT", " est"]
+            .iter()
+            .map(|d| {
+                format!(
+                    "data: {}\n\n",
+                    json!({"choices": [{"delta": {"content": d}}]})
+                )
+            })
+            .collect();
+        let body = gzip(events.as_bytes());
+        let mut response = b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nContent-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+        response.extend(chunked::encode_chunk(&body));
+        response.extend(chunked::LAST_CHUNK);
+        let mut client = Vec::new();
+        relay_response(&state, &EXCHANGE, &mut response.as_slice(), &mut client)
+            .await
+            .unwrap();
+        let head_end = find_header_end(&client).unwrap() + 4;
+        let (body, _) = chunked::decode_body(&client[head_end..]).unwrap();
+        let mut decoded = Vec::new();
+        flate2::write::GzDecoder::new(&mut decoded)
+            .write_all(&body)
+            .unwrap();
+        assert!(!String::from_utf8_lossy(&decoded).contains("SECRET-PROJECT"));
+        assert!(!String::from_utf8_lossy(&client).contains("content_filter"));
+    }
 
     #[test]
     fn test_parse_connect_target() {
@@ -512,6 +2363,602 @@ mod tests {
         assert_eq!(port, 443);
     }
 
+    fn queued_events(
+        dir: &tempfile::TempDir,
+        event_type: &str,
+    ) -> Vec<crate::storage::database::QueuedEvent> {
+        let db = Database::init(dir.path(), "test-key").unwrap();
+        db.get_pending_events(100)
+            .unwrap()
+            .into_iter()
+            .filter(|e| e.event_type == event_type)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_prompt_metadata_recorded() {
+        let (state, dir) = test_state(vec![]).await;
+        let body = r#"{"action":"next","messages":[{"id":"m1","author":{"role":"user"},"content":{"content_type":"text","parts":["Analyse le fichier"]},"metadata":{"attachments":[{"id":"file-X1","name":"paie.csv","size":2048}]}}],"gizmo_id":"g-Q1w2e3"}"#;
+        let raw = format!(
+            "POST /backend-api/conversation HTTP/1.1\r\nHost: chatgpt.com\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let req = request_parser::parse_raw_request(raw.as_bytes()).unwrap();
+        assert!(matches!(
+            inspect_prompt(&state, "chatgpt", "chatgpt.com", &req).await,
+            PromptVerdict::Forward
+        ));
+
+        let events = queued_events(&dir, "prompt");
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].prompt_excerpt.as_deref(),
+            Some("Analyse le fichier")
+        );
+        let metadata: serde_json::Value =
+            serde_json::from_str(events[0].metadata.as_deref().unwrap()).unwrap();
+        assert_eq!(metadata["gpt_id"], "g-Q1w2e3");
+        assert_eq!(metadata["files"][0]["name"], "paie.csv");
+    }
+
+    #[tokio::test]
+    async fn test_personal_account_blocked() {
+        let account_rule = Rule {
+            target: RuleTarget::Prompt,
+            condition: RuleCondition::Account {
+                types: vec![AccountType::Personal, AccountType::Unknown],
+                platforms: vec!["chatgpt".to_string(), "claude".to_string()],
+            },
+            action: RuleAction::Block {
+                message: "Utilisez l'espace ChatGPT Enterprise".to_string(),
+                template: None,
+            },
+            ..block_rule("")
+        };
+        let (state, dir) = test_state(vec![account_rule]).await;
+        state
+            .domain_filter
+            .set_approved_tenants(vec![ApprovedTenant {
+                platform: "chatgpt".to_string(),
+                tenant_id: "ws-acme".to_string(),
+            }])
+            .await;
+
+        let body = r#"{"action":"next","messages":[{"id":"m1","author":{"role":"user"},"content":{"content_type":"text","parts":["Bonjour"]}}]}"#;
+        let request = |headers: &str| {
+            let raw = format!(
+                "POST /backend-api/conversation HTTP/1.1\r\nHost: chatgpt.com\r\nCookie: oai-did=d1\r\n{}Content-Length: {}\r\n\r\n{}",
+                headers,
+                body.len(),
+                body
+            );
+            request_parser::parse_raw_request(raw.as_bytes()).unwrap()
+        };
+
+        let enterprise = request("ChatGPT-Account-Id: ws-acme\r\n");
+        assert!(matches!(
+            inspect_prompt(&state, "chatgpt", "chatgpt.com", &enterprise).await,
+            PromptVerdict::Forward
+        ));
+        let personal = request("");
+        assert!(matches!(
+            inspect_prompt(&state, "chatgpt", "chatgpt.com", &personal).await,
+            PromptVerdict::Block(_)
+        ));
+
+        // API calls are not accounts: their keys are checked by ApiKey rules
+        let body = r#"{"model":"claude-sonnet-4-5","max_tokens":64,"messages":[{"role":"user","content":"Bonjour"}]}"#;
+        let raw = format!(
+            "POST /v1/messages HTTP/1.1\r\nHost: api.anthropic.com\r\nx-api-key: sk-ant-api03-abc\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let api_call = request_parser::parse_raw_request(raw.as_bytes()).unwrap();
+        assert!(matches!(
+            inspect_prompt(&state, "claude", "api.anthropic.com", &api_call).await,
+            PromptVerdict::Forward
+        ));
+
+        let events = queued_events(&dir, "prompt");
+        assert_eq!(events.len(), 2);
+        let metadata: serde_json::Value =
+            serde_json::from_str(events[0].metadata.as_deref().unwrap()).unwrap();
+        assert_eq!(metadata["account_type"], "enterprise");
+        assert_eq!(metadata["tenant_id"], "ws-acme");
+        let metadata: serde_json::Value =
+            serde_json::from_str(events[1].metadata.as_deref().unwrap()).unwrap();
+        assert!(metadata.get("account_type").is_none());
+        let events = queued_events(&dir, "block");
+        let metadata: serde_json::Value =
+            serde_json::from_str(events[0].metadata.as_deref().unwrap()).unwrap();
+        assert_eq!(metadata["account_type"], "personal");
+    }
+
+    #[tokio::test]
+    async fn test_api_key_fingerprint_recorded() {
+        let key_rule = Rule {
+            target: RuleTarget::Prompt,
+            condition: RuleCondition::ApiKey {
+                allowed_fingerprints: vec![api_keys::fingerprint("sk-corporate")],
+                platforms: Vec::new(),
+            },
+            action: RuleAction::Block {
+                message: "Clé d'API non autorisée".to_string(),
+                template: None,
+            },
+            ..block_rule("")
+        };
+        let (state, dir) = test_state(vec![key_rule]).await;
+
+        let body =
+            r#"{"model":"claude-sonnet-4-5","messages":[{"role":"user","content":"Bonjour"}]}"#;
+        let request = |key: &str| {
+            let raw = format!(
+                "POST /v1/messages HTTP/1.1\r\nHost: api.anthropic.com\r\nx-api-key: {}\r\nContent-Length: {}\r\n\r\n{}",
+                key,
+                body.len(),
+                body
+            );
+            request_parser::parse_raw_request(raw.as_bytes()).unwrap()
+        };
+
+        assert!(matches!(
+            inspect_prompt(
+                &state,
+                "claude",
+                "api.anthropic.com",
+                &request("sk-corporate")
+            )
+            .await,
+            PromptVerdict::Forward
+        ));
+        assert!(matches!(
+            inspect_prompt(
+                &state,
+                "claude",
+                "api.anthropic.com",
+                &request("sk-ant-personal")
+            )
+            .await,
+            PromptVerdict::Block(_)
+        ));
+
+        let events = queued_events(&dir, "block");
+        let metadata = events[0].metadata.as_deref().unwrap();
+        assert!(!metadata.contains("sk-ant-personal"));
+        let metadata: serde_json::Value = serde_json::from_str(metadata).unwrap();
+        assert_eq!(
+            metadata["api_key_fingerprint"],
+            api_keys::fingerprint("sk-ant-personal")
+        );
+        assert_eq!(metadata["api_key_source"], "x-api-key");
+    }
+
+    #[tokio::test]
+    async fn test_answer_citations_recorded() {
+        let (state, dir) = test_state(vec![]).await;
+        let body = json!({
+            "citations": ["https://www.cnil.fr/"],
+            "choices": [{"message": {"role": "assistant", "content": "Selon la CNIL [1]"}}]
+        })
+        .to_string();
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        let exchange = ResponseExchange {
+            platform: "perplexity",
+            host: "api.perplexity.ai",
+            ..EXCHANGE
+        };
+
+        let mut upstream = response.as_bytes();
+        let mut client = Vec::new();
+        relay_response(&state, &exchange, &mut upstream, &mut client)
+            .await
+            .unwrap();
+
+        let events = queued_events(&dir, "response");
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].response_excerpt.as_deref(),
+            Some("Selon la CNIL [1]")
+        );
+        let metadata: serde_json::Value =
+            serde_json::from_str(events[0].metadata.as_deref().unwrap()).unwrap();
+        assert_eq!(metadata["citations"], json!(["https://www.cnil.fr/"]));
+    }
+
+    #[test]
+    fn test_strip_port() {
+        assert_eq!(strip_port("chatgpt.com:443"), "chatgpt.com");
+        assert_eq!(strip_port("chatgpt.com"), "chatgpt.com");
+        assert_eq!(strip_port("[::1]:8080"), "::1");
+    }
+
+    #[tokio::test]
+    async fn test_host_header_mismatch() {
+        let (state, dir) = test_state(vec![]).await;
+        state
+            .domain_filter
+            .update_domains(vec![
+                ("chatgpt.com".to_string(), false),
+                ("claude.ai".to_string(), true),
+            ])
+            .await;
+
+        let check = HostCheck::new("chatgpt.com", "chatgpt");
+        assert_eq!(
+            check.request_platform(&state, "ChatGPT.com:443").await,
+            Some("chatgpt")
+        );
+        assert!(queued_events(&dir, "evasion").is_empty());
+
+        // Fronted request to a blocked platform: refused, reported once
+        assert_eq!(check.request_platform(&state, "claude.ai").await, None);
+        assert_eq!(check.request_platform(&state, "claude.ai").await, None);
+        let events = queued_events(&dir, "evasion");
+        assert_eq!(events.len(), 1);
+        let metadata: serde_json::Value =
+            serde_json::from_str(events[0].metadata.as_deref().unwrap()).unwrap();
+        assert_eq!(metadata["check"], "host_header");
+        assert_eq!(metadata["declared_host"], "chatgpt.com");
+        assert_eq!(metadata["actual_host"], "claude.ai");
+    }
+
+    #[tokio::test]
+    async fn test_host_header_names_other_platform() {
+        let (state, dir) = test_state(vec![]).await;
+        let check = HostCheck::new("api.openai.com", "chatgpt");
+
+        // Fronted to another monitored platform: inspected as that platform
+        assert_eq!(
+            check.request_platform(&state, "api.anthropic.com").await,
+            Some("claude")
+        );
+        assert_eq!(queued_events(&dir, "evasion").len(), 1);
+        // Unknown host: the session's platform still applies
+        assert_eq!(
+            check.request_platform(&state, "cdn.example.com").await,
+            Some("chatgpt")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_sni_mismatch_reported() {
+        let (state, dir) = test_state(vec![]).await;
+        let state = Arc::new(state);
+
+        // Upstream that drops connections: the interception fails after the
+        // routing decision
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = upstream.accept().await {
+                drop(stream);
+            }
+        });
+
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = tokio::net::TcpStream::connect(proxy.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server_side, _) = proxy.accept().await.unwrap();
+        let handler = tokio::spawn(handle_connection(server_side, state.clone()));
+
+        // CONNECT to an unlisted address, then SNI of a monitored platform
+        client
+            .write_all(format!("CONNECT 127.0.0.1:{} HTTP/1.1\r\n\r\n", upstream_port).as_bytes())
+            .await
+            .unwrap();
+        let mut established = [0u8; 39];
+        client.read_exact(&mut established).await.unwrap();
+        assert!(established.starts_with(b"HTTP/1.1 200"));
+
+        let config = rustls::ClientConfig::builder()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth();
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+        let server_name = CaManager::server_name("api.openai.com").unwrap();
+        let _ = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            connector.connect(server_name, client),
+        )
+        .await;
+        let _ = tokio::time::timeout(std::time::Duration::from_secs(5), handler).await;
+
+        let events = queued_events(&dir, "evasion");
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].platform.as_deref(), Some("chatgpt"));
+        let metadata: serde_json::Value =
+            serde_json::from_str(events[0].metadata.as_deref().unwrap()).unwrap();
+        assert_eq!(metadata["check"], "sni");
+        assert_eq!(metadata["declared_host"], "127.0.0.1");
+        assert_eq!(metadata["actual_host"], "api.openai.com");
+    }
+
+    #[tokio::test]
+    async fn test_connect_server_first_protocol_relayed() {
+        let (state, _dir) = test_state(vec![]).await;
+
+        // Server that greets first, like SMTP
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            if let Ok((mut stream, _)) = upstream.accept().await {
+                let _ = stream.write_all(b"220 mail.gs2e.local ESMTP\r\n").await;
+                let _ = stream.read(&mut [0u8; 16]).await;
+            }
+        });
+
+        let proxy = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = tokio::net::TcpStream::connect(proxy.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server_side, _) = proxy.accept().await.unwrap();
+        tokio::spawn(handle_connection(server_side, Arc::new(state)));
+
+        client
+            .write_all(format!("CONNECT 127.0.0.1:{} HTTP/1.1\r\n\r\n", upstream_port).as_bytes())
+            .await
+            .unwrap();
+        let mut established = [0u8; 39];
+        client.read_exact(&mut established).await.unwrap();
+
+        // The client sends nothing: the greeting still arrives
+        let mut greeting = [0u8; 27];
+        tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            client.read_exact(&mut greeting),
+        )
+        .await
+        .expect("tunnel waited for client bytes")
+        .unwrap();
+        assert_eq!(&greeting, b"220 mail.gs2e.local ESMTP\r\n");
+    }
+
+    #[test]
+    fn test_parse_absolute_target() {
+        assert_eq!(
+            parse_absolute_target("POST http://10.0.0.5:11434/api/chat HTTP/1.1\r\nHost: x\r\n"),
+            Some(("10.0.0.5".to_string(), 11434, "/api/chat".to_string()))
+        );
+        assert_eq!(
+            parse_absolute_target("GET HTTP://llm.gs2e.local?x=1 HTTP/1.1\r\n"),
+            Some(("llm.gs2e.local".to_string(), 80, "/?x=1".to_string()))
+        );
+        assert_eq!(
+            parse_absolute_target("GET http://user:pw@[::1]:8080/v1/models HTTP/1.1\r\n"),
+            Some(("::1".to_string(), 8080, "/v1/models".to_string()))
+        );
+        assert!(parse_absolute_target("GET /proxy.pac HTTP/1.1\r\n").is_none());
+        assert!(parse_absolute_target("CONNECT api.openai.com:443 HTTP/1.1\r\n").is_none());
+        assert!(parse_absolute_target("GET https://api.openai.com/ HTTP/1.1\r\n").is_none());
+        assert!(parse_absolute_target("GET http://host:bad/ HTTP/1.1\r\n").is_none());
+    }
+
+    #[test]
+    fn test_to_origin_form() {
+        let request = b"POST http://llm.local/api/chat HTTP/1.1\r\nHost: llm.local\r\nProxy-Connection: keep-alive\r\nContent-Length: 2\r\n\r\n{}";
+        assert_eq!(
+            to_origin_form(request, "/api/chat"),
+            b"POST /api/chat HTTP/1.1\r\nHost: llm.local\r\nContent-Length: 2\r\n\r\n{}"
+        );
+    }
+
+    #[test]
+    fn test_with_identity_encoding() {
+        let request = b"POST /v1/chat/completions HTTP/1.1\r\nHost: api.openai.com\r\naccept-encoding: gzip, br\r\nContent-Length: 2\r\n\r\n{}";
+        assert_eq!(
+            with_identity_encoding(request),
+            b"POST /v1/chat/completions HTTP/1.1\r\nHost: api.openai.com\r\nContent-Length: 2\r\nAccept-Encoding: identity\r\n\r\n{}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plain_http_upload_streamed_to_unmonitored_host() {
+        let (state, _dir) = test_state(vec![]).await;
+        let body_len = MAX_READ_SIZE + 1;
+
+        // Server counting the bytes of an upload too large to be buffered
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let (head, body_start) = read_http_head(&mut stream).await.unwrap();
+            let mut received = head.len() - body_start.unwrap();
+            let mut chunk = vec![0u8; 64 * 1024];
+            while received < body_len {
+                received += stream.read(&mut chunk).await.unwrap();
+            }
+            stream
+                .write_all(
+                    b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                )
+                .await
+                .unwrap();
+            received
+        });
+
+        let (mut client, proxy_side) = tokio::io::duplex(64 * 1024);
+        let upload = async {
+            let head = format!(
+                "PUT http://127.0.0.1:{}/upload HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nContent-Length: {}\r\n\r\n",
+                port, port, body_len
+            );
+            client.write_all(head.as_bytes()).await.unwrap();
+            client.write_all(&vec![b'x'; body_len]).await.unwrap();
+            let mut response = Vec::new();
+            client.read_to_end(&mut response).await.unwrap();
+            response
+        };
+        let (_, response) = tokio::join!(proxy_plain_http(&state, proxy_side), upload);
+
+        assert!(String::from_utf8_lossy(&response).starts_with("HTTP/1.1 201"));
+        assert_eq!(server.await.unwrap(), body_len);
+    }
+
+    #[tokio::test]
+    async fn test_plain_http_requests_inspected() {
+        let prompt_rule = Rule {
+            target: RuleTarget::Prompt,
+            ..block_rule("SECRET-PROJECT")
+        };
+        let (state, _dir) = test_state(vec![prompt_rule]).await;
+        state
+            .domain_filter
+            .update_domains(vec![("127.0.0.1".to_string(), false)])
+            .await;
+
+        // Fake LLM gateway answering one request
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let gateway = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let request = read_http_message(&mut stream).await.unwrap();
+            let body = json!({"choices": [{"message": {"content": "Bonjour"}}]}).to_string();
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            stream.write_all(response.as_bytes()).await.unwrap();
+            request
+        });
+
+        let request = |content: &str| {
+            let body =
+                json!({"model": "llama3", "messages": [{"role": "user", "content": content}]})
+                    .to_string();
+            format!(
+                "POST http://127.0.0.1:{}/v1/chat/completions HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                port, port, body.len(), body
+            )
+        };
+
+        let (mut client, proxy_side) = tokio::io::duplex(64 * 1024);
+        let exchange = async {
+            client
+                .write_all(request("Voici le SECRET-PROJECT").as_bytes())
+                .await
+                .unwrap();
+            let blocked = read_http_message(&mut client).await.unwrap();
+            client
+                .write_all(request("Bonjour").as_bytes())
+                .await
+                .unwrap();
+            let mut forwarded = Vec::new();
+            client.read_to_end(&mut forwarded).await.unwrap();
+            (blocked, forwarded)
+        };
+        let (_, (blocked, forwarded)) =
+            tokio::join!(proxy_plain_http(&state, proxy_side), exchange);
+
+        assert!(String::from_utf8_lossy(&blocked).starts_with("HTTP/1.1 403"));
+        assert!(String::from_utf8_lossy(&forwarded).contains("Bonjour"));
+        let received = String::from_utf8(gateway.await.unwrap()).unwrap();
+        assert!(received.starts_with("POST /v1/chat/completions HTTP/1.1\r\n"));
+        assert!(!received.contains("SECRET-PROJECT"));
+    }
+
+    #[tokio::test]
+    async fn test_blocked_platform_redirected_to_approved_ai() {
+        let (state, dir) = test_state(vec![]).await;
+
+        // Internal OpenAI-compatible endpoint answering one request
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let gateway = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let request = read_http_message(&mut stream).await.unwrap();
+            let body =
+                json!({"choices": [{"message": {"content": "Réponse interne"}}]}).to_string();
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            stream.write_all(response.as_bytes()).await.unwrap();
+            request
+        });
+        let approved = ApprovedAi {
+            assistant_url: Some("https://marcelia.gs2e.ci/?q={{PROMPT}}".to_string()),
+            endpoint: Some(
+                ApiEndpoint::parse(
+                    &format!("http://127.0.0.1:{}/v1", port),
+                    Some("internal-key".to_string()),
+                    Some("llama3".to_string()),
+                )
+                .unwrap(),
+            ),
+        };
+        let answer = |raw: String| {
+            let state = &state;
+            let approved = &approved;
+            async move {
+                let mut client = tokio::io::duplex(64 * 1024);
+                answer_blocked_request(
+                    state,
+                    approved,
+                    "chatgpt",
+                    "api.openai.com",
+                    raw.as_bytes(),
+                    &mut client.0,
+                )
+                .await
+                .unwrap();
+                drop(client.0);
+                let mut response = Vec::new();
+                client.1.read_to_end(&mut response).await.unwrap();
+                String::from_utf8(response).unwrap()
+            }
+        };
+
+        // API call rerouted to the internal endpoint, with its key and model
+        let body = json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "Bonjour"}]})
+            .to_string();
+        let response = answer(format!(
+            "POST /v1/chat/completions HTTP/1.1\r\nHost: api.openai.com\r\nAuthorization: Bearer sk-user\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ))
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("Réponse interne"));
+        let received = String::from_utf8(gateway.await.unwrap()).unwrap();
+        assert!(received.starts_with(&format!(
+            "POST /v1/chat/completions HTTP/1.1\r\nHost: 127.0.0.1:{}\r\n",
+            port
+        )));
+        assert!(received.contains("Authorization: Bearer internal-key\r\n"));
+        assert!(!received.contains("sk-user"));
+        assert!(received.contains(r#""model":"llama3""#));
+        assert_eq!(queued_events(&dir, "prompt").len(), 1);
+
+        // Navigation redirected to the approved assistant with its prompt
+        let response = answer(
+            "GET /?q=R%C3%A9sume+ce+texte HTTP/1.1\r\nHost: chatgpt.com\r\nAccept: text/html\r\n\r\n".to_string(),
+        )
+        .await;
+        assert!(response.starts_with(
+            "HTTP/1.1 302 Found\r\nLocation: https://marcelia.gs2e.ci/?q=R%C3%A9sume%20ce%20texte\r\n"
+        ));
+
+        // Other requests are blocked
+        let response = answer(
+            "POST /v1/messages HTTP/1.1\r\nHost: api.anthropic.com\r\nContent-Length: 2\r\n\r\n{}"
+                .to_string(),
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden"));
+
+        let events = queued_events(&dir, "domain_block");
+        let actions: Vec<serde_json::Value> = events
+            .iter()
+            .map(|e| {
+                serde_json::from_str::<serde_json::Value>(e.metadata.as_deref().unwrap()).unwrap()
+            })
+            .collect();
+        assert_eq!(actions[0]["action"], "reroute");
+        assert_eq!(actions[1]["action"], "redirect");
+        assert_eq!(events[1].prompt_excerpt.as_deref(), Some("Résume ce texte"));
+        assert!(actions[2]["incident_id"].is_string());
+    }
+
     #[test]
     fn test_parse_connect_custom_port() {
         let req = "CONNECT example.com:8443 HTTP/1.1\r\n\r\n";
@@ -526,14 +2973,8 @@ mod tests {
             extract_content_length("Content-Length: 42\r\nHost: x"),
             Some(42)
         );
-        assert_eq!(
-            extract_content_length("content-length: 100\r\n"),
-            Some(100)
-        );
-        assert_eq!(
-            extract_content_length("Host: x\r\nAccept: */*"),
-            None
-        );
+        assert_eq!(extract_content_length("content-length: 100\r\n"), Some(100));
+        assert_eq!(extract_content_length("Host: x\r\nAccept: */*"), None);
     }
 
     #[test]
@@ -545,17 +2986,68 @@ mod tests {
         assert_eq!(find_header_end(data), None);
     }
 
-    #[test]
-    fn test_chunked_complete() {
-        assert!(is_chunked_complete(b"hello\r\n0\r\n\r\n"));
-        assert!(!is_chunked_complete(b"hello\r\n"));
-        assert!(!is_chunked_complete(b""));
+    #[tokio::test]
+    async fn test_read_chunked_request_split_reads() {
+        let body = json!({"model": "gpt-4", "messages": [{"role": "user", "content": "Bonjour 0\r\n\r\n"}]}).to_string();
+        let mut request = b"POST /v1/chat/completions HTTP/1.1\r\nHost: api.openai.com\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+        for part in body.as_bytes().chunks(10) {
+            request.extend(chunked::encode_chunk(part));
+        }
+        request.extend_from_slice(b"0\r\nX-Trailer: 1\r\n\r\n");
+
+        // Deliver the message a few bytes at a time, cutting chunk-size
+        // lines, data and CRLFs
+        let (mut client, mut server) = tokio::io::duplex(8);
+        let sent = request.clone();
+        tokio::spawn(async move {
+            for piece in sent.chunks(3) {
+                server.write_all(piece).await.unwrap();
+            }
+        });
+
+        let message = read_http_message(&mut client).await.unwrap();
+        assert_eq!(message, request);
+
+        let parsed = request_parser::parse_raw_request(&message).unwrap();
+        assert_eq!(parsed.body, body.as_bytes());
+        assert!(parsed
+            .headers
+            .iter()
+            .any(|(k, v)| k == "X-Trailer" && v == "1"));
+        assert_eq!(
+            request_parser::extract_prompt(&parsed.body, "chatgpt").as_deref(),
+            Some("Bonjour 0\r\n\r\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_request_with_content_length_and_chunked_rejected() {
+        // Framed by Content-Length, the chunked body would end early and the
+        // rest would reach the upstream as an uninspected second request
+        let smuggled = "POST /v1/chat/completions HTTP/1.1\r\nHost: api.openai.com\r\n\
+                        Content-Length: 4\r\nTransfer-Encoding: chunked\r\n\r\n\
+                        0\r\n\r\nPOST /v1/chat/completions HTTP/1.1\r\nHost: api.openai.com\r\n\r\n";
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        server.write_all(smuggled.as_bytes()).await.unwrap();
+        assert!(read_http_message(&mut client).await.is_err());
+
+        // Chunked framing is recognized whatever the header case and codings
+        let request =
+            b"POST / HTTP/1.1\r\ntransfer-encoding: gzip, Chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n";
+        let (mut client, mut server) = tokio::io::duplex(1024);
+        server.write_all(request).await.unwrap();
+        drop(server);
+        assert_eq!(read_http_message(&mut client).await.unwrap(), request);
     }
 
     #[test]
     fn test_is_pac_request() {
-        assert!(is_pac_request("GET /proxy.pac HTTP/1.1\r\nHost: 127.0.0.1:8443\r\n\r\n"));
-        assert!(!is_pac_request("CONNECT api.openai.com:443 HTTP/1.1\r\n\r\n"));
+        assert!(is_pac_request(
+            "GET /proxy.pac HTTP/1.1\r\nHost: 127.0.0.1:8443\r\n\r\n"
+        ));
+        assert!(!is_pac_request(
+            "CONNECT api.openai.com:443 HTTP/1.1\r\n\r\n"
+        ));
         assert!(!is_pac_request("GET /other HTTP/1.1\r\n\r\n"));
         assert!(!is_pac_request("POST /proxy.pac HTTP/1.1\r\n\r\n"));
     }
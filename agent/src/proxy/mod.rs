@@ -1,5 +1,21 @@
+pub mod account;
+pub mod api_keys;
+pub mod approved_ai;
+pub mod block_page;
+pub mod chunked;
+pub mod conversation;
+pub mod domain_filter;
+pub mod domain_pattern;
+pub mod encoding;
+pub mod extractors;
+pub mod http2;
 pub mod interceptor;
-pub mod tls;
+pub mod platforms;
 pub mod request_parser;
-pub mod domain_filter;
+pub mod sni;
+pub mod stream;
 pub mod system_proxy;
+pub mod tls;
+pub mod transparent;
+pub mod upstream;
+pub mod websocket;
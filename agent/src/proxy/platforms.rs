@@ -0,0 +1,533 @@
+use std::collections::HashSet;
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::proxy::domain_pattern::{DomainPattern, PatternSet};
+
+/// Key of the local config table caching the catalog synced from the server
+pub const CATALOG_CONFIG_KEY: &str = "platform_catalog";
+
+/// Built-in catalog, used until the server sends its own. Entries synced
+/// from the server replace the built-in ones with the same name.
+const BUILTIN_CATALOG: &str = r#"[
+    {
+        "name": "chatgpt",
+        "domains": ["openai.com", "chatgpt.com"],
+        "api_paths": ["/v1/chat/completions", "/backend-api/conversation", "/backend-anon/conversation"],
+        "prompt_paths": ["messages[role=user][-1].content"],
+        "response_paths": ["choices[0].message.content"],
+        "stream_paths": ["choices[0].delta.content"],
+        "format": "chatgpt"
+    },
+    {
+        "name": "claude",
+        "domains": ["claude.ai", "anthropic.com"],
+        "api_paths": ["regex:/api/(.*/)?chat|/chat.*/api/", "/v1/messages", "/api/organizations"],
+        "format": "anthropic"
+    },
+    {
+        "name": "copilot",
+        "domains": ["copilot.microsoft.com", "github.copilot.com"],
+        "api_paths": ["/v1/engines", "/v1/completions", "/chat/completions"],
+        "response_paths": ["choices[0].message.content"],
+        "stream_paths": ["choices[0].delta.content"],
+        "format": "copilot"
+    },
+    {
+        "name": "gemini",
+        "domains": ["gemini.google.com", "generativelanguage.googleapis.com"],
+        "api_paths": ["/v1beta/models", "/v1/models", ":generateContent"],
+        "prompt_paths": ["contents[role=user][-1].parts[*].text"],
+        "format": "gemini"
+    },
+    {
+        "name": "mistral",
+        "domains": ["mistral.ai"],
+        "api_paths": ["/v1/chat/completions"],
+        "prompt_paths": ["messages[role=user][-1].content"],
+        "format": "mistral"
+    },
+    {
+        "name": "perplexity",
+        "domains": ["perplexity.ai"],
+        "api_paths": ["/chat/completions"],
+        "prompt_paths": ["messages[role=user][-1].content"],
+        "format": "perplexity"
+    },
+    {
+        "name": "huggingface",
+        "domains": ["huggingface.co"]
+    }
+]"#;
+
+/// Current catalog
+static CATALOG: LazyLock<RwLock<Arc<PlatformCatalog>>> =
+    LazyLock::new(|| RwLock::new(Arc::new(PlatformCatalog::new(builtin_specs()))));
+
+/// Platform names handed out as `&'static str`; the set of names is small
+/// and each one is leaked once
+static PLATFORM_NAMES: LazyLock<Mutex<HashSet<&'static str>>> =
+    LazyLock::new(|| Mutex::new(HashSet::new()));
+
+/// Declarative description of an AI platform, as sent by the server in the
+/// domain sync response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlatformSpec {
+    pub name: String,
+    /// Domain patterns (see [`DomainPattern`])
+    pub domains: Vec<String>,
+    /// Request paths carrying prompts: substrings, or `regex:` followed by a
+    /// regular expression
+    #[serde(default)]
+    pub api_paths: Vec<String>,
+    /// JSON paths of the prompt in request bodies, tried in order
+    #[serde(default)]
+    pub prompt_paths: Vec<String>,
+    /// JSON paths of the answer in buffered response bodies
+    #[serde(default)]
+    pub response_paths: Vec<String>,
+    /// How streamed answers are framed
+    #[serde(default)]
+    pub stream_format: StreamFormat,
+    /// JSON paths of the text delta in each streamed event
+    #[serde(default)]
+    pub stream_paths: Vec<String>,
+    /// Native payload extractor (see `proxy::extractors`), tried before the
+    /// JSON paths
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+/// Framing of a streamed answer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamFormat {
+    /// Server-Sent Events (`data:` lines)
+    #[default]
+    Sse,
+    /// One JSON document per line
+    Ndjson,
+}
+
+/// Compiled platform catalog
+pub struct PlatformCatalog {
+    platforms: Vec<Platform>,
+}
+
+struct Platform {
+    name: &'static str,
+    domains: PatternSet,
+    api_paths: Vec<PathPattern>,
+    prompt_paths: Vec<JsonPath>,
+    response_paths: Vec<JsonPath>,
+    stream_format: StreamFormat,
+    stream_paths: Vec<JsonPath>,
+    format: Option<String>,
+}
+
+enum PathPattern {
+    Contains(String),
+    Regex(Regex),
+}
+
+impl PlatformCatalog {
+    /// Compile the specs; invalid patterns and paths are skipped
+    pub fn new(specs: Vec<PlatformSpec>) -> Self {
+        let platforms = specs
+            .into_iter()
+            .map(|spec| Platform {
+                name: intern(&spec.name),
+                domains: PatternSet::new(
+                    spec.domains
+                        .iter()
+                        .flat_map(|d| compile(&spec.name, d, DomainPattern::parse))
+                        .flatten()
+                        .collect(),
+                ),
+                api_paths: spec
+                    .api_paths
+                    .iter()
+                    .filter_map(|p| compile(&spec.name, p, PathPattern::parse))
+                    .collect(),
+                prompt_paths: compile_json_paths(&spec.name, &spec.prompt_paths),
+                response_paths: compile_json_paths(&spec.name, &spec.response_paths),
+                stream_format: spec.stream_format,
+                stream_paths: compile_json_paths(&spec.name, &spec.stream_paths),
+                format: spec.format,
+            })
+            .collect();
+        Self { platforms }
+    }
+
+    /// Platform whose domains match `host`
+    pub fn identify(&self, host: &str) -> Option<&'static str> {
+        self.platforms
+            .iter()
+            .find(|p| p.domains.matches(host))
+            .map(|p| p.name)
+    }
+
+    fn platform(&self, name: &str) -> Option<&Platform> {
+        self.platforms.iter().find(|p| p.name == name)
+    }
+
+    /// Whether `path` carries prompts on `platform`. None when the platform
+    /// is unknown or declares no API paths.
+    pub fn is_api_endpoint(&self, path: &str, platform: &str) -> Option<bool> {
+        let platform = self
+            .platform(platform)
+            .filter(|p| !p.api_paths.is_empty())?;
+        Some(platform.api_paths.iter().any(|p| p.matches(path)))
+    }
+
+    /// Prompt of a request body. None when the platform is unknown or
+    /// declares no prompt paths; Some(None) when the paths found nothing.
+    pub fn extract_prompt(&self, body: &str, platform: &str) -> Option<Option<String>> {
+        let platform = self
+            .platform(platform)
+            .filter(|p| !p.prompt_paths.is_empty())?;
+        Some(extract_paths(body, &platform.prompt_paths))
+    }
+
+    /// Answer of a buffered response body
+    pub fn extract_response(&self, body: &str, platform: &str) -> Option<String> {
+        extract_paths(body, &self.platform(platform)?.response_paths)
+    }
+
+    /// Text delta of one streamed event
+    pub fn extract_stream_event(&self, data: &str, platform: &str) -> Option<String> {
+        extract_paths(data, &self.platform(platform)?.stream_paths)
+    }
+
+    /// Native payload format of a platform
+    pub fn format(&self, platform: &str) -> Option<&str> {
+        self.platform(platform)?.format.as_deref()
+    }
+
+    /// Stream framing of a platform (SSE when unknown)
+    pub fn stream_format(&self, platform: &str) -> StreamFormat {
+        self.platform(platform)
+            .map(|p| p.stream_format)
+            .unwrap_or_default()
+    }
+}
+
+/// Current platform catalog
+pub fn catalog() -> Arc<PlatformCatalog> {
+    CATALOG
+        .read()
+        .map(|c| c.clone())
+        .unwrap_or_else(|e| e.into_inner().clone())
+}
+
+/// Built-in platform specs
+pub fn builtin_specs() -> Vec<PlatformSpec> {
+    serde_json::from_str(BUILTIN_CATALOG).expect("built-in platform catalog is valid JSON")
+}
+
+/// Install specs synced from the server (or read from the local cache) on
+/// top of the built-in catalog
+pub fn set_catalog(specs: Vec<PlatformSpec>) {
+    let mut merged: Vec<PlatformSpec> = builtin_specs()
+        .into_iter()
+        .filter(|builtin| !specs.iter().any(|s| s.name == builtin.name))
+        .collect();
+    // Server entries come first so they take precedence for shared domains
+    merged.splice(0..0, specs);
+
+    let catalog = Arc::new(PlatformCatalog::new(merged));
+    match CATALOG.write() {
+        Ok(mut current) => *current = catalog,
+        Err(e) => *e.into_inner() = catalog,
+    }
+}
+
+fn intern(name: &str) -> &'static str {
+    let mut names = PLATFORM_NAMES.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(existing) = names.get(name) {
+        return existing;
+    }
+    let leaked: &'static str = Box::leak(name.to_string().into_boxed_str());
+    names.insert(leaked);
+    leaked
+}
+
+fn compile<T>(
+    platform: &str,
+    source: &str,
+    parse: impl Fn(&str) -> anyhow::Result<T>,
+) -> Option<T> {
+    match parse(source) {
+        Ok(compiled) => Some(compiled),
+        Err(e) => {
+            warn!(platform, entry = %source, error = %e, "Skipping invalid platform catalog entry");
+            None
+        }
+    }
+}
+
+fn compile_json_paths(platform: &str, paths: &[String]) -> Vec<JsonPath> {
+    paths
+        .iter()
+        .filter_map(|p| compile(platform, p, JsonPath::parse))
+        .collect()
+}
+
+fn extract_paths(body: &str, paths: &[JsonPath]) -> Option<String> {
+    if paths.is_empty() {
+        return None;
+    }
+    let value: Value = serde_json::from_str(body).ok()?;
+    paths.iter().find_map(|path| path.extract_text(&value))
+}
+
+/// Prefix of the `api_paths` entries that are regular expressions
+const REGEX_PREFIX: &str = "regex:";
+
+impl PathPattern {
+    fn parse(source: &str) -> anyhow::Result<Self> {
+        match source.strip_prefix(REGEX_PREFIX) {
+            Some(expr) if !expr.is_empty() => Ok(PathPattern::Regex(Regex::new(expr)?)),
+            Some(_) => anyhow::bail!("Empty path regex"),
+            None => Ok(PathPattern::Contains(source.to_string())),
+        }
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        match self {
+            PathPattern::Contains(s) => path.contains(s.as_str()),
+            PathPattern::Regex(r) => r.is_match(path),
+        }
+    }
+}
+
+/// Minimal JSON path: `a.b` (object fields), `[2]` / `[-1]` (array index,
+/// negative from the end), `[*]` (every element) and `[role=user]` (array
+/// elements whose string field equals the value, kept as an array).
+/// E.g. `messages[role=user][-1].content`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath(Vec<Segment>);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Field(String),
+    Index(i64),
+    All,
+    Filter(String, String),
+}
+
+impl JsonPath {
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        let mut segments = Vec::new();
+        for part in source.split('.') {
+            let (field, mut rest) = match part.find('[') {
+                Some(i) => part.split_at(i),
+                None => (part, ""),
+            };
+            if !field.is_empty() {
+                segments.push(Segment::Field(field.to_string()));
+            } else if rest.is_empty() {
+                anyhow::bail!("empty segment in JSON path {}", source);
+            }
+            while let Some(inner) = rest.strip_prefix('[') {
+                let end = inner
+                    .find(']')
+                    .ok_or_else(|| anyhow::anyhow!("unclosed bracket in JSON path {}", source))?;
+                let selector = &inner[..end];
+                segments.push(if selector == "*" {
+                    Segment::All
+                } else if let Some((key, value)) = selector.split_once('=') {
+                    Segment::Filter(key.to_string(), value.to_string())
+                } else {
+                    Segment::Index(selector.parse().map_err(|_| {
+                        anyhow::anyhow!("invalid selector [{}] in JSON path {}", selector, source)
+                    })?)
+                });
+                rest = &inner[end + 1..];
+            }
+            if !rest.is_empty() {
+                anyhow::bail!("unexpected {} in JSON path {}", rest, source);
+            }
+        }
+        Ok(JsonPath(segments))
+    }
+
+    /// Values selected by the path
+    pub fn select<'a>(&self, value: &'a Value) -> Vec<&'a Value> {
+        let mut current = vec![Node::One(value)];
+        for segment in &self.0 {
+            current = current
+                .into_iter()
+                .flat_map(|node| step(node, segment))
+                .collect();
+        }
+        current
+            .into_iter()
+            .flat_map(|node| match node {
+                Node::One(v) => vec![v],
+                Node::Filtered(items) => items,
+            })
+            .collect()
+    }
+
+    /// Text of the selected values, joined by newlines: strings as-is, other
+    /// values as JSON. None when nothing non-empty is selected.
+    pub fn extract_text(&self, value: &Value) -> Option<String> {
+        let texts: Vec<String> = self
+            .select(value)
+            .into_iter()
+            .filter_map(|v| match v {
+                Value::String(s) if !s.is_empty() => Some(s.clone()),
+                Value::Null | Value::String(_) => None,
+                other => Some(other.to_string()),
+            })
+            .collect();
+        (!texts.is_empty()).then(|| texts.join("\n"))
+    }
+}
+
+/// Value reached while walking a path: a JSON value, or the elements of an
+/// array kept by a filter
+enum Node<'a> {
+    One(&'a Value),
+    Filtered(Vec<&'a Value>),
+}
+
+fn step<'a>(node: Node<'a>, segment: &Segment) -> Vec<Node<'a>> {
+    let items: Vec<&'a Value> = match node {
+        Node::One(Value::Object(map)) => {
+            return match segment {
+                Segment::Field(name) => map.get(name).map(Node::One).into_iter().collect(),
+                _ => vec![],
+            };
+        }
+        Node::One(Value::Array(items)) => items.iter().collect(),
+        Node::Filtered(items) => items,
+        Node::One(_) => return vec![],
+    };
+
+    match segment {
+        Segment::Field(_) => vec![],
+        Segment::Index(i) => {
+            let len = items.len() as i64;
+            let index = if *i < 0 { len + i } else { *i };
+            if (0..len).contains(&index) {
+                vec![Node::One(items[index as usize])]
+            } else {
+                vec![]
+            }
+        }
+        Segment::All => items.into_iter().map(Node::One).collect(),
+        Segment::Filter(key, expected) => vec![Node::Filtered(
+            items
+                .into_iter()
+                .filter(|item| item.get(key).and_then(|v| v.as_str()) == Some(expected.as_str()))
+                .collect(),
+        )],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_paths() {
+        let body: Value = serde_json::from_str(
+            r#"{"messages":[{"role":"user","content":"premier"},{"role":"assistant","content":"ok"},{"role":"user","content":"second"}],
+                "contents":[{"role":"user","parts":[{"text":"a"},{"inline_data":{}},{"text":"b"}]}]}"#,
+        )
+        .unwrap();
+
+        let path = |p: &str| JsonPath::parse(p).unwrap().extract_text(&body);
+        assert_eq!(
+            path("messages[role=user][-1].content").as_deref(),
+            Some("second")
+        );
+        assert_eq!(path("messages[0].content").as_deref(), Some("premier"));
+        assert_eq!(
+            path("contents[role=user][-1].parts[*].text").as_deref(),
+            Some("a\nb")
+        );
+        assert_eq!(path("messages[role=system][-1].content"), None);
+        assert_eq!(path("messages[7].content"), None);
+
+        assert!(JsonPath::parse("messages[").is_err());
+        assert!(JsonPath::parse("messages[x]").is_err());
+        assert!(JsonPath::parse("a..b").is_err());
+    }
+
+    #[test]
+    fn test_builtin_catalog() {
+        let catalog = PlatformCatalog::new(builtin_specs());
+        assert_eq!(catalog.identify("chat.mistral.ai"), Some("mistral"));
+        assert_eq!(catalog.identify("notopenai.com"), None);
+        assert_eq!(
+            catalog.is_api_endpoint("/api/abc/chat_conversations", "claude"),
+            Some(true)
+        );
+        assert_eq!(catalog.is_api_endpoint("/api/chat", "claude"), Some(true));
+        assert_eq!(
+            catalog.is_api_endpoint("/chat/x/api/send", "claude"),
+            Some(true)
+        );
+        assert_eq!(
+            catalog.is_api_endpoint("/api/account/settings", "claude"),
+            Some(false)
+        );
+        assert_eq!(catalog.is_api_endpoint("/login", "claude"), Some(false));
+        assert_eq!(catalog.is_api_endpoint("/api/chat", "huggingface"), None);
+        assert_eq!(catalog.extract_prompt("{}", "copilot"), None);
+    }
+
+    #[test]
+    fn test_path_patterns() {
+        // Paths between slashes are substrings, not regexes
+        let literal = PathPattern::parse("/v1/").unwrap();
+        assert!(literal.matches("/v1/chat/completions"));
+        assert!(!literal.matches("/v2/chat"));
+        assert!(PathPattern::parse("/api/v0.1/")
+            .unwrap()
+            .matches("/api/v0.1/chat"));
+        assert!(!PathPattern::parse("/api/v0.1/")
+            .unwrap()
+            .matches("/api/v0x1/chat"));
+
+        let regex = PathPattern::parse(r"regex:^/api/v\d+/chat$").unwrap();
+        assert!(regex.matches("/api/v2/chat"));
+        assert!(!regex.matches("/api/v2/chat/extra"));
+        assert!(PathPattern::parse("regex:(").is_err());
+        assert!(PathPattern::parse("regex:").is_err());
+    }
+
+    #[test]
+    fn test_server_spec_added() {
+        let spec: PlatformSpec = serde_json::from_str(
+            r#"{"name":"deepseek","domains":["chat.deepseek.com"],"api_paths":["/api/v0/chat/completion"],
+                "prompt_paths":["prompt"],"stream_format":"ndjson","stream_paths":["v"]}"#,
+        )
+        .unwrap();
+        let catalog = PlatformCatalog::new(vec![spec]);
+
+        assert_eq!(catalog.identify("chat.deepseek.com"), Some("deepseek"));
+        assert_eq!(
+            catalog.is_api_endpoint("/api/v0/chat/completion", "deepseek"),
+            Some(true)
+        );
+        assert_eq!(
+            catalog.extract_prompt(r#"{"prompt":"Bonjour"}"#, "deepseek"),
+            Some(Some("Bonjour".to_string()))
+        );
+        assert_eq!(catalog.stream_format("deepseek"), StreamFormat::Ndjson);
+        assert_eq!(
+            catalog
+                .extract_stream_event(r#"{"v":"Bon"}"#, "deepseek")
+                .as_deref(),
+            Some("Bon")
+        );
+    }
+}
@@ -1,6 +1,10 @@
 use serde::Deserialize;
 use sha2::{Digest, Sha256};
 
+use crate::proxy::chunked;
+use crate::proxy::extractors::{self, StreamState};
+use crate::proxy::platforms::{self, StreamFormat};
+
 /// A parsed HTTP request extracted from the decrypted TLS stream
 #[derive(Debug, Clone)]
 // Reserved for future request rewriting in the MITM pipeline
@@ -71,6 +75,8 @@ pub fn parse_raw_request(data: &[u8]) -> Option<ParsedHttpRequest> {
         }
     }
 
+    let body = dechunk_body(body, &mut headers)?;
+
     Some(ParsedHttpRequest {
         method,
         path,
@@ -82,8 +88,6 @@ pub fn parse_raw_request(data: &[u8]) -> Option<ParsedHttpRequest> {
 }
 
 /// Parse a raw HTTP response from bytes
-// Reserved for future response interception pipeline
-#[allow(dead_code)]
 pub fn parse_raw_response(data: &[u8]) -> Option<ParsedHttpResponse> {
     let text = String::from_utf8_lossy(data);
 
@@ -118,6 +122,8 @@ pub fn parse_raw_response(data: &[u8]) -> Option<ParsedHttpResponse> {
         }
     }
 
+    let body = dechunk_body(body, &mut headers)?;
+
     Some(ParsedHttpResponse {
         status_code,
         headers,
@@ -126,42 +132,33 @@ pub fn parse_raw_response(data: &[u8]) -> Option<ParsedHttpResponse> {
     })
 }
 
-/// Check if a request path looks like an API endpoint that carries prompts
+/// Decode `body` if the message uses chunked transfer encoding; trailer
+/// fields are appended to `headers`. Returns None on a malformed body.
+fn dechunk_body(body: Vec<u8>, headers: &mut Vec<(String, String)>) -> Option<Vec<u8>> {
+    let is_chunked = headers.iter().any(|(k, v)| {
+        k.eq_ignore_ascii_case("transfer-encoding") && v.to_ascii_lowercase().contains("chunked")
+    });
+    if !is_chunked || body.is_empty() {
+        return Some(body);
+    }
+    let (decoded, trailers) = chunked::decode_body(&body).ok()?;
+    headers.extend(trailers);
+    Some(decoded)
+}
+
+/// Check if a request path looks like an API endpoint that carries prompts,
+/// according to the platform catalog
 pub fn is_api_endpoint(path: &str, platform: &str) -> bool {
-    match platform {
-        "chatgpt" => {
-            path.contains("/v1/chat/completions")
-                || path.contains("/backend-api/conversation")
-                || path.contains("/backend-anon/conversation")
-        }
-        "claude" => {
-            path.contains("/api/") && path.contains("/chat")
-                || path.contains("/v1/messages")
-                || path.contains("/api/organizations")
-        }
-        "copilot" => {
-            path.contains("/v1/engines")
-                || path.contains("/v1/completions")
-                || path.contains("/chat/completions")
-        }
-        "gemini" => {
-            path.contains("/v1beta/models")
-                || path.contains("/v1/models")
-                || path.contains(":generateContent")
-        }
-        "mistral" => {
-            path.contains("/v1/chat/completions")
-        }
-        "perplexity" => {
-            path.contains("/chat/completions")
-        }
-        _ => {
-            // Generic: match common LLM API patterns
+    platforms::catalog()
+        .is_api_endpoint(path, platform)
+        .unwrap_or_else(|| {
+            // Generic: match common LLM API patterns (OpenAI-compatible
+            // gateways, Ollama)
             path.contains("/chat/completions")
                 || path.contains("/v1/messages")
                 || path.contains("/generate")
-        }
-    }
+                || path.contains("/api/chat")
+        })
 }
 
 /// Reconstruct the raw HTTP request bytes from a ParsedHttpRequest
@@ -207,25 +204,6 @@ pub fn serialize_response(resp: &ParsedHttpResponse) -> Vec<u8> {
     bytes
 }
 
-/// Build a blocked response (HTTP 403) with the Icon block page
-pub fn build_block_response(message: &str, rule_name: &str) -> Vec<u8> {
-    let body = BLOCK_PAGE_TEMPLATE
-        .replace("{{MESSAGE}}", message)
-        .replace("{{RULE_NAME}}", rule_name);
-
-    let mut out = format!(
-        "HTTP/1.1 403 Forbidden\r\n\
-         Content-Type: text/html; charset=utf-8\r\n\
-         Content-Length: {}\r\n\
-         Connection: close\r\n\
-         X-Icon-Blocked: true\r\n\
-         \r\n",
-        body.len()
-    );
-    out.push_str(&body);
-    out.into_bytes()
-}
-
 /// Compute SHA-256 hash of content
 pub fn content_hash(data: &[u8]) -> String {
     let mut hasher = Sha256::new();
@@ -238,59 +216,341 @@ pub fn truncate(s: &str, max_len: usize) -> String {
     if s.len() <= max_len {
         s.to_string()
     } else {
-        format!("{}...[truncated]", &s[..max_len])
+        // Cut on a char boundary: prompts are mostly non-ASCII text
+        format!("{}...[truncated]", &s[..s.floor_char_boundary(max_len)])
     }
 }
 
 // --- AI platform prompt/response extractors ---
 
-/// Identifies which AI platform a request belongs to based on the domain
+/// Identifies which AI platform a request belongs to based on the domain,
+/// according to the platform catalog
 pub fn identify_platform(host: &str) -> Option<&'static str> {
-    if host.contains("openai.com") || host.contains("chatgpt.com") {
-        Some("chatgpt")
-    } else if host.contains("claude.ai") || host.contains("anthropic.com") {
-        Some("claude")
-    } else if host.contains("copilot.microsoft.com") || host.contains("github.copilot") {
-        Some("copilot")
-    } else if host.contains("gemini.google.com")
-        || host.contains("generativelanguage.googleapis.com")
-    {
-        Some("gemini")
-    } else if host.contains("mistral.ai") {
-        Some("mistral")
-    } else if host.contains("perplexity.ai") {
-        Some("perplexity")
-    } else if host.contains("huggingface.co") {
-        Some("huggingface")
-    } else {
-        None
-    }
+    platforms::catalog().identify(host)
 }
 
-/// Extract the user prompt from a request body based on the platform
+/// Extract the user prompt from a request body, with the JSON paths the
+/// platform catalog declares for the platform
 pub fn extract_prompt(body: &[u8], platform: &str) -> Option<String> {
     let text = std::str::from_utf8(body).ok()?;
+    let catalog = platforms::catalog();
 
-    match platform {
-        "chatgpt" | "claude" | "mistral" | "perplexity" => extract_openai_format(text),
-        "gemini" => extract_gemini_format(text),
-        _ => extract_openai_format(text).or_else(|| {
+    if let Some(format) = catalog.format(platform) {
+        let json: serde_json::Value = serde_json::from_str(text).ok()?;
+        if let Some(prompt) = extractors::extract_prompt(format, &json) {
+            return Some(prompt);
+        }
+    }
+
+    match catalog.extract_prompt(text, platform) {
+        Some(prompt) => prompt,
+        None => extract_openai_format(text).or_else(|| {
             // Fallback: if it looks like JSON with a prompt/content field, extract it
             extract_generic_prompt(text)
         }),
     }
 }
 
+/// Assistant answer extracted from a response
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ExtractedAnswer {
+    pub text: String,
+    /// Sources the answer cites (URLs)
+    pub citations: Vec<String>,
+    pub usage: ModelUsage,
+}
+
+impl ExtractedAnswer {
+    /// Details recorded with the response event
+    pub fn metadata(&self) -> serde_json::Map<String, serde_json::Value> {
+        answer_metadata(&self.citations, &self.usage)
+    }
+}
+
+/// Model details a response reports: model, token usage and why the
+/// generation stopped
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ModelUsage {
+    pub model: Option<String>,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    pub finish_reason: Option<String>,
+}
+
+impl ModelUsage {
+    /// Merge the details of a response body or streamed event (OpenAI,
+    /// Anthropic, Gemini and ChatGPT web shapes). Streams report cumulative
+    /// counts, so later values replace earlier ones.
+    pub fn observe(&mut self, json: &serde_json::Value) {
+        if let serde_json::Value::Array(chunks) = json {
+            chunks.iter().for_each(|chunk| self.observe(chunk));
+            return;
+        }
+
+        let string = |pointer: &str| {
+            json.pointer(pointer)
+                .and_then(|v| v.as_str())
+                .map(str::to_string)
+        };
+        if let Some(model) = [
+            "/model",
+            "/message/model",
+            "/modelVersion",
+            "/message/metadata/model_slug",
+        ]
+        .iter()
+        .find_map(|p| string(p))
+        {
+            self.model = Some(model);
+        }
+
+        let usage = ["/usage", "/message/usage", "/usageMetadata"]
+            .iter()
+            .find_map(|p| json.pointer(p).filter(|u| u.is_object()));
+        if let Some(usage) = usage {
+            let count = |fields: &[&str]| {
+                fields
+                    .iter()
+                    .find_map(|f| usage.get(*f).and_then(|v| v.as_u64()))
+            };
+            if let Some(n) = count(&["prompt_tokens", "input_tokens", "promptTokenCount"]) {
+                self.prompt_tokens = Some(n);
+            }
+            if let Some(n) = count(&["completion_tokens", "output_tokens", "candidatesTokenCount"])
+            {
+                self.completion_tokens = Some(n);
+            }
+        }
+
+        if let Some(reason) = [
+            "/choices/0/finish_reason",
+            "/stop_reason",
+            "/delta/stop_reason",
+            "/candidates/0/finishReason",
+            "/message/metadata/finish_details/type",
+        ]
+        .iter()
+        .find_map(|p| string(p))
+        {
+            self.finish_reason = Some(reason);
+        }
+    }
+
+    /// Merge the details of a streamed event's `data`
+    pub fn observe_event(&mut self, data: &str) {
+        if let Ok(json) = serde_json::from_str::<serde_json::Value>(data) {
+            self.observe(&json);
+        }
+    }
+}
+
+/// Event metadata of an answer: cited sources and model usage
+pub fn answer_metadata(
+    citations: &[String],
+    usage: &ModelUsage,
+) -> serde_json::Map<String, serde_json::Value> {
+    let mut metadata = serde_json::Map::new();
+    if !citations.is_empty() {
+        metadata.insert("citations".to_string(), serde_json::json!(citations));
+    }
+    if let Some(model) = &usage.model {
+        metadata.insert("model".to_string(), serde_json::json!(model));
+    }
+    if usage.prompt_tokens.is_some() || usage.completion_tokens.is_some() {
+        metadata.insert(
+            "usage".to_string(),
+            serde_json::json!({
+                "prompt_tokens": usage.prompt_tokens,
+                "completion_tokens": usage.completion_tokens,
+            }),
+        );
+    }
+    if let Some(reason) = &usage.finish_reason {
+        metadata.insert("finish_reason".to_string(), serde_json::json!(reason));
+    }
+    metadata
+}
+
 /// Extract the assistant response from a response body
-pub fn extract_response(body: &[u8], _platform: &str) -> Option<String> {
+pub fn extract_response(body: &[u8], platform: &str) -> Option<ExtractedAnswer> {
     let text = std::str::from_utf8(body).ok()?;
+    let catalog = platforms::catalog();
+    let mut state = StreamState::default();
+    let mut usage = ModelUsage::default();
+    match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(json) => usage.observe(&json),
+        Err(_) => text
+            .lines()
+            .map(|line| line.strip_prefix("data: ").unwrap_or(line))
+            .for_each(|data| usage.observe_event(data)),
+    }
 
-    // Try OpenAI format first (most common)
-    extract_openai_response(text)
-        // Fallback: if it's SSE (streaming), collect text chunks
-        .or_else(|| extract_sse_response(text))
+    let answer = catalog
+        .format(platform)
+        .and_then(|format| {
+            let json: serde_json::Value = serde_json::from_str(text).ok()?;
+            extractors::extract_response(format, &json, &mut state)
+        })
+        .or_else(|| catalog.extract_response(text, platform))
+        // Then OpenAI format (most common)
+        .or_else(|| extract_openai_response(text))
+        // Fallback: if it's streamed, collect text chunks
+        .or_else(|| extract_stream_response(text, platform, &mut state))
         // Last resort: truncated raw body
-        .or_else(|| Some(truncate(text, 5000)))
+        .unwrap_or_else(|| truncate(text, 5000));
+
+    Some(ExtractedAnswer {
+        text: answer,
+        citations: state.citations().to_vec(),
+        usage,
+    })
+}
+
+/// Extract the request details recorded with the prompt event: model
+/// parameters (`model`, `temperature`, `tools`), then what the platform's
+/// native extractor finds (attached files, custom assistant...)
+pub fn extract_request_metadata(
+    body: &[u8],
+    path: &str,
+    platform: &str,
+) -> serde_json::Map<String, serde_json::Value> {
+    let json = std::str::from_utf8(body)
+        .ok()
+        .and_then(|text| serde_json::from_str::<serde_json::Value>(text).ok());
+    let mut metadata = serde_json::Map::new();
+
+    // Gemini names the model in the path: /v1beta/models/gemini-2.5-pro:generateContent
+    let path_model = path
+        .split_once("/models/")
+        .and_then(|(_, rest)| rest.split([':', '?', '/']).next())
+        .filter(|model| !model.is_empty());
+    let model = json
+        .as_ref()
+        .and_then(|j| j.get("model"))
+        .and_then(|m| m.as_str())
+        .or(path_model);
+    if let Some(model) = model {
+        metadata.insert("model".to_string(), serde_json::json!(model));
+    }
+
+    let Some(json) = json else {
+        return metadata;
+    };
+    if let Some(temperature) = ["/temperature", "/generationConfig/temperature"]
+        .iter()
+        .find_map(|p| json.pointer(p).filter(|t| t.is_number()))
+    {
+        metadata.insert("temperature".to_string(), temperature.clone());
+    }
+    let tools = tool_names(&json);
+    if !tools.is_empty() {
+        metadata.insert("tools".to_string(), serde_json::json!(tools));
+    }
+
+    if let Some(format) = platforms::catalog().format(platform) {
+        metadata.extend(extractors::extract_metadata(format, &json));
+    }
+    metadata
+}
+
+/// Names of the tools a request offers the model: OpenAI functions and
+/// built-in tools, Anthropic tools, Gemini function declarations and
+/// built-in tools (`{"googleSearch": {}}`)
+fn tool_names(body: &serde_json::Value) -> Vec<String> {
+    let list = |field: &str| {
+        body.get(field)
+            .and_then(|t| t.as_array())
+            .into_iter()
+            .flatten()
+    };
+    let mut names: Vec<String> = Vec::new();
+    for tool in list("tools").chain(list("functions")) {
+        let declared: Vec<&str> = match tool.get("functionDeclarations").and_then(|d| d.as_array())
+        {
+            Some(declarations) => declarations
+                .iter()
+                .filter_map(|d| d.get("name")?.as_str())
+                .collect(),
+            None => tool
+                .pointer("/function/name")
+                .or_else(|| tool.get("name"))
+                .or_else(|| tool.get("type"))
+                .and_then(|n| n.as_str())
+                .or_else(|| {
+                    tool.as_object()
+                        .filter(|o| o.len() == 1)?
+                        .keys()
+                        .next()
+                        .map(String::as_str)
+                })
+                .into_iter()
+                .collect(),
+        };
+        for name in declared {
+            if !names.iter().any(|n| n == name) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names
+}
+
+/// Extract the user prompt from a WebSocket text message.
+/// Handles SignalR records (Copilot), Socket.IO events (Perplexity) and
+/// plain JSON messages.
+pub fn extract_ws_prompt(message: &str, platform: &str) -> Option<String> {
+    // SignalR: JSON records terminated by the 0x1E record separator
+    message
+        .split('\u{1e}')
+        .map(str::trim)
+        .filter(|record| !record.is_empty())
+        .find_map(|record| extract_ws_record(record, platform))
+}
+
+fn extract_ws_record(record: &str, platform: &str) -> Option<String> {
+    // Socket.IO: numeric packet type prefix, e.g. `42["perplexity_ask", "query", {...}]`
+    let json = record.trim_start_matches(|c: char| c.is_ascii_digit());
+    let val: serde_json::Value = serde_json::from_str(json).ok()?;
+
+    if let serde_json::Value::Array(items) = &val {
+        // Socket.IO event: [event_name, payload...]
+        return items.iter().skip(1).find_map(|item| match item {
+            serde_json::Value::String(s) if !s.is_empty() => Some(s.clone()),
+            other => find_prompt_field(other),
+        });
+    }
+
+    // SignalR invocation (type 1 / 4): prompt in `arguments`
+    if let Some(args) = val.get("arguments").and_then(|a| a.as_array()) {
+        if val
+            .get("type")
+            .and_then(|t| t.as_u64())
+            .is_some_and(|t| t != 1 && t != 4)
+        {
+            return None;
+        }
+        return args.iter().find_map(find_prompt_field);
+    }
+
+    extract_prompt(json.as_bytes(), platform).or_else(|| find_prompt_field(&val))
+}
+
+/// Depth-first search for the first non-empty string under a field
+/// commonly used to carry a prompt
+fn find_prompt_field(val: &serde_json::Value) -> Option<String> {
+    const FIELDS: [&str; 6] = ["text", "prompt", "query", "content", "message", "input"];
+
+    match val {
+        serde_json::Value::Object(map) => FIELDS
+            .iter()
+            .find_map(|field| match map.get(*field) {
+                Some(serde_json::Value::String(s)) if !s.is_empty() => Some(s.clone()),
+                _ => None,
+            })
+            .or_else(|| map.values().find_map(find_prompt_field)),
+        serde_json::Value::Array(items) => items.iter().find_map(find_prompt_field),
+        _ => None,
+    }
 }
 
 // --- Internal parsers ---
@@ -317,22 +577,6 @@ struct Choice {
     delta: Option<ChatMessage>,
 }
 
-#[derive(Deserialize)]
-struct GeminiRequest {
-    contents: Option<Vec<GeminiContent>>,
-}
-
-#[derive(Deserialize)]
-struct GeminiContent {
-    parts: Option<Vec<GeminiPart>>,
-    role: Option<String>,
-}
-
-#[derive(Deserialize)]
-struct GeminiPart {
-    text: Option<String>,
-}
-
 fn extract_openai_format(body: &str) -> Option<String> {
     let req: OpenAiRequest = serde_json::from_str(body).ok()?;
     let messages = req.messages?;
@@ -362,52 +606,64 @@ fn extract_openai_response(body: &str) -> Option<String> {
     }
 }
 
-fn extract_gemini_format(body: &str) -> Option<String> {
-    let req: GeminiRequest = serde_json::from_str(body).ok()?;
-    let contents = req.contents?;
-
-    let user_content = contents
-        .iter()
-        .rev()
-        .find(|c| c.role.as_deref() == Some("user"))?;
-
-    let parts = user_content.parts.as_ref()?;
-    let texts: Vec<&str> = parts.iter().filter_map(|p| p.text.as_deref()).collect();
+/// Extract from streamed responses (SSE `data:` lines, or one JSON document
+/// per line depending on the platform)
+fn extract_stream_response(body: &str, platform: &str, state: &mut StreamState) -> Option<String> {
+    let ndjson = platforms::catalog().stream_format(platform) == StreamFormat::Ndjson;
+    let full_text: String = body
+        .lines()
+        .filter_map(|line| {
+            if ndjson {
+                Some(line)
+            } else {
+                line.strip_prefix("data: ")
+            }
+        })
+        .filter_map(|data| extract_stream_event_text(data, platform, state))
+        .collect();
 
-    if texts.is_empty() {
+    if full_text.is_empty() {
         None
     } else {
-        Some(texts.join("\n"))
+        Some(full_text)
     }
 }
 
-/// Extract from SSE (Server-Sent Events) streaming responses
-fn extract_sse_response(body: &str) -> Option<String> {
-    let mut full_text = String::new();
-
-    for line in body.lines() {
-        if let Some(data) = line.strip_prefix("data: ") {
-            if data == "[DONE]" {
-                continue;
-            }
-            if let Ok(chunk) = serde_json::from_str::<OpenAiResponse>(data) {
-                if let Some(choices) = chunk.choices {
-                    if let Some(choice) = choices.first() {
-                        if let Some(msg) = choice.delta.as_ref() {
-                            if let Some(serde_json::Value::String(s)) = &msg.content {
-                                full_text.push_str(s);
-                            }
-                        }
-                    }
-                }
-            }
-        }
+/// Extract the text added by a single streamed event, with the platform's
+/// native extractor, the JSON paths the platform catalog declares, or the
+/// OpenAI chunk format. `state` carries what the native extractors need to
+/// know of the previous events of the stream.
+pub fn extract_stream_event_text(
+    data: &str,
+    platform: &str,
+    state: &mut StreamState,
+) -> Option<String> {
+    if data == "[DONE]" {
+        return None;
     }
+    let catalog = platforms::catalog();
+    catalog
+        .format(platform)
+        .and_then(|format| {
+            let json: serde_json::Value = serde_json::from_str(data).ok()?;
+            extractors::extract_stream_event(format, &json, state)
+        })
+        .or_else(|| catalog.extract_stream_event(data, platform))
+        .or_else(|| extract_sse_event_text(data))
+}
 
-    if full_text.is_empty() {
-        None
-    } else {
-        Some(full_text)
+/// Extract the text delta carried by the `data` of a single SSE event
+/// (OpenAI chunk format)
+fn extract_sse_event_text(data: &str) -> Option<String> {
+    if data == "[DONE]" {
+        return None;
+    }
+    let chunk: OpenAiResponse = serde_json::from_str(data).ok()?;
+    let choices = chunk.choices?;
+    let msg = choices.first()?.delta.as_ref()?;
+    match &msg.content {
+        Some(serde_json::Value::String(s)) => Some(s.clone()),
+        _ => None,
     }
 }
 
@@ -424,48 +680,6 @@ fn extract_generic_prompt(body: &str) -> Option<String> {
     None
 }
 
-/// HTML page displayed when a request is blocked
-const BLOCK_PAGE_TEMPLATE: &str = r#"<!DOCTYPE html>
-<html lang="fr">
-<head>
-    <meta charset="utf-8">
-    <title>Icon - Requête bloquée</title>
-    <style>
-        body {
-            font-family: -apple-system, BlinkMacSystemFont, 'Segoe UI', sans-serif;
-            display: flex; justify-content: center; align-items: center;
-            min-height: 100vh; margin: 0;
-            background: linear-gradient(135deg, #1a1a2e 0%, #16213e 100%);
-            color: #e0e0e0;
-        }
-        .container {
-            text-align: center; padding: 3rem;
-            background: rgba(255,255,255,0.05);
-            border-radius: 16px; border: 1px solid rgba(255,255,255,0.1);
-            max-width: 500px;
-        }
-        .icon { font-size: 4rem; margin-bottom: 1rem; }
-        h1 { color: #e74c3c; font-size: 1.5rem; margin-bottom: 0.5rem; }
-        p { color: #bbb; line-height: 1.6; }
-        .rule { color: #f39c12; font-weight: 600; margin-top: 1rem; }
-        .redirect {
-            display: inline-block; margin-top: 1.5rem; padding: 0.75rem 2rem;
-            background: #2ecc71; color: #fff; text-decoration: none;
-            border-radius: 8px; font-weight: 600;
-        }
-    </style>
-</head>
-<body>
-    <div class="container">
-        <div class="icon">🛡️</div>
-        <h1>Requête bloquée par Icon</h1>
-        <p>{{MESSAGE}}</p>
-        <p class="rule">Règle : {{RULE_NAME}}</p>
-        <a class="redirect" href="https://marcelia.gs2e.ci">Utiliser Marcel'IA</a>
-    </div>
-</body>
-</html>"#;
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -478,6 +692,14 @@ mod tests {
         assert_eq!(identify_platform("unknown.com"), None);
     }
 
+    #[test]
+    fn test_truncate_on_char_boundary() {
+        assert_eq!(truncate("court", 10), "court");
+        // "é" spans bytes 1..3: the cut falls back before it
+        assert_eq!(truncate("Résumé", 2), "R...[truncated]");
+        assert_eq!(truncate("日本語", 4), "日...[truncated]");
+    }
+
     #[test]
     fn test_extract_openai_prompt() {
         let body = r#"{"messages":[{"role":"system","content":"You are helpful"},{"role":"user","content":"Génère un cahier des charges"}]}"#;
@@ -491,6 +713,18 @@ mod tests {
         assert!(is_api_endpoint("/backend-api/conversation", "chatgpt"));
         assert!(is_api_endpoint("/v1/messages", "claude"));
         assert!(!is_api_endpoint("/static/logo.png", "chatgpt"));
+        assert!(is_api_endpoint("/api/chat", "unknown"));
+        assert!(is_api_endpoint("/api/chat", "claude"));
+        assert!(is_api_endpoint("/api/generate", "huggingface"));
+    }
+
+    #[test]
+    fn test_extract_gemini_prompt() {
+        let body = r#"{"contents":[{"role":"user","parts":[{"text":"Résume"},{"text":"ce texte"}]},{"role":"model","parts":[{"text":"ok"}]}]}"#;
+        assert_eq!(
+            extract_prompt(body.as_bytes(), "gemini").as_deref(),
+            Some("Résume\nce texte")
+        );
     }
 
     #[test]
@@ -500,37 +734,200 @@ mod tests {
         assert_eq!(req.method, "POST");
         assert_eq!(req.path, "/v1/chat/completions");
         assert_eq!(req.host, "api.openai.com");
-        assert_eq!(
-            req.content_type.as_deref(),
-            Some("application/json")
-        );
+        assert_eq!(req.content_type.as_deref(), Some("application/json"));
         assert_eq!(std::str::from_utf8(&req.body).unwrap(), "{\"messages\":[]}");
     }
 
+    #[test]
+    fn test_parse_chunked_request() {
+        let raw = b"POST /api HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n7\r\n{\"a\":1}\r\n0\r\n\r\n";
+        let req = parse_raw_request(raw).unwrap();
+        assert_eq!(req.body, b"{\"a\":1}");
+
+        let malformed = b"POST /api HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n";
+        assert!(parse_raw_request(malformed).is_none());
+    }
+
     #[test]
     fn test_parse_raw_response() {
         let raw = b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{\"choices\":[]}";
         let resp = parse_raw_response(raw).unwrap();
         assert_eq!(resp.status_code, 200);
-        assert_eq!(
-            std::str::from_utf8(&resp.body).unwrap(),
-            "{\"choices\":[]}"
-        );
+        assert_eq!(std::str::from_utf8(&resp.body).unwrap(), "{\"choices\":[]}");
     }
 
     #[test]
     fn test_sse_response_extraction() {
         let body = "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"content\":\"Hello\"}}]}\n\ndata: {\"choices\":[{\"delta\":{\"content\":\" world\"}}]}\n\ndata: [DONE]\n\n";
-        let result = extract_sse_response(body);
+        let result = extract_stream_response(body, "chatgpt", &mut StreamState::default());
         assert_eq!(result, Some("Hello world".to_string()));
     }
 
     #[test]
-    fn test_block_response() {
-        let resp = build_block_response("Interdit", "Rule-1");
-        let text = String::from_utf8(resp).unwrap();
-        assert!(text.starts_with("HTTP/1.1 403"));
-        assert!(text.contains("Interdit"));
-        assert!(text.contains("Rule-1"));
+    fn test_claude_extraction() {
+        let api = r#"{"model":"claude-opus-4-1","system":"Sois concis","messages":[{"role":"user","content":[{"type":"text","text":"Traduis ce mémo"}]}],"stream":true}"#;
+        assert_eq!(
+            extract_prompt(api.as_bytes(), "claude").as_deref(),
+            Some("Sois concis\n\nTraduis ce mémo")
+        );
+
+        let web = r#"{"prompt":"Corrige ce texte","attachments":[],"files":[]}"#;
+        assert_eq!(
+            extract_prompt(web.as_bytes(), "claude").as_deref(),
+            Some("Corrige ce texte")
+        );
+
+        let stream = "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"id\":\"msg_1\",\"content\":[]}}\n\n\
+                      event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"text\",\"text\":\"\"}}\n\n\
+                      event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Here is\"}}\n\n\
+                      event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\" the memo\"}}\n\n\
+                      event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n";
+        assert_eq!(
+            extract_response(stream.as_bytes(), "claude").unwrap().text,
+            "Here is the memo"
+        );
+    }
+
+    #[test]
+    fn test_chatgpt_web_extraction() {
+        let request = r#"{"action":"next","messages":[{"id":"m1","author":{"role":"user"},"content":{"content_type":"text","parts":["Rédige un courrier"]},"metadata":{}}],"model":"auto","gizmo_id":"g-abc123"}"#;
+        assert_eq!(
+            extract_prompt(request.as_bytes(), "chatgpt").as_deref(),
+            Some("Rédige un courrier")
+        );
+        let metadata =
+            extract_request_metadata(request.as_bytes(), "/backend-api/conversation", "chatgpt");
+        assert_eq!(metadata["gpt_id"], "g-abc123");
+        assert_eq!(metadata["model"], "auto");
+
+        // Cumulative snapshots are not reported twice
+        let stream = "data: {\"message\":{\"id\":\"a1\",\"author\":{\"role\":\"assistant\"},\"content\":{\"content_type\":\"text\",\"parts\":[\"Madame\"]}}}\n\n\
+                      data: {\"message\":{\"id\":\"a1\",\"author\":{\"role\":\"assistant\"},\"content\":{\"content_type\":\"text\",\"parts\":[\"Madame, Monsieur\"]}}}\n\n\
+                      data: [DONE]\n\n";
+        assert_eq!(
+            extract_response(stream.as_bytes(), "chatgpt").unwrap().text,
+            "Madame, Monsieur"
+        );
+    }
+
+    #[test]
+    fn test_request_model_metadata() {
+        let openai = r#"{"model":"gpt-4o","temperature":0.2,"messages":[{"role":"user","content":"Météo ?"}],
+            "tools":[{"type":"function","function":{"name":"get_weather","parameters":{}}},{"type":"web_search_preview"}]}"#;
+        let metadata =
+            extract_request_metadata(openai.as_bytes(), "/v1/chat/completions", "chatgpt");
+        assert_eq!(metadata["model"], "gpt-4o");
+        assert_eq!(metadata["temperature"], 0.2);
+        assert_eq!(
+            metadata["tools"],
+            serde_json::json!(["get_weather", "web_search_preview"])
+        );
+
+        let anthropic = r#"{"model":"claude-sonnet-4-5","max_tokens":1024,"tools":[{"name":"lookup_order","input_schema":{}}],"messages":[]}"#;
+        let metadata = extract_request_metadata(anthropic.as_bytes(), "/v1/messages", "claude");
+        assert_eq!(metadata["tools"], serde_json::json!(["lookup_order"]));
+        assert!(!metadata.contains_key("temperature"));
+
+        let gemini = r#"{"contents":[],"generationConfig":{"temperature":0.7},
+            "tools":[{"functionDeclarations":[{"name":"find_flights"},{"name":"book_flight"}]},{"googleSearch":{}}]}"#;
+        let metadata = extract_request_metadata(
+            gemini.as_bytes(),
+            "/v1beta/models/gemini-2.5-pro:streamGenerateContent?alt=sse",
+            "gemini",
+        );
+        assert_eq!(metadata["model"], "gemini-2.5-pro");
+        assert_eq!(metadata["temperature"], 0.7);
+        assert_eq!(
+            metadata["tools"],
+            serde_json::json!(["find_flights", "book_flight", "googleSearch"])
+        );
+    }
+
+    #[test]
+    fn test_response_model_usage() {
+        let openai = r#"{"model":"gpt-4o-2024-08-06","choices":[{"message":{"role":"assistant","content":"Oui"},"finish_reason":"stop"}],
+            "usage":{"prompt_tokens":12,"completion_tokens":3,"total_tokens":15}}"#;
+        let answer = extract_response(openai.as_bytes(), "chatgpt").unwrap();
+        assert_eq!(
+            answer.usage,
+            ModelUsage {
+                model: Some("gpt-4o-2024-08-06".to_string()),
+                prompt_tokens: Some(12),
+                completion_tokens: Some(3),
+                finish_reason: Some("stop".to_string()),
+            }
+        );
+        let metadata = answer.metadata();
+        assert_eq!(
+            metadata["usage"],
+            serde_json::json!({"prompt_tokens": 12, "completion_tokens": 3})
+        );
+        assert!(!metadata.contains_key("citations"));
+
+        // Anthropic stream: input tokens at the start, cumulative output at the end
+        let stream = "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"model\":\"claude-sonnet-4-5\",\"content\":[],\"usage\":{\"input_tokens\":25,\"output_tokens\":1}}}\n\n\
+                      event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Oui\"}}\n\n\
+                      event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":15}}\n\n";
+        let usage = extract_response(stream.as_bytes(), "claude").unwrap().usage;
+        assert_eq!(usage.model.as_deref(), Some("claude-sonnet-4-5"));
+        assert_eq!(
+            (usage.prompt_tokens, usage.completion_tokens),
+            (Some(25), Some(15))
+        );
+        assert_eq!(usage.finish_reason.as_deref(), Some("end_turn"));
+
+        // Gemini array
+        let gemini = r#"[{"candidates":[{"content":{"parts":[{"text":"Oui"}]},"finishReason":"STOP"}],
+            "usageMetadata":{"promptTokenCount":4,"candidatesTokenCount":1},"modelVersion":"gemini-2.5-flash"}]"#;
+        let usage = extract_response(gemini.as_bytes(), "gemini").unwrap().usage;
+        assert_eq!(usage.model.as_deref(), Some("gemini-2.5-flash"));
+        assert_eq!(usage.completion_tokens, Some(1));
+        assert_eq!(usage.finish_reason.as_deref(), Some("STOP"));
+    }
+
+    #[test]
+    fn test_gemini_extraction() {
+        // streamGenerateContent without alt=sse: one JSON array of chunks
+        let body = r#"[{"candidates":[{"content":{"parts":[{"text":"Nantes "}],"role":"model"}}]},
+                       {"candidates":[{"content":{"parts":[{"text":"est en Loire-Atlantique."}],"role":"model"},"finishReason":"STOP",
+                         "groundingMetadata":{"groundingChunks":[{"web":{"uri":"https://fr.wikipedia.org/wiki/Nantes"}}]}}]}]"#;
+        let answer = extract_response(body.as_bytes(), "gemini").unwrap();
+        assert_eq!(answer.text, "Nantes est en Loire-Atlantique.");
+        assert_eq!(answer.citations, ["https://fr.wikipedia.org/wiki/Nantes"]);
+
+        let sse = "data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"Bon\"}]}}]}\r\n\r\n\
+                   data: {\"candidates\":[{\"content\":{\"parts\":[{\"text\":\"jour\"}]},\"finishReason\":\"STOP\"}]}\r\n\r\n";
+        assert_eq!(
+            extract_response(sse.as_bytes(), "gemini").unwrap().text,
+            "Bonjour"
+        );
+    }
+
+    #[test]
+    fn test_extract_ws_prompt_signalr() {
+        let message = "{\"type\":6}\u{1e}{\"type\":4,\"target\":\"chat\",\"arguments\":[{\"source\":\"cib\",\"message\":{\"author\":\"user\",\"text\":\"Résume ce contrat\"}}]}\u{1e}";
+        assert_eq!(
+            extract_ws_prompt(message, "copilot"),
+            Some("Résume ce contrat".to_string())
+        );
+    }
+
+    #[test]
+    fn test_extract_ws_prompt_socketio() {
+        let message = r#"421["perplexity_ask","Quel est le CA de GS2E ?",{"version":"2.9","source":"default"}]"#;
+        assert_eq!(
+            extract_ws_prompt(message, "perplexity"),
+            Some("Quel est le CA de GS2E ?".to_string())
+        );
+        assert_eq!(extract_ws_prompt("2", "perplexity"), None);
+    }
+
+    #[test]
+    fn test_extract_ws_prompt_json() {
+        let message = r#"{"event":"send","content":[{"type":"text","text":"Bonjour"}]}"#;
+        assert_eq!(
+            extract_ws_prompt(message, "copilot"),
+            Some("Bonjour".to_string())
+        );
     }
 }
@@ -0,0 +1,236 @@
+/// TLS record type of handshake messages
+const CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+
+/// Handshake message type of a ClientHello
+const HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+
+/// Extension carrying the server name
+const EXTENSION_SERVER_NAME: u16 = 0x0000;
+
+/// Largest ClientHello we are willing to buffer before giving up
+pub const MAX_CLIENT_HELLO_SIZE: usize = 16 * 1024;
+
+/// Result of parsing the start of a TLS connection
+#[derive(Debug, PartialEq)]
+pub enum ClientHello {
+    /// More bytes are needed to read the whole ClientHello
+    Incomplete,
+    /// The bytes are not a TLS ClientHello
+    NotTls,
+    /// Complete ClientHello, with its server name if the client sent one
+    Parsed { server_name: Option<String> },
+}
+
+/// Parse the ClientHello at the start of `data` (as read from the client,
+/// before any TLS processing) and extract the SNI server name.
+/// The ClientHello may span several TLS records.
+pub fn parse_client_hello(data: &[u8]) -> ClientHello {
+    // Reassemble the handshake message from the records read so far
+    let mut handshake = Vec::new();
+    let mut pos = 0;
+    loop {
+        if data.len() < pos + 5 {
+            return ClientHello::Incomplete;
+        }
+        if data[pos] != CONTENT_TYPE_HANDSHAKE || data[pos + 1] != 0x03 {
+            return ClientHello::NotTls;
+        }
+        let record_len = u16::from_be_bytes([data[pos + 3], data[pos + 4]]) as usize;
+        let Some(record) = data.get(pos + 5..pos + 5 + record_len) else {
+            return ClientHello::Incomplete;
+        };
+        handshake.extend_from_slice(record);
+        pos += 5 + record_len;
+
+        if handshake.len() >= 4 {
+            if handshake[0] != HANDSHAKE_CLIENT_HELLO {
+                return ClientHello::NotTls;
+            }
+            let message_len =
+                u32::from_be_bytes([0, handshake[1], handshake[2], handshake[3]]) as usize;
+            if message_len > MAX_CLIENT_HELLO_SIZE {
+                return ClientHello::NotTls;
+            }
+            if handshake.len() >= 4 + message_len {
+                return match server_name(&handshake[4..4 + message_len]) {
+                    Some(server_name) => ClientHello::Parsed { server_name },
+                    None => ClientHello::NotTls,
+                };
+            }
+        }
+    }
+}
+
+/// Extract the host name from a ClientHello body.
+/// Returns None if the message is malformed, Some(None) without SNI.
+fn server_name(hello: &[u8]) -> Option<Option<String>> {
+    let mut reader = Reader {
+        data: hello,
+        pos: 0,
+    };
+    reader.skip(2 + 32)?; // legacy_version, random
+    let session_id_len = reader.u8()? as usize;
+    reader.skip(session_id_len)?;
+    let cipher_suites_len = reader.u16()? as usize;
+    reader.skip(cipher_suites_len)?;
+    let compression_len = reader.u8()? as usize;
+    reader.skip(compression_len)?;
+
+    if reader.remaining() == 0 {
+        // No extensions at all
+        return Some(None);
+    }
+    let extensions_len = reader.u16()? as usize;
+    let mut extensions = Reader {
+        data: reader.take(extensions_len)?,
+        pos: 0,
+    };
+
+    while extensions.remaining() > 0 {
+        let extension_type = extensions.u16()?;
+        let extension_len = extensions.u16()? as usize;
+        let extension = extensions.take(extension_len)?;
+        if extension_type != EXTENSION_SERVER_NAME {
+            continue;
+        }
+
+        let mut names = Reader {
+            data: extension,
+            pos: 0,
+        };
+        let list_len = names.u16()? as usize;
+        let mut list = Reader {
+            data: names.take(list_len)?,
+            pos: 0,
+        };
+        while list.remaining() > 0 {
+            let name_type = list.u8()?;
+            let name_len = list.u16()? as usize;
+            let name = list.take(name_len)?;
+            // 0 = host_name
+            if name_type == 0 {
+                let name = std::str::from_utf8(name).ok()?;
+                return Some(Some(name.trim_end_matches('.').to_ascii_lowercase()));
+            }
+        }
+        return Some(None);
+    }
+    Some(None)
+}
+
+/// Bounds-checked big-endian reader over a byte slice
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let slice = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(slice)
+    }
+
+    fn skip(&mut self, n: usize) -> Option<()> {
+        self.take(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a ClientHello record with the given SNI and padding extension
+    fn client_hello(server_name: Option<&str>, padding: usize) -> Vec<u8> {
+        let mut extensions = Vec::new();
+        if let Some(name) = server_name {
+            let mut list = vec![0u8];
+            list.extend((name.len() as u16).to_be_bytes());
+            list.extend(name.as_bytes());
+            let mut data = (list.len() as u16).to_be_bytes().to_vec();
+            data.extend(list);
+            extensions.extend(EXTENSION_SERVER_NAME.to_be_bytes());
+            extensions.extend((data.len() as u16).to_be_bytes());
+            extensions.extend(data);
+        }
+        // padding extension (21)
+        extensions.extend(21u16.to_be_bytes());
+        extensions.extend((padding as u16).to_be_bytes());
+        extensions.extend(vec![0u8; padding]);
+
+        let mut hello = vec![0x03, 0x03];
+        hello.extend([7u8; 32]);
+        hello.push(0); // session id
+        hello.extend([0, 2, 0x13, 0x01]); // one cipher suite
+        hello.extend([1, 0]); // null compression
+        hello.extend((extensions.len() as u16).to_be_bytes());
+        hello.extend(extensions);
+
+        let mut handshake = vec![HANDSHAKE_CLIENT_HELLO];
+        handshake.extend(&(hello.len() as u32).to_be_bytes()[1..]);
+        handshake.extend(hello);
+
+        // Split into records of at most 512 bytes
+        let mut out = Vec::new();
+        for fragment in handshake.chunks(512) {
+            out.extend([CONTENT_TYPE_HANDSHAKE, 0x03, 0x01]);
+            out.extend((fragment.len() as u16).to_be_bytes());
+            out.extend(fragment);
+        }
+        out
+    }
+
+    #[test]
+    fn test_sni_extracted() {
+        let hello = client_hello(Some("API.OpenAI.com"), 10);
+        assert_eq!(
+            parse_client_hello(&hello),
+            ClientHello::Parsed {
+                server_name: Some("api.openai.com".to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn test_without_sni() {
+        let hello = client_hello(None, 10);
+        assert_eq!(
+            parse_client_hello(&hello),
+            ClientHello::Parsed { server_name: None }
+        );
+    }
+
+    #[test]
+    fn test_incomplete_and_multi_record() {
+        let hello = client_hello(Some("claude.ai"), 2000);
+        for cut in [0, 3, 5, 100, 600, hello.len() - 1] {
+            assert_eq!(parse_client_hello(&hello[..cut]), ClientHello::Incomplete);
+        }
+        assert_eq!(
+            parse_client_hello(&hello),
+            ClientHello::Parsed {
+                server_name: Some("claude.ai".to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn test_not_tls() {
+        assert_eq!(
+            parse_client_hello(b"GET / HTTP/1.1\r\n\r\n"),
+            ClientHello::NotTls
+        );
+    }
+}
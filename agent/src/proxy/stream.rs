@@ -0,0 +1,473 @@
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+use crate::proxy::chunked::ChunkedDecoder;
+use crate::proxy::extractors::StreamState;
+use crate::proxy::platforms::{self, StreamFormat};
+use crate::proxy::request_parser::{self, ModelUsage};
+
+/// Maximum size of a response head (status line + headers)
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+/// Read buffer size for body relaying
+const READ_BUF_SIZE: usize = 16 * 1024;
+
+/// Maximum size of a single streamed event: longer lines are dropped
+const MAX_EVENT_SIZE: usize = 1024 * 1024;
+
+/// Maximum size of the text accumulated from a streamed answer: the rest
+/// of the answer is relayed without being inspected
+const MAX_STREAM_TEXT: usize = 4 * 1024 * 1024;
+
+/// How the end of a response body is delimited
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BodyFraming {
+    /// No body (HEAD request, 204, 304)
+    Empty,
+    /// Exactly that many bytes
+    ContentLength(usize),
+    /// Transfer-Encoding: chunked
+    Chunked,
+    /// Body ends when the server closes the connection
+    UntilClose,
+}
+
+/// Whether a Content-Type denotes a streamed answer
+pub fn is_stream_content_type(content_type: &str) -> bool {
+    let content_type = content_type.to_ascii_lowercase();
+    content_type.starts_with("text/event-stream")
+        || content_type.starts_with("application/x-ndjson")
+}
+
+/// Status line and headers of an upstream response
+#[derive(Debug)]
+pub struct ResponseHead {
+    /// Raw head bytes, forwarded as-is to the client
+    pub raw: Vec<u8>,
+    pub status_code: u16,
+    pub headers: Vec<(String, String)>,
+}
+
+impl ResponseHead {
+    /// First value of a header (case-insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Interim 1xx response, followed by the final response
+    pub fn is_informational(&self) -> bool {
+        (100..200).contains(&self.status_code) && self.status_code != 101
+    }
+
+    /// Server-Sent Events or NDJSON stream (streamed chat completions)
+    pub fn is_event_stream(&self) -> bool {
+        self.header("content-type")
+            .is_some_and(is_stream_content_type)
+    }
+
+    /// Whether the connection can be reused after this response
+    pub fn keep_alive(&self) -> bool {
+        !self
+            .header("connection")
+            .is_some_and(|c| c.to_ascii_lowercase().contains("close"))
+    }
+
+    /// Body framing, per RFC 9112 section 6.3
+    pub fn framing(&self, request_method: &str) -> BodyFraming {
+        if request_method.eq_ignore_ascii_case("HEAD")
+            || (100..200).contains(&self.status_code)
+            || self.status_code == 204
+            || self.status_code == 304
+        {
+            return BodyFraming::Empty;
+        }
+        if self
+            .header("transfer-encoding")
+            .is_some_and(|te| te.to_ascii_lowercase().contains("chunked"))
+        {
+            return BodyFraming::Chunked;
+        }
+        match self
+            .header("content-length")
+            .and_then(|cl| cl.trim().parse().ok())
+        {
+            Some(0) => BodyFraming::Empty,
+            Some(cl) => BodyFraming::ContentLength(cl),
+            None => BodyFraming::UntilClose,
+        }
+    }
+}
+
+/// Read a response head from `stream`.
+/// Returns the head and the body bytes already read past it, or None if the
+/// connection was closed before a response started.
+pub async fn read_response_head<S: AsyncRead + Unpin>(
+    stream: &mut S,
+) -> anyhow::Result<Option<(ResponseHead, Vec<u8>)>> {
+    let mut buf = Vec::with_capacity(READ_BUF_SIZE);
+    let mut chunk = vec![0u8; READ_BUF_SIZE];
+
+    loop {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            anyhow::bail!("Connection closed inside response head");
+        }
+        buf.extend_from_slice(&chunk[..n]);
+
+        if let Some(header_end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            let leftover = buf.split_off(header_end + 4);
+            let parsed = request_parser::parse_raw_response(&buf)
+                .ok_or_else(|| anyhow::anyhow!("Malformed response head"))?;
+            let head = ResponseHead {
+                raw: buf,
+                status_code: parsed.status_code,
+                headers: parsed.headers,
+            };
+            return Ok(Some((head, leftover)));
+        }
+
+        if buf.len() > MAX_HEAD_SIZE {
+            anyhow::bail!("Response head too large");
+        }
+    }
+}
+
+/// A piece of response body as read from the network
+pub struct BodyPiece {
+    /// Bytes exactly as received (including chunk framing), to forward
+    pub raw: Vec<u8>,
+    /// Body payload carried by `raw`
+    pub decoded: Vec<u8>,
+}
+
+/// Reads a response body incrementally, following its framing, without
+/// buffering it.
+pub struct BodyReader {
+    framing: BodyFraming,
+    pending: Vec<u8>,
+    remaining: usize,
+    decoder: ChunkedDecoder,
+    done: bool,
+}
+
+impl BodyReader {
+    /// `leftover` holds the body bytes read together with the head
+    pub fn new(framing: BodyFraming, leftover: Vec<u8>) -> Self {
+        let remaining = match framing {
+            BodyFraming::ContentLength(cl) => cl,
+            _ => 0,
+        };
+        Self {
+            framing,
+            pending: leftover,
+            remaining,
+            decoder: ChunkedDecoder::new(),
+            done: framing == BodyFraming::Empty,
+        }
+    }
+
+    /// Whether the whole body has been read
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Next piece of body, or None once the body is complete
+    pub async fn next<S: AsyncRead + Unpin>(
+        &mut self,
+        stream: &mut S,
+    ) -> anyhow::Result<Option<BodyPiece>> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let input = if self.pending.is_empty() {
+            let mut buf = vec![0u8; READ_BUF_SIZE];
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                self.done = true;
+                if self.framing == BodyFraming::UntilClose {
+                    return Ok(None);
+                }
+                anyhow::bail!("Connection closed before end of body");
+            }
+            buf.truncate(n);
+            buf
+        } else {
+            std::mem::take(&mut self.pending)
+        };
+
+        let piece = match self.framing {
+            BodyFraming::Empty => unreachable!("empty bodies are done from the start"),
+            BodyFraming::ContentLength(_) => {
+                let n = self.remaining.min(input.len());
+                self.remaining -= n;
+                self.done = self.remaining == 0;
+                let raw = input[..n].to_vec();
+                BodyPiece {
+                    decoded: raw.clone(),
+                    raw,
+                }
+            }
+            BodyFraming::Chunked => {
+                let mut decoded = Vec::new();
+                let consumed = self.decoder.feed(&input, &mut decoded)?;
+                self.done = self.decoder.is_done();
+                BodyPiece {
+                    raw: input[..consumed].to_vec(),
+                    decoded,
+                }
+            }
+            BodyFraming::UntilClose => BodyPiece {
+                decoded: input.clone(),
+                raw: input,
+            },
+        };
+        Ok(Some(piece))
+    }
+}
+
+/// Accumulates the text streamed in a Server-Sent Events response, event by
+/// event, as the body arrives. Platforms streaming one JSON document per
+/// line (see the platform catalog) are handled too.
+pub struct SseAccumulator {
+    platform: &'static str,
+    ndjson: bool,
+    state: StreamState,
+    usage: ModelUsage,
+    line: Vec<u8>,
+    /// The current line went over MAX_EVENT_SIZE and is skipped
+    overflow: bool,
+    data: String,
+    text: String,
+}
+
+impl SseAccumulator {
+    pub fn new() -> Self {
+        Self::for_platform("unknown")
+    }
+
+    /// Accumulator extracting events with the catalog entry of `platform`
+    pub fn for_platform(platform: &'static str) -> Self {
+        Self {
+            platform,
+            ndjson: platforms::catalog().stream_format(platform) == StreamFormat::Ndjson,
+            state: StreamState::default(),
+            usage: ModelUsage::default(),
+            line: Vec::new(),
+            overflow: false,
+            data: String::new(),
+            text: String::new(),
+        }
+    }
+
+    /// Text accumulated from the complete events received so far
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Details of the answer recorded with the response event: sources
+    /// cited and model usage reported by the events received so far
+    pub fn metadata(&self) -> serde_json::Map<String, serde_json::Value> {
+        request_parser::answer_metadata(self.state.citations(), &self.usage)
+    }
+
+    /// Feed decoded body bytes
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if byte != b'\n' {
+                if self.line.len() < MAX_EVENT_SIZE {
+                    self.line.push(byte);
+                } else {
+                    self.overflow = true;
+                }
+                continue;
+            }
+            let mut line = std::mem::take(&mut self.line);
+            if std::mem::take(&mut self.overflow) {
+                continue;
+            }
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            self.process_line(&String::from_utf8_lossy(&line));
+        }
+    }
+
+    fn process_line(&mut self, line: &str) {
+        if self.ndjson {
+            // Each line is a complete event
+            self.push_event(line);
+        } else if line.is_empty() {
+            // Blank line: dispatch the event
+            let data = std::mem::take(&mut self.data);
+            self.push_event(&data);
+        } else if let Some(value) = line.strip_prefix("data:") {
+            if self.data.len() + value.len() > MAX_EVENT_SIZE {
+                return;
+            }
+            if !self.data.is_empty() {
+                self.data.push('\n');
+            }
+            self.data.push_str(value.strip_prefix(' ').unwrap_or(value));
+        }
+        // Other fields (event:, id:, retry:) and comments are ignored
+    }
+
+    fn push_event(&mut self, data: &str) {
+        self.usage.observe_event(data);
+        if let Some(delta) =
+            request_parser::extract_stream_event_text(data, self.platform, &mut self.state)
+        {
+            let room = MAX_STREAM_TEXT.saturating_sub(self.text.len());
+            self.text
+                .push_str(&delta[..delta.floor_char_boundary(room)]);
+        }
+    }
+}
+
+impl Default for SseAccumulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn head(raw: &str) -> ResponseHead {
+        let parsed = request_parser::parse_raw_response(raw.as_bytes()).unwrap();
+        ResponseHead {
+            raw: raw.as_bytes().to_vec(),
+            status_code: parsed.status_code,
+            headers: parsed.headers,
+        }
+    }
+
+    #[test]
+    fn test_framing() {
+        let chunked = head("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nContent-Type: text/event-stream; charset=utf-8\r\n\r\n");
+        assert_eq!(chunked.framing("POST"), BodyFraming::Chunked);
+        assert!(chunked.is_event_stream());
+        assert_eq!(chunked.framing("HEAD"), BodyFraming::Empty);
+
+        let sized = head("HTTP/1.1 200 OK\r\ncontent-length: 12\r\nConnection: close\r\n\r\n");
+        assert_eq!(sized.framing("GET"), BodyFraming::ContentLength(12));
+        assert!(!sized.keep_alive());
+
+        let until_close = head("HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\r\n");
+        assert_eq!(until_close.framing("POST"), BodyFraming::UntilClose);
+
+        assert_eq!(
+            head("HTTP/1.1 304 Not Modified\r\n\r\n").framing("GET"),
+            BodyFraming::Empty
+        );
+        assert!(head("HTTP/1.1 100 Continue\r\n\r\n").is_informational());
+    }
+
+    #[tokio::test]
+    async fn test_read_head_and_chunked_body() {
+        let response =
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\nNEXT";
+        let mut stream = &response[..];
+
+        let (head, leftover) = read_response_head(&mut stream).await.unwrap().unwrap();
+        assert_eq!(head.status_code, 200);
+
+        let mut reader = BodyReader::new(head.framing("GET"), leftover);
+        let mut raw = Vec::new();
+        let mut decoded = Vec::new();
+        while let Some(piece) = reader.next(&mut stream).await.unwrap() {
+            raw.extend(piece.raw);
+            decoded.extend(piece.decoded);
+        }
+        assert_eq!(decoded, b"hello");
+        assert_eq!(raw, b"5\r\nhello\r\n0\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_content_length_body_split_reads() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            server
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\n01234")
+                .await
+                .unwrap();
+            server.flush().await.unwrap();
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            server.write_all(b"56789").await.unwrap();
+        });
+
+        let (head, leftover) = read_response_head(&mut client).await.unwrap().unwrap();
+        let mut reader = BodyReader::new(head.framing("GET"), leftover);
+        let mut body = Vec::new();
+        while let Some(piece) = reader.next(&mut client).await.unwrap() {
+            body.extend(piece.decoded);
+        }
+        assert_eq!(body, b"0123456789");
+    }
+
+    #[test]
+    fn test_sse_accumulator_split_events() {
+        let stream = "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\r\n\r\n\
+                      : keep-alive\n\n\
+                      data: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n\
+                      data: [DONE]\n\n";
+        let mut acc = SseAccumulator::new();
+        for piece in stream.as_bytes().chunks(7) {
+            acc.feed(piece);
+        }
+        assert_eq!(acc.text(), "Hello");
+    }
+
+    #[test]
+    fn test_sse_accumulator_incomplete_event_not_counted() {
+        let mut acc = SseAccumulator::new();
+        acc.feed(b"data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n");
+        assert_eq!(acc.text(), "");
+        acc.feed(b"\n");
+        assert_eq!(acc.text(), "Hi");
+    }
+
+    #[test]
+    fn test_sse_accumulator_bounded() {
+        let mut acc = SseAccumulator::new();
+        // A line without end is not kept whole
+        acc.feed(&vec![b'x'; MAX_EVENT_SIZE + 10]);
+        assert!(acc.line.len() <= MAX_EVENT_SIZE);
+        acc.feed(b"\n\ndata: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\n");
+        assert_eq!(acc.text(), "Hi");
+
+        let delta = "é".repeat(1000);
+        let event = format!(
+            "data: {}\n\n",
+            serde_json::json!({"choices": [{"delta": {"content": delta}}]})
+        );
+        for _ in 0..(MAX_STREAM_TEXT / delta.len() + 2) {
+            acc.feed(event.as_bytes());
+        }
+        assert!(acc.text().len() <= MAX_STREAM_TEXT);
+    }
+
+    #[test]
+    fn test_sse_accumulator_usage_metadata() {
+        // OpenAI with stream_options.include_usage: usage in a last, choiceless chunk
+        let stream = "data: {\"model\":\"gpt-4o-mini\",\"choices\":[{\"delta\":{\"content\":\"Hi\"},\"finish_reason\":null}]}\n\n\
+                      data: {\"model\":\"gpt-4o-mini\",\"choices\":[{\"delta\":{},\"finish_reason\":\"length\"}]}\n\n\
+                      data: {\"model\":\"gpt-4o-mini\",\"choices\":[],\"usage\":{\"prompt_tokens\":9,\"completion_tokens\":2}}\n\n\
+                      data: [DONE]\n\n";
+        let mut acc = SseAccumulator::new();
+        acc.feed(stream.as_bytes());
+        let metadata = acc.metadata();
+        assert_eq!(metadata["model"], "gpt-4o-mini");
+        assert_eq!(metadata["finish_reason"], "length");
+        assert_eq!(metadata["usage"]["prompt_tokens"], 9);
+        assert_eq!(metadata["usage"]["completion_tokens"], 2);
+    }
+}
@@ -3,7 +3,12 @@
 // binary, so we silence the dead-code lint here.
 #![allow(dead_code)]
 
-use tracing::{info, warn, debug};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+use tracing::warn;
+use tracing::{debug, info};
 
 /// Configure the operating system to use the PAC file served by the Icon agent.
 /// This is the cross-platform entry point.
@@ -53,6 +58,349 @@ pub fn is_proxy_configured(pac_url: &str) -> bool {
     }
 }
 
+/// System proxy in place before the agent installed its PAC file
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreviousProxy {
+    /// Static proxy (`host:port` or URL)
+    Static(String),
+    /// Proxy auto-config script URL
+    Pac(String),
+}
+
+/// Read the system proxy settings that the agent is about to replace.
+/// Settings pointing at the agent's own PAC URL (left by a previous run) are
+/// ignored. This is the cross-platform entry point.
+pub fn detect_previous_proxy(agent_pac_url: &str) -> Option<PreviousProxy> {
+    #[cfg(target_os = "macos")]
+    let previous = detect_previous_proxy_macos();
+
+    #[cfg(target_os = "windows")]
+    let previous = detect_previous_proxy_windows();
+
+    // Elsewhere the proxy is configured through the environment
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let previous = ["HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.trim().is_empty()))
+        .map(PreviousProxy::Static);
+
+    previous.filter(|p| *p != PreviousProxy::Pac(agent_pac_url.to_string()))
+}
+
+/// PAC script reproducing the previous system proxy: the script itself for
+/// a PAC URL (fetched directly, without proxy), or a one-line script
+/// returning a static proxy
+pub async fn previous_pac_script(previous: &PreviousProxy) -> Option<String> {
+    match previous {
+        PreviousProxy::Static(proxy) => {
+            let proxy = proxy
+                .rsplit("://")
+                .next()
+                .unwrap_or(proxy)
+                .trim_end_matches('/');
+            Some(format!(
+                "function FindProxyForURL(url, host) {{ return \"PROXY {}; DIRECT\"; }}",
+                proxy
+            ))
+        }
+        PreviousProxy::Pac(url) => {
+            let client = reqwest::Client::builder()
+                .no_proxy()
+                .timeout(std::time::Duration::from_secs(10))
+                .build()
+                .ok()?;
+            let response = client
+                .get(url)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            match response {
+                Ok(response) => response.text().await.ok(),
+                Err(e) => {
+                    tracing::warn!(url = %url, error = %e, "Failed to fetch the previous PAC file");
+                    None
+                }
+            }
+        }
+    }
+}
+
+/// Proxy the previous system configuration sent traffic through: the static
+/// proxy itself, or the first `PROXY host:port` (or `HTTPS host:port`)
+/// directive of the previous PAC script
+pub fn previous_upstream_proxy(previous: &PreviousProxy, script: Option<&str>) -> Option<String> {
+    match previous {
+        PreviousProxy::Static(proxy) => Some(proxy.clone()),
+        PreviousProxy::Pac(_) => script.and_then(proxy_from_pac),
+    }
+}
+
+/// First `PROXY host:port` (or `HTTPS host:port`) directive of a PAC script
+pub fn proxy_from_pac(script: &str) -> Option<String> {
+    let tokens: Vec<&str> = script
+        .split(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == ';')
+        .filter(|t| !t.is_empty())
+        .collect();
+    tokens.windows(2).find_map(|pair| {
+        let is_directive = pair[0] == "PROXY" || pair[0] == "HTTPS";
+        (is_directive && pair[1].contains(':')).then(|| pair[1].to_string())
+    })
+}
+
+/// Parse `networksetup -getautoproxyurl` output (`URL: ...`, `Enabled: Yes`)
+/// into the configured URL, if any, and whether the auto proxy is enabled
+pub fn parse_networksetup_autoproxy_state(output: &str) -> (Option<String>, bool) {
+    let field = |name: &str| {
+        output
+            .lines()
+            .find_map(|l| l.strip_prefix(name))
+            .map(|v| v.trim().to_string())
+    };
+    let enabled = field("Enabled:").is_some_and(|v| v.eq_ignore_ascii_case("yes"));
+    let url = field("URL:").filter(|url| !url.is_empty() && url != "(null)");
+    (url, enabled)
+}
+
+/// Enabled auto proxy URL from `networksetup -getautoproxyurl` output
+pub fn parse_networksetup_autoproxy(output: &str) -> Option<String> {
+    match parse_networksetup_autoproxy_state(output) {
+        (Some(url), true) => Some(url),
+        _ => None,
+    }
+}
+
+/// Parse `networksetup -getsecurewebproxy` output
+/// (`Enabled: Yes`, `Server: ...`, `Port: ...`) into `host:port`
+pub fn parse_networksetup_proxy(output: &str) -> Option<String> {
+    let field = |name: &str| {
+        output
+            .lines()
+            .find_map(|l| l.strip_prefix(name))
+            .map(|v| v.trim().to_string())
+    };
+    let enabled = field("Enabled:").is_some_and(|v| v.eq_ignore_ascii_case("yes"));
+    let server = field("Server:").filter(|s| !s.is_empty())?;
+    let port = field("Port:").filter(|p| p != "0")?;
+    enabled.then(|| format!("{}:{}", server, port))
+}
+
+/// Parse the Windows `ProxyServer` value: `host:port`, or per-protocol
+/// `http=host:port;https=host:port` (the HTTPS entry is preferred)
+pub fn parse_windows_proxy_server(value: &str) -> Option<String> {
+    let value = value.trim();
+    if !value.contains('=') {
+        return (!value.is_empty()).then(|| value.to_string());
+    }
+    let entries: Vec<(&str, &str)> = value.split(';').filter_map(|e| e.split_once('=')).collect();
+    ["https", "http"].iter().find_map(|scheme| {
+        entries
+            .iter()
+            .find(|(k, _)| k.trim().eq_ignore_ascii_case(scheme))
+            .map(|(_, v)| v.trim().to_string())
+    })
+}
+
+/// Value of a `reg query` output line (`    Name    REG_SZ    value`)
+pub fn parse_reg_value(output: &str, name: &str) -> Option<String> {
+    output.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        if parts.next()? != name {
+            return None;
+        }
+        let _reg_type = parts.next()?;
+        let value = parts.collect::<Vec<_>>().join(" ");
+        (!value.is_empty()).then_some(value)
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn detect_previous_proxy_macos() -> Option<PreviousProxy> {
+    for service in get_macos_network_services() {
+        let query = |flag: &str| {
+            std::process::Command::new("networksetup")
+                .args([flag, &service])
+                .output()
+                .ok()
+                .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+        };
+        if let Some(url) = query("-getautoproxyurl").and_then(|o| parse_networksetup_autoproxy(&o))
+        {
+            return Some(PreviousProxy::Pac(url));
+        }
+        if let Some(proxy) = query("-getsecurewebproxy").and_then(|o| parse_networksetup_proxy(&o))
+        {
+            return Some(PreviousProxy::Static(proxy));
+        }
+    }
+    None
+}
+
+#[cfg(target_os = "windows")]
+fn detect_previous_proxy_windows() -> Option<PreviousProxy> {
+    let output = std::process::Command::new("reg")
+        .args([
+            "query",
+            r"HKCU\Software\Microsoft\Windows\CurrentVersion\Internet Settings",
+        ])
+        .output()
+        .ok()?;
+    let output = String::from_utf8_lossy(&output.stdout);
+
+    if let Some(url) = parse_reg_value(&output, "AutoConfigURL") {
+        return Some(PreviousProxy::Pac(url));
+    }
+    let enabled = parse_reg_value(&output, "ProxyEnable").is_some_and(|v| v == "0x1");
+    if enabled {
+        if let Some(proxy) =
+            parse_reg_value(&output, "ProxyServer").and_then(|v| parse_windows_proxy_server(&v))
+        {
+            return Some(PreviousProxy::Static(proxy));
+        }
+    }
+    None
+}
+
+// ---------------------------------------------------------------------------
+// Saved settings
+// ---------------------------------------------------------------------------
+
+/// File of the data directory holding the proxy settings replaced by the agent
+const SAVED_SETTINGS_FILE: &str = "previous_proxy.json";
+
+/// Auto proxy (PAC) setting of one network service. On Windows there is a
+/// single entry with an empty service name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutoProxySetting {
+    pub service: String,
+    pub url: Option<String>,
+    pub enabled: bool,
+}
+
+/// System proxy settings as they were before the agent configured its PAC
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProxySnapshot {
+    pub auto_proxy: Vec<AutoProxySetting>,
+}
+
+impl ProxySnapshot {
+    /// Previous PAC URL, ignoring the agent's own
+    pub fn previous_pac(&self, agent_pac_url: &str) -> Option<&str> {
+        self.auto_proxy
+            .iter()
+            .filter(|s| s.enabled)
+            .filter_map(|s| s.url.as_deref())
+            .find(|url| *url != agent_pac_url)
+    }
+}
+
+/// Read the current auto proxy settings. None on platforms where the
+/// agent does not configure the system proxy.
+pub fn capture_proxy_settings() -> Option<ProxySnapshot> {
+    #[cfg(target_os = "macos")]
+    let auto_proxy = get_macos_network_services()
+        .into_iter()
+        .map(|service| {
+            let output = std::process::Command::new("networksetup")
+                .args(["-getautoproxyurl", &service])
+                .output()
+                .map(|o| String::from_utf8_lossy(&o.stdout).to_string())
+                .unwrap_or_default();
+            let (url, enabled) = parse_networksetup_autoproxy_state(&output);
+            AutoProxySetting {
+                service,
+                url,
+                enabled,
+            }
+        })
+        .collect();
+
+    #[cfg(target_os = "windows")]
+    let auto_proxy = {
+        let url = std::process::Command::new("reg")
+            .args([
+                "query",
+                r"HKCU\Software\Microsoft\Windows\CurrentVersion\Internet Settings",
+                "/v",
+                "AutoConfigURL",
+            ])
+            .output()
+            .ok()
+            .and_then(|o| parse_reg_value(&String::from_utf8_lossy(&o.stdout), "AutoConfigURL"));
+        vec![AutoProxySetting {
+            service: String::new(),
+            enabled: url.is_some(),
+            url,
+        }]
+    };
+
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    return Some(ProxySnapshot { auto_proxy });
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    None
+}
+
+/// Save the proxy settings the agent is about to replace, before
+/// [`configure_system_proxy`]. If settings saved by a previous run were never
+/// restored (crash), they are kept: the current ones are the agent's.
+/// Nothing is saved on platforms without a snapshot.
+pub fn save_proxy_settings(data_dir: &Path) -> anyhow::Result<Option<ProxySnapshot>> {
+    if let Some(saved) = load_proxy_settings(data_dir) {
+        debug!("Keeping proxy settings saved by a previous run");
+        return Ok(Some(saved));
+    }
+    let Some(snapshot) = capture_proxy_settings() else {
+        return Ok(None);
+    };
+    std::fs::create_dir_all(data_dir)?;
+    std::fs::write(
+        data_dir.join(SAVED_SETTINGS_FILE),
+        serde_json::to_string_pretty(&snapshot)?,
+    )?;
+    Ok(Some(snapshot))
+}
+
+/// Proxy settings saved by [`save_proxy_settings`], if any
+pub fn load_proxy_settings(data_dir: &Path) -> Option<ProxySnapshot> {
+    let content = std::fs::read_to_string(data_dir.join(SAVED_SETTINGS_FILE)).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(snapshot) => Some(snapshot),
+        Err(e) => {
+            tracing::warn!(error = %e, "Ignoring unreadable saved proxy settings");
+            None
+        }
+    }
+}
+
+/// Put back the proxy settings saved by [`save_proxy_settings`] exactly as
+/// they were, then forget them. Without saved settings, the agent's proxy
+/// configuration is simply removed.
+pub fn restore_system_proxy(data_dir: &Path) -> anyhow::Result<()> {
+    #[cfg(any(target_os = "macos", target_os = "windows"))]
+    {
+        let Some(snapshot) = load_proxy_settings(data_dir) else {
+            return remove_system_proxy();
+        };
+
+        #[cfg(target_os = "macos")]
+        restore_system_proxy_macos(&snapshot)?;
+
+        #[cfg(target_os = "windows")]
+        restore_system_proxy_windows(&snapshot)?;
+
+        std::fs::remove_file(data_dir.join(SAVED_SETTINGS_FILE))?;
+        info!("Previous system proxy settings restored");
+        return Ok(());
+    }
+
+    // No settings are captured on this platform: there is nothing to restore
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    {
+        let _ = data_dir;
+        remove_system_proxy()
+    }
+}
+
 // ---------------------------------------------------------------------------
 // macOS implementation
 // ---------------------------------------------------------------------------
@@ -127,6 +475,40 @@ pub fn remove_system_proxy_macos() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[cfg(target_os = "macos")]
+pub fn restore_system_proxy_macos(snapshot: &ProxySnapshot) -> anyhow::Result<()> {
+    for setting in &snapshot.auto_proxy {
+        let service = setting.service.as_str();
+        // Setting the URL enables the auto proxy: the state is applied after.
+        // Without a saved URL, the agent's one is cleared.
+        let url = setting.url.as_deref().unwrap_or("");
+        let result = std::process::Command::new("networksetup")
+            .args(["-setautoproxyurl", service, url])
+            .output();
+        if let Err(e) = result {
+            warn!(service = %service, error = %e, "Failed to run networksetup");
+        }
+
+        let state = if setting.enabled { "on" } else { "off" };
+        match std::process::Command::new("networksetup")
+            .args(["-setautoproxystate", service, state])
+            .output()
+        {
+            Ok(o) if o.status.success() => {
+                debug!(service = %service, state, "Auto proxy restored");
+            }
+            Ok(o) => {
+                let stderr = String::from_utf8_lossy(&o.stderr);
+                warn!(service = %service, stderr = %stderr, "Failed to restore auto proxy state");
+            }
+            Err(e) => {
+                warn!(service = %service, error = %e, "Failed to run networksetup");
+            }
+        }
+    }
+    Ok(())
+}
+
 #[cfg(target_os = "macos")]
 pub fn is_proxy_configured_macos(pac_url: &str) -> bool {
     let services = get_macos_network_services();
@@ -183,9 +565,12 @@ pub fn configure_system_proxy_windows(pac_url: &str) -> anyhow::Result<()> {
         .args([
             "add",
             r"HKCU\Software\Microsoft\Windows\CurrentVersion\Internet Settings",
-            "/v", "AutoConfigURL",
-            "/t", "REG_SZ",
-            "/d", pac_url,
+            "/v",
+            "AutoConfigURL",
+            "/t",
+            "REG_SZ",
+            "/d",
+            pac_url,
             "/f",
         ])
         .output()?;
@@ -216,7 +601,8 @@ pub fn remove_system_proxy_windows() -> anyhow::Result<()> {
         .args([
             "delete",
             r"HKCU\Software\Microsoft\Windows\CurrentVersion\Internet Settings",
-            "/v", "AutoConfigURL",
+            "/v",
+            "AutoConfigURL",
             "/f",
         ])
         .output()?;
@@ -238,13 +624,23 @@ pub fn remove_system_proxy_windows() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[cfg(target_os = "windows")]
+pub fn restore_system_proxy_windows(snapshot: &ProxySnapshot) -> anyhow::Result<()> {
+    let previous_url = snapshot.auto_proxy.first().and_then(|s| s.url.as_deref());
+    let Some(url) = previous_url else {
+        return remove_system_proxy_windows();
+    };
+    configure_system_proxy_windows(url)
+}
+
 #[cfg(target_os = "windows")]
 pub fn is_proxy_configured_windows(pac_url: &str) -> bool {
     let output = std::process::Command::new("reg")
         .args([
             "query",
             r"HKCU\Software\Microsoft\Windows\CurrentVersion\Internet Settings",
-            "/v", "AutoConfigURL",
+            "/v",
+            "AutoConfigURL",
         ])
         .output();
 
@@ -261,6 +657,143 @@ pub fn is_proxy_configured_windows(pac_url: &str) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_proxy_from_pac() {
+        let pac = r#"function FindProxyForURL(url, host) {
+            if (isPlainHostName(host)) return "DIRECT";
+            return "PROXY squid.gs2e.local:3128; DIRECT";
+        }"#;
+        assert_eq!(
+            proxy_from_pac(pac).as_deref(),
+            Some("squid.gs2e.local:3128")
+        );
+        assert_eq!(
+            proxy_from_pac("function FindProxyForURL(u, h) { return 'DIRECT'; }"),
+            None
+        );
+
+        let previous = PreviousProxy::Pac("http://wpad.gs2e.local/proxy.pac".to_string());
+        assert_eq!(
+            previous_upstream_proxy(&previous, Some(pac)).as_deref(),
+            Some("squid.gs2e.local:3128")
+        );
+        assert_eq!(previous_upstream_proxy(&previous, None), None);
+        let previous = PreviousProxy::Static("socks5://gw.gs2e.local:1080".to_string());
+        assert_eq!(
+            previous_upstream_proxy(&previous, None).as_deref(),
+            Some("socks5://gw.gs2e.local:1080")
+        );
+    }
+
+    #[test]
+    fn test_saved_settings_kept_until_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let agent_pac = "http://127.0.0.1:8443/proxy.pac";
+        let snapshot = ProxySnapshot {
+            auto_proxy: vec![
+                AutoProxySetting {
+                    service: "Wi-Fi".to_string(),
+                    url: Some("http://wpad.gs2e.local/proxy.pac".to_string()),
+                    enabled: true,
+                },
+                AutoProxySetting {
+                    service: "Ethernet".to_string(),
+                    url: None,
+                    enabled: false,
+                },
+            ],
+        };
+        std::fs::write(
+            dir.path().join(SAVED_SETTINGS_FILE),
+            serde_json::to_string(&snapshot).unwrap(),
+        )
+        .unwrap();
+
+        // A previous run did not restore its settings: they are kept
+        let saved = save_proxy_settings(dir.path()).unwrap().unwrap();
+        assert_eq!(saved, snapshot);
+        assert_eq!(
+            saved.previous_pac(agent_pac),
+            Some("http://wpad.gs2e.local/proxy.pac")
+        );
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    #[test]
+    fn test_no_settings_saved_without_system_proxy() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(save_proxy_settings(dir.path()).unwrap(), None);
+        assert!(!dir.path().join(SAVED_SETTINGS_FILE).exists());
+        restore_system_proxy(dir.path()).unwrap();
+    }
+
+    #[test]
+    fn test_previous_pac_ignores_agent_pac() {
+        let agent_pac = "http://127.0.0.1:8443/proxy.pac";
+        let snapshot = ProxySnapshot {
+            auto_proxy: vec![AutoProxySetting {
+                service: "Wi-Fi".to_string(),
+                url: Some(agent_pac.to_string()),
+                enabled: true,
+            }],
+        };
+        assert_eq!(snapshot.previous_pac(agent_pac), None);
+    }
+
+    #[tokio::test]
+    async fn test_static_previous_proxy_script() {
+        let script = previous_pac_script(&PreviousProxy::Static("http://squid:3128/".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(proxy_from_pac(&script).as_deref(), Some("squid:3128"));
+    }
+
+    #[test]
+    fn test_parse_networksetup_output() {
+        let auto = "URL: http://wpad.gs2e.local/proxy.pac\nEnabled: Yes\n";
+        assert_eq!(
+            parse_networksetup_autoproxy(auto).as_deref(),
+            Some("http://wpad.gs2e.local/proxy.pac")
+        );
+        assert_eq!(
+            parse_networksetup_autoproxy("URL: (null)\nEnabled: No\n"),
+            None
+        );
+        assert_eq!(
+            parse_networksetup_autoproxy_state("URL: http://wpad/proxy.pac\nEnabled: No\n"),
+            (Some("http://wpad/proxy.pac".to_string()), false)
+        );
+
+        let web = "Enabled: Yes\nServer: 10.1.1.1\nPort: 8080\nAuthenticated Proxy Enabled: 0\n";
+        assert_eq!(
+            parse_networksetup_proxy(web).as_deref(),
+            Some("10.1.1.1:8080")
+        );
+        assert_eq!(
+            parse_networksetup_proxy("Enabled: No\nServer: \nPort: 0\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_windows_proxy_settings() {
+        let output = "\r\nHKEY_CURRENT_USER\\Software\\Microsoft\\Windows\\CurrentVersion\\Internet Settings\r\n    ProxyEnable    REG_DWORD    0x1\r\n    ProxyServer    REG_SZ    http=squid:3128;https=squid:3129\r\n";
+        assert_eq!(
+            parse_reg_value(output, "ProxyEnable").as_deref(),
+            Some("0x1")
+        );
+        let server = parse_reg_value(output, "ProxyServer").unwrap();
+        assert_eq!(
+            parse_windows_proxy_server(&server).as_deref(),
+            Some("squid:3129")
+        );
+        assert_eq!(
+            parse_windows_proxy_server("squid:3128").as_deref(),
+            Some("squid:3128")
+        );
+        assert_eq!(parse_reg_value(output, "AutoConfigURL"), None);
+    }
+
     #[test]
     fn test_is_proxy_configured_returns_bool() {
         // Basic smoke test: the function should not panic
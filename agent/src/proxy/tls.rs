@@ -12,6 +12,12 @@ use tracing::{debug, info};
 /// Max number of domain certificates to keep in the LRU cache
 const CERT_CACHE_SIZE: usize = 256;
 
+/// ALPN protocol identifier for HTTP/2
+pub const ALPN_H2: &[u8] = b"h2";
+
+/// ALPN protocol identifier for HTTP/1.1
+pub const ALPN_HTTP1: &[u8] = b"http/1.1";
+
 /// Manages the local CA certificate and per-domain cert generation for MITM
 #[allow(dead_code)] // pub API fields/methods used by installers and system setup
 pub struct CaManager {
@@ -53,6 +59,10 @@ impl CaManager {
             (cert, key)
         };
 
+        // Both rustls crypto backends end up compiled in through our
+        // dependencies, so the process-level provider must be chosen explicitly
+        let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+
         // Build a client TLS config that trusts the standard webpki roots
         // (used to connect to upstream AI servers)
         let mut root_store = rustls::RootCertStore::empty();
@@ -163,9 +173,25 @@ impl CaManager {
         Ok(tokio_rustls::TlsAcceptor::from(config))
     }
 
-    /// Build a tokio-rustls TlsConnector for the upstream-side handshake
-    pub fn make_tls_connector(&self) -> tokio_rustls::TlsConnector {
-        tokio_rustls::TlsConnector::from(self.upstream_tls_config.clone())
+    /// ServerConfig for the given domain that negotiates a single ALPN
+    /// protocol (the one agreed with the upstream server)
+    pub async fn get_server_config_with_alpn(
+        &self,
+        domain: &str,
+        alpn: &[u8],
+    ) -> anyhow::Result<Arc<ServerConfig>> {
+        let base = self.get_server_config(domain).await?;
+        let mut config = (*base).clone();
+        config.alpn_protocols = vec![alpn.to_vec()];
+        Ok(Arc::new(config))
+    }
+
+    /// Build a tokio-rustls TlsConnector for the upstream-side handshake,
+    /// offering the given ALPN protocols (in order of preference)
+    pub fn make_tls_connector(&self, alpn: &[&[u8]]) -> tokio_rustls::TlsConnector {
+        let mut config = (*self.upstream_tls_config).clone();
+        config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+        tokio_rustls::TlsConnector::from(Arc::new(config))
     }
 
     /// Convert a domain string to a rustls ServerName
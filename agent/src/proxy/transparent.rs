@@ -0,0 +1,321 @@
+// Transparent interception (Linux): HTTPS traffic to AI platforms is
+// redirected to the agent by nftables rules, for applications that ignore
+// the system proxy (CLI tools, SDKs, IDE plugins).
+
+use std::collections::BTreeSet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{debug, info, warn};
+
+use crate::proxy::domain_filter::DomainFilter;
+use crate::proxy::domain_pattern::IpNetwork;
+
+/// nftables table owned by the agent
+const NFT_TABLE: &str = "icon_agent";
+
+/// Firewall mark of the agent's own upstream connections, which the
+/// redirect rules let through
+pub const BYPASS_MARK: u32 = 0x1c0;
+
+/// How often the monitored domains are re-resolved into the redirect sets
+const RESOLVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Build the nftables ruleset that redirects HTTPS connections to the
+/// monitored addresses to the transparent listener on `port`
+// Only applied on Linux; built and tested everywhere
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn build_ruleset(port: u16) -> String {
+    format!(
+        r#"add table inet {table}
+delete table inet {table}
+table inet {table} {{
+    set ai_v4 {{
+        type ipv4_addr
+        flags interval
+        auto-merge
+    }}
+    set ai_v6 {{
+        type ipv6_addr
+        flags interval
+        auto-merge
+    }}
+    chain output {{
+        type nat hook output priority -100; policy accept;
+        meta mark {mark:#x} return
+        ip daddr @ai_v4 tcp dport 443 redirect to :{port}
+        ip6 daddr @ai_v6 tcp dport 443 redirect to :{port}
+    }}
+}}
+"#,
+        table = NFT_TABLE,
+        mark = BYPASS_MARK,
+        port = port,
+    )
+}
+
+/// Build the nftables commands replacing the content of the redirect sets:
+/// the resolved addresses, and the monitored networks as intervals
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub fn build_set_update(addresses: &BTreeSet<IpAddr>, networks: &[IpNetwork]) -> String {
+    let mut script = format!(
+        "flush set inet {table} ai_v4\nflush set inet {table} ai_v6\n",
+        table = NFT_TABLE
+    );
+    for (set, ipv4) in [("ai_v4", true), ("ai_v6", false)] {
+        let mut elements: Vec<String> = addresses
+            .iter()
+            .filter(|ip| ip.is_ipv4() == ipv4 && !networks.iter().any(|n| n.contains(**ip)))
+            .map(|ip| ip.to_string())
+            .collect();
+        for network in networks.iter().filter(|n| n.is_ipv4() == ipv4) {
+            let network = network.masked().to_string();
+            if !elements.contains(&network) {
+                elements.push(network);
+            }
+        }
+        if elements.is_empty() {
+            continue;
+        }
+        script.push_str(&format!(
+            "add element inet {} {} {{ {} }}\n",
+            NFT_TABLE,
+            set,
+            elements.join(", ")
+        ));
+    }
+    script
+}
+
+/// Install the redirect rules and mark the agent's upstream connections so
+/// that they bypass them. The sets are filled by [`run_redirect_updater`].
+pub fn install_redirect(port: u16) -> anyhow::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        run_nft(&build_ruleset(port))?;
+        crate::proxy::upstream::set_socket_mark(BYPASS_MARK);
+        info!(port, "Transparent redirect rules installed");
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = port;
+        info!("Transparent interception is only supported on Linux");
+        Ok(())
+    }
+}
+
+/// Remove the redirect rules installed by [`install_redirect`]
+pub fn remove_redirect() -> anyhow::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        run_nft(&format!("delete table inet {}\n", NFT_TABLE))?;
+        crate::proxy::upstream::set_socket_mark(0);
+        info!("Transparent redirect rules removed");
+    }
+    Ok(())
+}
+
+/// Periodically resolve the monitored domains and load their addresses,
+/// with the monitored networks, into the redirect sets
+pub async fn run_redirect_updater(domain_filter: Arc<DomainFilter>) {
+    let mut current = (BTreeSet::new(), Vec::new());
+    let mut warned = BTreeSet::new();
+    loop {
+        for pattern in domain_filter.unresolvable_patterns().await {
+            if warned.insert(pattern.clone()) {
+                warn!(%pattern, "Regex domain pattern only redirected for hosts already seen through the proxy");
+            }
+        }
+
+        let addresses = resolve_domains(&domain_filter.monitored_domains().await).await;
+        let networks = domain_filter.monitored_networks().await;
+        if (&addresses, &networks) != (&current.0, &current.1) {
+            match update_sets(&addresses, &networks) {
+                Ok(()) => {
+                    debug!(
+                        count = addresses.len(),
+                        networks = networks.len(),
+                        "Transparent redirect sets updated"
+                    );
+                    current = (addresses, networks);
+                }
+                Err(e) => warn!(error = %e, "Failed to update transparent redirect sets"),
+            }
+        }
+        tokio::time::sleep(RESOLVE_INTERVAL).await;
+    }
+}
+
+async fn resolve_domains(domains: &[String]) -> BTreeSet<IpAddr> {
+    let mut addresses = BTreeSet::new();
+    for domain in domains {
+        match tokio::net::lookup_host((domain.as_str(), 443)).await {
+            Ok(addrs) => addresses.extend(addrs.map(|a| a.ip())),
+            Err(e) => debug!(%domain, error = %e, "Failed to resolve monitored domain"),
+        }
+    }
+    addresses
+}
+
+fn update_sets(addresses: &BTreeSet<IpAddr>, networks: &[IpNetwork]) -> anyhow::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        run_nft(&build_set_update(addresses, networks))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (addresses, networks);
+        Ok(())
+    }
+}
+
+/// Apply an nftables script (`nft -f -`)
+#[cfg(target_os = "linux")]
+fn run_nft(script: &str) -> anyhow::Result<()> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new("nft")
+        .args(["-f", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "nft failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Destination the client connected to before its connection was
+/// redirected to the agent (`SO_ORIGINAL_DST`)
+#[cfg(target_os = "linux")]
+pub fn original_destination(stream: &tokio::net::TcpStream) -> std::io::Result<SocketAddr> {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::os::fd::AsRawFd;
+
+    /// Same value for IPv4 (SO_ORIGINAL_DST) and IPv6 (IP6T_SO_ORIGINAL_DST)
+    const SO_ORIGINAL_DST: libc::c_int = 80;
+
+    let fd = stream.as_raw_fd();
+    if stream.local_addr()?.is_ipv4() {
+        // SAFETY: sockaddr_in is plain data; the kernel writes at most `len` bytes
+        let mut addr: libc::sockaddr_in = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::SOL_IP,
+                SO_ORIGINAL_DST,
+                &mut addr as *mut libc::sockaddr_in as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if ret != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let ip = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+        Ok(SocketAddr::new(ip.into(), u16::from_be(addr.sin_port)))
+    } else {
+        // SAFETY: as above, with sockaddr_in6
+        let mut addr: libc::sockaddr_in6 = unsafe { std::mem::zeroed() };
+        let mut len = std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                fd,
+                libc::SOL_IPV6,
+                SO_ORIGINAL_DST,
+                &mut addr as *mut libc::sockaddr_in6 as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        if ret != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let ip = Ipv6Addr::from(addr.sin6_addr.s6_addr);
+        Ok(SocketAddr::new(ip.into(), u16::from_be(addr.sin6_port)))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn original_destination(_stream: &tokio::net::TcpStream) -> std::io::Result<SocketAddr> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "SO_ORIGINAL_DST is only available on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ruleset() {
+        let ruleset = build_ruleset(8444);
+        assert!(ruleset.starts_with("add table inet icon_agent\ndelete table inet icon_agent\n"));
+        assert!(ruleset.contains("meta mark 0x1c0 return"));
+        assert!(ruleset.contains("ip daddr @ai_v4 tcp dport 443 redirect to :8444"));
+        assert!(ruleset.contains("ip6 daddr @ai_v6 tcp dport 443 redirect to :8444"));
+        assert!(ruleset.contains("flags interval"));
+    }
+
+    #[test]
+    fn test_set_update() {
+        let addresses: BTreeSet<IpAddr> =
+            ["104.18.32.47", "2606:4700::6812:202f", "162.159.140.245"]
+                .iter()
+                .map(|ip| ip.parse().unwrap())
+                .collect();
+        assert_eq!(
+            build_set_update(&addresses, &[]),
+            "flush set inet icon_agent ai_v4\n\
+             flush set inet icon_agent ai_v6\n\
+             add element inet icon_agent ai_v4 { 104.18.32.47, 162.159.140.245 }\n\
+             add element inet icon_agent ai_v6 { 2606:4700::6812:202f }\n"
+        );
+        assert_eq!(
+            build_set_update(&BTreeSet::new(), &[]),
+            "flush set inet icon_agent ai_v4\nflush set inet icon_agent ai_v6\n"
+        );
+
+        // Monitored networks are interval elements; addresses they cover
+        // are not repeated
+        let networks = [
+            IpNetwork::parse("104.18.5.9/16").unwrap(),
+            IpNetwork::parse("10.8.0.7").unwrap(),
+            IpNetwork::parse("fd00:a1::/48").unwrap(),
+        ];
+        assert_eq!(
+            build_set_update(&addresses, &networks),
+            "flush set inet icon_agent ai_v4\n\
+             flush set inet icon_agent ai_v6\n\
+             add element inet icon_agent ai_v4 { 162.159.140.245, 104.18.0.0/16, 10.8.0.7/32 }\n\
+             add element inet icon_agent ai_v6 { 2606:4700::6812:202f, fd00:a1::/48 }\n"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_original_destination_without_redirect() {
+        // Without a redirect rule the kernel has no original destination
+        // (or reports the local address, depending on conntrack)
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+        if let Ok(original) = original_destination(&server) {
+            assert_eq!(original, addr);
+        }
+    }
+}
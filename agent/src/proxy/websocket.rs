@@ -0,0 +1,856 @@
+use flate2::{Decompress, FlushDecompress, Status};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::oneshot;
+use tracing::{debug, info};
+
+use crate::proxy::extractors::StreamState;
+use crate::proxy::interceptor::{self, PromptVerdict, ProxyState, ResponseExchange, MAX_READ_SIZE};
+use crate::proxy::request_parser::{self, ExtractedAnswer, ParsedHttpRequest};
+
+/// Read buffer size for frame relaying
+const READ_BUF_SIZE: usize = 16 * 1024;
+
+/// Opcodes (RFC 6455 section 5.2)
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+
+/// Close status sent to the client when a message is blocked
+const CLOSE_POLICY_VIOLATION: u16 = 1008;
+
+/// Close status sent to the client when a message is too large to inspect
+/// and cannot be skipped
+const CLOSE_MESSAGE_TOO_BIG: u16 = 1009;
+
+/// How long the close frame of a blocked prompt waits for the end of the
+/// server frame being relayed
+const CLOSE_FRAME_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Trailer removed by permessage-deflate senders (RFC 7692 section 7.2.1)
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// A WebSocket connection upgraded from an intercepted HTTP/1.1 request.
+/// (Browsers only use WebSockets over HTTP/2 when the server advertises
+/// extended CONNECT, which the proxy does not.)
+pub struct WsSession<'a> {
+    pub platform: &'static str,
+    pub host: &'a str,
+    /// Path of the upgrade request
+    pub path: &'a str,
+    /// Extensions accepted by the server (`Sec-WebSocket-Extensions`)
+    pub extensions: Option<&'a str>,
+}
+
+/// A single decoded frame
+#[derive(Debug)]
+struct WsFrame {
+    fin: bool,
+    /// Set on the first frame of a compressed message (permessage-deflate)
+    rsv1: bool,
+    opcode: u8,
+    /// Unmasked payload
+    payload: Vec<u8>,
+    /// Length of the frame on the wire
+    wire_len: usize,
+}
+
+/// Frame header fields needed before the payload is available
+struct FrameHeader {
+    header_len: usize,
+    payload_len: usize,
+}
+
+/// Relay a WebSocket connection. Client messages are reassembled, decoded
+/// and inspected as prompts before being forwarded; server messages are
+/// relayed frame by frame, and the answers they stream are logged once
+/// complete. A blocked prompt is not forwarded and the connection is closed
+/// with a policy violation status; an answer matching a Block rule was
+/// already relayed, but closes the connection the same way.
+pub async fn relay_websocket<C, U>(
+    state: &ProxyState,
+    session: &WsSession<'_>,
+    client: C,
+    upstream: U,
+) -> anyhow::Result<()>
+where
+    C: AsyncRead + AsyncWrite + Unpin,
+    U: AsyncRead + AsyncWrite + Unpin,
+{
+    info!(host = %session.host, path = %session.path, "WebSocket connection intercepted");
+
+    let (mut client_read, mut client_write) = tokio::io::split(client);
+    let (mut upstream_read, mut upstream_write) = tokio::io::split(upstream);
+    let exchange = ResponseExchange {
+        platform: session.platform,
+        host: session.host,
+        method: "GET",
+        inspect: true,
+        client: None,
+    };
+    let mut answers = ServerMessages::new(session);
+    // Only the server relay writes to the client: the close frame of a
+    // blocked prompt is handed to it, to be written between two frames
+    let (close_tx, close_rx) = oneshot::channel();
+
+    let result = {
+        let downstream = relay_server_frames(
+            state,
+            &exchange,
+            &mut answers,
+            &mut upstream_read,
+            &mut client_write,
+            close_rx,
+        );
+        tokio::pin!(downstream);
+        let upstream_relay =
+            relay_client_frames(state, session, &mut client_read, &mut upstream_write);
+
+        tokio::select! {
+            r = &mut downstream => r,
+            r = upstream_relay => match r {
+                Ok(Some(close)) => {
+                    let _ = close_tx.send(close);
+                    tokio::time::timeout(CLOSE_FRAME_TIMEOUT, downstream)
+                        .await
+                        .unwrap_or(Ok(()))
+                }
+                Ok(None) => Ok(()),
+                Err(e) => Err(e),
+            },
+        }
+    };
+
+    // Answer interrupted by the end of the connection
+    if let Some(answer) = answers.finish() {
+        interceptor::log_response(state, &exchange, &answer.text, &answer.metadata(), None).await;
+    }
+
+    let _ = client_write.shutdown().await;
+    let _ = upstream_write.shutdown().await;
+    result
+}
+
+/// Relay server frames to the client as they complete, following the
+/// answers they stream. A close frame (blocked answer, or blocked prompt
+/// received on `close_rx`) is only written between two frames.
+async fn relay_server_frames<R, W>(
+    state: &ProxyState,
+    exchange: &ResponseExchange<'_>,
+    answers: &mut ServerMessages,
+    upstream_read: &mut R,
+    client_write: &mut W,
+    mut close_rx: oneshot::Receiver<Vec<u8>>,
+) -> anyhow::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = vec![0u8; READ_BUF_SIZE];
+    let mut forward = Vec::new();
+    let mut close: Option<Vec<u8>> = None;
+    let mut close_received = false;
+
+    loop {
+        if let Some(frame) = &close {
+            if answers.is_unframed() {
+                // No frame boundary to wait for: the connection is just closed
+                return Ok(());
+            }
+            if answers.at_frame_boundary() {
+                client_write.write_all(frame).await?;
+                return Ok(());
+            }
+        }
+
+        let n = tokio::select! {
+            frame = &mut close_rx, if !close_received => {
+                close_received = true;
+                close = close.or(frame.ok());
+                continue;
+            }
+            n = upstream_read.read(&mut buf) => n?,
+        };
+        if n == 0 {
+            return Ok(());
+        }
+
+        let completed = answers.feed(&buf[..n], &mut forward);
+        client_write.write_all(&forward).await?;
+        forward.clear();
+
+        if close.is_some() {
+            continue;
+        }
+        for answer in completed {
+            if let Some(notice) =
+                interceptor::log_response(state, exchange, &answer.text, &answer.metadata(), None)
+                    .await
+            {
+                close = Some(build_close_frame(
+                    CLOSE_POLICY_VIOLATION,
+                    &format!("{} ({})", notice.message, notice.rule_name),
+                ));
+                break;
+            }
+        }
+    }
+}
+
+/// Relay client frames to the upstream server, holding each data message
+/// until it is complete and inspected. A message too large to inspect is
+/// relayed as-is and the following ones are inspected again, unless it is
+/// compressed with a shared context, which it would leave out of sync.
+/// Returns the close frame to send to the client when a prompt is blocked.
+async fn relay_client_frames<R, W>(
+    state: &ProxyState,
+    session: &WsSession<'_>,
+    client_read: &mut R,
+    upstream_write: &mut W,
+) -> anyhow::Result<Option<Vec<u8>>>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut inflater = session
+        .extensions
+        .and_then(|e| parse_permessage_deflate(e, "client_no_context_takeover"))
+        .map(Inflater::new);
+    let mut buf: Vec<u8> = Vec::new();
+    let mut message = PendingMessage::default();
+    let mut chunk = vec![0u8; READ_BUF_SIZE];
+    // Bytes left of an oversized frame, relayed as they come
+    let mut passthrough = 0;
+    // Set while the fragments of an oversized message are relayed as-is
+    let mut skipping = false;
+
+    loop {
+        let n = passthrough.min(buf.len());
+        upstream_write.write_all(&buf[..n]).await?;
+        buf.drain(..n);
+        passthrough -= n;
+
+        // Decode every complete frame in the buffer
+        while passthrough == 0 {
+            let Some(header) = parse_frame_header(&buf)? else {
+                break;
+            };
+            if header.payload_len > MAX_READ_SIZE
+                || message.raw.len() + header.payload_len > MAX_READ_SIZE
+            {
+                let fin = buf[0] & 0x80 != 0;
+                let compressed = if buf[0] & 0x0f == OPCODE_CONTINUATION {
+                    message.compressed
+                } else {
+                    buf[0] & 0x40 != 0
+                };
+                if compressed && inflater.as_ref().is_some_and(|i| !i.no_context_takeover) {
+                    debug!(host = %session.host, "Compressed WebSocket message too large to inspect, closing");
+                    return Ok(Some(build_close_frame(
+                        CLOSE_MESSAGE_TOO_BIG,
+                        "Message too large to inspect",
+                    )));
+                }
+                // Too large to inspect: relay this message as-is
+                debug!(host = %session.host, "WebSocket message too large to inspect, relaying as-is");
+                upstream_write
+                    .write_all(&std::mem::take(&mut message).raw)
+                    .await?;
+                let wire_len = header.header_len + header.payload_len;
+                let n = wire_len.min(buf.len());
+                upstream_write.write_all(&buf[..n]).await?;
+                buf.drain(..n);
+                passthrough = wire_len - n;
+                skipping = !fin;
+                continue;
+            }
+            let Some(frame) = parse_frame(&buf, &header) else {
+                break;
+            };
+            let raw: Vec<u8> = buf.drain(..frame.wire_len).collect();
+
+            // Control frames may be interleaved with fragments: forward now,
+            // like the fragments of a skipped message
+            if frame.opcode >= OPCODE_CLOSE || skipping {
+                upstream_write.write_all(&raw).await?;
+                skipping &= frame.opcode >= OPCODE_CLOSE || !frame.fin;
+                continue;
+            }
+
+            if frame.opcode != OPCODE_CONTINUATION {
+                message.opcode = frame.opcode;
+                message.compressed = frame.rsv1;
+            }
+            message.raw.extend_from_slice(&raw);
+            message.payload.extend_from_slice(&frame.payload);
+            if !frame.fin {
+                continue;
+            }
+
+            let message = std::mem::take(&mut message);
+            if let Some(text) = message.text(inflater.as_mut()) {
+                if let PromptVerdict::Block(notice) = inspect_message(state, session, &text).await {
+                    let close = build_close_frame(
+                        CLOSE_POLICY_VIOLATION,
+                        &format!("{} ({})", notice.message, notice.rule_name),
+                    );
+                    return Ok(Some(close));
+                }
+            }
+            upstream_write.write_all(&message.raw).await?;
+        }
+
+        let n = client_read.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// Extract and evaluate the prompt carried by a client text message
+async fn inspect_message(state: &ProxyState, session: &WsSession<'_>, text: &str) -> PromptVerdict {
+    let Some(prompt) = request_parser::extract_ws_prompt(text, session.platform) else {
+        return PromptVerdict::Forward;
+    };
+
+    let req = ParsedHttpRequest {
+        method: "GET".to_string(),
+        path: session.path.to_string(),
+        host: session.host.to_string(),
+        headers: Vec::new(),
+        body: text.as_bytes().to_vec(),
+        content_type: None,
+    };
+    interceptor::evaluate_prompt(state, session.platform, session.host, &req, prompt).await
+}
+
+/// Server frames, cut from the relayed bytes so that they are forwarded
+/// whole, and decoded to follow the answers they stream (SignalR records,
+/// JSON events...)
+struct ServerMessages {
+    platform: &'static str,
+    /// Start of the next frame, until it is complete
+    buf: Vec<u8>,
+    /// Bytes left of a frame too large to be held, relayed as they come
+    passthrough: usize,
+    /// Set when the frames cannot be delimited: bytes are relayed as-is
+    unframed: bool,
+    message: PendingMessage,
+    inflater: Option<Inflater>,
+    state: StreamState,
+    /// Text of the answer being streamed
+    text: String,
+    /// Set when the answers cannot be followed (oversized or undecodable)
+    stopped: bool,
+}
+
+impl ServerMessages {
+    fn new(session: &WsSession<'_>) -> Self {
+        Self {
+            platform: session.platform,
+            buf: Vec::new(),
+            passthrough: 0,
+            unframed: false,
+            message: PendingMessage::default(),
+            inflater: session
+                .extensions
+                .and_then(|e| parse_permessage_deflate(e, "server_no_context_takeover"))
+                .map(Inflater::new),
+            state: StreamState::default(),
+            text: String::new(),
+            stopped: false,
+        }
+    }
+
+    /// Feed bytes received from the server. The complete frames (and the
+    /// available part of an oversized one) are appended to `out`, to be
+    /// relayed; returns the answers they complete.
+    fn feed(&mut self, bytes: &[u8], out: &mut Vec<u8>) -> Vec<ExtractedAnswer> {
+        let mut answers = Vec::new();
+        if self.unframed {
+            out.extend_from_slice(bytes);
+            return answers;
+        }
+        let n = self.passthrough.min(bytes.len());
+        out.extend_from_slice(&bytes[..n]);
+        self.passthrough -= n;
+        self.buf.extend_from_slice(&bytes[n..]);
+
+        loop {
+            let header = match parse_frame_header(&self.buf) {
+                Ok(Some(header)) => header,
+                Ok(None) => break,
+                Err(_) => {
+                    self.unframed = true;
+                    out.append(&mut self.buf);
+                    self.stop();
+                    break;
+                }
+            };
+            if header.payload_len > MAX_READ_SIZE {
+                debug!(platform = %self.platform, "WebSocket frame too large to inspect");
+                self.stop();
+                let wire_len = header.header_len.saturating_add(header.payload_len);
+                let available = wire_len.min(self.buf.len());
+                out.extend(self.buf.drain(..available));
+                self.passthrough = wire_len - available;
+                continue;
+            }
+            let Some(frame) = parse_frame(&self.buf, &header) else {
+                break;
+            };
+            out.extend(self.buf.drain(..frame.wire_len));
+
+            if self.stopped || frame.opcode >= OPCODE_CLOSE {
+                continue;
+            }
+            if self.message.payload.len() > MAX_READ_SIZE {
+                debug!(platform = %self.platform, "WebSocket answer too large to inspect");
+                self.stop();
+                continue;
+            }
+            if frame.opcode != OPCODE_CONTINUATION {
+                self.message.opcode = frame.opcode;
+                self.message.compressed = frame.rsv1;
+            }
+            self.message.payload.extend_from_slice(&frame.payload);
+            if !frame.fin {
+                continue;
+            }
+
+            let message = std::mem::take(&mut self.message);
+            let Some(text) = message.text(self.inflater.as_mut()) else {
+                continue;
+            };
+            // SignalR: several records per message, separated by 0x1E
+            for record in text
+                .split('\u{1e}')
+                .map(str::trim)
+                .filter(|r| !r.is_empty())
+            {
+                if let Some(delta) = request_parser::extract_stream_event_text(
+                    record,
+                    self.platform,
+                    &mut self.state,
+                ) {
+                    self.text.push_str(&delta);
+                }
+                if self.state.take_completed() {
+                    answers.extend(self.finish());
+                }
+            }
+        }
+        answers
+    }
+
+    /// Answer streamed so far, if any; the next one starts afresh
+    fn finish(&mut self) -> Option<ExtractedAnswer> {
+        let answer = ExtractedAnswer {
+            text: std::mem::take(&mut self.text),
+            citations: self.state.citations().to_vec(),
+            usage: Default::default(),
+        };
+        self.state.reset();
+        (!answer.text.is_empty()).then_some(answer)
+    }
+
+    /// Whether the bytes relayed so far end on a frame boundary
+    fn at_frame_boundary(&self) -> bool {
+        !self.unframed && self.passthrough == 0
+    }
+
+    fn is_unframed(&self) -> bool {
+        self.unframed
+    }
+
+    fn stop(&mut self) {
+        self.stopped = true;
+        self.message = PendingMessage::default();
+    }
+}
+
+/// Data message being reassembled from its fragments
+#[derive(Default)]
+struct PendingMessage {
+    opcode: u8,
+    compressed: bool,
+    payload: Vec<u8>,
+    /// Frames as received, forwarded once the message is inspected
+    raw: Vec<u8>,
+}
+
+impl PendingMessage {
+    /// Decoded text of a text message (None for binary messages)
+    fn text(&self, inflater: Option<&mut Inflater>) -> Option<String> {
+        // Compressed binary messages are inflated too, to keep the shared
+        // compression context in sync
+        let payload = if self.compressed {
+            match inflater?.inflate(&self.payload) {
+                Ok(data) => data,
+                Err(e) => {
+                    debug!(error = %e, "Failed to inflate WebSocket message");
+                    return None;
+                }
+            }
+        } else if self.opcode == OPCODE_TEXT {
+            self.payload.clone()
+        } else {
+            return None;
+        };
+        if self.opcode != OPCODE_TEXT {
+            return None;
+        }
+        String::from_utf8(payload).ok()
+    }
+}
+
+/// Decompressor for messages compressed with permessage-deflate
+struct Inflater {
+    decompress: Decompress,
+    /// The sender resets its compression context after each message
+    no_context_takeover: bool,
+}
+
+impl Inflater {
+    fn new(no_context_takeover: bool) -> Self {
+        Self {
+            // Raw deflate; a 15-bit window decodes any smaller window
+            decompress: Decompress::new(false),
+            no_context_takeover,
+        }
+    }
+
+    fn inflate(&mut self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut input = data.to_vec();
+        input.extend_from_slice(&DEFLATE_TAIL);
+
+        let mut out = Vec::with_capacity(input.len() * 4);
+        let mut offset = 0;
+        loop {
+            if out.len() == out.capacity() {
+                out.reserve(out.capacity().max(READ_BUF_SIZE));
+            }
+            let (in_before, out_before) = (self.decompress.total_in(), out.len());
+            let status = self.decompress.decompress_vec(
+                &input[offset..],
+                &mut out,
+                FlushDecompress::Sync,
+            )?;
+            offset += (self.decompress.total_in() - in_before) as usize;
+
+            if out.len() > MAX_READ_SIZE {
+                anyhow::bail!("Inflated WebSocket message too large");
+            }
+            let progressed = self.decompress.total_in() > in_before || out.len() > out_before;
+            let input_done = offset >= input.len() && out.len() < out.capacity();
+            if status == Status::StreamEnd || input_done || !progressed {
+                break;
+            }
+        }
+
+        if self.no_context_takeover {
+            self.decompress.reset(false);
+        }
+        Ok(out)
+    }
+}
+
+/// Parse the permessage-deflate parameters accepted by the server.
+/// Returns whether `no_context_takeover` (`client_no_context_takeover` or
+/// `server_no_context_takeover`) is set, or None if the extension is absent.
+fn parse_permessage_deflate(extensions: &str, no_context_takeover: &str) -> Option<bool> {
+    extensions.split(',').find_map(|extension| {
+        let mut params = extension.split(';').map(str::trim);
+        if !params.next()?.eq_ignore_ascii_case("permessage-deflate") {
+            return None;
+        }
+        Some(params.any(|p| p.eq_ignore_ascii_case(no_context_takeover)))
+    })
+}
+
+/// Parse the header of the frame at the start of `buf`, if complete
+fn parse_frame_header(buf: &[u8]) -> anyhow::Result<Option<FrameHeader>> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    let masked = buf[1] & 0x80 != 0;
+    let (len_bytes, payload_len) = match buf[1] & 0x7f {
+        126 => {
+            if buf.len() < 4 {
+                return Ok(None);
+            }
+            (2, u16::from_be_bytes([buf[2], buf[3]]) as usize)
+        }
+        127 => {
+            if buf.len() < 10 {
+                return Ok(None);
+            }
+            let len = u64::from_be_bytes(buf[2..10].try_into()?);
+            (8, usize::try_from(len)?)
+        }
+        len => (0, len as usize),
+    };
+    let header_len = 2 + len_bytes + if masked { 4 } else { 0 };
+    Ok(Some(FrameHeader {
+        header_len,
+        payload_len,
+    }))
+}
+
+/// Decode the frame at the start of `buf` once its payload is complete
+fn parse_frame(buf: &[u8], header: &FrameHeader) -> Option<WsFrame> {
+    let wire_len = header.header_len + header.payload_len;
+    if buf.len() < wire_len {
+        return None;
+    }
+
+    let mut payload = buf[header.header_len..wire_len].to_vec();
+    if buf[1] & 0x80 != 0 {
+        let mask = &buf[header.header_len - 4..header.header_len];
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+
+    Some(WsFrame {
+        fin: buf[0] & 0x80 != 0,
+        rsv1: buf[0] & 0x40 != 0,
+        opcode: buf[0] & 0x0f,
+        payload,
+        wire_len,
+    })
+}
+
+/// Build an unmasked (server to client) close frame
+fn build_close_frame(code: u16, reason: &str) -> Vec<u8> {
+    // Control frame payloads are limited to 125 bytes
+    let mut reason_len = reason.len().min(123);
+    while !reason.is_char_boundary(reason_len) {
+        reason_len -= 1;
+    }
+
+    let mut frame = vec![0x80 | OPCODE_CLOSE, (2 + reason_len) as u8];
+    frame.extend_from_slice(&code.to_be_bytes());
+    frame.extend_from_slice(&reason.as_bytes()[..reason_len]);
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{Compress, Compression, FlushCompress};
+
+    /// Build a masked (client to server) frame
+    fn client_frame(fin: bool, rsv1: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x12, 0x34, 0x56, 0x78];
+        let mut frame = vec![(fin as u8) << 7 | (rsv1 as u8) << 6 | opcode];
+        match payload.len() {
+            len if len < 126 => frame.push(0x80 | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(0x80 | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(0x80 | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut compress = Compress::new(Compression::default(), false);
+        let mut out = Vec::with_capacity(data.len() + 64);
+        compress
+            .compress_vec(data, &mut out, FlushCompress::Sync)
+            .unwrap();
+        assert!(out.ends_with(&DEFLATE_TAIL));
+        out.truncate(out.len() - DEFLATE_TAIL.len());
+        out
+    }
+
+    #[test]
+    fn test_parse_masked_frames() {
+        let long = "x".repeat(300);
+        let mut buf = client_frame(true, false, OPCODE_TEXT, b"hello");
+        buf.extend(client_frame(true, false, OPCODE_TEXT, long.as_bytes()));
+
+        let header = parse_frame_header(&buf).unwrap().unwrap();
+        let frame = parse_frame(&buf, &header).unwrap();
+        assert_eq!(frame.payload, b"hello");
+        assert!(frame.fin);
+
+        let rest = &buf[frame.wire_len..];
+        let header = parse_frame_header(rest).unwrap().unwrap();
+        assert_eq!(header.payload_len, 300);
+        assert_eq!(parse_frame(rest, &header).unwrap().payload, long.as_bytes());
+    }
+
+    #[test]
+    fn test_incomplete_frame() {
+        let frame = client_frame(true, false, OPCODE_TEXT, b"hello");
+        let header = parse_frame_header(&frame[..4]).unwrap().unwrap();
+        assert!(parse_frame(&frame[..4], &header).is_none());
+        assert!(parse_frame_header(&frame[..1]).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_inflate_with_context_takeover() {
+        let mut compress = Compress::new(Compression::default(), false);
+        let mut inflater = Inflater::new(false);
+        for text in ["premier message", "premier message encore"] {
+            let mut out = Vec::with_capacity(256);
+            compress
+                .compress_vec(text.as_bytes(), &mut out, FlushCompress::Sync)
+                .unwrap();
+            out.truncate(out.len() - DEFLATE_TAIL.len());
+            assert_eq!(inflater.inflate(&out).unwrap(), text.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_parse_permessage_deflate() {
+        let client = "client_no_context_takeover";
+        assert_eq!(
+            parse_permessage_deflate("permessage-deflate", client),
+            Some(false)
+        );
+        assert_eq!(
+            parse_permessage_deflate(
+                "permessage-deflate; client_no_context_takeover; server_max_window_bits=10",
+                client
+            ),
+            Some(true)
+        );
+        assert_eq!(
+            parse_permessage_deflate(
+                "permessage-deflate; client_no_context_takeover",
+                "server_no_context_takeover"
+            ),
+            Some(false)
+        );
+        assert_eq!(
+            parse_permessage_deflate("x-webkit-deflate-frame", client),
+            None
+        );
+    }
+
+    #[test]
+    fn test_close_frame() {
+        let frame = build_close_frame(CLOSE_POLICY_VIOLATION, &"é".repeat(100));
+        assert_eq!(frame[0], 0x88);
+        assert!(frame[1] as usize <= 125);
+        assert_eq!(u16::from_be_bytes([frame[2], frame[3]]), 1008);
+        assert!(std::str::from_utf8(&frame[4..]).is_ok());
+    }
+
+    /// Build an unmasked (server to client) frame
+    fn server_frame(fin: bool, rsv1: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mut frame = client_frame(fin, rsv1, opcode, payload);
+        let mask_at = frame.len() - payload.len() - 4;
+        frame[1] &= 0x7f;
+        frame.drain(mask_at..mask_at + 4);
+        frame[mask_at..].copy_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn test_server_answers_followed() {
+        let session = WsSession {
+            platform: "copilot",
+            host: "copilot.microsoft.com",
+            path: "/c/api/chat",
+            extensions: Some("permessage-deflate; server_no_context_takeover"),
+        };
+        let mut messages = ServerMessages::new(&session);
+
+        let events = [
+            r#"{"event":"appendText","messageId":"m1","text":"Le contrat "}"#,
+            r#"{"event":"appendText","messageId":"m1","text":"expire en mars."}"#,
+            r#"{"event":"citation","messageId":"m1","url":"https://example.org/contrat"}"#,
+        ];
+        let mut bytes: Vec<u8> = events
+            .iter()
+            .flat_map(|e| server_frame(true, true, OPCODE_TEXT, &deflate(e.as_bytes())))
+            .collect();
+        // Fragmented, uncompressed completion split across reads
+        bytes.extend(server_frame(false, false, OPCODE_TEXT, br#"{"event":"#));
+        bytes.extend(server_frame(
+            true,
+            false,
+            OPCODE_CONTINUATION,
+            br#""done","messageId":"m1"}"#,
+        ));
+
+        // Only whole frames are relayed
+        let (first, second) = bytes.split_at(bytes.len() - 5);
+        let mut out = Vec::new();
+        assert!(messages.feed(first, &mut out).is_empty());
+        assert!(bytes[out.len()..].len() > 5);
+        let answers = messages.feed(second, &mut out);
+        assert_eq!(out, bytes);
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].text, "Le contrat expire en mars.");
+        assert_eq!(answers[0].citations, ["https://example.org/contrat"]);
+
+        // Next answer, interrupted by the end of the connection
+        messages.feed(
+            &server_frame(
+                true,
+                false,
+                OPCODE_TEXT,
+                br#"{"event":"appendText","text":"Autre"}"#,
+            ),
+            &mut out,
+        );
+        assert_eq!(messages.finish().unwrap().text, "Autre");
+        assert!(messages.finish().is_none());
+    }
+
+    #[test]
+    fn test_oversized_server_frame_relayed() {
+        let session = WsSession {
+            platform: "copilot",
+            host: "copilot.microsoft.com",
+            path: "/c/api/chat",
+            extensions: None,
+        };
+        let mut messages = ServerMessages::new(&session);
+
+        let payload_len = MAX_READ_SIZE + 10;
+        let mut header = vec![0x82, 127];
+        header.extend_from_slice(&(payload_len as u64).to_be_bytes());
+        let mut out = Vec::new();
+        messages.feed(&[header.as_slice(), b"abc"].concat(), &mut out);
+        assert_eq!(out.len(), header.len() + 3);
+        assert!(!messages.at_frame_boundary());
+
+        // The rest of the frame, then the start of the next one
+        let rest = vec![0u8; payload_len - 3];
+        let next = server_frame(true, false, OPCODE_TEXT, b"{}");
+        messages.feed(&[rest.as_slice(), &next[..2]].concat(), &mut out);
+        assert!(messages.at_frame_boundary());
+        assert_eq!(out.len(), header.len() + payload_len);
+        messages.feed(&next[2..], &mut out);
+        assert!(out.ends_with(&next));
+    }
+
+    #[test]
+    fn test_pending_message_text() {
+        let mut inflater = Inflater::new(true);
+        let message = PendingMessage {
+            opcode: OPCODE_TEXT,
+            compressed: true,
+            payload: deflate("{\"text\":\"Bonjour\"}".as_bytes()),
+            raw: Vec::new(),
+        };
+        assert_eq!(
+            message.text(Some(&mut inflater)).unwrap(),
+            "{\"text\":\"Bonjour\"}"
+        );
+        // Compressed message without negotiated extension is not decoded
+        assert!(message.text(None).is_none());
+    }
+}
@@ -0,0 +1,164 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+use tracing::warn;
+
+use crate::rules::models::ThresholdMetric;
+use crate::storage::database::Database;
+
+/// Width of a counter bucket in seconds
+const BUCKET_SECS: i64 = 60;
+
+/// Longest window a threshold rule can look back over (7 days).
+/// Older buckets are purged.
+const MAX_WINDOW_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// Sliding-window activity counters used by `Threshold` rule conditions.
+///
+/// Activity is aggregated in one-minute buckets persisted in the local
+/// database, so that restarting the agent does not reset the windows.
+pub struct CounterStore {
+    db: Arc<Database>,
+    /// Bucket at which old buckets were last purged
+    last_purge_bucket: AtomicI64,
+}
+
+impl CounterStore {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self {
+            db,
+            last_purge_bucket: AtomicI64::new(0),
+        }
+    }
+
+    /// Record `amount` of activity for a metric, attributed to `platform`
+    /// (None for activity not tied to an AI platform, e.g. clipboard)
+    pub fn record(&self, metric: ThresholdMetric, platform: Option<&str>, amount: u64) {
+        self.record_at(metric, platform, amount, chrono::Utc::now().timestamp());
+    }
+
+    /// Total activity for a metric over the last `window_secs` seconds.
+    /// An empty `platforms` slice counts activity on every platform.
+    pub fn total(&self, metric: ThresholdMetric, platforms: &[String], window_secs: u64) -> u64 {
+        self.total_at(
+            metric,
+            platforms,
+            window_secs,
+            chrono::Utc::now().timestamp(),
+        )
+    }
+
+    fn record_at(&self, metric: ThresholdMetric, platform: Option<&str>, amount: u64, now: i64) {
+        let bucket = now / BUCKET_SECS;
+        if let Err(e) =
+            self.db
+                .add_activity(metric.as_str(), platform.unwrap_or(""), bucket, amount)
+        {
+            warn!(error = %e, metric = metric.as_str(), "Failed to record activity counter");
+        }
+
+        // Purge at most once per bucket
+        if self.last_purge_bucket.swap(bucket, Ordering::Relaxed) != bucket {
+            if let Err(e) = self
+                .db
+                .purge_activity(bucket - MAX_WINDOW_SECS / BUCKET_SECS)
+            {
+                warn!(error = %e, "Failed to purge old activity counters");
+            }
+        }
+    }
+
+    fn total_at(
+        &self,
+        metric: ThresholdMetric,
+        platforms: &[String],
+        window_secs: u64,
+        now: i64,
+    ) -> u64 {
+        let window_buckets = window_secs.min(MAX_WINDOW_SECS as u64) as i64 / BUCKET_SECS;
+        let since_bucket = now / BUCKET_SECS - window_buckets;
+
+        self.db
+            .sum_activity(metric.as_str(), platforms, since_bucket)
+            .unwrap_or_else(|e| {
+                warn!(error = %e, metric = metric.as_str(), "Failed to read activity counter");
+                0
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_store() -> (CounterStore, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::init(dir.path(), "test-key").unwrap();
+        db.run_migrations().unwrap();
+        (CounterStore::new(Arc::new(db)), dir)
+    }
+
+    #[test]
+    fn test_total_within_window() {
+        let (store, _dir) = test_store();
+        let now = 1_700_000_000;
+        store.record_at(ThresholdMetric::PromptCount, Some("chatgpt"), 1, now - 7200);
+        store.record_at(ThresholdMetric::PromptCount, Some("chatgpt"), 1, now - 600);
+        store.record_at(ThresholdMetric::PromptCount, Some("chatgpt"), 1, now);
+
+        assert_eq!(
+            store.total_at(ThresholdMetric::PromptCount, &[], 3600, now),
+            2
+        );
+        assert_eq!(
+            store.total_at(ThresholdMetric::PromptCount, &[], 86400, now),
+            3
+        );
+    }
+
+    #[test]
+    fn test_total_filters_platforms_and_metrics() {
+        let (store, _dir) = test_store();
+        let now = 1_700_000_000;
+        store.record_at(ThresholdMetric::PromptBytes, Some("chatgpt"), 1000, now);
+        store.record_at(ThresholdMetric::PromptBytes, Some("claude"), 500, now);
+        store.record_at(ThresholdMetric::ClipboardBytes, None, 300, now);
+
+        assert_eq!(
+            store.total_at(ThresholdMetric::PromptBytes, &[], 60, now),
+            1500
+        );
+        assert_eq!(
+            store.total_at(
+                ThresholdMetric::PromptBytes,
+                &["claude".to_string()],
+                60,
+                now
+            ),
+            500
+        );
+        assert_eq!(
+            store.total_at(ThresholdMetric::ClipboardBytes, &[], 60, now),
+            300
+        );
+    }
+
+    #[test]
+    fn test_old_buckets_purged() {
+        let (store, _dir) = test_store();
+        let now = 1_700_000_000;
+        store.record_at(
+            ThresholdMetric::PromptCount,
+            None,
+            1,
+            now - MAX_WINDOW_SECS - 120,
+        );
+        store.record_at(ThresholdMetric::PromptCount, None, 1, now);
+
+        assert_eq!(store.db.sum_activity("prompt_count", &[], 0).unwrap(), 1);
+        assert_eq!(
+            store.total_at(ThresholdMetric::PromptCount, &[], u64::MAX, now),
+            1
+        );
+    }
+}
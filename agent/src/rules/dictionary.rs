@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind};
+use tracing::warn;
+
+use crate::rules::models::Dictionary;
+
+/// A dictionary compiled into a multi-pattern automaton
+struct CompiledDictionary {
+    version: u64,
+    automaton: AhoCorasick,
+}
+
+/// In-memory set of compiled entity dictionaries referenced by `Dictionary`
+/// rule conditions. Thousands of terms are matched in a single pass over
+/// the content.
+pub struct DictionaryStore {
+    compiled: RwLock<HashMap<String, CompiledDictionary>>,
+}
+
+impl DictionaryStore {
+    pub fn new() -> Self {
+        Self {
+            compiled: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Replace all compiled dictionaries
+    pub fn replace_all(&self, dictionaries: Vec<Dictionary>) {
+        let compiled: HashMap<String, CompiledDictionary> = dictionaries
+            .into_iter()
+            .filter_map(|d| compile(&d).map(|c| (d.name, c)))
+            .collect();
+        *self.compiled.write().unwrap() = compiled;
+    }
+
+    /// Highest dictionary version known locally (for incremental sync)
+    pub fn latest_version(&self) -> u64 {
+        let compiled = self.compiled.read().unwrap();
+        compiled.values().map(|d| d.version).max().unwrap_or(0)
+    }
+
+    /// Count whole-word, case-insensitive occurrences of the dictionary terms
+    /// in `content`. Returns None if the dictionary is unknown.
+    pub fn count_hits(&self, name: &str, content: &str) -> Option<usize> {
+        let compiled = self.compiled.read().unwrap();
+        let dictionary = compiled.get(name)?;
+
+        let haystack = content.to_lowercase();
+        let hits = dictionary
+            .automaton
+            .find_iter(&haystack)
+            .filter(|m| is_whole_word(&haystack, m.start(), m.end()))
+            .count();
+        Some(hits)
+    }
+}
+
+impl Default for DictionaryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn compile(dictionary: &Dictionary) -> Option<CompiledDictionary> {
+    let terms: Vec<String> = dictionary
+        .terms
+        .iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+
+    // Leftmost-longest so that "Projet Atlas" wins over "Atlas"
+    match AhoCorasickBuilder::new()
+        .match_kind(MatchKind::LeftmostLongest)
+        .build(&terms)
+    {
+        Ok(automaton) => Some(CompiledDictionary {
+            version: dictionary.version,
+            automaton,
+        }),
+        Err(e) => {
+            warn!(dictionary = %dictionary.name, error = %e, "Failed to compile dictionary");
+            None
+        }
+    }
+}
+
+/// Check that a match is not part of a larger word
+fn is_whole_word(haystack: &str, start: usize, end: usize) -> bool {
+    let before = haystack[..start].chars().next_back();
+    let after = haystack[end..].chars().next();
+    !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> DictionaryStore {
+        let store = DictionaryStore::new();
+        store.replace_all(vec![
+            Dictionary {
+                name: "clients".to_string(),
+                version: 3,
+                terms: vec![
+                    "Orange".to_string(),
+                    "Société Générale".to_string(),
+                    "CIE".to_string(),
+                ],
+            },
+            Dictionary {
+                name: "projets".to_string(),
+                version: 5,
+                terms: vec!["Atlas".to_string(), "Projet Atlas".to_string()],
+            },
+        ]);
+        store
+    }
+
+    #[test]
+    fn test_count_hits_case_insensitive() {
+        let store = store();
+        let content = "Contrat ORANGE et avenant société générale, copie à Orange.";
+        assert_eq!(store.count_hits("clients", content), Some(3));
+    }
+
+    #[test]
+    fn test_whole_words_only() {
+        let store = store();
+        assert_eq!(
+            store.count_hits("clients", "Une orangeade et une science"),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_leftmost_longest() {
+        let store = store();
+        assert_eq!(
+            store.count_hits("projets", "Le projet Atlas avance"),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_unknown_dictionary() {
+        assert_eq!(store().count_hits("inconnu", "Orange"), None);
+    }
+
+    #[test]
+    fn test_latest_version() {
+        assert_eq!(store().latest_version(), 5);
+        assert_eq!(DictionaryStore::new().latest_version(), 0);
+    }
+}
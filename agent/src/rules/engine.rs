@@ -1,8 +1,10 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{info, debug};
+use tracing::{debug, info};
 
-use crate::rules::matcher;
+use crate::rules::counters::CounterStore;
+use crate::rules::dictionary::DictionaryStore;
+use crate::rules::matcher::{self, MatchInput};
 use crate::rules::models::*;
 use crate::storage::database::Database;
 
@@ -10,11 +12,17 @@ pub struct RuleEngine {
     db: Arc<Database>,
     /// Rules cached in memory, sorted by priority (descending)
     cached_rules: RwLock<Vec<Rule>>,
+    /// Sliding-window activity counters for `Threshold` conditions
+    counters: CounterStore,
+    /// Compiled entity dictionaries for `Dictionary` conditions
+    dictionaries: DictionaryStore,
 }
 
 impl RuleEngine {
     pub fn new(db: Arc<Database>) -> Self {
         Self {
+            counters: CounterStore::new(db.clone()),
+            dictionaries: DictionaryStore::new(),
             db,
             cached_rules: RwLock::new(Vec::new()),
         }
@@ -26,7 +34,7 @@ impl RuleEngine {
         let count = rules.len();
         let mut cache = self.cached_rules.write().await;
         *cache = rules;
-        cache.sort_by(|a, b| b.priority.cmp(&a.priority));
+        cache.sort_by_key(|r| std::cmp::Reverse(r.priority));
         info!(count, "Rules loaded into cache");
         Ok(())
     }
@@ -45,6 +53,39 @@ impl RuleEngine {
         self.load_rules().await
     }
 
+    /// Load entity dictionaries from local SQLite and compile them
+    pub fn load_dictionaries(&self) -> anyhow::Result<()> {
+        let dictionaries = self.db.get_all_dictionaries()?;
+        let count = dictionaries.len();
+        self.dictionaries.replace_all(dictionaries);
+        info!(count, "Dictionaries loaded");
+        Ok(())
+    }
+
+    /// Save dictionaries from server to local DB and recompile them
+    pub fn update_dictionaries(&self, dictionaries: Vec<Dictionary>) -> anyhow::Result<()> {
+        for dictionary in &dictionaries {
+            self.db.upsert_dictionary(dictionary)?;
+        }
+        self.load_dictionaries()
+    }
+
+    /// Delete a dictionary by name
+    pub fn delete_dictionary(&self, name: &str) -> anyhow::Result<()> {
+        self.db.delete_dictionary(name)?;
+        self.load_dictionaries()
+    }
+
+    /// Get the latest dictionary version number (for incremental sync)
+    pub fn latest_dictionary_version(&self) -> u64 {
+        self.dictionaries.latest_version()
+    }
+
+    /// Record activity feeding `Threshold` conditions (prompts, clipboard copies)
+    pub fn record_activity(&self, metric: ThresholdMetric, platform: Option<&str>, amount: u64) {
+        self.counters.record(metric, platform, amount);
+    }
+
     /// Get the latest rule version number (for incremental sync)
     pub async fn latest_version(&self) -> u64 {
         let cache = self.cached_rules.read().await;
@@ -53,7 +94,20 @@ impl RuleEngine {
 
     /// Evaluate content against all rules for a given target type.
     /// Returns the result of the first matching rule (highest priority).
+    #[cfg(test)]
     pub async fn evaluate(&self, content: &str, target: RuleTarget) -> EvaluationResult {
+        self.evaluate_context(&EvaluationContext::new(content), target)
+            .await
+    }
+
+    /// Evaluate a message and its aggregates against all rules for a given target type.
+    /// Conversation-scoped rules are only considered when the context carries
+    /// conversation aggregates.
+    pub async fn evaluate_context(
+        &self,
+        ctx: &EvaluationContext,
+        target: RuleTarget,
+    ) -> EvaluationResult {
         let rules = self.cached_rules.read().await;
 
         for rule in rules.iter() {
@@ -65,14 +119,47 @@ impl RuleEngine {
                 continue;
             }
 
-            if matcher::matches_condition(content, &rule.condition) {
+            let input = match rule.scope {
+                // DLP counts and language are only computed for the rules using them
+                RuleScope::Message => MatchInput {
+                    content: &ctx.content,
+                    dlp_counts: matches!(rule.condition, RuleCondition::DlpCount { .. })
+                        .then(|| ctx.dlp_counts()),
+                    counters: Some(&self.counters),
+                    platform: ctx.platform.as_deref(),
+                    language: match rule.condition {
+                        RuleCondition::Language { .. } => ctx.language(),
+                        _ => None,
+                    },
+                    dictionaries: Some(&self.dictionaries),
+                    account: ctx.account,
+                    api_key: ctx.api_key_fingerprint.as_deref(),
+                },
+                RuleScope::Conversation => match &ctx.conversation {
+                    Some(conv) => MatchInput {
+                        content: &conv.content,
+                        dlp_counts: Some(&conv.dlp_counts),
+                        counters: Some(&self.counters),
+                        platform: ctx.platform.as_deref(),
+                        // Detected on the aggregate content when needed
+                        language: None,
+                        dictionaries: Some(&self.dictionaries),
+                        account: ctx.account,
+                        api_key: ctx.api_key_fingerprint.as_deref(),
+                    },
+                    None => continue,
+                },
+            };
+
+            if matcher::matches_input(&input, &rule.condition) {
                 debug!(rule_id = %rule.id, rule_name = %rule.name, "Rule matched");
 
                 return match &rule.action {
-                    RuleAction::Block { message } => EvaluationResult::Blocked {
+                    RuleAction::Block { message, template } => EvaluationResult::Blocked {
                         rule_id: rule.id.clone(),
                         rule_name: rule.name.clone(),
                         message: message.clone(),
+                        template: template.clone(),
                     },
                     RuleAction::Alert { severity } => EvaluationResult::Alerted {
                         rule_id: rule.id.clone(),
@@ -89,3 +176,187 @@ impl RuleEngine {
         EvaluationResult::NoMatch
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_engine() -> (RuleEngine, tempfile::TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Database::init(dir.path(), "test-key").unwrap();
+        db.run_migrations().unwrap();
+        (RuleEngine::new(Arc::new(db)), dir)
+    }
+
+    fn rule(id: &str, scope: RuleScope, condition: RuleCondition) -> Rule {
+        Rule {
+            id: id.to_string(),
+            name: id.to_string(),
+            version: 1,
+            category: RuleCategory::Alert,
+            target: RuleTarget::Prompt,
+            condition,
+            action: RuleAction::Alert {
+                severity: AlertSeverity::Warning,
+            },
+            priority: 10,
+            enabled: true,
+            scope,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conversation_scope_uses_aggregate() {
+        let (engine, _dir) = test_engine();
+        engine
+            .update_rules(vec![rule(
+                "conv-cards",
+                RuleScope::Conversation,
+                RuleCondition::DlpCount {
+                    patterns: vec!["credit_card".to_string()],
+                    min_count: 2,
+                },
+            )])
+            .await
+            .unwrap();
+
+        let message = "Et la seconde : 5425233430109903";
+        assert!(matches!(
+            engine.evaluate(message, RuleTarget::Prompt).await,
+            EvaluationResult::NoMatch
+        ));
+
+        let ctx = EvaluationContext::new(message).with_conversation(ConversationAggregate {
+            conversation_id: "c1".to_string(),
+            message_count: 2,
+            content: format!("Carte 4532015112830366\n{}", message),
+            dlp_counts: crate::rules::dlp::count_matches("4532015112830366 5425233430109903"),
+        });
+        assert!(matches!(
+            engine.evaluate_context(&ctx, RuleTarget::Prompt).await,
+            EvaluationResult::Alerted { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_dlp_and_language_computed_on_demand() {
+        let (engine, _dir) = test_engine();
+        engine
+            .update_rules(vec![rule(
+                "secret",
+                RuleScope::Message,
+                RuleCondition::Keyword {
+                    keywords: vec!["confidentiel".to_string()],
+                    match_all: false,
+                },
+            )])
+            .await
+            .unwrap();
+
+        let ctx = EvaluationContext::new("Carte 4532015112830366, rédige un courrier de relance");
+        engine.evaluate_context(&ctx, RuleTarget::Prompt).await;
+        assert!(!ctx.dlp_computed());
+        assert_eq!(ctx.detected_language(), None);
+        assert_eq!(ctx.language(), Some("fr"));
+        assert_eq!(ctx.detected_language(), Some("fr"));
+
+        assert_eq!(ctx.dlp_counts().get("credit_card"), Some(&1));
+        assert!(ctx.dlp_computed());
+    }
+
+    #[tokio::test]
+    async fn test_threshold_on_recorded_activity() {
+        let (engine, _dir) = test_engine();
+        engine
+            .update_rules(vec![rule(
+                "too-many-prompts",
+                RuleScope::Message,
+                RuleCondition::Threshold {
+                    metric: ThresholdMetric::PromptCount,
+                    window: 3600,
+                    limit: 2,
+                    platforms: vec!["perplexity".to_string()],
+                },
+            )])
+            .await
+            .unwrap();
+
+        for _ in 0..3 {
+            engine.record_activity(ThresholdMetric::PromptCount, Some("chatgpt"), 1);
+        }
+        let ctx = EvaluationContext::new("bonjour").with_platform("chatgpt");
+        assert!(matches!(
+            engine.evaluate_context(&ctx, RuleTarget::Prompt).await,
+            EvaluationResult::NoMatch
+        ));
+
+        for _ in 0..3 {
+            engine.record_activity(ThresholdMetric::PromptCount, Some("perplexity"), 1);
+        }
+        let ctx = EvaluationContext::new("bonjour").with_platform("perplexity");
+        assert!(matches!(
+            engine.evaluate_context(&ctx, RuleTarget::Prompt).await,
+            EvaluationResult::Alerted { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_dictionary_persisted_and_matched() {
+        let (engine, _dir) = test_engine();
+        engine
+            .update_dictionaries(vec![Dictionary {
+                name: "projets".to_string(),
+                version: 4,
+                terms: vec!["Kilimandjaro".to_string()],
+            }])
+            .unwrap();
+        engine
+            .update_rules(vec![rule(
+                "code-names",
+                RuleScope::Message,
+                RuleCondition::Dictionary {
+                    name: "projets".to_string(),
+                    min_hits: 1,
+                },
+            )])
+            .await
+            .unwrap();
+
+        // Reload from the DB as on restart
+        engine.load_dictionaries().unwrap();
+        assert_eq!(engine.latest_dictionary_version(), 4);
+        assert!(matches!(
+            engine
+                .evaluate("Point d'avancement Kilimandjaro", RuleTarget::Prompt)
+                .await,
+            EvaluationResult::Alerted { .. }
+        ));
+
+        engine.delete_dictionary("projets").unwrap();
+        assert!(matches!(
+            engine
+                .evaluate("Point d'avancement Kilimandjaro", RuleTarget::Prompt)
+                .await,
+            EvaluationResult::NoMatch
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_scope_persisted() {
+        let (engine, _dir) = test_engine();
+        engine
+            .update_rules(vec![rule(
+                "conv-kw",
+                RuleScope::Conversation,
+                RuleCondition::Keyword {
+                    keywords: vec!["secret".to_string()],
+                    match_all: false,
+                },
+            )])
+            .await
+            .unwrap();
+
+        let rules = engine.db.get_all_rules().unwrap();
+        assert_eq!(rules[0].scope, RuleScope::Conversation);
+    }
+}
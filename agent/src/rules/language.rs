@@ -0,0 +1,113 @@
+use whatlang::Lang;
+
+/// Minimum number of characters needed for a meaningful detection
+const MIN_DETECTION_CHARS: usize = 20;
+
+/// Only the beginning of long texts is analysed (detection cost is linear)
+const MAX_DETECTION_CHARS: usize = 4096;
+
+/// ISO 639-3 → ISO 639-1 codes for the languages commonly seen in prompts.
+/// Languages without an entry are reported with their ISO 639-3 code.
+const ISO_639_1: &[(&str, &str)] = &[
+    ("fra", "fr"),
+    ("eng", "en"),
+    ("spa", "es"),
+    ("por", "pt"),
+    ("deu", "de"),
+    ("ita", "it"),
+    ("nld", "nl"),
+    ("ara", "ar"),
+    ("cmn", "zh"),
+    ("jpn", "ja"),
+    ("kor", "ko"),
+    ("rus", "ru"),
+    ("ukr", "uk"),
+    ("pol", "pl"),
+    ("tur", "tr"),
+    ("hin", "hi"),
+    ("vie", "vi"),
+    ("ind", "id"),
+    ("swe", "sv"),
+    ("dan", "da"),
+    ("fin", "fi"),
+    ("ell", "el"),
+    ("heb", "he"),
+    ("ron", "ro"),
+    ("ces", "cs"),
+    ("hun", "hu"),
+    ("yor", "yo"),
+    ("swh", "sw"),
+];
+
+/// Identify the language of a text.
+/// Returns an ISO 639-1 code when one exists (ISO 639-3 otherwise), or None
+/// when the text is too short to be identified. Closely related languages
+/// (e.g. Russian / Ukrainian) may be confused on short texts.
+pub fn detect(text: &str) -> Option<String> {
+    let sample: String = text.chars().take(MAX_DETECTION_CHARS).collect();
+    if sample.chars().filter(|c| c.is_alphabetic()).count() < MIN_DETECTION_CHARS {
+        return None;
+    }
+
+    let lang = whatlang::detect_lang(&sample)?;
+    Some(short_code(lang).to_string())
+}
+
+/// Check whether a detected language code matches one of the policy codes.
+/// Policy codes may be given in ISO 639-1 ("fr") or ISO 639-3 ("fra").
+pub fn code_matches(detected: &str, codes: &[String]) -> bool {
+    let long = ISO_639_1
+        .iter()
+        .find(|(_, short)| *short == detected)
+        .map(|(long, _)| *long);
+
+    codes.iter().any(|code| {
+        code.eq_ignore_ascii_case(detected) || long.is_some_and(|l| code.eq_ignore_ascii_case(l))
+    })
+}
+
+fn short_code(lang: Lang) -> &'static str {
+    let code = lang.code();
+    ISO_639_1
+        .iter()
+        .find(|(long, _)| *long == code)
+        .map(|(_, short)| *short)
+        .unwrap_or(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_french() {
+        let text = "Peux-tu me rédiger un cahier des charges pour la refonte du portail client ?";
+        assert_eq!(detect(text).as_deref(), Some("fr"));
+    }
+
+    #[test]
+    fn test_detect_english() {
+        let text = "Please write a detailed specification for the new customer portal redesign.";
+        assert_eq!(detect(text).as_deref(), Some("en"));
+    }
+
+    #[test]
+    fn test_detect_non_latin_script() {
+        let text = "请为新的客户门户网站编写详细的规格说明书，包括所有的功能需求和技术要求。";
+        assert_eq!(detect(text).as_deref(), Some("zh"));
+    }
+
+    #[test]
+    fn test_detect_too_short() {
+        assert_eq!(detect("ok merci"), None);
+        assert_eq!(detect("1234 5678 9012 3456 7890"), None);
+    }
+
+    #[test]
+    fn test_code_matches() {
+        let codes = vec!["FR".to_string(), "eng".to_string()];
+        assert!(code_matches("fr", &codes));
+        assert!(code_matches("en", &codes));
+        assert!(!code_matches("es", &codes));
+    }
+}
@@ -1,8 +1,12 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::sync::Mutex;
 
+use crate::rules::counters::CounterStore;
+use crate::rules::dictionary::DictionaryStore;
+use crate::rules::models::{AccountType, RuleCondition};
+use crate::rules::{dlp, language};
 use regex::Regex;
-use crate::rules::models::RuleCondition;
 
 /// Type alias for the regex cache to reduce complexity.
 type RegexCache = Mutex<HashMap<(String, bool), Result<Regex, String>>>;
@@ -12,31 +16,78 @@ type RegexCache = Mutex<HashMap<(String, bool), Result<Regex, String>>>;
 static REGEX_CACHE: std::sync::LazyLock<RegexCache> =
     std::sync::LazyLock::new(|| Mutex::new(HashMap::new()));
 
-/// Évalue si un contenu matche une condition de règle
-pub fn matches_condition(content: &str, condition: &RuleCondition) -> bool {
+/// Données sur lesquelles une condition est évaluée
+pub struct MatchInput<'a> {
+    pub content: &'a str,
+    /// Comptes DLP pré-calculés ; recalculés depuis `content` si absents
+    pub dlp_counts: Option<&'a HashMap<String, usize>>,
+    /// Compteurs d'activité pour les conditions `Threshold`
+    pub counters: Option<&'a CounterStore>,
+    /// Plateforme IA du contenu évalué
+    pub platform: Option<&'a str>,
+    /// Langue détectée ; détectée depuis `content` si absente
+    pub language: Option<&'a str>,
+    /// Dictionnaires d'entités pour les conditions `Dictionary`
+    pub dictionaries: Option<&'a DictionaryStore>,
+    /// Type de compte utilisé sur la plateforme, s'il est détectable
+    pub account: Option<AccountType>,
+    /// Empreinte salée de la clé d'API utilisée par la requête
+    pub api_key: Option<&'a str>,
+}
+
+impl<'a> MatchInput<'a> {
+    /// Contenu seul, sans agrégats ni contexte
+    #[cfg(test)]
+    pub fn new(content: &'a str) -> Self {
+        Self {
+            content,
+            dlp_counts: None,
+            counters: None,
+            platform: None,
+            language: None,
+            dictionaries: None,
+            account: None,
+            api_key: None,
+        }
+    }
+}
+
+/// Évalue une condition sur un contenu accompagné de ses agrégats
+pub fn matches_input(input: &MatchInput, condition: &RuleCondition) -> bool {
+    let content = input.content;
     match condition {
-        RuleCondition::Regex { pattern, case_insensitive } => {
-            match get_or_compile_regex(pattern, *case_insensitive) {
-                Ok(re) => re.is_match(content),
-                Err(e) => {
-                    tracing::warn!(pattern, error = %e, "Invalid regex pattern in rule");
-                    false
-                }
+        RuleCondition::Regex {
+            pattern,
+            case_insensitive,
+        } => match get_or_compile_regex(pattern, *case_insensitive) {
+            Ok(re) => re.is_match(content),
+            Err(e) => {
+                tracing::warn!(pattern, error = %e, "Invalid regex pattern in rule");
+                false
             }
-        }
+        },
 
-        RuleCondition::Keyword { keywords, match_all } => {
+        RuleCondition::Keyword {
+            keywords,
+            match_all,
+        } => {
             let lower_content = content.to_lowercase();
             if *match_all {
-                keywords.iter().all(|kw| lower_content.contains(&kw.to_lowercase()))
+                keywords
+                    .iter()
+                    .all(|kw| lower_content.contains(&kw.to_lowercase()))
             } else {
-                keywords.iter().any(|kw| lower_content.contains(&kw.to_lowercase()))
+                keywords
+                    .iter()
+                    .any(|kw| lower_content.contains(&kw.to_lowercase()))
             }
         }
 
         RuleCondition::DomainList { domains } => {
             let lower_content = content.to_lowercase();
-            domains.iter().any(|d| lower_content.contains(&d.to_lowercase()))
+            domains
+                .iter()
+                .any(|d| lower_content.contains(&d.to_lowercase()))
         }
 
         // ContentLength triggers when content is OUTSIDE the allowed range:
@@ -49,6 +100,90 @@ pub fn matches_condition(content: &str, condition: &RuleCondition) -> bool {
             let below_min = min.is_some_and(|m| len < m);
             exceeds_max || below_min
         }
+
+        RuleCondition::DlpCount {
+            patterns,
+            min_count,
+        } => {
+            let counts = match input.dlp_counts {
+                Some(c) => Cow::Borrowed(c),
+                None => Cow::Owned(dlp::count_matches(content)),
+            };
+            let total: usize = counts
+                .iter()
+                .filter(|(name, _)| patterns.is_empty() || patterns.contains(name))
+                .map(|(_, count)| count)
+                .sum();
+            *min_count > 0 && total >= *min_count
+        }
+
+        // Threshold triggers when activity over the window EXCEEDS the limit.
+        // Without a counter store (content-only evaluation) it never matches.
+        RuleCondition::Threshold {
+            metric,
+            window,
+            limit,
+            platforms,
+        } => {
+            let counters = match input.counters {
+                Some(c) => c,
+                None => return false,
+            };
+            if !platforms.is_empty()
+                && !input
+                    .platform
+                    .is_some_and(|p| platforms.iter().any(|x| x == p))
+            {
+                return false;
+            }
+            counters.total(*metric, platforms, *window) > *limit
+        }
+
+        RuleCondition::Language { codes, exclude } => {
+            let detected = match input.language {
+                Some(l) => Cow::Borrowed(l),
+                None => match language::detect(content) {
+                    Some(l) => Cow::Owned(l),
+                    None => return false,
+                },
+            };
+            language::code_matches(&detected, codes) != *exclude
+        }
+
+        // Unknown dictionaries (not synced yet) never match
+        RuleCondition::Dictionary { name, min_hits } => input
+            .dictionaries
+            .and_then(|d| d.count_hits(name, content))
+            .is_some_and(|hits| hits > 0 && hits >= *min_hits),
+
+        RuleCondition::Account { types, platforms } => {
+            if !platforms.is_empty()
+                && !input
+                    .platform
+                    .is_some_and(|p| platforms.iter().any(|x| x == p))
+            {
+                return false;
+            }
+            input.account.is_some_and(|a| types.contains(&a))
+        }
+
+        RuleCondition::ApiKey {
+            allowed_fingerprints,
+            platforms,
+        } => {
+            if !platforms.is_empty()
+                && !input
+                    .platform
+                    .is_some_and(|p| platforms.iter().any(|x| x == p))
+            {
+                return false;
+            }
+            input.api_key.is_some_and(|fingerprint| {
+                !allowed_fingerprints
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(fingerprint))
+            })
+        }
     }
 }
 
@@ -82,13 +217,21 @@ fn get_or_compile_regex(pattern: &str, case_insensitive: bool) -> Result<Regex,
 mod tests {
     use super::*;
 
+    /// Évalue si un contenu matche une condition de règle
+    fn matches_condition(content: &str, condition: &RuleCondition) -> bool {
+        matches_input(&MatchInput::new(content), condition)
+    }
+
     #[test]
     fn test_keyword_match_any() {
         let condition = RuleCondition::Keyword {
             keywords: vec!["confidentiel".to_string(), "secret".to_string()],
             match_all: false,
         };
-        assert!(matches_condition("Ce document est confidentiel", &condition));
+        assert!(matches_condition(
+            "Ce document est confidentiel",
+            &condition
+        ));
         assert!(matches_condition("Données top secret", &condition));
         assert!(!matches_condition("Document public", &condition));
     }
@@ -99,7 +242,10 @@ mod tests {
             keywords: vec!["cahier".to_string(), "charges".to_string()],
             match_all: true,
         };
-        assert!(matches_condition("Génère un cahier des charges", &condition));
+        assert!(matches_condition(
+            "Génère un cahier des charges",
+            &condition
+        ));
         assert!(!matches_condition("Un cahier de notes", &condition));
     }
 
@@ -146,7 +292,7 @@ mod tests {
         let short = "court"; // 5 chars — within limit
         let long = "a".repeat(200); // 200 chars — exceeds max
         assert!(!matches_condition(short, &condition)); // within range → no match
-        assert!(matches_condition(&long, &condition));  // exceeds max → MATCH
+        assert!(matches_condition(&long, &condition)); // exceeds max → MATCH
     }
 
     #[test]
@@ -157,7 +303,7 @@ mod tests {
         };
         let short = "court"; // 5 chars — below min
         let long = "a".repeat(200); // 200 chars — above min
-        assert!(matches_condition(short, &condition));  // below min → MATCH
+        assert!(matches_condition(short, &condition)); // below min → MATCH
         assert!(!matches_condition(&long, &condition)); // above min → no match
     }
 
@@ -179,8 +325,8 @@ mod tests {
         };
         let short = "court"; // 5 chars < 100
         let long = "a".repeat(10000); // 10000 > 5000
-        assert!(matches_condition(short, &condition));  // below min → MATCH
-        assert!(matches_condition(&long, &condition));  // exceeds max → MATCH
+        assert!(matches_condition(short, &condition)); // below min → MATCH
+        assert!(matches_condition(&long, &condition)); // exceeds max → MATCH
     }
 
     #[test]
@@ -197,11 +343,142 @@ mod tests {
         let condition = RuleCondition::DomainList {
             domains: vec!["openai.com".to_string(), "claude.ai".to_string()],
         };
-        assert!(matches_condition("https://api.openai.com/v1/chat", &condition));
+        assert!(matches_condition(
+            "https://api.openai.com/v1/chat",
+            &condition
+        ));
         assert!(matches_condition("https://claude.ai/chat", &condition));
         assert!(!matches_condition("https://google.com", &condition));
     }
 
+    #[test]
+    fn test_dlp_count_from_content() {
+        let condition = RuleCondition::DlpCount {
+            patterns: vec!["credit_card".to_string()],
+            min_count: 2,
+        };
+        assert!(!matches_condition("Carte 4532015112830366", &condition));
+        assert!(matches_condition(
+            "Cartes 4532015112830366 et 5425233430109903",
+            &condition
+        ));
+    }
+
+    #[test]
+    fn test_dlp_count_uses_precomputed_counts() {
+        let condition = RuleCondition::DlpCount {
+            patterns: vec![],
+            min_count: 3,
+        };
+        let counts = HashMap::from([("credit_card".to_string(), 1), ("aws_key".to_string(), 2)]);
+        let input = MatchInput {
+            dlp_counts: Some(&counts),
+            ..MatchInput::new("")
+        };
+        assert!(matches_input(&input, &condition));
+    }
+
+    #[test]
+    fn test_language_condition() {
+        let condition = RuleCondition::Language {
+            codes: vec!["fr".to_string(), "en".to_string()],
+            exclude: true,
+        };
+        assert!(!matches_condition(
+            "Rédige un résumé du rapport financier trimestriel",
+            &condition
+        ));
+        assert!(matches_condition(
+            "Escribe un resumen del informe financiero trimestral de la empresa",
+            &condition
+        ));
+        // Undetectable content never matches
+        assert!(!matches_condition("ok", &condition));
+    }
+
+    #[test]
+    fn test_language_condition_uses_precomputed_language() {
+        let condition = RuleCondition::Language {
+            codes: vec!["de".to_string()],
+            exclude: false,
+        };
+        let input = MatchInput {
+            language: Some("de"),
+            ..MatchInput::new("")
+        };
+        assert!(matches_input(&input, &condition));
+    }
+
+    #[test]
+    fn test_account_condition() {
+        let condition = RuleCondition::Account {
+            types: vec![AccountType::Personal, AccountType::Unknown],
+            platforms: vec!["chatgpt".to_string()],
+        };
+        let input = |platform, account| MatchInput {
+            platform: Some(platform),
+            account,
+            ..MatchInput::new("")
+        };
+        assert!(matches_input(
+            &input("chatgpt", Some(AccountType::Personal)),
+            &condition
+        ));
+        assert!(!matches_input(
+            &input("chatgpt", Some(AccountType::Enterprise)),
+            &condition
+        ));
+        assert!(!matches_input(
+            &input("claude", Some(AccountType::Unknown)),
+            &condition
+        ));
+        // Account context not detected
+        assert!(!matches_input(&input("chatgpt", None), &condition));
+    }
+
+    #[test]
+    fn test_api_key_condition() {
+        let condition = RuleCondition::ApiKey {
+            allowed_fingerprints: vec!["AB12".to_string()],
+            platforms: Vec::new(),
+        };
+        let input = |api_key| MatchInput {
+            api_key,
+            ..MatchInput::new("")
+        };
+        assert!(!matches_input(&input(Some("ab12")), &condition));
+        assert!(matches_input(&input(Some("cd34")), &condition));
+        // No API key (web app session)
+        assert!(!matches_input(&input(None), &condition));
+    }
+
+    #[test]
+    fn test_dictionary_condition() {
+        let store = DictionaryStore::new();
+        store.replace_all(vec![crate::rules::models::Dictionary {
+            name: "clients".to_string(),
+            version: 1,
+            terms: vec!["Orange".to_string(), "Moov".to_string()],
+        }]);
+        let condition = RuleCondition::Dictionary {
+            name: "clients".to_string(),
+            min_hits: 2,
+        };
+
+        let input = MatchInput {
+            dictionaries: Some(&store),
+            ..MatchInput::new("Offre Orange et Moov")
+        };
+        assert!(matches_input(&input, &condition));
+        let input = MatchInput {
+            dictionaries: Some(&store),
+            ..MatchInput::new("Offre Orange")
+        };
+        assert!(!matches_input(&input, &condition));
+        // Without dictionaries loaded, never matches
+        assert!(!matches_condition("Offre Orange et Moov", &condition));
+    }
+
     #[test]
     fn test_invalid_regex_returns_false() {
         let condition = RuleCondition::Regex {
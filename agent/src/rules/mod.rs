@@ -1,3 +1,7 @@
+pub mod counters;
+pub mod dictionary;
+pub mod dlp;
 pub mod engine;
+pub mod language;
 pub mod matcher;
 pub mod models;
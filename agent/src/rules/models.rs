@@ -1,5 +1,10 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
 use serde::{Deserialize, Serialize};
 
+use crate::rules::{dlp, language};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rule {
     pub id: String,
@@ -11,6 +16,19 @@ pub struct Rule {
     pub action: RuleAction,
     pub priority: u32,
     pub enabled: bool,
+    #[serde(default)]
+    pub scope: RuleScope,
+}
+
+/// Portée d'évaluation d'une règle
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleScope {
+    /// Évaluée sur le message courant uniquement
+    #[default]
+    Message,
+    /// Évaluée sur le contenu cumulé de la conversation
+    Conversation,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -50,6 +68,110 @@ pub enum RuleCondition {
         min: Option<usize>,
         max: Option<usize>,
     },
+    /// Au moins `min_count` correspondances DLP (tous les motifs si `patterns` est vide)
+    DlpCount {
+        #[serde(default)]
+        patterns: Vec<String>,
+        min_count: usize,
+    },
+    /// Activité `metric` des `window` dernières secondes supérieure à `limit`,
+    /// limitée aux plateformes `platforms` si la liste n'est pas vide
+    Threshold {
+        metric: ThresholdMetric,
+        window: u64,
+        limit: u64,
+        #[serde(default)]
+        platforms: Vec<String>,
+    },
+    /// Langue détectée parmi `codes` (ISO 639-1 ou 639-3), ou hors de la liste
+    /// avec `exclude` ; jamais vraie si la langue n'est pas détectée
+    Language {
+        codes: Vec<String>,
+        #[serde(default)]
+        exclude: bool,
+    },
+    /// Au moins `min_hits` termes du dictionnaire `name` (mots entiers, casse ignorée)
+    Dictionary {
+        name: String,
+        #[serde(default = "default_min_hits")]
+        min_hits: usize,
+    },
+    /// Prompt envoyé depuis un compte de l'un des `types`, sur les plateformes
+    /// `platforms` si la liste n'est pas vide ; jamais vraie sans compte détecté
+    Account {
+        types: Vec<AccountType>,
+        #[serde(default)]
+        platforms: Vec<String>,
+    },
+    /// Clé d'API absente de `allowed_fingerprints` (toute clé si la liste est
+    /// vide), sur les plateformes `platforms` si la liste n'est pas vide
+    ApiKey {
+        #[serde(default)]
+        allowed_fingerprints: Vec<String>,
+        #[serde(default)]
+        platforms: Vec<String>,
+    },
+}
+
+fn default_min_hits() -> usize {
+    1
+}
+
+/// Dictionnaire d'entités nommées (noms de clients, noms de code projets)
+/// synchronisé depuis le serveur
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dictionary {
+    pub name: String,
+    pub version: u64,
+    pub terms: Vec<String>,
+}
+
+/// Type de compte utilisé sur une plateforme IA, d'après l'organisation ou
+/// l'espace de travail déclaré par la requête
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountType {
+    /// Organisation approuvée par l'entreprise
+    Enterprise,
+    /// Organisation inconnue (autre entreprise, compte personnel nommé)
+    Unknown,
+    /// Aucune organisation déclarée : compte personnel
+    Personal,
+}
+
+impl AccountType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountType::Enterprise => "enterprise",
+            AccountType::Unknown => "unknown",
+            AccountType::Personal => "personal",
+        }
+    }
+}
+
+/// Activité comptabilisée par le compteur à fenêtre glissante
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ThresholdMetric {
+    /// Nombre de prompts envoyés aux plateformes IA
+    PromptCount,
+    /// Volume de texte envoyé aux plateformes IA (octets)
+    PromptBytes,
+    /// Nombre de copies dans le presse-papier
+    ClipboardCount,
+    /// Volume de texte copié dans le presse-papier (octets)
+    ClipboardBytes,
+}
+
+impl ThresholdMetric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThresholdMetric::PromptCount => "prompt_count",
+            ThresholdMetric::PromptBytes => "prompt_bytes",
+            ThresholdMetric::ClipboardCount => "clipboard_count",
+            ThresholdMetric::ClipboardBytes => "clipboard_bytes",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -57,6 +179,10 @@ pub enum RuleCondition {
 pub enum RuleAction {
     Block {
         message: String,
+        /// Block template shown to the user (see `proxy::block_page`); the
+        /// `default` template when absent
+        #[serde(default)]
+        template: Option<String>,
     },
     Alert {
         severity: AlertSeverity,
@@ -80,6 +206,7 @@ pub enum EvaluationResult {
         rule_id: String,
         rule_name: String,
         message: String,
+        template: Option<String>,
     },
     /// Alerte générée mais contenu autorisé
     Alerted {
@@ -88,9 +215,95 @@ pub enum EvaluationResult {
         severity: AlertSeverity,
     },
     /// Contenu loggé (pas d'action spéciale)
-    Logged {
-        rule_id: Option<String>,
-    },
+    Logged { rule_id: Option<String> },
     /// Aucune règle ne matche
     NoMatch,
 }
+
+/// Contenu soumis au rule engine, avec les agrégats calculés en amont.
+/// Les correspondances DLP et la langue ne sont calculées qu'à la première
+/// règle qui en a besoin.
+#[derive(Debug, Clone, Default)]
+pub struct EvaluationContext {
+    /// Contenu du message courant
+    pub content: String,
+    /// Nombre de correspondances DLP intégrées dans le message courant
+    dlp_counts: OnceLock<HashMap<String, usize>>,
+    /// Agrégats de la conversation, si le message en fait partie
+    pub conversation: Option<ConversationAggregate>,
+    /// Plateforme IA d'origine (None pour le presse-papier)
+    pub platform: Option<String>,
+    /// Langue détectée du message courant (code ISO 639-1 si disponible)
+    language: OnceLock<Option<String>>,
+    /// Type de compte utilisé sur la plateforme, s'il est détectable
+    pub account: Option<AccountType>,
+    /// Empreinte salée de la clé d'API utilisée par la requête
+    pub api_key_fingerprint: Option<String>,
+}
+
+impl EvaluationContext {
+    pub fn new(content: &str) -> Self {
+        Self {
+            content: content.to_string(),
+            dlp_counts: OnceLock::new(),
+            conversation: None,
+            platform: None,
+            language: OnceLock::new(),
+            account: None,
+            api_key_fingerprint: None,
+        }
+    }
+
+    /// Nombre de correspondances DLP du message courant
+    pub fn dlp_counts(&self) -> &HashMap<String, usize> {
+        self.dlp_counts
+            .get_or_init(|| dlp::count_matches(&self.content))
+    }
+
+    /// Langue détectée du message courant
+    pub fn language(&self) -> Option<&str> {
+        self.language
+            .get_or_init(|| language::detect(&self.content))
+            .as_deref()
+    }
+
+    /// Langue du message courant si elle a déjà été détectée, sans la détecter
+    pub fn detected_language(&self) -> Option<&str> {
+        self.language.get().and_then(|l| l.as_deref())
+    }
+
+    /// Indique si les correspondances DLP ont déjà été calculées
+    #[cfg(test)]
+    pub fn dlp_computed(&self) -> bool {
+        self.dlp_counts.get().is_some()
+    }
+
+    pub fn with_platform(mut self, platform: &str) -> Self {
+        self.platform = Some(platform.to_string());
+        self
+    }
+
+    pub fn with_account(mut self, account: Option<AccountType>) -> Self {
+        self.account = account;
+        self
+    }
+
+    pub fn with_api_key(mut self, fingerprint: Option<String>) -> Self {
+        self.api_key_fingerprint = fingerprint;
+        self
+    }
+
+    pub fn with_conversation(mut self, conversation: ConversationAggregate) -> Self {
+        self.conversation = Some(conversation);
+        self
+    }
+}
+
+/// Contenu cumulé de tous les messages utilisateur d'une conversation
+#[derive(Debug, Clone, Default)]
+pub struct ConversationAggregate {
+    pub conversation_id: String,
+    pub message_count: usize,
+    pub content: String,
+    pub dlp_counts: HashMap<String, usize>,
+}
@@ -1,9 +1,9 @@
+use rusqlite::Connection;
 use std::path::{Path, PathBuf};
 use std::sync::Mutex;
-use rusqlite::Connection;
 use tracing::info;
 
-use crate::rules::models::Rule;
+use crate::rules::models::{Dictionary, Rule};
 use crate::storage::migrations;
 
 pub struct Database {
@@ -35,47 +35,65 @@ impl Database {
 
     /// Run database migrations
     pub fn run_migrations(&self) -> anyhow::Result<()> {
-        let conn = self.conn.lock().map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
         migrations::run_migrations(&conn)
     }
 
     /// Get all enabled rules from local storage
     pub fn get_all_rules(&self) -> anyhow::Result<Vec<Rule>> {
-        let conn = self.conn.lock().map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
         let mut stmt = conn.prepare(
-            "SELECT id, name, version, category, target, condition, action, priority, enabled
+            "SELECT id, name, version, category, target, condition, action, priority, enabled, scope
              FROM rules WHERE enabled = 1 ORDER BY priority DESC"
         )?;
 
-        let rules = stmt.query_map([], |row| {
-            let condition_json: String = row.get(5)?;
-            let action_json: String = row.get(6)?;
-
-            Ok(Rule {
-                id: row.get(0)?,
-                name: row.get(1)?,
-                version: row.get(2)?,
-                category: serde_json::from_str(&row.get::<_, String>(3)?).unwrap_or(crate::rules::models::RuleCategory::Log),
-                target: serde_json::from_str(&row.get::<_, String>(4)?).unwrap_or(crate::rules::models::RuleTarget::Prompt),
-                condition: serde_json::from_str(&condition_json).unwrap_or(crate::rules::models::RuleCondition::Keyword {
-                    keywords: vec![],
-                    match_all: false,
-                }),
-                action: serde_json::from_str(&action_json).unwrap_or(crate::rules::models::RuleAction::Log),
-                priority: row.get(7)?,
-                enabled: row.get::<_, i32>(8)? == 1,
-            })
-        })?.filter_map(|r| r.ok()).collect();
+        let rules = stmt
+            .query_map([], |row| {
+                let condition_json: String = row.get(5)?;
+                let action_json: String = row.get(6)?;
+
+                Ok(Rule {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    version: row.get(2)?,
+                    category: serde_json::from_str(&row.get::<_, String>(3)?)
+                        .unwrap_or(crate::rules::models::RuleCategory::Log),
+                    target: serde_json::from_str(&row.get::<_, String>(4)?)
+                        .unwrap_or(crate::rules::models::RuleTarget::Prompt),
+                    condition: serde_json::from_str(&condition_json).unwrap_or(
+                        crate::rules::models::RuleCondition::Keyword {
+                            keywords: vec![],
+                            match_all: false,
+                        },
+                    ),
+                    action: serde_json::from_str(&action_json)
+                        .unwrap_or(crate::rules::models::RuleAction::Log),
+                    priority: row.get(7)?,
+                    enabled: row.get::<_, i32>(8)? == 1,
+                    scope: serde_json::from_str(&row.get::<_, String>(9)?).unwrap_or_default(),
+                })
+            })?
+            .filter_map(|r| r.ok())
+            .collect();
 
         Ok(rules)
     }
 
     /// Insert or update a rule
     pub fn upsert_rule(&self, rule: &Rule) -> anyhow::Result<()> {
-        let conn = self.conn.lock().map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
         conn.execute(
-            "INSERT INTO rules (id, name, version, category, target, condition, action, priority, enabled, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, datetime('now'))
+            "INSERT INTO rules (id, name, version, category, target, condition, action, priority, enabled, scope, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, datetime('now'))
              ON CONFLICT(id) DO UPDATE SET
                 name = excluded.name,
                 version = excluded.version,
@@ -85,6 +103,7 @@ impl Database {
                 action = excluded.action,
                 priority = excluded.priority,
                 enabled = excluded.enabled,
+                scope = excluded.scope,
                 updated_at = datetime('now')",
             rusqlite::params![
                 rule.id,
@@ -96,6 +115,7 @@ impl Database {
                 serde_json::to_string(&rule.action)?,
                 rule.priority,
                 rule.enabled as i32,
+                serde_json::to_string(&rule.scope)?,
             ],
         )?;
         Ok(())
@@ -103,11 +123,69 @@ impl Database {
 
     /// Delete a rule by ID
     pub fn delete_rule(&self, rule_id: &str) -> anyhow::Result<()> {
-        let conn = self.conn.lock().map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
         conn.execute("DELETE FROM rules WHERE id = ?1", [rule_id])?;
         Ok(())
     }
 
+    /// Get all entity dictionaries from local storage
+    pub fn get_all_dictionaries(&self) -> anyhow::Result<Vec<Dictionary>> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        let mut stmt = conn.prepare("SELECT name, version, terms FROM dictionaries")?;
+
+        let dictionaries = stmt
+            .query_map([], |row| {
+                let terms_json: String = row.get(2)?;
+                Ok(Dictionary {
+                    name: row.get(0)?,
+                    version: row.get(1)?,
+                    terms: serde_json::from_str(&terms_json).unwrap_or_default(),
+                })
+            })?
+            .filter_map(|r| r.ok())
+            .collect();
+
+        Ok(dictionaries)
+    }
+
+    /// Insert or replace an entity dictionary
+    pub fn upsert_dictionary(&self, dictionary: &Dictionary) -> anyhow::Result<()> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        conn.execute(
+            "INSERT INTO dictionaries (name, version, terms, updated_at)
+             VALUES (?1, ?2, ?3, datetime('now'))
+             ON CONFLICT(name) DO UPDATE SET
+                version = excluded.version,
+                terms = excluded.terms,
+                updated_at = datetime('now')",
+            rusqlite::params![
+                dictionary.name,
+                dictionary.version,
+                serde_json::to_string(&dictionary.terms)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Delete an entity dictionary by name
+    pub fn delete_dictionary(&self, name: &str) -> anyhow::Result<()> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        conn.execute("DELETE FROM dictionaries WHERE name = ?1", [name])?;
+        Ok(())
+    }
+
     /// Queue an event for later sync to server
     #[allow(clippy::too_many_arguments)]
     pub fn queue_event(
@@ -122,14 +200,24 @@ impl Database {
         severity: Option<&str>,
         metadata: Option<&str>,
     ) -> anyhow::Result<i64> {
-        let conn = self.conn.lock().map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
         conn.execute(
             "INSERT INTO event_queue (event_type, platform, domain, content_hash, prompt_excerpt,
              response_excerpt, rule_id, severity, metadata, occurred_at)
              VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, datetime('now'))",
             rusqlite::params![
-                event_type, platform, domain, content_hash,
-                prompt_excerpt, response_excerpt, rule_id, severity, metadata,
+                event_type,
+                platform,
+                domain,
+                content_hash,
+                prompt_excerpt,
+                response_excerpt,
+                rule_id,
+                severity,
+                metadata,
             ],
         )?;
         Ok(conn.last_insert_rowid())
@@ -137,36 +225,45 @@ impl Database {
 
     /// Get pending (unsynced) events, up to `limit`
     pub fn get_pending_events(&self, limit: usize) -> anyhow::Result<Vec<QueuedEvent>> {
-        let conn = self.conn.lock().map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
         let mut stmt = conn.prepare(
             "SELECT id, event_type, platform, domain, content_hash, prompt_excerpt,
                     response_excerpt, rule_id, severity, metadata, occurred_at
              FROM event_queue WHERE synced = 0
-             ORDER BY created_at ASC LIMIT ?1"
+             ORDER BY created_at ASC LIMIT ?1",
         )?;
 
-        let events = stmt.query_map([limit], |row| {
-            Ok(QueuedEvent {
-                id: row.get(0)?,
-                event_type: row.get(1)?,
-                platform: row.get(2)?,
-                domain: row.get(3)?,
-                content_hash: row.get(4)?,
-                prompt_excerpt: row.get(5)?,
-                response_excerpt: row.get(6)?,
-                rule_id: row.get(7)?,
-                severity: row.get(8)?,
-                metadata: row.get(9)?,
-                occurred_at: row.get(10)?,
-            })
-        })?.filter_map(|r| r.ok()).collect();
+        let events = stmt
+            .query_map([limit], |row| {
+                Ok(QueuedEvent {
+                    id: row.get(0)?,
+                    event_type: row.get(1)?,
+                    platform: row.get(2)?,
+                    domain: row.get(3)?,
+                    content_hash: row.get(4)?,
+                    prompt_excerpt: row.get(5)?,
+                    response_excerpt: row.get(6)?,
+                    rule_id: row.get(7)?,
+                    severity: row.get(8)?,
+                    metadata: row.get(9)?,
+                    occurred_at: row.get(10)?,
+                })
+            })?
+            .filter_map(|r| r.ok())
+            .collect();
 
         Ok(events)
     }
 
     /// Mark events as synced
     pub fn mark_events_synced(&self, event_ids: &[i64]) -> anyhow::Result<()> {
-        let conn = self.conn.lock().map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
         for id in event_ids {
             conn.execute("UPDATE event_queue SET synced = 1 WHERE id = ?1", [id])?;
         }
@@ -175,7 +272,10 @@ impl Database {
 
     /// Purge old synced events beyond retention period
     pub fn purge_old_events(&self, retention_days: u32) -> anyhow::Result<usize> {
-        let conn = self.conn.lock().map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
         let deleted = conn.execute(
             "DELETE FROM event_queue WHERE synced = 1
              AND created_at < datetime('now', ?1)",
@@ -184,9 +284,75 @@ impl Database {
         Ok(deleted)
     }
 
+    /// Add `amount` to the activity counter of a metric/platform for a minute bucket
+    pub fn add_activity(
+        &self,
+        metric: &str,
+        platform: &str,
+        bucket: i64,
+        amount: u64,
+    ) -> anyhow::Result<()> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        conn.execute(
+            "INSERT INTO activity_counters (metric, platform, bucket, value)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(metric, platform, bucket) DO UPDATE SET value = value + excluded.value",
+            rusqlite::params![metric, platform, bucket, amount as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Sum an activity metric from `since_bucket` onwards.
+    /// An empty `platforms` slice sums over all platforms.
+    pub fn sum_activity(
+        &self,
+        metric: &str,
+        platforms: &[String],
+        since_bucket: i64,
+    ) -> anyhow::Result<u64> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        let mut stmt = conn.prepare(
+            "SELECT platform, COALESCE(SUM(value), 0) FROM activity_counters
+             WHERE metric = ?1 AND bucket >= ?2 GROUP BY platform",
+        )?;
+
+        let total = stmt
+            .query_map(rusqlite::params![metric, since_bucket], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })?
+            .filter_map(|r| r.ok())
+            .filter(|(platform, _)| platforms.is_empty() || platforms.contains(platform))
+            .map(|(_, value)| value.max(0) as u64)
+            .sum();
+
+        Ok(total)
+    }
+
+    /// Delete activity buckets older than `before_bucket`
+    pub fn purge_activity(&self, before_bucket: i64) -> anyhow::Result<usize> {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        let deleted = conn.execute(
+            "DELETE FROM activity_counters WHERE bucket < ?1",
+            [before_bucket],
+        )?;
+        Ok(deleted)
+    }
+
     /// Get a config value
     pub fn get_config(&self, key: &str) -> anyhow::Result<Option<String>> {
-        let conn = self.conn.lock().map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
         let mut stmt = conn.prepare("SELECT value FROM config WHERE key = ?1")?;
         let result = stmt.query_row([key], |row| row.get(0)).ok();
         Ok(result)
@@ -194,7 +360,10 @@ impl Database {
 
     /// Set a config value
     pub fn set_config(&self, key: &str, value: &str) -> anyhow::Result<()> {
-        let conn = self.conn.lock().map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Lock error: {}", e))?;
         conn.execute(
             "INSERT INTO config (key, value) VALUES (?1, ?2)
              ON CONFLICT(key) DO UPDATE SET value = excluded.value",
@@ -46,8 +46,55 @@ pub fn run_migrations(conn: &Connection) -> anyhow::Result<()> {
             platform    TEXT,
             is_blocked  INTEGER NOT NULL DEFAULT 0
         );
-        "
+
+        -- Sliding-window activity counters (one row per metric/platform/minute)
+        CREATE TABLE IF NOT EXISTS activity_counters (
+            metric      TEXT NOT NULL,
+            platform    TEXT NOT NULL DEFAULT '',
+            bucket      INTEGER NOT NULL,  -- Unix time / 60
+            value       INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (metric, platform, bucket)
+        );
+
+        CREATE TABLE IF NOT EXISTS dictionaries (
+            name        TEXT PRIMARY KEY,
+            version     INTEGER NOT NULL DEFAULT 1,
+            terms       TEXT NOT NULL,  -- JSON array
+            updated_at  TEXT NOT NULL DEFAULT (datetime('now'))
+        );
+        ",
     )?;
 
+    // Columns added after the initial schema
+    add_column_if_missing(
+        conn,
+        "rules",
+        "scope",
+        "TEXT NOT NULL DEFAULT '\"message\"'",
+    )?;
+
+    Ok(())
+}
+
+/// Add a column to an existing table unless it is already present
+/// (SQLite has no `ADD COLUMN IF NOT EXISTS`).
+fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> anyhow::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .filter_map(|r| r.ok())
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {};",
+            table, column, definition
+        ))?;
+    }
     Ok(())
 }
@@ -1,12 +1,15 @@
+use hmac::{Hmac, Mac};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use tracing::info;
-use hmac::{Hmac, Mac};
 use sha2::Sha256;
 use std::time::Duration;
+use tracing::info;
 
 use crate::config::AppConfig;
-use crate::rules::models::Rule;
+use crate::proxy::block_page::BlockTemplateSet;
+use crate::proxy::domain_filter::ApprovedTenant;
+use crate::proxy::platforms::PlatformSpec;
+use crate::rules::models::{Dictionary, Rule};
 use crate::sync::cert_pinning;
 
 type HmacSha256 = Hmac<Sha256>;
@@ -47,6 +50,12 @@ pub struct DomainEntry {
 #[derive(Debug, Deserialize)]
 pub struct DomainSyncResponse {
     pub domains: Vec<DomainEntry>,
+    /// Platform catalog (empty with servers that do not send it)
+    #[serde(default)]
+    pub platforms: Vec<PlatformSpec>,
+    /// Organizations approved for enterprise use of the platforms
+    #[serde(default)]
+    pub approved_tenants: Vec<ApprovedTenant>,
 }
 
 #[derive(Debug, Serialize)]
@@ -97,6 +106,13 @@ pub struct RuleSyncResponse {
     pub deleted_ids: Vec<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct DictionarySyncResponse {
+    pub dictionaries: Vec<Dictionary>,
+    #[serde(default)]
+    pub deleted_names: Vec<String>,
+}
+
 /// Watchdog alert payload (sent by the watchdog binary)
 #[derive(Debug, Serialize)]
 // Used by the icon-watchdog binary, not the main agent
@@ -156,7 +172,8 @@ impl ApiClient {
             agent_version: env!("CARGO_PKG_VERSION").to_string(),
         };
 
-        let mut request = self.client
+        let mut request = self
+            .client
             .post(format!("{}/api/agents/register", self.server_url))
             .json(&req);
 
@@ -168,10 +185,7 @@ impl ApiClient {
 
         let resp = request.send().await?;
 
-        let resp = resp
-            .error_for_status()?
-            .json::<RegisterResponse>()
-            .await?;
+        let resp = resp.error_for_status()?.json::<RegisterResponse>().await?;
 
         info!(machine_id = %resp.machine_id, "Successfully registered with server");
 
@@ -186,8 +200,12 @@ impl ApiClient {
     }
 
     /// Send heartbeat to server
-    pub async fn send_heartbeat(&self, heartbeat: &HeartbeatRequest) -> anyhow::Result<HeartbeatResponse> {
-        let resp = self.authenticated_post("/api/agents/heartbeat", heartbeat)
+    pub async fn send_heartbeat(
+        &self,
+        heartbeat: &HeartbeatRequest,
+    ) -> anyhow::Result<HeartbeatResponse> {
+        let resp = self
+            .authenticated_post("/api/agents/heartbeat", heartbeat)
             .await?
             .json::<HeartbeatResponse>()
             .await?;
@@ -207,9 +225,13 @@ impl ApiClient {
 
     /// Fetch rules that are newer than the given version
     pub async fn sync_rules(&self, since_version: u64) -> anyhow::Result<RuleSyncResponse> {
-        let url = format!("{}/api/rules/sync?version={}", self.server_url, since_version);
+        let url = format!(
+            "{}/api/rules/sync?version={}",
+            self.server_url, since_version
+        );
 
-        let resp = self.authenticated_get(&url)
+        let resp = self
+            .authenticated_get(&url)
             .await?
             .json::<RuleSyncResponse>()
             .await?;
@@ -222,6 +244,47 @@ impl ApiClient {
         Ok(resp)
     }
 
+    /// Fetch entity dictionaries that are newer than the given version
+    pub async fn sync_dictionaries(
+        &self,
+        since_version: u64,
+    ) -> anyhow::Result<DictionarySyncResponse> {
+        let url = format!(
+            "{}/api/dictionaries/sync?version={}",
+            self.server_url, since_version
+        );
+
+        let resp = self
+            .authenticated_get(&url)
+            .await?
+            .json::<DictionarySyncResponse>()
+            .await?;
+
+        info!(
+            updated = resp.dictionaries.len(),
+            deleted = resp.deleted_names.len(),
+            "Dictionaries sync completed"
+        );
+        Ok(resp)
+    }
+
+    /// Fetch the block page templates and the support contact
+    pub async fn sync_block_templates(&self) -> anyhow::Result<BlockTemplateSet> {
+        let url = format!("{}/api/block-templates/sync", self.server_url);
+
+        let resp = self
+            .authenticated_get(&url)
+            .await?
+            .json::<BlockTemplateSet>()
+            .await?;
+
+        info!(
+            count = resp.templates.len(),
+            "Block templates sync completed"
+        );
+        Ok(resp)
+    }
+
     /// Check for agent updates
     // Update checks are currently handled via HeartbeatResponse.update_available;
     // this method is retained for direct/CLI-triggered update checks.
@@ -247,15 +310,13 @@ impl ApiClient {
     pub async fn sync_domains(&self) -> anyhow::Result<DomainSyncResponse> {
         let url = format!("{}/api/domains/sync", self.server_url);
 
-        let resp = self.authenticated_get(&url)
+        let resp = self
+            .authenticated_get(&url)
             .await?
             .json::<DomainSyncResponse>()
             .await?;
 
-        info!(
-            count = resp.domains.len(),
-            "Domain sync completed"
-        );
+        info!(count = resp.domains.len(), "Domain sync completed");
         Ok(resp)
     }
 
@@ -280,7 +341,13 @@ impl ApiClient {
     /// Check server connectivity (used by queue to determine online/offline status)
     pub async fn is_server_reachable(&self) -> bool {
         let url = format!("{}/api/health", self.server_url);
-        match self.client.get(&url).timeout(Duration::from_secs(5)).send().await {
+        match self
+            .client
+            .get(&url)
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+        {
             Ok(resp) => resp.status().is_success(),
             Err(_) => false,
         }
@@ -303,7 +370,9 @@ impl ApiClient {
         let timestamp = chrono::Utc::now().timestamp().to_string();
         let signature = self.sign_payload(&timestamp, &body_json);
 
-        let mut req = self.client.post(&url)
+        let mut req = self
+            .client
+            .post(&url)
             .header("Content-Type", "application/json")
             .header("X-Timestamp", &timestamp)
             .body(body_json);
@@ -323,8 +392,7 @@ impl ApiClient {
     async fn authenticated_get(&self, url: &str) -> anyhow::Result<reqwest::Response> {
         let timestamp = chrono::Utc::now().timestamp().to_string();
 
-        let mut req = self.client.get(url)
-            .header("X-Timestamp", &timestamp);
+        let mut req = self.client.get(url).header("X-Timestamp", &timestamp);
 
         if let Some(ref key) = self.api_key {
             req = req.header("X-Api-Key", key);
@@ -2,7 +2,7 @@ use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::Mutex;
 use tokio::time::{interval, Duration};
-use tracing::{info, warn, debug, error};
+use tracing::{debug, error, info, warn};
 
 use crate::config::AppConfig;
 use crate::rules::engine::RuleEngine;
@@ -19,7 +19,10 @@ pub async fn run_heartbeat_loop(
     let mut heartbeat_interval = interval(Duration::from_secs(config.heartbeat_interval_secs));
     let start_time = Instant::now();
 
-    let machine_id = config.machine_id.clone().unwrap_or_else(|| "unregistered".to_string());
+    let machine_id = config
+        .machine_id
+        .clone()
+        .unwrap_or_else(|| "unregistered".to_string());
 
     // Track the last version we attempted to update to, so we don't retry
     // the same failed update on every heartbeat cycle.
@@ -29,7 +32,8 @@ pub async fn run_heartbeat_loop(
         heartbeat_interval.tick().await;
 
         // Get actual queue size from database
-        let queue_size = db.get_pending_events(1)
+        let queue_size = db
+            .get_pending_events(1)
             .map(|events| events.len())
             .unwrap_or(0);
 
@@ -48,9 +52,21 @@ pub async fn run_heartbeat_loop(
                 // Handle force rule sync
                 if resp.force_sync_rules {
                     info!("Server requested force rule sync");
-                    if let Err(e) = crate::sync::rules_sync::sync_rules(&api_client, &rule_engine).await {
+                    if let Err(e) =
+                        crate::sync::rules_sync::sync_rules(&api_client, &rule_engine).await
+                    {
                         error!(error = %e, "Force rule sync failed");
                     }
+                    if let Err(e) =
+                        crate::sync::rules_sync::sync_dictionaries(&api_client, &rule_engine).await
+                    {
+                        error!(error = %e, "Force dictionary sync failed");
+                    }
+                    if let Err(e) =
+                        crate::sync::rules_sync::sync_block_templates(&api_client, &db).await
+                    {
+                        error!(error = %e, "Force block template sync failed");
+                    }
                 }
 
                 // Handle available update — only attempt once per version
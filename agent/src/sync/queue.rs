@@ -1,6 +1,6 @@
 use std::sync::Arc;
 use tokio::time::{interval, Duration};
-use tracing::{warn, error, debug};
+use tracing::{debug, error, warn};
 
 use crate::storage::database::Database;
 use crate::sync::api_client::{ApiClient, EventBatch, EventPayload};
@@ -13,11 +13,22 @@ pub struct EventQueue {
 }
 
 impl EventQueue {
-    pub fn new(db: Arc<Database>, api_client: Arc<ApiClient>, sync_interval_secs: u64, batch_size: usize) -> Self {
-        Self { db, api_client, sync_interval_secs, batch_size }
+    pub fn new(
+        db: Arc<Database>,
+        api_client: Arc<ApiClient>,
+        sync_interval_secs: u64,
+        batch_size: usize,
+    ) -> Self {
+        Self {
+            db,
+            api_client,
+            sync_interval_secs,
+            batch_size,
+        }
     }
 
-    /// Log an event to the local queue (non-blocking)
+    /// Log an event to the local queue (non-blocking).
+    /// Returns the local event ID, or None if the event could not be queued.
     #[allow(clippy::too_many_arguments)]
     pub async fn log_event(
         &self,
@@ -29,13 +40,19 @@ impl EventQueue {
         response_excerpt: Option<&str>,
         rule_id: Option<&str>,
         severity: Option<&str>,
-    ) {
-        if let Err(e) = self.db.queue_event(
-            event_type, platform, domain, content_hash,
-            prompt_excerpt, response_excerpt, rule_id, severity, None,
-        ) {
-            error!(error = %e, "Failed to queue event locally");
-        }
+    ) -> Option<i64> {
+        self.log_event_with_metadata(
+            event_type,
+            platform,
+            domain,
+            content_hash,
+            prompt_excerpt,
+            response_excerpt,
+            rule_id,
+            severity,
+            None,
+        )
+        .await
     }
 
     /// Log an event with additional metadata (e.g. DLP match details)
@@ -51,12 +68,23 @@ impl EventQueue {
         rule_id: Option<&str>,
         severity: Option<&str>,
         metadata: Option<&str>,
-    ) {
-        if let Err(e) = self.db.queue_event(
-            event_type, platform, domain, content_hash,
-            prompt_excerpt, response_excerpt, rule_id, severity, metadata,
+    ) -> Option<i64> {
+        match self.db.queue_event(
+            event_type,
+            platform,
+            domain,
+            content_hash,
+            prompt_excerpt,
+            response_excerpt,
+            rule_id,
+            severity,
+            metadata,
         ) {
-            error!(error = %e, "Failed to queue event locally");
+            Ok(id) => Some(id),
+            Err(e) => {
+                error!(error = %e, "Failed to queue event locally");
+                None
+            }
         }
     }
 
@@ -97,18 +125,21 @@ impl EventQueue {
 
             let batch = EventBatch {
                 machine_id: self.get_machine_id(),
-                events: events.into_iter().map(|e| EventPayload {
-                    event_type: e.event_type,
-                    platform: e.platform,
-                    domain: e.domain,
-                    content_hash: e.content_hash,
-                    prompt_excerpt: e.prompt_excerpt,
-                    response_excerpt: e.response_excerpt,
-                    rule_id: e.rule_id,
-                    severity: e.severity,
-                    metadata: e.metadata,
-                    occurred_at: e.occurred_at,
-                }).collect(),
+                events: events
+                    .into_iter()
+                    .map(|e| EventPayload {
+                        event_type: e.event_type,
+                        platform: e.platform,
+                        domain: e.domain,
+                        content_hash: e.content_hash,
+                        prompt_excerpt: e.prompt_excerpt,
+                        response_excerpt: e.response_excerpt,
+                        rule_id: e.rule_id,
+                        severity: e.severity,
+                        metadata: e.metadata,
+                        occurred_at: e.occurred_at,
+                    })
+                    .collect(),
             };
 
             match self.api_client.send_events(&batch).await {
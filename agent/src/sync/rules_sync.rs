@@ -1,7 +1,9 @@
 use std::sync::Arc;
 use tracing::info;
 
+use crate::proxy::block_page;
 use crate::rules::engine::RuleEngine;
+use crate::storage::database::Database;
 use crate::sync::api_client::ApiClient;
 
 /// Sync rules from the server (incremental)
@@ -28,3 +30,49 @@ pub async fn sync_rules(
     info!("Rule sync complete");
     Ok(())
 }
+
+/// Sync entity dictionaries from the server (incremental)
+pub async fn sync_dictionaries(
+    api_client: &Arc<ApiClient>,
+    rule_engine: &Arc<RuleEngine>,
+) -> anyhow::Result<()> {
+    let current_version = rule_engine.latest_dictionary_version();
+    info!(
+        since_version = current_version,
+        "Syncing dictionaries from server"
+    );
+
+    let response = api_client.sync_dictionaries(current_version).await?;
+
+    if !response.dictionaries.is_empty() {
+        info!(
+            count = response.dictionaries.len(),
+            "Applying new/updated dictionaries"
+        );
+        rule_engine.update_dictionaries(response.dictionaries)?;
+    }
+
+    for name in &response.deleted_names {
+        rule_engine.delete_dictionary(name)?;
+    }
+
+    info!("Dictionary sync complete");
+    Ok(())
+}
+
+/// Sync block page templates from the server (cached in the local DB)
+pub async fn sync_block_templates(
+    api_client: &Arc<ApiClient>,
+    db: &Database,
+) -> anyhow::Result<()> {
+    let templates = api_client.sync_block_templates().await?;
+
+    db.set_config(
+        block_page::TEMPLATES_CONFIG_KEY,
+        &serde_json::to_string(&templates)?,
+    )?;
+    block_page::set_templates(templates);
+
+    info!("Block template sync complete");
+    Ok(())
+}
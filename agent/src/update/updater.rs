@@ -1,7 +1,8 @@
+use sha2::{Digest, Sha256};
 use std::path::PathBuf;
+#[cfg(any(target_os = "macos", target_os = "windows"))]
 use std::process::Command;
-use sha2::{Sha256, Digest};
-use tracing::{info, warn, error};
+use tracing::{error, info, warn};
 
 use crate::sync::api_client::UpdateInfo;
 
@@ -11,7 +12,8 @@ pub async fn apply_update(update: &UpdateInfo) -> anyhow::Result<()> {
 
     // Download the new binary
     let client = reqwest::Client::new();
-    let response = client.get(&update.download_url)
+    let response = client
+        .get(&update.download_url)
         .send()
         .await?
         .error_for_status()?;
@@ -67,10 +69,7 @@ pub async fn apply_update(update: &UpdateInfo) -> anyhow::Result<()> {
             // Clean up the unverified binary
             let _ = std::fs::remove_file(&temp_path);
             warn!(error = %e, "Code signature verification error, aborting update");
-            anyhow::bail!(
-                "Update aborted: code signature verification error: {}",
-                e
-            );
+            anyhow::bail!("Update aborted: code signature verification error: {}", e);
         }
     }
 
@@ -119,7 +118,10 @@ fn restart_service() {
     #[cfg(target_os = "windows")]
     {
         let _ = std::process::Command::new("cmd")
-            .args(["/C", "sc stop IconAgent && timeout /t 2 && sc start IconAgent"])
+            .args([
+                "/C",
+                "sc stop IconAgent && timeout /t 2 && sc start IconAgent",
+            ])
             .spawn();
     }
 }
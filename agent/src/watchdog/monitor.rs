@@ -1,5 +1,6 @@
 use tracing::info;
 
+#[cfg(any(target_os = "macos", target_os = "windows"))]
 const AGENT_PROCESS_NAME: &str = "icon-agent";
 
 /// Check if the main agent process is running
@@ -61,7 +62,8 @@ pub fn is_proxy_configured() -> bool {
             .args([
                 "query",
                 r"HKCU\Software\Microsoft\Windows\CurrentVersion\Internet Settings",
-                "/v", "AutoConfigURL",
+                "/v",
+                "AutoConfigURL",
             ])
             .output();
 
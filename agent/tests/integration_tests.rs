@@ -40,6 +40,15 @@ fn test_config(server_url: &str) -> AppConfig {
         reverb_app_key: None,
         reverb_channel: None,
         enrollment_key: None,
+        transparent_mode: false,
+        transparent_port: 0,
+        upstream_proxy: None,
+        upstream_proxy_bypass: None,
+        api_key_salt: None,
+        approved_assistant_url: None,
+        approved_api_endpoint: None,
+        approved_api_key: None,
+        approved_api_model: None,
     }
 }
 
@@ -113,8 +122,13 @@ async fn test_registration_sends_required_fields() {
             let body: serde_json::Value =
                 serde_json::from_slice(&req.body).expect("body should be valid JSON");
             // All four required fields must be present and non-empty
-            body.get("hostname").and_then(|v| v.as_str()).is_some_and(|s| !s.is_empty())
-                && body.get("os").and_then(|v| v.as_str()).is_some_and(|s| !s.is_empty())
+            body.get("hostname")
+                .and_then(|v| v.as_str())
+                .is_some_and(|s| !s.is_empty())
+                && body
+                    .get("os")
+                    .and_then(|v| v.as_str())
+                    .is_some_and(|s| !s.is_empty())
                 && body.get("os_version").and_then(|v| v.as_str()).is_some()
                 && body
                     .get("agent_version")
@@ -255,9 +269,14 @@ async fn test_heartbeat_with_update_available() {
     let resp = client.send_heartbeat(&heartbeat).await.unwrap();
     assert!(!resp.force_sync_rules);
 
-    let update = resp.update_available.expect("update_available should be Some");
+    let update = resp
+        .update_available
+        .expect("update_available should be Some");
     assert_eq!(update.version, "1.2.0");
-    assert_eq!(update.download_url, "https://releases.example.com/agent-1.2.0");
+    assert_eq!(
+        update.download_url,
+        "https://releases.example.com/agent-1.2.0"
+    );
     assert_eq!(update.checksum, "sha256:abcdef1234567890");
 }
 
@@ -800,10 +819,7 @@ async fn test_sync_rules_with_domain_list_condition() {
 
     let rule = &resp.rules[0];
     assert_eq!(rule.id, "rule-domain-1");
-    assert_eq!(
-        rule.target,
-        icon_agent::rules::models::RuleTarget::Domain
-    );
+    assert_eq!(rule.target, icon_agent::rules::models::RuleTarget::Domain);
 
     // Verify the condition is DomainList
     match &rule.condition {
@@ -812,13 +828,81 @@ async fn test_sync_rules_with_domain_list_condition() {
             assert!(domains.contains(&"unauthorized-ai.com".to_string()));
             assert!(domains.contains(&"shady-llm.io".to_string()));
         }
-        other => panic!(
-            "Expected DomainList condition, got: {:?}",
-            other
-        ),
+        other => panic!("Expected DomainList condition, got: {:?}", other),
     }
 }
 
+#[tokio::test]
+async fn test_sync_dictionaries_success() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/dictionaries/sync"))
+        .and(query_param("version", "3"))
+        .and(header("X-Api-Key", "test-api-key-123"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "dictionaries": [{
+                "name": "clients",
+                "version": 4,
+                "terms": ["Orange", "Société Générale"]
+            }],
+            "deleted_names": ["old-projects"]
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let config = authenticated_config(&mock_server.uri());
+    let client = ApiClient::new(&config).unwrap();
+
+    let resp = client.sync_dictionaries(3).await.unwrap();
+    assert_eq!(resp.dictionaries.len(), 1);
+    assert_eq!(resp.dictionaries[0].name, "clients");
+    assert_eq!(resp.dictionaries[0].version, 4);
+    assert_eq!(resp.dictionaries[0].terms.len(), 2);
+    assert_eq!(resp.deleted_names, vec!["old-projects".to_string()]);
+}
+
+#[tokio::test]
+async fn test_sync_block_templates_success() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/block-templates/sync"))
+        .and(header("X-Api-Key", "test-api-key-123"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "templates": [
+                {
+                    "name": "default",
+                    "locale": "fr",
+                    "html": "<p>{{MESSAGE}} - {{INCIDENT_ID}}</p>"
+                },
+                {
+                    "name": "secrets",
+                    "locale": "en",
+                    "html": "<p>{{RULE_NAME}}</p>",
+                    "text": "Blocked: {{MESSAGE}}"
+                }
+            ],
+            "support_contact": "support-ia@gs2e.ci"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let config = authenticated_config(&mock_server.uri());
+    let client = ApiClient::new(&config).unwrap();
+
+    let resp = client.sync_block_templates().await.unwrap();
+    assert_eq!(resp.templates.len(), 2);
+    assert_eq!(resp.templates[0].text, None);
+    assert_eq!(
+        resp.templates[1].text.as_deref(),
+        Some("Blocked: {{MESSAGE}}")
+    );
+    assert_eq!(resp.support_contact.as_deref(), Some("support-ia@gs2e.ci"));
+}
+
 // ===========================================================================
 // 5. Domain sync
 // ===========================================================================
@@ -868,10 +952,7 @@ async fn test_sync_domains_success() {
 
     // Verify first domain
     assert_eq!(resp.domains[0].domain, "chat.openai.com");
-    assert_eq!(
-        resp.domains[0].platform_name.as_deref(),
-        Some("ChatGPT")
-    );
+    assert_eq!(resp.domains[0].platform_name.as_deref(), Some("ChatGPT"));
     assert!(!resp.domains[0].is_blocked);
 
     // Verify blocked domain
@@ -884,6 +965,51 @@ async fn test_sync_domains_success() {
     assert!(resp.domains[3].is_blocked);
 }
 
+#[tokio::test]
+async fn test_sync_domains_platform_catalog() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/domains/sync"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "domains": [
+                {
+                    "domain": "chat.deepseek.com",
+                    "platform_name": "DeepSeek",
+                    "is_blocked": false
+                }
+            ],
+            "platforms": [
+                {
+                    "name": "deepseek",
+                    "domains": ["chat.deepseek.com"],
+                    "api_paths": ["/api/v0/chat/completion"],
+                    "prompt_paths": ["prompt"],
+                    "stream_format": "sse",
+                    "stream_paths": ["choices[0].delta.content"]
+                }
+            ],
+            "approved_tenants": [
+                { "platform": "chatgpt", "tenant_id": "org-Acme42" }
+            ]
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let config = authenticated_config(&mock_server.uri());
+    let client = ApiClient::new(&config).unwrap();
+
+    let resp: DomainSyncResponse = client.sync_domains().await.unwrap();
+
+    assert_eq!(resp.platforms.len(), 1);
+    assert_eq!(resp.platforms[0].name, "deepseek");
+    assert_eq!(resp.platforms[0].prompt_paths, vec!["prompt"]);
+    assert!(resp.platforms[0].response_paths.is_empty());
+    assert_eq!(resp.approved_tenants.len(), 1);
+    assert_eq!(resp.approved_tenants[0].tenant_id, "org-Acme42");
+}
+
 #[tokio::test]
 async fn test_sync_domains_empty_list() {
     let mock_server = MockServer::start().await;
@@ -1200,8 +1326,7 @@ async fn test_hmac_api_key_not_sent_without_credentials() {
     Mock::given(method("POST"))
         .and(path("/api/agents/heartbeat"))
         .and(|req: &wiremock::Request| {
-            req.headers.get("X-Api-Key").is_none()
-                && req.headers.get("X-Signature").is_none()
+            req.headers.get("X-Api-Key").is_none() && req.headers.get("X-Signature").is_none()
         })
         .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
             "force_sync_rules": false,
@@ -1256,7 +1381,10 @@ async fn test_hmac_different_payloads_produce_different_signatures() {
     let sig_a = compute_hmac(secret, timestamp, payload_a);
     let sig_b = compute_hmac(secret, timestamp, payload_b);
 
-    assert_ne!(sig_a, sig_b, "Different payloads should produce different HMACs");
+    assert_ne!(
+        sig_a, sig_b,
+        "Different payloads should produce different HMACs"
+    );
 }
 
 #[tokio::test]